@@ -103,6 +103,45 @@ impl Apu {
         }
     }
 
+    /// Output of a single channel as if the others were silent, on the same scale as the mixed
+    /// output.
+    #[inline]
+    #[must_use]
+    pub fn channel_output(&self, channel: Channel) -> f32 {
+        match channel {
+            Channel::Pulse1 => PULSE_TABLE[self.pulse1.output() as usize % PULSE_TABLE.len()],
+            Channel::Pulse2 => PULSE_TABLE[self.pulse2.output() as usize % PULSE_TABLE.len()],
+            Channel::Triangle => {
+                TND_TABLE[(3.0 * self.triangle.output()) as usize % TND_TABLE.len()]
+            }
+            Channel::Noise => TND_TABLE[(2.0 * self.noise.output()) as usize % TND_TABLE.len()],
+            Channel::Dmc => TND_TABLE[self.dmc.output() as usize % TND_TABLE.len()],
+        }
+    }
+
+    /// Mixed output with the level of each channel scaled by `volumes`, in `Channel` order.
+    /// Levels are mixed non-linearly the same way as `output`, using the formulas behind the
+    /// mixer tables since scaled levels fall between table entries.
+    #[must_use]
+    pub fn mixed_output(&self, volumes: &[f32]) -> f32 {
+        let pulse = volumes[0].mul_add(self.pulse1.output(), volumes[1] * self.pulse2.output());
+        let tnd = (3.0 * volumes[2]).mul_add(
+            self.triangle.output(),
+            (2.0 * volumes[3]).mul_add(self.noise.output(), volumes[4] * self.dmc.output()),
+        );
+        let pulse_out = if pulse > 0.0 {
+            95.52 / (8_128.0 / pulse + 100.0)
+        } else {
+            0.0
+        };
+        let tnd_out = if tnd > 0.0 {
+            163.67 / (24_329.0 / tnd + 100.0)
+        } else {
+            0.0
+        };
+        pulse_out + tnd_out
+    }
+
     #[inline]
     pub fn irqs_pending(&self) -> Irq {
         let mut irq = Irq::empty();
@@ -1,13 +1,23 @@
-use crate::{audio::filter::Filter, NesResult};
+use crate::{
+    audio::{
+        filter::{FilterChain, FilterProfile},
+        wav::{WavFormat, WavWriter},
+    },
+    NesResult,
+};
 use anyhow::anyhow;
 #[cfg(not(target_arch = "wasm32"))]
 use pix_engine::prelude::*;
 use ringbuf::{Consumer, HeapRb, Producer, SharedRb};
+use serde::{Deserialize, Serialize};
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Duration;
-use std::{fmt, mem::MaybeUninit, sync::Arc};
+use std::{fmt, fs::File, io::BufWriter, mem::MaybeUninit, path::Path, sync::Arc};
 
+pub mod blip;
 pub mod filter;
+pub mod stems;
+pub mod wav;
 pub mod window_sinc;
 
 type RbRef = Arc<SharedRb<f32, Vec<MaybeUninit<f32>>>>;
@@ -16,19 +26,62 @@ pub trait Audio {
     fn output(&self) -> f32;
 }
 
+/// Volume and stereo panning of an audio channel.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[must_use]
+pub struct ChannelMix {
+    /// Volume from `0.0` up to `MAX_VOLUME`, where `1.0` is the original level.
+    pub volume: f32,
+    /// Panning from `-1.0`, fully left, to `1.0`, fully right.
+    pub pan: f32,
+}
+
+impl Default for ChannelMix {
+    fn default() -> Self {
+        Self::new(1.0, 0.0)
+    }
+}
+
+impl ChannelMix {
+    pub const MAX_VOLUME: f32 = 2.0;
+
+    pub const fn new(volume: f32, pan: f32) -> Self {
+        Self { volume, pan }
+    }
+
+    /// Gains of the left and right output channels. Centered channels play at full volume on
+    /// both sides, so stereo output matches mono output until channels are panned.
+    #[inline]
+    #[must_use]
+    pub fn gains(&self) -> (f32, f32) {
+        let volume = self.volume.clamp(0.0, Self::MAX_VOLUME);
+        let pan = self.pan.clamp(-1.0, 1.0);
+        (volume * (1.0 - pan).min(1.0), volume * (1.0 + pan).min(1.0))
+    }
+}
+
 pub struct NesAudioCallback {
     initialized: bool,
+    channels: usize,
     buffer: Consumer<f32, RbRef>,
 }
 
 impl NesAudioCallback {
-    const fn new(buffer: Consumer<f32, RbRef>) -> Self {
+    const fn new(buffer: Consumer<f32, RbRef>, channels: usize) -> Self {
         Self {
             initialized: false,
+            channels,
             buffer,
         }
     }
 
+    /// Number of interleaved channels read into each output frame.
+    #[inline]
+    #[must_use]
+    pub const fn channels(&self) -> usize {
+        self.channels
+    }
+
     #[inline]
     pub fn clear(&mut self) {
         self.buffer.clear();
@@ -53,11 +106,14 @@ impl NesAudioCallback {
         }
         self.initialized = true;
 
-        for val in out {
-            if let Some(sample) = self.buffer.pop() {
-                *val = sample;
+        // Read whole frames so channels stay in order after the buffer runs dry
+        for frame in out.chunks_mut(self.channels) {
+            if self.buffer.len() >= frame.len() {
+                for val in frame {
+                    *val = self.buffer.pop().unwrap_or_default();
+                }
             } else {
-                *val = 0.0;
+                frame.fill(0.0);
             }
         }
     }
@@ -76,6 +132,7 @@ impl fmt::Debug for NesAudioCallback {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("NesAudioCallback")
             .field("initialized", &self.initialized)
+            .field("channels", &self.channels)
             .field("buffer_len", &self.buffer.len())
             .field("buffer_capacity", &self.buffer.capacity())
             .finish()
@@ -88,18 +145,15 @@ pub struct AudioMixer {
     device: Option<AudioDevice<NesAudioCallback>>,
     producer: Producer<f32, RbRef>,
     consumer: Option<Consumer<f32, RbRef>>,
-    input_frequency: f32,
     output_frequency: f32,
-    decim_ratio: f32,
+    channels: usize,
     pitch_ratio: f32,
-    fraction: f32,
-    avg: f32,
-    count: f32,
-    filters: [Filter; 3],
+    filters: Vec<FilterChain>,
+    recording: Option<WavWriter<BufWriter<File>>>,
 }
 
 impl AudioMixer {
-    pub fn new(input_frequency: f32, output_frequency: f32, buffer_size: usize) -> Self {
+    pub fn new(output_frequency: f32, buffer_size: usize) -> Self {
         let buffer = HeapRb::<f32>::new(buffer_size);
         let (producer, consumer) = buffer.split();
         Self {
@@ -107,19 +161,11 @@ impl AudioMixer {
             device: None,
             producer,
             consumer: Some(consumer),
-            input_frequency,
             output_frequency,
-            decim_ratio: input_frequency / output_frequency,
+            channels: 1,
             pitch_ratio: 1.0,
-            fraction: 0.0,
-            avg: 0.0,
-            count: 0.0,
-            filters: [
-                Filter::high_pass(output_frequency, 90.0, 1500.0),
-                Filter::high_pass(output_frequency, 440.0, 1500.0),
-                // Should be 14k, but this allows 2X speed within the Nyquist limit
-                Filter::low_pass(output_frequency, 12_000.0, 1500.0),
-            ],
+            filters: vec![FilterChain::new(FilterProfile::default(), output_frequency)],
+            recording: None,
         }
     }
 
@@ -128,6 +174,22 @@ impl AudioMixer {
         self.output_frequency
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Sets the number of interleaved channels in consumed samples, `1` for mono or `2` for
+    /// stereo to match `ControlDeck::audio_channels`. Only call before opening playback or a
+    /// callback.
+    #[inline]
+    pub fn set_channels(&mut self, channels: usize) {
+        self.channels = channels.clamp(1, 2);
+        self.filters =
+            vec![FilterChain::new(self.filter_profile(), self.output_frequency); self.channels];
+    }
+
     /// Opens audio callback device for playback
     ///
     /// # Errors
@@ -138,13 +200,16 @@ impl AudioMixer {
     pub fn open_playback(&mut self, s: &mut PixState) -> NesResult<()> {
         match self.consumer.take() {
             Some(consumer) => {
+                let channels = self.channels;
                 let spec = AudioSpecDesired {
                     freq: Some(self.output_frequency as i32),
-                    channels: Some(1),
+                    channels: Some(channels as u8),
                     samples: Some((self.capacity() / 2) as u16),
                 };
                 self.device =
-                    Some(s.open_playback(None, &spec, |_| NesAudioCallback::new(consumer))?);
+                    Some(s.open_playback(None, &spec, |_| {
+                        NesAudioCallback::new(consumer, channels)
+                    })?);
                 Ok(())
             }
             None => Err(anyhow!("can only open_playback once")),
@@ -158,7 +223,7 @@ impl AudioMixer {
     /// This function will return an error if `open_buffer` is called more than once.
     pub fn open_callback(&mut self) -> NesResult<NesAudioCallback> {
         match self.consumer.take() {
-            Some(consumer) => Ok(NesAudioCallback::new(consumer)),
+            Some(consumer) => Ok(NesAudioCallback::new(consumer, self.channels)),
             None => Err(anyhow!("can only open_buffer exactly once")),
         }
     }
@@ -170,9 +235,7 @@ impl AudioMixer {
     /// This function will return an error if the audio device fails to be opened.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn reset(&mut self, buffer_size: usize) {
-        self.decim_ratio = self.input_frequency / self.output_frequency;
         self.pitch_ratio = 1.0;
-        self.fraction = 0.0;
         let buffer = HeapRb::<f32>::new(buffer_size);
         let (producer, consumer) = buffer.split();
         self.producer = producer;
@@ -196,13 +259,61 @@ impl AudioMixer {
     }
 
     #[inline]
-    pub fn set_input_frequency(&mut self, input_frequency: f32) {
-        self.input_frequency = input_frequency;
+    pub fn set_output_frequency(&mut self, output_frequency: f32) {
+        self.output_frequency = output_frequency;
+        self.set_filter_profile(self.filter_profile());
     }
 
     #[inline]
-    pub fn set_output_frequency(&mut self, output_frequency: f32) {
-        self.output_frequency = output_frequency;
+    pub fn filter_profile(&self) -> FilterProfile {
+        self.filters[0].profile()
+    }
+
+    /// Sets which console's analog output filtering to model.
+    #[inline]
+    pub fn set_filter_profile(&mut self, profile: FilterProfile) {
+        self.filters = vec![FilterChain::new(profile, self.output_frequency); self.channels];
+    }
+
+    /// Starts recording output samples to a WAV file, stopping any previous recording.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be created, an error is returned.
+    pub fn start_recording<P: AsRef<Path>>(&mut self, path: P, format: WavFormat) -> NesResult<()> {
+        self.stop_recording()?;
+        self.recording = Some(WavWriter::create(
+            path,
+            self.output_frequency as u32,
+            self.channels as u16,
+            format,
+        )?);
+        Ok(())
+    }
+
+    /// Stops recording, writing the final WAV header.
+    ///
+    /// # Errors
+    ///
+    /// If the header fails to be written, an error is returned.
+    pub fn stop_recording(&mut self) -> NesResult<()> {
+        match self.recording.take() {
+            Some(mut recording) => recording.finish(),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Moves an in-progress recording over from another mixer, e.g. when replacing it for a new
+    /// region.
+    #[inline]
+    pub fn take_recording(&mut self, other: &mut Self) {
+        self.recording = other.recording.take();
     }
 
     #[inline]
@@ -229,7 +340,16 @@ impl AudioMixer {
         self.pitch_ratio
     }
 
-    /// Outputs audio using multi-rate-control re-sampling.
+    /// Sample rate to request from `ControlDeck::set_sample_rate` for the next frame, adjusted by
+    /// dynamic rate control to keep the buffer half full.
+    #[inline]
+    #[must_use]
+    pub fn sample_rate(&self) -> f32 {
+        self.output_frequency * self.pitch_ratio
+    }
+
+    /// Outputs audio samples from `ControlDeck::audio_samples`, interleaved by channel, updating
+    /// the pitch ratio used by `sample_rate` with dynamic rate control.
     ///
     /// Sources:
     /// - <https://near.sh/articles/audio/dynamic-rate-control>
@@ -240,6 +360,30 @@ impl AudioMixer {
         dynamic_rate_control: bool,
         max_delta: f32,
     ) -> usize {
+        let mut out = [0.0; 2];
+        for frame in samples.chunks_exact(self.channels) {
+            let out = &mut out[..self.channels];
+            for ((out, sample), filter) in out.iter_mut().zip(frame).zip(&mut self.filters) {
+                *out = filter.apply(*sample);
+            }
+            if let Some(ref mut recording) = self.recording {
+                if let Err(err) = recording.write_samples(out) {
+                    log::error!("{:?}", err);
+                    self.recording = None;
+                }
+            }
+            // Only push whole frames so channels stay in order, and only wait for the buffer to
+            // drain when playback or a callback is reading it, which isn't the case when headless
+            // and only recording
+            if self.producer.free_len() >= out.len() {
+                self.producer.push_slice(out);
+            } else if self.consumer.is_none() {
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    std::thread::sleep(Duration::from_micros(10));
+                }
+            }
+        }
         self.pitch_ratio = if dynamic_rate_control {
             let size = self.producer.len() as f32;
             let capacity = self.producer.capacity() as f32;
@@ -247,30 +391,7 @@ impl AudioMixer {
         } else {
             1.0
         };
-        self.decim_ratio = self.input_frequency / (self.output_frequency * self.pitch_ratio);
-        let mut sample_count = 0;
-        for sample in samples {
-            self.avg += *sample;
-            self.count += 1.0;
-            while self.fraction <= 0.0 {
-                let sample = self
-                    .filters
-                    .iter_mut()
-                    .fold(self.avg / self.count, |sample, filter| filter.apply(sample));
-                if self.producer.push(sample).is_err() {
-                    #[cfg(not(target_arch = "wasm32"))]
-                    {
-                        std::thread::sleep(Duration::from_micros(10));
-                    }
-                }
-                self.avg = 0.0;
-                self.count = 0.0;
-                sample_count += 1;
-                self.fraction += self.decim_ratio;
-            }
-            self.fraction -= 1.0;
-        }
-        sample_count
+        samples.len()
     }
 }
 
@@ -279,12 +400,11 @@ impl fmt::Debug for AudioMixer {
         f.debug_struct("AudioMixer")
             .field("producer_len", &self.producer.len())
             .field("producer_capacity", &self.producer.capacity())
-            .field("input_frequency", &self.input_frequency)
             .field("output_frequency", &self.output_frequency)
-            .field("decim_ratio", &self.decim_ratio)
             .field("pitch_ratio", &self.pitch_ratio)
-            .field("fraction", &self.fraction)
+            .field("channels", &self.channels)
             .field("filters", &self.filters)
+            .field("recording", &self.recording)
             .finish()
     }
 }
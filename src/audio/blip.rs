@@ -0,0 +1,266 @@
+//! Band-limited sound synthesis.
+//!
+//! Instead of sampling the mixed output every CPU cycle and averaging it down to the output
+//! sample rate, which aliases high-pitched pulse and noise channels, amplitude changes are added
+//! as band-limited steps at the exact clock they happen and summed into output samples. Based on
+//! the approach of Shay Green's `blip_buf` library.
+
+use crate::{common::NesRegion, cpu::Cpu};
+use once_cell::sync::Lazy;
+
+/// Number of output samples each step is spread over.
+const WIDTH: usize = 16;
+/// Number of sub-sample positions steps are aligned to.
+const PHASES: usize = 32;
+/// Cutoff as a fraction of the output sample rate, just under the Nyquist limit.
+const CUTOFF: f64 = 0.45;
+
+/// Band-limited impulse for each phase, the derivative of a band-limited step, which is restored
+/// by summing deltas when reading samples.
+static KERNEL: Lazy<[[f32; WIDTH]; PHASES + 1]> = Lazy::new(|| {
+    use std::f64::consts::PI;
+    let half = (WIDTH / 2) as f64;
+    let mut kernel = [[0.0; WIDTH]; PHASES + 1];
+    for (phase, taps) in kernel.iter_mut().enumerate() {
+        let offset = phase as f64 / PHASES as f64;
+        let mut impulse = [0.0f64; WIDTH];
+        for (k, tap) in impulse.iter_mut().enumerate() {
+            let x = k as f64 - half - offset;
+            if x.abs() >= half {
+                continue;
+            }
+            let t = 2.0 * CUTOFF * x;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (PI * t).sin() / (PI * t)
+            };
+            // Blackman window
+            let window = 0.08f64.mul_add(
+                (2.0 * PI * x / half).cos(),
+                0.5f64.mul_add((PI * x / half).cos(), 0.42),
+            );
+            *tap = sinc * window;
+        }
+        // Each step sums to exactly the delta added
+        let sum: f64 = impulse.iter().sum();
+        for (tap, value) in taps.iter_mut().zip(impulse) {
+            *tap = (value / sum) as f32;
+        }
+    }
+    kernel
+});
+
+/// Converts amplitude changes at a clock rate into samples at an output sample rate, for one or
+/// more interleaved channels.
+///
+/// Output is delayed by `WIDTH / 2` samples.
+#[derive(Clone)]
+#[must_use]
+pub struct BlipBuf {
+    channels: usize,
+    clock_rate: f32,
+    sample_rate: f32,
+    factor: f64,
+    offset: f64,
+    avail: usize,
+    amplitudes: Vec<f32>,
+    integrators: Vec<f32>,
+    deltas: Vec<f32>,
+}
+
+impl Default for BlipBuf {
+    fn default() -> Self {
+        Self::new(1, Cpu::region_clock_rate(NesRegion::Ntsc), 44_100.0)
+    }
+}
+
+impl BlipBuf {
+    /// Clocks added before `end_frame` needs to be called, to bound the buffer size.
+    pub const MAX_FRAME_CLOCKS: u32 = 8192;
+
+    pub fn new(channels: usize, clock_rate: f32, sample_rate: f32) -> Self {
+        let mut blip = Self {
+            channels,
+            clock_rate,
+            sample_rate,
+            factor: 0.0,
+            offset: 0.0,
+            avail: 0,
+            amplitudes: vec![0.0; channels],
+            integrators: vec![0.0; channels],
+            deltas: vec![],
+        };
+        blip.set_rates(clock_rate, sample_rate);
+        blip
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn channels(&self) -> usize {
+        self.channels
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn clock_rate(&self) -> f32 {
+        self.clock_rate
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Sets the input clock rate and output sample rate. Only call between frames, after
+    /// `end_frame`.
+    pub fn set_rates(&mut self, clock_rate: f32, sample_rate: f32) {
+        self.clock_rate = clock_rate;
+        self.sample_rate = sample_rate.clamp(1.0, clock_rate);
+        self.factor = f64::from(self.sample_rate) / f64::from(clock_rate);
+        self.reserve();
+    }
+
+    /// Adds an amplitude change to a channel at a clock relative to the start of the frame.
+    #[inline]
+    pub fn add_delta(&mut self, channel: usize, clock: u32, delta: f32) {
+        let time = f64::from(clock).mul_add(self.factor, self.offset);
+        let sample = time.floor();
+        let phase = ((time - sample) * PHASES as f64).round() as usize;
+        let start = (self.avail + sample as usize) * self.channels + channel;
+        let deltas = self.deltas[start..].iter_mut().step_by(self.channels);
+        for (out, tap) in deltas.zip(KERNEL[phase]) {
+            *out += delta * tap;
+        }
+    }
+
+    /// Sets the amplitude of a channel at a clock relative to the start of the frame, adding a
+    /// delta if it changed.
+    #[inline]
+    pub fn set_amplitude(&mut self, channel: usize, clock: u32, amplitude: f32) {
+        let delta = amplitude - self.amplitudes[channel];
+        if delta != 0.0 {
+            self.amplitudes[channel] = amplitude;
+            self.add_delta(channel, clock, delta);
+        }
+    }
+
+    /// Ends the current frame after `clocks`, making samples before it available to read. The
+    /// next frame starts at clock 0.
+    pub fn end_frame(&mut self, clocks: u32) {
+        let time = f64::from(clocks).mul_add(self.factor, self.offset);
+        let samples = time.floor();
+        self.avail += samples as usize;
+        self.offset = time - samples;
+        self.reserve();
+    }
+
+    /// Number of samples available to read for each channel.
+    #[inline]
+    #[must_use]
+    pub const fn samples_avail(&self) -> usize {
+        self.avail
+    }
+
+    /// Appends available samples, interleaved by channel, to `out`, returning the number read for
+    /// each channel.
+    pub fn read_samples(&mut self, out: &mut Vec<f32>) -> usize {
+        let len = self.avail * self.channels;
+        out.reserve(len);
+        for frame in self.deltas[..len].chunks_exact(self.channels) {
+            for (integrator, delta) in self.integrators.iter_mut().zip(frame) {
+                *integrator += delta;
+                out.push(*integrator);
+            }
+        }
+        self.deltas.copy_within(len.., 0);
+        let remaining = self.deltas.len() - len;
+        self.deltas[remaining..].fill(0.0);
+        std::mem::replace(&mut self.avail, 0)
+    }
+
+    /// Discards available samples and pending deltas, keeping the current amplitudes.
+    pub fn clear(&mut self) {
+        self.avail = 0;
+        self.offset = 0.0;
+        self.deltas.fill(0.0);
+        self.integrators.copy_from_slice(&self.amplitudes);
+    }
+
+    /// Grows the buffer to fit a full frame after the available samples.
+    fn reserve(&mut self) {
+        let frame_samples = (f64::from(Self::MAX_FRAME_CLOCKS) * self.factor).ceil() as usize;
+        let len = (self.avail + frame_samples + WIDTH + 1) * self.channels;
+        if self.deltas.len() < len {
+            self.deltas.resize(len, 0.0);
+        }
+    }
+}
+
+impl std::fmt::Debug for BlipBuf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlipBuf")
+            .field("channels", &self.channels)
+            .field("clock_rate", &self.clock_rate)
+            .field("sample_rate", &self.sample_rate)
+            .field("offset", &self.offset)
+            .field("avail", &self.avail)
+            .field("amplitudes", &self.amplitudes)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_count() {
+        let mut blip = BlipBuf::new(1, 1_790_000.0, 44_100.0);
+        let mut samples = vec![];
+        for _ in 0..1000 {
+            blip.end_frame(1790);
+            blip.read_samples(&mut samples);
+        }
+        assert!(
+            (samples.len() as i32 - 44_100).abs() <= 1,
+            "{}",
+            samples.len()
+        );
+        assert!(samples.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn band_limited_step() {
+        for clock in 0..64 {
+            let mut blip = BlipBuf::new(2, 1_789_773.0, 48_000.0);
+            blip.set_amplitude(0, clock, 0.5);
+            blip.set_amplitude(1, clock, -1.0);
+            blip.set_amplitude(1, clock, -1.0);
+            blip.end_frame(1024);
+            let mut samples = vec![];
+            let count = blip.read_samples(&mut samples);
+            assert_eq!(samples.len(), 2 * count);
+            assert!(samples[0].abs() < 0.01, "starts silent");
+            let (left, right) = (samples[2 * count - 2], samples[2 * count - 1]);
+            assert!((left - 0.5).abs() < 1e-5, "settles to amplitude: {left}");
+            assert!((right + 1.0).abs() < 1e-5, "settles to amplitude: {right}");
+            // Band-limiting rings around the step instead of jumping
+            assert!(samples.iter().step_by(2).any(|&sample| sample > 0.5));
+        }
+    }
+
+    #[test]
+    fn clear() {
+        let mut blip = BlipBuf::default();
+        blip.set_amplitude(0, 10, 0.25);
+        blip.end_frame(4096);
+        blip.clear();
+        assert_eq!(blip.samples_avail(), 0);
+        blip.end_frame(4096);
+        let mut samples = vec![];
+        blip.read_samples(&mut samples);
+        assert!(samples.iter().all(|&sample| sample == 0.25));
+    }
+}
@@ -1,47 +1,183 @@
-use crate::audio::window_sinc::WindowSinc;
+//! Output filters modelling the analog audio path of the console.
+//!
+//! The NES and Famicom both filter the mixed APU output through first-order RC stages before it
+//! reaches the TV. See <https://www.nesdev.org/wiki/APU_Mixer>.
+
 use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[must_use]
 enum Type {
     LowPass,
     HighPass,
-    BandPass,
-    BandReject,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A first-order IIR filter.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 #[must_use]
 pub struct Filter {
     ty: Type,
-    sinc: WindowSinc,
+    alpha: f32,
+    prev_input: f32,
+    prev_output: f32,
 }
 
 impl Filter {
-    pub fn low_pass(sample_rate: f32, cutoff: f32, bandwidth: f32) -> Self {
-        let sinc = WindowSinc::new(sample_rate, cutoff, bandwidth);
+    pub fn low_pass(sample_rate: f32, cutoff: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Self::new(Type::LowPass, dt / (rc + dt))
+    }
+
+    pub fn high_pass(sample_rate: f32, cutoff: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Self::new(Type::HighPass, rc / (rc + dt))
+    }
+
+    const fn new(ty: Type, alpha: f32) -> Self {
         Self {
-            ty: Type::LowPass,
-            sinc,
+            ty,
+            alpha,
+            prev_input: 0.0,
+            prev_output: 0.0,
         }
     }
 
-    pub fn high_pass(sample_rate: f32, cutoff: f32, bandwidth: f32) -> Self {
-        let mut sinc = WindowSinc::new(sample_rate, cutoff, bandwidth);
-        sinc.spectral_invert();
-        Self {
-            ty: Type::HighPass,
-            sinc,
+    #[inline]
+    #[must_use]
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        let output = match self.ty {
+            Type::LowPass => self
+                .alpha
+                .mul_add(sample - self.prev_output, self.prev_output),
+            Type::HighPass => self.alpha * (self.prev_output + sample - self.prev_input),
+        };
+        self.prev_input = sample;
+        self.prev_output = output;
+        output
+    }
+}
+
+/// Which console's output filtering to model.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum FilterProfile {
+    #[default]
+    NesFrontLoader,
+    Famicom,
+    Unfiltered,
+}
+
+impl FilterProfile {
+    pub const fn as_slice() -> &'static [Self] {
+        &[Self::NesFrontLoader, Self::Famicom, Self::Unfiltered]
+    }
+}
+
+impl AsRef<str> for FilterProfile {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::NesFrontLoader => "NES Front-Loader",
+            Self::Famicom => "Famicom",
+            Self::Unfiltered => "Unfiltered",
         }
     }
+}
+
+impl From<usize> for FilterProfile {
+    fn from(value: usize) -> Self {
+        match value {
+            1 => Self::Famicom,
+            2 => Self::Unfiltered,
+            _ => Self::NesFrontLoader,
+        }
+    }
+}
+
+/// Filters for a `FilterProfile`, applied in series.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[must_use]
+pub struct FilterChain {
+    profile: FilterProfile,
+    filters: Vec<Filter>,
+}
+
+impl Default for FilterChain {
+    fn default() -> Self {
+        Self::new(FilterProfile::default(), 44_100.0)
+    }
+}
+
+impl FilterChain {
+    pub fn new(profile: FilterProfile, sample_rate: f32) -> Self {
+        let filters = match profile {
+            FilterProfile::NesFrontLoader => vec![
+                Filter::high_pass(sample_rate, 90.0),
+                Filter::high_pass(sample_rate, 440.0),
+                Filter::low_pass(sample_rate, 14_000.0),
+            ],
+            FilterProfile::Famicom => vec![
+                Filter::high_pass(sample_rate, 37.0),
+                Filter::low_pass(sample_rate, 14_000.0),
+            ],
+            FilterProfile::Unfiltered => vec![],
+        };
+        Self { profile, filters }
+    }
+
+    #[inline]
+    pub const fn profile(&self) -> FilterProfile {
+        self.profile
+    }
 
     #[inline]
     #[must_use]
-    pub fn apply(&self, sample: f32) -> f32 {
-        let mut out = 0.0;
-        for h in self.sinc.taps() {
-            out += sample * h;
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        self.filters
+            .iter_mut()
+            .fold(sample, |sample, filter| filter.apply(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn high_pass_removes_dc() {
+        let mut filter = Filter::high_pass(44_100.0, 90.0);
+        let first = filter.apply(1.0);
+        assert!(first > 0.9, "passes the step: {first}");
+        let last = (0..44_100).fold(0.0, |_, _| filter.apply(1.0));
+        assert!(last.abs() < 1e-3, "{last}");
+    }
+
+    #[test]
+    fn low_pass_attenuates_high_frequencies() {
+        let mut filter = Filter::low_pass(44_100.0, 14_000.0);
+        let dc = (0..1000).fold(0.0, |_, _| filter.apply(1.0));
+        assert!((dc - 1.0).abs() < 1e-3, "{dc}");
+
+        // Alternating samples at the Nyquist frequency
+        let mut filter = Filter::low_pass(44_100.0, 14_000.0);
+        let peak = (0..1000)
+            .map(|i| filter.apply(if i % 2 == 0 { 1.0 } else { -1.0 }))
+            .skip(100)
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak < 0.6, "{peak}");
+    }
+
+    #[test]
+    fn profiles() {
+        let mut unfiltered = FilterChain::new(FilterProfile::Unfiltered, 44_100.0);
+        assert!((unfiltered.apply(0.25) - 0.25).abs() < f32::EPSILON);
+
+        for profile in [FilterProfile::NesFrontLoader, FilterProfile::Famicom] {
+            let mut chain = FilterChain::new(profile, 44_100.0);
+            let settled = (0..44_100).fold(0.0, |_, _| chain.apply(0.5));
+            assert!(settled.abs() < 1e-3, "{profile:?} removes DC: {settled}");
         }
-        out
     }
 }
@@ -0,0 +1,345 @@
+//! Per-channel audio stems.
+//!
+//! While stems are enabled with `ControlDeck::set_stems_enabled`, every audio sample also records
+//! the output of each channel on its own, interleaved `Stem::COUNT` values at a time in `Stem`
+//! order and at the same sample rate. Each is scaled as if the other channels were silent, so
+//! stems sum to roughly the mixed output, differing only by the non-linear mixing of the APU
+//! channels.
+//!
+//! `StemRecorder` writes stems to a WAV file per stem, or to a single WAV file with a channel per
+//! stem.
+
+use crate::{
+    audio::{
+        filter::{FilterChain, FilterProfile},
+        wav::{WavFormat, WavWriter},
+    },
+    NesResult,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+/// An audio channel, including expansion audio channels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum Stem {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    Mmc5Pulse1,
+    Mmc5Pulse2,
+    Mmc5Pcm,
+    Vrc6Pulse1,
+    Vrc6Pulse2,
+    Vrc6Saw,
+}
+
+impl Stem {
+    /// Number of stems recorded for each audio sample.
+    pub const COUNT: usize = 11;
+
+    pub const fn as_slice() -> &'static [Self] {
+        &[
+            Self::Pulse1,
+            Self::Pulse2,
+            Self::Triangle,
+            Self::Noise,
+            Self::Dmc,
+            Self::Mmc5Pulse1,
+            Self::Mmc5Pulse2,
+            Self::Mmc5Pcm,
+            Self::Vrc6Pulse1,
+            Self::Vrc6Pulse2,
+            Self::Vrc6Saw,
+        ]
+    }
+
+    /// APU channels, present in every game.
+    pub const fn apu() -> &'static [Self] {
+        &[
+            Self::Pulse1,
+            Self::Pulse2,
+            Self::Triangle,
+            Self::Noise,
+            Self::Dmc,
+        ]
+    }
+
+    /// MMC5 expansion audio channels.
+    pub const fn mmc5() -> &'static [Self] {
+        &[Self::Mmc5Pulse1, Self::Mmc5Pulse2, Self::Mmc5Pcm]
+    }
+
+    /// VRC6 expansion audio channels.
+    pub const fn vrc6() -> &'static [Self] {
+        &[Self::Vrc6Pulse1, Self::Vrc6Pulse2, Self::Vrc6Saw]
+    }
+
+    /// Suffix appended to file names when writing a file per stem.
+    #[must_use]
+    pub const fn file_suffix(self) -> &'static str {
+        match self {
+            Self::Pulse1 => "pulse1",
+            Self::Pulse2 => "pulse2",
+            Self::Triangle => "triangle",
+            Self::Noise => "noise",
+            Self::Dmc => "dmc",
+            Self::Mmc5Pulse1 => "mmc5_pulse1",
+            Self::Mmc5Pulse2 => "mmc5_pulse2",
+            Self::Mmc5Pcm => "mmc5_pcm",
+            Self::Vrc6Pulse1 => "vrc6_pulse1",
+            Self::Vrc6Pulse2 => "vrc6_pulse2",
+            Self::Vrc6Saw => "vrc6_saw",
+        }
+    }
+}
+
+impl AsRef<str> for Stem {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Pulse1 => "Pulse 1",
+            Self::Pulse2 => "Pulse 2",
+            Self::Triangle => "Triangle",
+            Self::Noise => "Noise",
+            Self::Dmc => "DMC",
+            Self::Mmc5Pulse1 => "MMC5 Pulse 1",
+            Self::Mmc5Pulse2 => "MMC5 Pulse 2",
+            Self::Mmc5Pcm => "MMC5 PCM",
+            Self::Vrc6Pulse1 => "VRC6 Pulse 1",
+            Self::Vrc6Pulse2 => "VRC6 Pulse 2",
+            Self::Vrc6Saw => "VRC6 Sawtooth",
+        }
+    }
+}
+
+/// Whether stems are written to a file each, or to one file with a channel each.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum StemLayout {
+    #[default]
+    Separate,
+    Multichannel,
+}
+
+impl StemLayout {
+    pub const fn as_slice() -> &'static [Self] {
+        &[Self::Separate, Self::Multichannel]
+    }
+}
+
+impl AsRef<str> for StemLayout {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Separate => "File per Channel",
+            Self::Multichannel => "Multichannel File",
+        }
+    }
+}
+
+impl From<usize> for StemLayout {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::Multichannel
+        } else {
+            Self::Separate
+        }
+    }
+}
+
+/// Filters stems and writes them to WAV files.
+#[must_use]
+pub struct StemRecorder<W: Write + Seek> {
+    stems: Vec<Stem>,
+    writers: Vec<WavWriter<W>>,
+    filters: Vec<FilterChain>,
+    out: Vec<f32>,
+}
+
+impl StemRecorder<BufWriter<File>> {
+    /// Creates WAV files for `stems` based on `path`. With `StemLayout::Separate`, each file is
+    /// named after `path` with the stem's suffix appended, e.g. `recording_pulse1.wav`.
+    ///
+    /// # Errors
+    ///
+    /// If any file fails to be created, an error is returned.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        stems: &[Stem],
+        layout: StemLayout,
+        sample_rate: f32,
+        format: WavFormat,
+        profile: FilterProfile,
+    ) -> NesResult<Self> {
+        let path = path.as_ref();
+        let writers = match layout {
+            StemLayout::Separate => stems
+                .iter()
+                .map(|&stem| {
+                    WavWriter::create(Self::stem_path(path, stem), sample_rate as u32, 1, format)
+                })
+                .collect::<NesResult<Vec<_>>>()?,
+            StemLayout::Multichannel => vec![WavWriter::create(
+                path,
+                sample_rate as u32,
+                stems.len() as u16,
+                format,
+            )?],
+        };
+        Ok(Self::new(writers, stems, sample_rate, profile))
+    }
+
+    /// Path of the file for a stem when writing a file per stem.
+    #[must_use]
+    pub fn stem_path(path: &Path, stem: Stem) -> PathBuf {
+        let name = path
+            .file_stem()
+            .map_or_else(|| "stems".into(), |name| name.to_string_lossy());
+        path.with_file_name(format!("{name}_{}.wav", stem.file_suffix()))
+    }
+}
+
+impl<W: Write + Seek> StemRecorder<W> {
+    /// Records `stems` to either one mono writer per stem, or a single writer with a channel per
+    /// stem.
+    pub fn new(
+        writers: Vec<WavWriter<W>>,
+        stems: &[Stem],
+        sample_rate: f32,
+        profile: FilterProfile,
+    ) -> Self {
+        Self {
+            stems: stems.to_vec(),
+            writers,
+            filters: stems
+                .iter()
+                .map(|_| FilterChain::new(profile, sample_rate))
+                .collect(),
+            out: Vec::with_capacity(stems.len()),
+        }
+    }
+
+    #[inline]
+    pub fn stems(&self) -> &[Stem] {
+        &self.stems
+    }
+
+    #[inline]
+    pub fn writers(&self) -> &[WavWriter<W>] {
+        &self.writers
+    }
+
+    /// Writes interleaved stem samples from `ControlDeck::stem_samples`.
+    ///
+    /// # Errors
+    ///
+    /// If the samples fail to be written, an error is returned.
+    pub fn consume(&mut self, samples: &[f32]) -> NesResult<()> {
+        for frame in samples.chunks_exact(Stem::COUNT) {
+            self.out.clear();
+            for (&stem, filter) in self.stems.iter().zip(&mut self.filters) {
+                self.out.push(filter.apply(frame[stem as usize]));
+            }
+            if let [ref mut writer] = self.writers[..] {
+                writer.write_samples(&self.out)?;
+            } else {
+                for (writer, sample) in self.writers.iter_mut().zip(&self.out) {
+                    writer.write_samples(&[*sample])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the final WAV headers. Called when dropped if not called before.
+    ///
+    /// # Errors
+    ///
+    /// If any header fails to be written, an error is returned.
+    pub fn finish(&mut self) -> NesResult<()> {
+        for writer in &mut self.writers {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> std::fmt::Debug for StemRecorder<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StemRecorder")
+            .field("stems", &self.stems)
+            .field("writers", &self.writers)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn stem_frames(count: usize) -> Vec<f32> {
+        (0..count)
+            .flat_map(|_| (0..Stem::COUNT).map(|stem| stem as f32 / 100.0))
+            .collect()
+    }
+
+    #[test]
+    fn separate_stems() {
+        let stems = Stem::apu();
+        let writers = stems
+            .iter()
+            .map(|_| WavWriter::new(Cursor::new(vec![]), 44_100, 1, WavFormat::Float32))
+            .collect::<NesResult<Vec<_>>>()
+            .expect("headers");
+        let mut recorder = StemRecorder::new(writers, stems, 44_100.0, FilterProfile::default());
+        recorder.consume(&stem_frames(10)).expect("samples");
+        assert_eq!(recorder.writers().len(), 5);
+        for writer in recorder.writers() {
+            assert_eq!(writer.channels(), 1);
+            assert_eq!(writer.frames(), 10);
+        }
+    }
+
+    #[test]
+    fn multichannel_stems() {
+        let stems = Stem::vrc6();
+        let writer =
+            WavWriter::new(Cursor::new(vec![]), 44_100, 3, WavFormat::Float32).expect("header");
+        let mut recorder =
+            StemRecorder::new(vec![writer], stems, 44_100.0, FilterProfile::default());
+        recorder.consume(&stem_frames(10)).expect("samples");
+        recorder.finish().expect("finished");
+
+        let writer = &recorder.writers()[0];
+        assert_eq!(writer.frames(), 10);
+        let bytes = writer.get_ref().get_ref();
+        let sample = |offset: usize| {
+            f32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ])
+        };
+        // Channels are interleaved in stem order with the output filters applied
+        let data = bytes.len() - 10 * 3 * 4;
+        let first = sample(data);
+        let saw = sample(data + 8);
+        assert!(first != 0.0);
+        assert!((saw / first - 10.0 / 8.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn stem_path() {
+        assert_eq!(
+            StemRecorder::stem_path(Path::new("dir/Sound_Recording.wav"), Stem::Vrc6Saw),
+            Path::new("dir/Sound_Recording_vrc6_saw.wav")
+        );
+    }
+}
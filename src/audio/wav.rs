@@ -0,0 +1,325 @@
+//! Streaming WAV file writer for sound recordings.
+//!
+//! Sizes in the header are updated every second of audio, so a recording cut short by a crash is
+//! still a playable file up to the last update.
+//!
+//! `AudioMixer::start_recording` records the mixed and resampled output during playback. Headless
+//! `ControlDeck` runs can do the same by passing `ControlDeck::audio_samples` to
+//! `AudioMixer::consume` each frame without opening playback, or write the raw samples at
+//! `ControlDeck::sample_rate` with a `WavWriter` directly.
+
+use crate::NesResult;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Sample format of a WAV file.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum WavFormat {
+    #[default]
+    Pcm16,
+    Float32,
+}
+
+impl WavFormat {
+    pub const fn as_slice() -> &'static [Self] {
+        &[Self::Pcm16, Self::Float32]
+    }
+
+    const fn format_tag(self) -> u16 {
+        match self {
+            Self::Pcm16 => 0x0001,
+            Self::Float32 => 0x0003,
+        }
+    }
+
+    const fn bytes_per_sample(self) -> u16 {
+        match self {
+            Self::Pcm16 => 2,
+            Self::Float32 => 4,
+        }
+    }
+}
+
+impl AsRef<str> for WavFormat {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Pcm16 => "16-bit PCM",
+            Self::Float32 => "32-bit Float",
+        }
+    }
+}
+
+impl From<usize> for WavFormat {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::Float32
+        } else {
+            Self::Pcm16
+        }
+    }
+}
+
+/// Writes samples to a WAV file as they're produced.
+#[must_use]
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    format: WavFormat,
+    channels: u16,
+    sample_rate: u32,
+    frames: u32,
+    unflushed_frames: u32,
+    fact_offset: Option<u64>,
+    data_offset: u64,
+    finished: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Creates a WAV file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be created or the header fails to be written, an error is returned.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        sample_rate: u32,
+        channels: u16,
+        format: WavFormat,
+    ) -> NesResult<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("failed to create {path:?}"))?;
+        Self::new(BufWriter::new(file), sample_rate, channels, format)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    const RIFF_SIZE_OFFSET: u64 = 4;
+
+    /// Writes a WAV header to `writer`, with sizes filled in as samples are written.
+    ///
+    /// # Errors
+    ///
+    /// If the header fails to be written, an error is returned.
+    pub fn new(
+        mut writer: W,
+        sample_rate: u32,
+        channels: u16,
+        format: WavFormat,
+    ) -> NesResult<Self> {
+        let block_align = channels * format.bytes_per_sample();
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        // Non-PCM formats have an extension size and a fact chunk
+        let pcm = format == WavFormat::Pcm16;
+        writer.write_all(&(if pcm { 16u32 } else { 18 }).to_le_bytes())?;
+        writer.write_all(&format.format_tag().to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * u32::from(block_align)).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&(8 * format.bytes_per_sample()).to_le_bytes())?;
+        let fact_offset = if pcm {
+            None
+        } else {
+            writer.write_all(&0u16.to_le_bytes())?;
+            writer.write_all(b"fact")?;
+            writer.write_all(&4u32.to_le_bytes())?;
+            let offset = writer.stream_position()?;
+            writer.write_all(&0u32.to_le_bytes())?;
+            Some(offset)
+        };
+
+        writer.write_all(b"data")?;
+        let data_offset = writer.stream_position()?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            format,
+            channels,
+            sample_rate,
+            frames: 0,
+            unflushed_frames: 0,
+            fact_offset,
+            data_offset,
+            finished: false,
+        })
+    }
+
+    #[inline]
+    pub const fn format(&self) -> WavFormat {
+        self.format
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Number of sample frames written, one sample for each channel.
+    #[inline]
+    #[must_use]
+    pub const fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Writes interleaved samples in the range -1.0..=1.0.
+    ///
+    /// # Errors
+    ///
+    /// If the samples fail to be written, an error is returned.
+    pub fn write_samples(&mut self, samples: &[f32]) -> NesResult<()> {
+        for &sample in samples {
+            match self.format {
+                WavFormat::Pcm16 => {
+                    let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+                    self.writer.write_all(&sample.to_le_bytes())?;
+                }
+                WavFormat::Float32 => self.writer.write_all(&sample.to_le_bytes())?,
+            }
+        }
+        let frames = (samples.len() / usize::from(self.channels)) as u32;
+        self.frames = self.frames.saturating_add(frames);
+        self.unflushed_frames += frames;
+        if self.unflushed_frames >= self.sample_rate {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Updates the header with the samples written so far and flushes them to the writer.
+    ///
+    /// # Errors
+    ///
+    /// If the header fails to be written, an error is returned.
+    pub fn flush(&mut self) -> NesResult<()> {
+        let data_size = self
+            .frames
+            .saturating_mul(u32::from(self.channels * self.format.bytes_per_sample()));
+        let riff_size = (self.data_offset as u32 + 4 - 8).saturating_add(data_size);
+
+        self.writer.seek(SeekFrom::Start(Self::RIFF_SIZE_OFFSET))?;
+        self.writer.write_all(&riff_size.to_le_bytes())?;
+        if let Some(offset) = self.fact_offset {
+            self.writer.seek(SeekFrom::Start(offset))?;
+            self.writer.write_all(&self.frames.to_le_bytes())?;
+        }
+        self.writer.seek(SeekFrom::Start(self.data_offset))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        self.unflushed_frames = 0;
+        Ok(())
+    }
+
+    /// Writes the final header. Called when dropped if not called before.
+    ///
+    /// # Errors
+    ///
+    /// If the header fails to be written, an error is returned.
+    pub fn finish(&mut self) -> NesResult<()> {
+        self.finished = true;
+        self.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(err) = self.finish() {
+                log::error!("failed to finish wav file: {:?}", err);
+            }
+        }
+    }
+}
+
+impl<W: Write + Seek> std::fmt::Debug for WavWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WavWriter")
+            .field("format", &self.format)
+            .field("channels", &self.channels)
+            .field("sample_rate", &self.sample_rate)
+            .field("frames", &self.frames)
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ])
+    }
+
+    #[test]
+    fn pcm16() {
+        let mut wav =
+            WavWriter::new(Cursor::new(vec![]), 44_100, 1, WavFormat::Pcm16).expect("header");
+        wav.write_samples(&[0.0, 1.0, -1.0, 2.0]).expect("samples");
+        wav.finish().expect("finished");
+
+        let bytes = wav.get_ref().get_ref();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(bytes, 4), 36 + 8, "riff size");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(bytes, 24), 44_100, "sample rate");
+        assert_eq!(u32_at(bytes, 28), 2 * 44_100, "byte rate");
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(bytes, 40), 8, "data size");
+        assert_eq!(
+            &bytes[44..],
+            [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F],
+            "clamped samples"
+        );
+    }
+
+    #[test]
+    fn float32_flushes() {
+        let mut wav =
+            WavWriter::new(Cursor::new(vec![]), 4, 2, WavFormat::Float32).expect("header");
+        // Two seconds of stereo
+        wav.write_samples(&[0.5; 16]).expect("samples");
+
+        // Header is current without calling finish
+        let bytes = wav.get_ref().get_ref();
+        assert_eq!(bytes.len(), 58 + 16 * 4);
+        assert_eq!(u32_at(bytes, 16), 18, "fmt size");
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(u32_at(bytes, 46), 8, "sample frames");
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(u32_at(bytes, 54), 16 * 4, "data size");
+        assert_eq!(u32_at(bytes, 4), bytes.len() as u32 - 8, "riff size");
+        assert_eq!(&bytes[58..62], 0.5f32.to_le_bytes());
+        assert_eq!(wav.frames(), 8);
+    }
+}
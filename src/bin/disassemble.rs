@@ -0,0 +1,105 @@
+use anyhow::Context;
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+use tetanes::{
+    cart::Cart,
+    debugger::{cdl::CodeDataLog, disasm::Disassembly},
+    mem::RamState,
+    NesResult,
+};
+
+const HEADER_LEN: usize = 16;
+const TRAINER_LEN: usize = 512;
+
+fn main() -> NesResult<()> {
+    let opt = Opt::from_args();
+    let rom = fs::read(&opt.path).with_context(|| format!("failed to read rom {:?}", opt.path))?;
+    let cart = Cart::from_path(&opt.path, RamState::default())?;
+
+    // Keep the original header bytes, and any trainer, so the ROM reassembles identically
+    let trainer = rom.get(6).map_or(false, |flags| flags & 0x04 == 0x04);
+    let header_len = HEADER_LEN + if trainer { TRAINER_LEN } else { 0 };
+    let header = rom.get(..header_len).context("truncated rom header")?;
+
+    let cdl = match opt.cdl {
+        Some(ref path) => {
+            let mut reader = BufReader::new(
+                File::open(path).with_context(|| format!("failed to open cdl {path:?}"))?,
+            );
+            Some(CodeDataLog::load(
+                &mut reader,
+                cart.prg_rom().len(),
+                cart.chr_rom().len(),
+            )?)
+        }
+        None => None,
+    };
+    let disasm = Disassembly::new(cart.prg_rom(), opt.bank_size, cdl.as_ref())?;
+
+    let output = opt.output.unwrap_or_else(|| opt.path.with_extension("s"));
+    let chr_file = if cart.chr_rom().is_empty() {
+        None
+    } else {
+        let path = output.with_extension("chr");
+        fs::write(&path, cart.chr_rom()).with_context(|| format!("failed to write {path:?}"))?;
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    };
+    let mut writer = BufWriter::new(
+        File::create(&output).with_context(|| format!("failed to create {output:?}"))?,
+    );
+    disasm.write_source(&mut writer, header, chr_file.as_deref())?;
+    writer.flush()?;
+
+    let config = output.with_extension("cfg");
+    let mut writer = BufWriter::new(
+        File::create(&config).with_context(|| format!("failed to create {config:?}"))?,
+    );
+    disasm.write_linker_config(&mut writer, header_len, cart.chr_rom().len())?;
+    writer.flush()?;
+
+    println!(
+        "disassembled {} banks of ${:04X} bytes, {} of {} bytes as code",
+        disasm.bank_count(),
+        disasm.bank_size(),
+        disasm.code_len(),
+        cart.prg_rom().len()
+    );
+    println!(
+        "reassemble with: ca65 {0:?} && ld65 -C {1:?} -o rebuilt.nes {2:?}",
+        output,
+        config,
+        output.with_extension("o")
+    );
+    Ok(())
+}
+
+#[derive(StructOpt, Debug)]
+#[must_use]
+struct Opt {
+    #[structopt(help = "The NES ROM to disassemble.")]
+    path: PathBuf,
+    #[structopt(
+        short = "c",
+        long = "cdl",
+        help = "FCEUX `.cdl` code/data log to tell code from data. Without one, code is traced from the interrupt vectors."
+    )]
+    cdl: Option<PathBuf>,
+    #[structopt(
+        short = "b",
+        long = "bank_size",
+        default_value = "0",
+        help = "PRG-ROM bank size in bytes: 8192, 16384 or 32768. Defaults to a single bank for 32K or less, 16K banks otherwise."
+    )]
+    bank_size: usize,
+    #[structopt(
+        short = "o",
+        long = "output",
+        help = "Source file to write. Defaults to the ROM path with a `.s` extension. The linker config and CHR-ROM are written next to it."
+    )]
+    output: Option<PathBuf>,
+}
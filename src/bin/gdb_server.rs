@@ -0,0 +1,48 @@
+use anyhow::Context;
+use std::{env, fs::File, io::BufReader, path::PathBuf};
+use structopt::StructOpt;
+use tetanes::{
+    control_deck::ControlDeck,
+    debugger::{gdb::GdbServer, symbols::Symbols},
+    mem::RamState,
+    NesResult,
+};
+
+fn main() -> NesResult<()> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let mut deck = ControlDeck::new(opt.ram_state.unwrap_or_default());
+    let name = opt.path.to_string_lossy();
+    let mut rom = BufReader::new(
+        File::open(&opt.path).with_context(|| format!("failed to open rom {:?}", opt.path))?,
+    );
+    deck.load_rom(&name, &mut rom)?;
+    deck.load_symbols(Symbols::from_rom_path(&opt.path)?);
+
+    let server = GdbServer::bind(&opt.listen)?;
+    log::info!("listening for gdb connections on {}", server.local_addr()?);
+    server.serve(&mut deck)
+}
+
+#[derive(StructOpt, Debug)]
+#[must_use]
+struct Opt {
+    #[structopt(help = "The NES ROM to debug.")]
+    path: PathBuf,
+    #[structopt(
+        short = "l",
+        long = "listen",
+        default_value = "127.0.0.1:9001",
+        help = "Address to listen for GDB connections on."
+    )]
+    listen: String,
+    #[structopt(
+        long = "ram_state",
+        help = "Choose power-up RAM state: 'all_zeros', `all_ones`, `random` (default)."
+    )]
+    ram_state: Option<RamState>,
+}
@@ -0,0 +1,84 @@
+use anyhow::Context;
+use std::{
+    env,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+use tetanes::{control_deck::ControlDeck, debugger::symbols::Symbols, mem::RamState, NesResult};
+
+fn main() -> NesResult<()> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let mut deck = ControlDeck::new(opt.ram_state.unwrap_or_default());
+    let name = opt.path.to_string_lossy();
+    let mut rom = BufReader::new(
+        File::open(&opt.path).with_context(|| format!("failed to open rom {:?}", opt.path))?,
+    );
+    deck.load_rom(&name, &mut rom)?;
+    deck.load_symbols(Symbols::from_rom_path(&opt.path)?);
+
+    deck.start_profiler();
+    for _ in 0..opt.frames {
+        let _ = deck.clock_frame()?;
+    }
+    let profiler = deck.stop_profiler().context("profiler not running")?;
+    log::info!(
+        "profiled {} cycles over {} frames",
+        profiler.total_cycles(),
+        opt.frames
+    );
+
+    match opt.table {
+        Some(ref path) => {
+            let mut writer = BufWriter::new(
+                File::create(path).with_context(|| format!("failed to create {path:?}"))?,
+            );
+            profiler.write_table(&mut writer, deck.symbols())?;
+        }
+        None => profiler.write_table(&mut io::stdout().lock(), deck.symbols())?,
+    }
+    if let Some(ref path) = opt.folded {
+        let mut writer = BufWriter::new(
+            File::create(path).with_context(|| format!("failed to create {path:?}"))?,
+        );
+        profiler.write_folded(&mut writer, deck.symbols())?;
+    }
+    Ok(())
+}
+
+#[derive(StructOpt, Debug)]
+#[must_use]
+struct Opt {
+    #[structopt(help = "The NES ROM to profile.")]
+    path: PathBuf,
+    #[structopt(
+        short = "n",
+        long = "frames",
+        default_value = "600",
+        help = "Number of frames to profile."
+    )]
+    frames: u32,
+    #[structopt(
+        short = "t",
+        long = "table",
+        help = "Write the routine table to a file instead of stdout."
+    )]
+    table: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "folded",
+        help = "Write folded call stacks for flamegraph.pl or inferno to a file."
+    )]
+    folded: Option<PathBuf>,
+    #[structopt(
+        long = "ram_state",
+        help = "Choose power-up RAM state: 'all_zeros', `all_ones`, `random` (default)."
+    )]
+    ram_state: Option<RamState>,
+}
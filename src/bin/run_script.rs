@@ -0,0 +1,59 @@
+use anyhow::Context;
+use std::{env, fs::File, io::BufReader, ops::ControlFlow, path::PathBuf};
+use structopt::StructOpt;
+use tetanes::{control_deck::ControlDeck, mem::RamState, script::Script, NesResult};
+
+fn main() -> NesResult<()> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let mut deck = ControlDeck::new(opt.ram_state.unwrap_or_default());
+    let name = opt.path.to_string_lossy();
+    let mut rom = BufReader::new(
+        File::open(&opt.path).with_context(|| format!("failed to open rom {:?}", opt.path))?,
+    );
+    deck.load_rom(&name, &mut rom)?;
+
+    let mut script = Script::from_path(&opt.script)?;
+    script.start(deck.cpu_mut())?;
+    let mut frames = 0;
+    while !script.quit_requested() && opt.frames.map_or(true, |max| frames < max) {
+        let mut result = Ok(());
+        let flow = deck.clock_frame_inspect(|cpu| {
+            if result.is_ok() {
+                result = script.inspect(cpu);
+            }
+        })?;
+        result?;
+        if let ControlFlow::Break(_) = flow {
+            log::info!("breakpoint hit at ${:04X}", deck.cpu().pc());
+            break;
+        }
+        frames += 1;
+    }
+    log::info!("ran {} frames", frames);
+    Ok(())
+}
+
+#[derive(StructOpt, Debug)]
+#[must_use]
+struct Opt {
+    #[structopt(help = "The NES ROM to run.")]
+    path: PathBuf,
+    #[structopt(help = "The Rhai script to run.")]
+    script: PathBuf,
+    #[structopt(
+        short = "n",
+        long = "frames",
+        help = "Maximum number of frames to run. [default: until the script quits]"
+    )]
+    frames: Option<u32>,
+    #[structopt(
+        long = "ram_state",
+        help = "Choose power-up RAM state: 'all_zeros', `all_ones`, `random` (default)."
+    )]
+    ram_state: Option<RamState>,
+}
@@ -1,16 +1,24 @@
 use crate::{
     apu::{Apu, ApuRegisters, Channel},
-    audio::Audio,
+    audio::{blip::BlipBuf, stems::Stem, Audio, ChannelMix},
     cart::Cart,
+    cheat::{self, Cheat},
     common::{Clock, Kind, NesRegion, Regional, Reset},
     cpu::{Cpu, Irq},
+    debugger::{
+        cdl::{CodeDataLog, PrgFlags},
+        events::PpuEventKind,
+        memory::{AccessLog, MemAccess, MemRegion},
+        AddrSpace, Watchpoint, WatchpointHit,
+    },
     genie::GenieCode,
-    input::{FourPlayer, Input, InputRegisters, Joypad, Slot, Zapper},
+    input::{FourPlayer, Input, InputRegisters, InputState, Joypad, Slot, Zapper},
     mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::{Access, Mem, RamState},
     ppu::{Ppu, PpuRegisters},
     NesResult,
 };
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -59,9 +67,40 @@ pub struct CpuBus {
     oam_dma: bool,
     oam_dma_addr: u16,
     audio_samples: Vec<f32>,
-    genie_codes: HashMap<u16, GenieCode>,
+    #[serde(
+        rename = "genie_codes",
+        serialize_with = "cheat::serialize_genie_codes",
+        deserialize_with = "cheat::deserialize_genie_codes"
+    )]
+    cheats: HashMap<u16, Cheat>,
+    #[serde(skip)]
+    blip: BlipBuf,
+    #[serde(skip)]
+    blip_clock: u32,
     cycle: usize, // Total number of CPU cycles ran
     open_bus: u8,
+    #[serde(skip)]
+    watchpoints: Vec<Watchpoint>,
+    #[serde(skip)]
+    watchpoint_hit: Option<WatchpointHit>,
+    #[serde(skip)]
+    prg_cdl: Option<Vec<u8>>,
+    #[serde(skip)]
+    mem_hooks: Vec<Watchpoint>,
+    #[serde(skip)]
+    mem_hook_hits: Vec<WatchpointHit>,
+    #[serde(skip)]
+    cheat_frame: u32,
+    #[serde(skip)]
+    access_log: Option<AccessLog>,
+    #[serde(skip)]
+    stem_blip: Option<BlipBuf>,
+    #[serde(skip)]
+    stem_samples: Vec<f32>,
+    #[serde(skip)]
+    channel_mix: [ChannelMix; Stem::COUNT],
+    #[serde(skip)]
+    channel_gains: Vec<[f32; Stem::COUNT]>, // Gains of each stem for each output channel
 }
 
 impl Default for CpuBus {
@@ -72,6 +111,7 @@ impl Default for CpuBus {
 
 impl CpuBus {
     const WRAM_SIZE: usize = 0x0800; // 2K NES Work Ram available to the CPU
+    const AUDIO_FRAME_CLOCKS: u32 = 1024; // Clocks between converting audio deltas to samples
 
     pub fn new(ram_state: RamState) -> Self {
         let mut wram = vec![0x00; Self::WRAM_SIZE];
@@ -90,9 +130,22 @@ impl CpuBus {
             oam_dma: false,
             oam_dma_addr: 0x0000,
             audio_samples: vec![],
-            genie_codes: HashMap::new(),
+            cheats: HashMap::new(),
+            blip: BlipBuf::default(),
+            blip_clock: 0,
             cycle: 0,
             open_bus: 0x00,
+            watchpoints: vec![],
+            watchpoint_hit: None,
+            prg_cdl: None,
+            mem_hooks: vec![],
+            mem_hook_hits: vec![],
+            cheat_frame: 0,
+            access_log: None,
+            stem_blip: None,
+            stem_samples: vec![],
+            channel_mix: [ChannelMix::default(); Stem::COUNT],
+            channel_gains: vec![],
         }
     }
 
@@ -151,11 +204,23 @@ impl CpuBus {
         self.input.zapper_mut()
     }
 
+    #[inline]
+    pub fn input_state(&self) -> InputState {
+        self.input.state()
+    }
+
+    #[inline]
+    pub fn set_input_state(&mut self, state: InputState) {
+        self.input.set_state(state);
+    }
+
     #[inline]
     pub fn load_cart(&mut self, cart: Cart) {
         // Start with ~20ms of audio capacity
-        self.audio_samples
-            .resize((Cpu::region_clock_rate(cart.region()) * 0.02) as usize, 0.0);
+        self.audio_samples.resize(
+            (self.sample_rate() * 0.02) as usize * self.audio_channels(),
+            0.0,
+        );
         self.battery_backed = cart.battery_backed();
         self.set_region(cart.region());
         self.load_prg_rom(cart.prg_rom);
@@ -164,6 +229,231 @@ impl CpuBus {
         self.ppu.load_chr_ram(cart.chr_ram);
         self.ppu.load_ex_ram(cart.ex_ram);
         self.ppu.load_mapper(cart.mapper);
+        self.stop_cdl();
+    }
+
+    #[inline]
+    pub fn watchpoints(&self, space: AddrSpace) -> &[Watchpoint] {
+        match space {
+            AddrSpace::Cpu => &self.watchpoints,
+            AddrSpace::Ppu => self.ppu.watchpoints(),
+        }
+    }
+
+    #[inline]
+    pub fn watchpoints_mut(&mut self, space: AddrSpace) -> &mut Vec<Watchpoint> {
+        match space {
+            AddrSpace::Cpu => &mut self.watchpoints,
+            AddrSpace::Ppu => self.ppu.watchpoints_mut(),
+        }
+    }
+
+    /// Returns the first watchpoint hit since the last call, if any.
+    #[inline]
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.watchpoint_hit
+            .take()
+            .or_else(|| self.ppu.take_watchpoint_hit())
+    }
+
+    /// Starts logging code and data accesses, continuing from an existing log if provided.
+    ///
+    /// # Errors
+    ///
+    /// If the provided log doesn't match the loaded ROM sizes, an error is returned.
+    pub fn start_cdl(&mut self, cdl: Option<CodeDataLog>) -> NesResult<()> {
+        let prg_len = self.prg_rom.len();
+        let chr_len = self.ppu.chr_rom_len();
+        let cdl = cdl.unwrap_or_else(|| CodeDataLog::new(prg_len, chr_len));
+        if cdl.prg.len() != prg_len || cdl.chr.len() != chr_len {
+            return Err(anyhow!(
+                "cdl size mismatch: prg {}/{}, chr {}/{}",
+                cdl.prg.len(),
+                prg_len,
+                cdl.chr.len(),
+                chr_len
+            ));
+        }
+        self.prg_cdl = Some(cdl.prg);
+        *self.ppu.chr_cdl_mut() = Some(cdl.chr);
+        Ok(())
+    }
+
+    /// Returns a copy of the current code/data log, if logging.
+    #[must_use]
+    pub fn code_data_log(&self) -> Option<CodeDataLog> {
+        self.prg_cdl.as_ref().map(|prg| CodeDataLog {
+            prg: prg.clone(),
+            chr: self.ppu.chr_cdl().map(<[u8]>::to_vec).unwrap_or_default(),
+        })
+    }
+
+    /// Stops logging and returns the code/data log, if logging.
+    pub fn stop_cdl(&mut self) -> Option<CodeDataLog> {
+        let chr = self.ppu.chr_cdl_mut().take().unwrap_or_default();
+        self.prg_cdl.take().map(|prg| CodeDataLog { prg, chr })
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn cdl_enabled(&self) -> bool {
+        self.prg_cdl.is_some()
+    }
+
+    /// Moves the code/data log over from another bus, e.g. when loading a save state.
+    #[inline]
+    pub(crate) fn take_cdl(&mut self, bus: &mut Self) {
+        self.prg_cdl = bus.prg_cdl.take();
+        *self.ppu.chr_cdl_mut() = bus.ppu.chr_cdl_mut().take();
+    }
+
+    /// Logs a PRG-ROM access for the Code/Data Logger, if enabled.
+    #[inline]
+    pub fn log_prg(&mut self, addr: u16, flags: PrgFlags) {
+        if addr < 0x4020 {
+            return;
+        }
+        if let Some(prg_cdl) = &mut self.prg_cdl {
+            if let MappedRead::PrgRom(offset) = self.ppu.mapper().map_peek(addr) {
+                if let Some(logged) = prg_cdl.get_mut(offset) {
+                    let bank = PrgFlags::bank(addr).bits();
+                    *logged = (*logged & !PrgFlags::BANK.bits()) | flags.bits() | bank;
+                }
+            }
+        }
+    }
+
+    /// Resolves WRAM and PPU register mirrors to the address they mirror, so watchpoints, memory
+    /// hooks and the access log see each access once on a single address.
+    #[inline]
+    const fn canonical_addr(addr: u16) -> u16 {
+        match addr {
+            0x0800..=0x1FFF => addr & 0x07FF, // WRAM Mirrors
+            0x2008..=0x3FFF => addr & 0x2007, // Ppu Mirrors
+            _ => addr,
+        }
+    }
+
+    #[inline]
+    fn check_watchpoints(&mut self, addr: u16, access: Access, old: u8, new: u8) {
+        if self.watchpoint_hit.is_none() {
+            self.watchpoint_hit =
+                WatchpointHit::check(&self.watchpoints, self.ppu.mapper(), addr, access, old, new);
+        }
+    }
+
+    /// Memory hooks record every matching access without halting execution, unlike watchpoints.
+    #[inline]
+    pub fn mem_hooks_mut(&mut self) -> &mut Vec<Watchpoint> {
+        &mut self.mem_hooks
+    }
+
+    /// Returns all memory hook hits since the last call.
+    #[inline]
+    pub fn take_mem_hook_hits(&mut self) -> Vec<WatchpointHit> {
+        std::mem::take(&mut self.mem_hook_hits)
+    }
+
+    #[inline]
+    fn check_mem_hooks(&mut self, addr: u16, access: Access, old: u8, new: u8) {
+        let hit = WatchpointHit::check(&self.mem_hooks, self.ppu.mapper(), addr, access, old, new);
+        if let Some(hit) = hit {
+            self.mem_hook_hits.push(hit);
+        }
+    }
+
+    /// Starts recording the frame each byte of memory was last read or written on.
+    #[inline]
+    pub fn start_access_log(&mut self) {
+        self.access_log = Some(AccessLog::new());
+        self.ppu.start_access_log();
+    }
+
+    #[inline]
+    pub fn stop_access_log(&mut self) {
+        self.access_log = None;
+        self.ppu.stop_access_log();
+    }
+
+    /// Moves the access log over from another bus, e.g. when loading a save state.
+    #[inline]
+    pub(crate) fn take_access_log(&mut self, bus: &mut Self) {
+        self.access_log = bus.access_log.take();
+        self.ppu.take_access_log(&mut bus.ppu);
+    }
+
+    /// Stops the CPU and PPU access logs, returning them to be resumed with
+    /// `resume_access_log`.
+    #[inline]
+    pub(crate) fn suspend_access_log(&mut self) -> (Option<AccessLog>, Option<AccessLog>) {
+        (self.access_log.take(), self.ppu.suspend_access_log())
+    }
+
+    #[inline]
+    pub(crate) fn resume_access_log(&mut self, (cpu, ppu): (Option<AccessLog>, Option<AccessLog>)) {
+        self.access_log = cpu;
+        self.ppu.resume_access_log(ppu);
+    }
+
+    /// Returns the frames a byte was last read and written on, if the access log is running.
+    #[inline]
+    #[must_use]
+    pub fn last_access(&self, region: MemRegion, index: usize) -> Option<MemAccess> {
+        match region {
+            MemRegion::Cpu | MemRegion::PrgRam => self
+                .access_log
+                .as_ref()
+                .map(|log| log.last_access(region, index)),
+            _ => self.ppu.last_access(region, index),
+        }
+    }
+
+    #[inline]
+    fn log_access(&mut self, region: MemRegion, index: usize, access: Access) {
+        if let Some(log) = &mut self.access_log {
+            log.set_frame(self.ppu.frame_number());
+            log.log(region, index, access);
+        }
+    }
+
+    /// Size of a memory region, 0 if the cartridge doesn't have it.
+    #[must_use]
+    pub fn mem_size(&self, region: MemRegion) -> usize {
+        match region {
+            MemRegion::Cpu => 0x10000,
+            MemRegion::PrgRam => self.prg_ram.len(),
+            _ => self.ppu.mem_size(region),
+        }
+    }
+
+    #[must_use]
+    pub fn peek_mem(&self, region: MemRegion, index: usize) -> u8 {
+        match region {
+            MemRegion::Cpu => self.peek(index as u16, Access::Dummy),
+            MemRegion::PrgRam => self.prg_ram.get(index).copied().unwrap_or_default(),
+            _ => self.ppu.peek_mem(region, index),
+        }
+    }
+
+    /// Writes a byte without side effects. Only WRAM, PRG-RAM and PRG-ROM can be written through
+    /// CPU addresses, registers are left alone.
+    pub fn poke_mem(&mut self, region: MemRegion, index: usize, val: u8) {
+        let byte = match region {
+            MemRegion::Cpu => match index as u16 {
+                addr @ 0x0000..=0x1FFF => self.wram.get_mut(addr as usize & 0x07FF),
+                addr @ 0x4020..=0xFFFF => match self.mapper().map_peek(addr) {
+                    MappedRead::PrgRam(addr) => self.prg_ram.get_mut(addr),
+                    MappedRead::PrgRom(addr) => self.prg_rom.get_mut(addr),
+                    _ => None,
+                },
+                _ => None,
+            },
+            MemRegion::PrgRam => self.prg_ram.get_mut(index),
+            _ => return self.ppu.poke_mem(region, index, val),
+        };
+        if let Some(byte) = byte {
+            *byte = val;
+        }
     }
 
     #[inline]
@@ -207,27 +497,228 @@ impl CpuBus {
     ///
     /// Errors if genie code is invalid.
     pub fn add_genie_code(&mut self, code: String) -> NesResult<()> {
-        let genie_code = GenieCode::new(code)?;
-        let addr = genie_code.addr();
-        self.genie_codes.insert(addr, genie_code);
+        self.add_cheat(GenieCode::new(code)?.into());
         Ok(())
     }
 
     #[inline]
     pub fn remove_genie_code(&mut self, code: &str) {
-        self.genie_codes.retain(|_, gc| gc.code() != code);
+        self.remove_cheat(code);
+    }
+
+    /// Add a cheat, replacing any existing cheat for the same address.
+    #[inline]
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.insert(cheat.addr(), cheat);
+        // Apply RAM cheats right away instead of waiting for the next frame
+        self.cheat_frame = self.frame_number().wrapping_sub(1);
     }
 
     #[inline]
-    fn genie_read(&self, addr: u16, val: u8) -> u8 {
-        self.genie_codes
+    pub fn remove_cheat(&mut self, code: &str) {
+        self.cheats.retain(|_, cheat| cheat.code() != code);
+    }
+
+    #[inline]
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+    }
+
+    /// Returns all cheats, ordered by address.
+    #[must_use]
+    pub fn cheats(&self) -> Vec<&Cheat> {
+        let mut cheats: Vec<&Cheat> = self.cheats.values().collect();
+        cheats.sort_by_key(|cheat| cheat.addr());
+        cheats
+    }
+
+    #[inline]
+    pub fn cheat_mut(&mut self, code: &str) -> Option<&mut Cheat> {
+        self.cheats.values_mut().find(|cheat| cheat.code() == code)
+    }
+
+    /// Moves the cheats over from another bus, e.g. when loading a save state, adding back any
+    /// Game Genie codes saved with this bus that aren't already set.
+    #[inline]
+    pub(crate) fn take_cheats(&mut self, bus: &mut Self) {
+        let saved = std::mem::replace(&mut self.cheats, std::mem::take(&mut bus.cheats));
+        for (addr, cheat) in saved {
+            self.cheats.entry(addr).or_insert(cheat);
+        }
+    }
+
+    /// Writes enabled RAM cheats to WRAM or PRG-RAM once per frame, without side effects like
+    /// `poke_mem`. PRG-ROM cheats are applied on read instead.
+    pub fn apply_cheats(&mut self) {
+        let frame = self.frame_number();
+        if self.cheats.is_empty() || frame == self.cheat_frame {
+            return;
+        }
+        self.cheat_frame = frame;
+        let cheats = std::mem::take(&mut self.cheats);
+        for cheat in cheats.values() {
+            if !cheat.enabled() || cheat.patches_rom() {
+                continue;
+            }
+            let byte = match cheat.addr() {
+                addr @ 0x0000..=0x1FFF => self.wram.get_mut(addr as usize & 0x07FF),
+                addr => match self.mapper().map_peek(addr) {
+                    MappedRead::PrgRam(addr) => self.prg_ram.get_mut(addr),
+                    _ => None,
+                },
+            };
+            if let Some(byte) = byte {
+                *byte = cheat.read(*byte);
+            }
+        }
+        self.cheats = cheats;
+    }
+
+    #[inline]
+    fn cheat_read(&self, addr: u16, val: u8) -> u8 {
+        self.cheats
             .get(&addr)
-            .map_or(val, |genie_code| genie_code.read(val))
+            .filter(|cheat| cheat.enabled() && cheat.patches_rom())
+            .map_or(val, |cheat| cheat.read(val))
+    }
+
+    fn mix_audio(&mut self) {
+        if self.channel_gains.is_empty() {
+            let apu_output = self.apu.output();
+            let mapper_output = match self.mapper() {
+                Mapper::Exrom(ref exrom) => exrom.output(),
+                Mapper::Vrc6(ref vrc6) => vrc6.output(),
+                _ => 0.0,
+            };
+            for channel in 0..self.blip.channels() {
+                self.blip
+                    .set_amplitude(channel, self.blip_clock, apu_output + mapper_output);
+            }
+        } else {
+            let expansion = self.expansion_outputs();
+            for (channel, gains) in self.channel_gains.iter().enumerate() {
+                let mut output = self.apu.mixed_output(&gains[..Stem::apu().len()]);
+                if let Some((first, outputs)) = expansion {
+                    let gains = &gains[first as usize..];
+                    output += outputs
+                        .iter()
+                        .zip(gains)
+                        .map(|(output, gain)| output * gain)
+                        .sum::<f32>();
+                }
+                self.blip.set_amplitude(channel, self.blip_clock, output);
+            }
+        }
+    }
+
+    /// Outputs of the expansion audio channels of the loaded mapper, if any, along with the
+    /// first `Stem` they belong to.
+    fn expansion_outputs(&self) -> Option<(Stem, [f32; 3])> {
+        match self.mapper() {
+            Mapper::Exrom(ref exrom) => Some((Stem::Mmc5Pulse1, exrom.channel_outputs())),
+            Mapper::Vrc6(ref vrc6) => Some((Stem::Vrc6Pulse1, vrc6.channel_outputs())),
+            _ => None,
+        }
+    }
+
+    /// Number of interleaved channels in audio samples, `2` if stereo is enabled.
+    #[inline]
+    #[must_use]
+    pub const fn audio_channels(&self) -> usize {
+        self.blip.channels()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn stereo(&self) -> bool {
+        self.blip.channels() == 2
+    }
+
+    /// Enables or disables stereo output, clearing audio samples since their channels change.
+    pub fn set_stereo(&mut self, stereo: bool) {
+        if stereo != self.stereo() {
+            self.end_audio_frame();
+            self.audio_samples.clear();
+            let channels = if stereo { 2 } else { 1 };
+            self.blip = BlipBuf::new(channels, self.blip.clock_rate(), self.blip.sample_rate());
+            self.update_channel_gains();
+        }
+    }
+
+    #[inline]
+    pub const fn channel_mix(&self, stem: Stem) -> ChannelMix {
+        self.channel_mix[stem as usize]
     }
 
     #[inline]
-    fn mix_audio(&mut self, sample1: f32, sample2: f32) {
-        self.audio_samples.push(sample1 + sample2);
+    pub fn set_channel_mix(&mut self, stem: Stem, mix: ChannelMix) {
+        self.channel_mix[stem as usize] = mix;
+        self.update_channel_gains();
+    }
+
+    /// Computes the gains of each stem for each output channel, or none if every channel is at
+    /// its default mix so the exact mixer tables can be used instead.
+    fn update_channel_gains(&mut self) {
+        self.channel_gains.clear();
+        let default_mix = ChannelMix::default();
+        if self.channel_mix.iter().all(|mix| *mix == default_mix) {
+            return;
+        }
+        let mut left = [0.0; Stem::COUNT];
+        let mut right = [0.0; Stem::COUNT];
+        for ((mix, left), right) in self.channel_mix.iter().zip(&mut left).zip(&mut right) {
+            (*left, *right) = mix.gains();
+        }
+        if self.stereo() {
+            self.channel_gains.extend([left, right]);
+        } else {
+            let volumes = self
+                .channel_mix
+                .map(|mix| mix.volume.clamp(0.0, ChannelMix::MAX_VOLUME));
+            self.channel_gains.push(volumes);
+        }
+    }
+
+    /// Converts audio deltas added so far to samples.
+    fn end_audio_frame(&mut self) {
+        self.blip.end_frame(self.blip_clock);
+        self.blip.read_samples(&mut self.audio_samples);
+        if let Some(ref mut stem_blip) = self.stem_blip {
+            stem_blip.end_frame(self.blip_clock);
+            stem_blip.read_samples(&mut self.stem_samples);
+        }
+        self.blip_clock = 0;
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn sample_rate(&self) -> f32 {
+        self.blip.sample_rate()
+    }
+
+    #[inline]
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.set_audio_rates(Cpu::region_clock_rate(self.region), sample_rate);
+    }
+
+    fn set_audio_rates(&mut self, clock_rate: f32, sample_rate: f32) {
+        self.end_audio_frame();
+        self.blip.set_rates(clock_rate, sample_rate);
+        if let Some(ref mut stem_blip) = self.stem_blip {
+            stem_blip.set_rates(clock_rate, sample_rate);
+        }
+    }
+
+    /// Moves audio synthesis state over from another bus, e.g. when loading a save state, so
+    /// output continues without a gap.
+    pub(crate) fn take_audio(&mut self, other: &mut Self) {
+        other.end_audio_frame();
+        std::mem::swap(&mut self.blip, &mut other.blip);
+        std::mem::swap(&mut self.stem_blip, &mut other.stem_blip);
+        self.channel_mix = other.channel_mix;
+        self.update_channel_gains();
+        self.blip_clock = 0;
+        self.set_audio_rates(Cpu::region_clock_rate(self.region), self.sample_rate());
     }
 
     #[inline]
@@ -239,6 +730,66 @@ impl CpuBus {
     #[inline]
     pub fn clear_audio_samples(&mut self) {
         self.audio_samples.clear();
+        self.stem_samples.clear();
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn stems_enabled(&self) -> bool {
+        self.stem_blip.is_some()
+    }
+
+    #[inline]
+    pub fn set_stems_enabled(&mut self, enabled: bool) {
+        if enabled != self.stems_enabled() {
+            self.end_audio_frame();
+            self.stem_blip = enabled.then(|| {
+                BlipBuf::new(Stem::COUNT, self.blip.clock_rate(), self.blip.sample_rate())
+            });
+            self.stem_samples.clear();
+        }
+    }
+
+    /// Interleaved output of each `Stem` for every audio sample, or empty if stems are disabled.
+    #[inline]
+    #[must_use]
+    pub fn stem_samples(&self) -> &[f32] {
+        &self.stem_samples
+    }
+
+    /// Stems for the APU and the expansion audio of the loaded mapper, if any.
+    #[must_use]
+    pub fn audio_stems(&self) -> Vec<Stem> {
+        let mut stems = Stem::apu().to_vec();
+        match self.mapper() {
+            Mapper::Exrom(_) => stems.extend_from_slice(Stem::mmc5()),
+            Mapper::Vrc6(_) => stems.extend_from_slice(Stem::vrc6()),
+            _ => (),
+        }
+        stems
+    }
+
+    fn record_stems(&mut self) {
+        let mut stems = [0.0; Stem::COUNT];
+        let channels = [
+            Channel::Pulse1,
+            Channel::Pulse2,
+            Channel::Triangle,
+            Channel::Noise,
+            Channel::Dmc,
+        ];
+        for (stem, channel) in stems.iter_mut().zip(channels) {
+            *stem = self.apu.channel_output(channel);
+        }
+        if let Some((first, outputs)) = self.expansion_outputs() {
+            let first = first as usize;
+            stems[first..first + outputs.len()].copy_from_slice(&outputs);
+        }
+        if let Some(ref mut stem_blip) = self.stem_blip {
+            for (channel, output) in stems.into_iter().enumerate() {
+                stem_blip.set_amplitude(channel, self.blip_clock, output);
+            }
+        }
     }
 
     #[inline]
@@ -343,13 +894,14 @@ impl Clock for CpuBus {
         self.mapper_mut().clock();
         self.input.clock();
 
-        let apu_output = self.apu.output();
-        let mapper_output = match self.mapper() {
-            Mapper::Exrom(ref exrom) => exrom.output(),
-            Mapper::Vrc6(ref vrc6) => vrc6.output(),
-            _ => 0.0,
-        };
-        self.mix_audio(apu_output, mapper_output);
+        self.mix_audio();
+        if self.stems_enabled() {
+            self.record_stems();
+        }
+        self.blip_clock += 1;
+        if self.blip_clock == Self::AUDIO_FRAME_CLOCKS {
+            self.end_audio_frame();
+        }
 
         1
     }
@@ -360,17 +912,21 @@ impl Clock for CpuBus {
 }
 
 impl Mem for CpuBus {
-    fn read(&mut self, addr: u16, _access: Access) -> u8 {
+    fn read(&mut self, addr: u16, access: Access) -> u8 {
+        let addr = Self::canonical_addr(addr);
         let val = match addr {
             0x0000..=0x07FF => self.wram[addr as usize],
             0x4020..=0xFFFF => {
                 let val = match self.mapper_mut().map_read(addr) {
                     MappedRead::Data(val) => val,
-                    MappedRead::PrgRam(addr) => self.prg_ram[addr],
+                    MappedRead::PrgRam(addr) => {
+                        self.log_access(MemRegion::PrgRam, addr, access);
+                        self.prg_ram[addr]
+                    }
                     MappedRead::PrgRom(addr) => self.prg_rom[addr],
                     _ => self.open_bus,
                 };
-                self.genie_read(addr, val)
+                self.cheat_read(addr, val)
             }
             0x2002 => self.ppu.read_status(),
             0x2004 => self.ppu.read_oamdata(),
@@ -379,16 +935,22 @@ impl Mem for CpuBus {
             0x4016 => self.input.read(Slot::One, &self.ppu),
             0x4017 => self.input.read(Slot::Two, &self.ppu),
             0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => self.ppu.open_bus(),
-            0x0800..=0x1FFF => self.read(addr & 0x07FF, _access), // WRAM Mirrors
-            0x2008..=0x3FFF => self.read(addr & 0x2007, _access), // Ppu Mirrors
             _ => self.open_bus,
         };
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, access, val, val);
+        }
+        if !self.mem_hooks.is_empty() {
+            self.check_mem_hooks(addr, access, val, val);
+        }
+        self.log_access(MemRegion::Cpu, addr.into(), access);
         self.open_bus = val;
         self.mapper_mut().cpu_bus_read(addr);
         val
     }
 
     fn peek(&self, addr: u16, _access: Access) -> u8 {
+        let addr = Self::canonical_addr(addr);
         match addr {
             0x0000..=0x07FF => self.wram[addr as usize],
             0x4020..=0xFFFF => {
@@ -398,7 +960,7 @@ impl Mem for CpuBus {
                     MappedRead::PrgRom(addr) => self.prg_rom[addr],
                     _ => self.open_bus,
                 };
-                self.genie_read(addr, val)
+                self.cheat_read(addr, val)
             }
             0x2002 => self.ppu.peek_status(),
             0x2004 => self.ppu.peek_oamdata(),
@@ -407,20 +969,32 @@ impl Mem for CpuBus {
             0x4016 => self.input.peek(Slot::One, &self.ppu),
             0x4017 => self.input.peek(Slot::Two, &self.ppu),
             0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => self.ppu.open_bus(),
-            0x0800..=0x1FFF => self.peek(addr & 0x07FF, _access), // WRAM Mirrors
-            0x2008..=0x3FFF => self.peek(addr & 0x2007, _access), // Ppu Mirrors
             _ => self.open_bus,
         }
     }
 
-    fn write(&mut self, addr: u16, val: u8, _access: Access) {
+    fn write(&mut self, addr: u16, val: u8, access: Access) {
+        let addr = Self::canonical_addr(addr);
+        if !self.watchpoints.is_empty() || !self.mem_hooks.is_empty() {
+            let old = self.peek(addr, Access::Dummy);
+            self.check_watchpoints(addr, access, old, val);
+            self.check_mem_hooks(addr, access, old, val);
+        }
+        self.log_access(MemRegion::Cpu, addr.into(), access);
         match addr {
             0x0000..=0x07FF => self.wram[addr as usize] = val,
             0x4020..=0xFFFF => {
                 let prg_ram_enabled = !self.prg_ram.is_empty() && !self.prg_ram_protect;
                 match self.mapper_mut().map_write(addr, val) {
-                    MappedWrite::PrgRam(addr, val) if prg_ram_enabled => self.prg_ram[addr] = val,
-                    MappedWrite::PrgRamProtect(protect) => self.prg_ram_protect = protect,
+                    MappedWrite::PrgRam(addr, val) if prg_ram_enabled => {
+                        self.log_access(MemRegion::PrgRam, addr, access);
+                        self.prg_ram[addr] = val;
+                    }
+                    MappedWrite::PrgRamProtect(protect) => {
+                        self.prg_ram_protect = protect;
+                        self.ppu.log_event(PpuEventKind::MapperWrite, addr, val);
+                    }
+                    MappedWrite::Bus => self.ppu.log_event(PpuEventKind::MapperWrite, addr, val),
                     _ => (),
                 }
                 self.ppu.update_mirroring();
@@ -458,10 +1032,16 @@ impl Mem for CpuBus {
             0x4016 => self.input.write(val),
             0x4017 => self.apu.write_frame_counter(val),
             0x2002 => self.ppu.set_open_bus(val),
-            0x0800..=0x1FFF => return self.write(addr & 0x07FF, val, _access), // WRAM Mirrors
-            0x2008..=0x3FFF => return self.write(addr & 0x2007, val, _access), // Ppu Mirrors
             _ => (),
         }
+        // OAM DMA writes to $2004 are dummy accesses and only recorded once as `OamDma`
+        if access != Access::Dummy {
+            match addr {
+                0x2000..=0x2007 => self.ppu.log_event(PpuEventKind::PpuWrite, addr, val),
+                0x4014 => self.ppu.log_event(PpuEventKind::OamDma, addr, val),
+                _ => (),
+            }
+        }
         self.open_bus = val;
         self.mapper_mut().cpu_bus_write(addr, val);
     }
@@ -478,6 +1058,7 @@ impl Regional for CpuBus {
         self.region = region;
         self.ppu.set_region(region);
         self.apu.set_region(region);
+        self.set_audio_rates(Cpu::region_clock_rate(region), self.sample_rate());
     }
 }
 
@@ -507,9 +1088,12 @@ impl std::fmt::Debug for CpuBus {
             .field("oam_dma", &self.oam_dma)
             .field("oam_dma_addr", &self.oam_dma_addr)
             .field("audio_samples_len", &self.audio_samples.len())
-            .field("genie_codes", &self.genie_codes.values())
+            .field("blip", &self.blip)
+            .field("stems_enabled", &self.stems_enabled())
+            .field("cheats", &self.cheats.values())
             .field("cycle", &self.cycle)
             .field("open_bus", &format_args!("${:02X}", &self.open_bus))
+            .field("watchpoints", &self.watchpoints)
             .finish()
     }
 }
@@ -517,7 +1101,7 @@ impl std::fmt::Debug for CpuBus {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::cart::Cart;
+    use crate::{apu::PULSE_TABLE, cart::Cart};
 
     #[test]
     fn load_cart_values() {
@@ -635,6 +1219,61 @@ mod test {
         assert_eq!(bus.apu.cycle(), 1, "apu clock");
     }
 
+    #[test]
+    fn stems() {
+        let mut bus = CpuBus::default();
+
+        bus.clock();
+        assert!(bus.stem_samples().is_empty(), "disabled");
+        bus.set_stems_enabled(true);
+        for _ in 0..CpuBus::AUDIO_FRAME_CLOCKS {
+            bus.clock();
+        }
+        let samples = bus.stem_samples().len();
+        assert!(samples > 0, "stem samples");
+        assert_eq!(samples % Stem::COUNT, 0, "interleaved stems");
+        assert_eq!(bus.audio_stems(), Stem::apu());
+        bus.clear_audio_samples();
+        assert!(bus.stem_samples().is_empty(), "cleared");
+        assert!(bus.stems_enabled());
+    }
+
+    #[test]
+    fn channel_mix() {
+        let mut bus = CpuBus::default();
+        assert_eq!(bus.audio_channels(), 1);
+        bus.set_stereo(true);
+        assert_eq!(bus.audio_channels(), 2);
+        bus.set_channel_mix(Stem::Pulse1, ChannelMix::new(1.5, -1.0));
+
+        // Pulse 1 at constant full volume
+        bus.write(0x4015, 0x01, Access::Write);
+        bus.write(0x4000, 0xBF, Access::Write);
+        bus.write(0x4002, 0xFD, Access::Write);
+        bus.write(0x4003, 0x00, Access::Write);
+        for _ in 0..4 * CpuBus::AUDIO_FRAME_CLOCKS {
+            bus.clock();
+        }
+        let samples = bus.audio_samples();
+        assert_eq!(samples.len() % 2, 0, "interleaved stereo");
+        let peak = |channel: usize| {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(2)
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+        };
+        let (left, right) = (peak(0), peak(1));
+        assert!(left > 1.2 * PULSE_TABLE[15], "boosted left: {left}");
+        assert!(right < 1e-6, "silent right: {right}");
+
+        bus.set_channel_mix(Stem::Pulse1, ChannelMix::default());
+        assert_eq!(bus.channel_mix(Stem::Pulse1), ChannelMix::default());
+        bus.set_stereo(false);
+        assert!(bus.audio_samples().is_empty(), "cleared");
+        assert_eq!(bus.audio_channels(), 1);
+    }
+
     #[test]
     fn read_write_ram() {
         let mut bus = CpuBus::default();
@@ -0,0 +1,439 @@
+//! Cheats patching CPU memory from Game Genie, Pro Action Replay or raw address/value codes.
+//!
+//! Cheats at `$8000` and above substitute reads from PRG-ROM like a Game Genie does. Cheats on WRAM
+//! (`$0000-$1FFF`) or PRG-RAM (`$6000-$7FFF`) are written to RAM once per frame instead, so they
+//! hold even if the game overwrites them. Cheats on the registers in between are rejected. Enabled
+//! Game Genie codes are also saved with save states.
+//!
+//! Cheat files use the FCEUX `.cht` format, one cheat per line:
+//!
+//! ```text
+//! [S][C][:]AAAA:VV[:CC]:Description
+//! ```
+//!
+//! `C` marks a compare value and a leading `:` marks a disabled cheat. `S` is accepted but
+//! ignored.
+
+use crate::{genie::GenieCode, NesResult};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{BufRead, Write},
+};
+
+/// The format a [`Cheat`] was entered in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum CheatKind {
+    /// 6 or 8 letter Game Genie code, e.g. `SXIOPO`.
+    GameGenie,
+    /// 8 digit Pro Action Replay code, `00AAAAVV`.
+    ProActionReplay,
+    /// Raw address and value, `AAAA:VV` or `AAAA:VV:CC` with a compare value.
+    Raw,
+}
+
+/// A single cheat which replaces the value at an address, optionally only when the current value
+/// matches a compare value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Cheat {
+    kind: CheatKind,
+    code: String,
+    addr: u16,
+    value: u8,
+    compare: Option<u8>,
+    enabled: bool,
+    description: String,
+}
+
+impl Cheat {
+    /// Parses a Game Genie, Pro Action Replay or raw cheat code. Codes made up only of Game Genie
+    /// letters are always parsed as Game Genie codes.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the given code is not in a recognized format.
+    pub fn new(code: &str) -> NesResult<Self> {
+        let code = code.trim().to_ascii_uppercase();
+        if code.contains(':') {
+            let mut parts = code.split(':');
+            let cheat = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(addr), Some(value), compare, None) => Self::raw(
+                    parse_addr(addr)?,
+                    parse_hex(value, 2)? as u8,
+                    compare
+                        .map(|compare| parse_hex(compare, 2))
+                        .transpose()?
+                        .map(|c| c as u8),
+                ),
+                _ => return Err(anyhow!("invalid raw cheat code: {}", code)),
+            };
+            Ok(cheat)
+        } else if let Ok(genie_code) = GenieCode::new(code.clone()) {
+            Ok(Self::from(genie_code))
+        } else if code.len() == 8 && code.is_ascii() {
+            // Only the `00` prefix is used by NES Pro Action Replay codes
+            if !code.starts_with("00") {
+                return Err(anyhow!("invalid pro action replay code: {}", code));
+            }
+            let addr = parse_addr(&code[2..6])?;
+            let value = parse_hex(&code[6..8], 2)? as u8;
+            Ok(Self {
+                kind: CheatKind::ProActionReplay,
+                ..Self::raw(addr, value, None)
+            }
+            .with_code(code))
+        } else {
+            Err(anyhow!("invalid cheat code: {}", code))
+        }
+    }
+
+    /// Creates an enabled raw cheat for an address and value, with an optional compare value.
+    pub fn raw(addr: u16, value: u8, compare: Option<u8>) -> Self {
+        let code = match compare {
+            Some(compare) => format!("{addr:04X}:{value:02X}:{compare:02X}"),
+            None => format!("{addr:04X}:{value:02X}"),
+        };
+        Self {
+            kind: CheatKind::Raw,
+            code,
+            addr,
+            value,
+            compare,
+            enabled: true,
+            description: String::new(),
+        }
+    }
+
+    fn with_code(mut self, code: String) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Encodes this cheat as a Game Genie code.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the cheat address is below `$8000`.
+    pub fn to_genie_code(&self) -> NesResult<GenieCode> {
+        GenieCode::encode(self.addr, self.value, self.compare)
+    }
+
+    #[inline]
+    pub const fn kind(&self) -> CheatKind {
+        self.kind
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn addr(&self) -> u16 {
+        self.addr
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn value(&self) -> u8 {
+        self.value
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn compare(&self) -> Option<u8> {
+        self.compare
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[inline]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    #[inline]
+    pub fn set_description<S: Into<String>>(&mut self, description: S) {
+        self.description = description.into();
+    }
+
+    /// Whether this cheat substitutes PRG-ROM reads instead of being written to RAM.
+    #[inline]
+    #[must_use]
+    pub const fn patches_rom(&self) -> bool {
+        self.addr >= 0x8000
+    }
+
+    /// Returns the value read with this cheat applied.
+    #[inline]
+    #[must_use]
+    pub const fn read(&self, val: u8) -> u8 {
+        match self.compare {
+            Some(compare) if val != compare => val,
+            _ => self.value,
+        }
+    }
+}
+
+impl From<GenieCode> for Cheat {
+    fn from(genie_code: GenieCode) -> Self {
+        Self {
+            kind: CheatKind::GameGenie,
+            ..Self::raw(genie_code.addr(), genie_code.data(), genie_code.compare())
+        }
+        .with_code(genie_code.code().to_string())
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.description.is_empty() {
+            write!(f, "{}", self.code)
+        } else {
+            write!(f, "{} {}", self.code, self.description)
+        }
+    }
+}
+
+/// Loads cheats from an FCEUX `.cht` file.
+///
+/// # Errors
+///
+/// If the file fails to be read or a line is invalid, an error is returned.
+pub fn load_cht<R: BufRead>(reader: R) -> NesResult<Vec<Cheat>> {
+    let mut cheats = vec![];
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let cheat = parse_cht_line(&line)
+            .with_context(|| format!("invalid cheat on line {}", number + 1))?;
+        cheats.push(cheat);
+    }
+    Ok(cheats)
+}
+
+/// Saves cheats as an FCEUX `.cht` file.
+///
+/// # Errors
+///
+/// If the file fails to be written, an error is returned.
+pub fn save_cht<W: Write>(writer: &mut W, cheats: &[Cheat]) -> NesResult<()> {
+    for cheat in cheats {
+        if cheat.compare.is_some() {
+            write!(writer, "C")?;
+        }
+        if !cheat.enabled {
+            write!(writer, ":")?;
+        }
+        write!(writer, "{:04X}:{:02X}", cheat.addr, cheat.value)?;
+        if let Some(compare) = cheat.compare {
+            write!(writer, ":{compare:02X}")?;
+        }
+        writeln!(writer, ":{}", cheat.description)?;
+    }
+    Ok(())
+}
+
+fn parse_cht_line(line: &str) -> NesResult<Cheat> {
+    let line = line.strip_prefix('S').unwrap_or(line);
+    let (has_compare, line) = line
+        .strip_prefix('C')
+        .map_or((false, line), |line| (true, line));
+    let (enabled, line) = line
+        .strip_prefix(':')
+        .map_or((true, line), |line| (false, line));
+    let fields = if has_compare { 4 } else { 3 };
+    let parts: Vec<&str> = line.splitn(fields, ':').collect();
+    if parts.len() < fields - 1 {
+        return Err(anyhow!("expected address and value: {}", line));
+    }
+    let compare = if has_compare {
+        Some(parse_hex(parts[2], 2)? as u8)
+    } else {
+        None
+    };
+    let mut cheat = Cheat::raw(
+        parse_addr(parts[0])?,
+        parse_hex(parts[1], 2)? as u8,
+        compare,
+    );
+    cheat.enabled = enabled;
+    if let Some(description) = parts.get(fields - 1) {
+        cheat.set_description(*description);
+    }
+    Ok(cheat)
+}
+
+// PPU, APU, input and mapper registers have side effects when written, so can't hold a cheat
+fn parse_addr(hex: &str) -> NesResult<u16> {
+    let addr = parse_hex(hex, 4)?;
+    if (0x2000..=0x5FFF).contains(&addr) {
+        return Err(anyhow!("cheat address is not RAM or ROM: ${:04X}", addr));
+    }
+    Ok(addr)
+}
+
+fn parse_hex(hex: &str, max_len: usize) -> NesResult<u16> {
+    if hex.is_empty() || hex.len() > max_len {
+        return Err(anyhow!("invalid hex value: {:?}", hex));
+    }
+    u16::from_str_radix(hex, 16).with_context(|| format!("invalid hex value: {hex:?}"))
+}
+
+/// Serializes enabled Game Genie cheats in the `HashMap<u16, GenieCode>` format save states have
+/// always used. Other cheats are only kept in the per-game cheat file.
+pub(crate) fn serialize_genie_codes<S: Serializer>(
+    cheats: &HashMap<u16, Cheat>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let genie_codes: HashMap<u16, GenieCode> = cheats
+        .values()
+        .filter(|cheat| cheat.enabled() && cheat.kind() == CheatKind::GameGenie)
+        .filter_map(|cheat| GenieCode::new(cheat.code().to_string()).ok())
+        .map(|genie_code| (genie_code.addr(), genie_code))
+        .collect();
+    genie_codes.serialize(serializer)
+}
+
+/// Deserializes the Game Genie codes saved by [`serialize_genie_codes`] as cheats.
+pub(crate) fn deserialize_genie_codes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<u16, Cheat>, D::Error> {
+    let genie_codes = HashMap::<u16, GenieCode>::deserialize(deserializer)?;
+    Ok(genie_codes
+        .into_iter()
+        .map(|(addr, genie_code)| (addr, Cheat::from(genie_code)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control_deck::ControlDeck,
+        cpu::Cpu,
+        debugger::tests::{load_program, LOOP},
+        mem::{Access, Mem},
+    };
+    use flate2::bufread::DeflateDecoder;
+    use std::{
+        fs::File,
+        io::{BufReader, Read},
+    };
+
+    #[test]
+    fn parse_codes() {
+        let cheat = Cheat::new("sxiopo").expect("valid genie code");
+        assert_eq!(cheat.kind(), CheatKind::GameGenie);
+        assert_eq!((cheat.addr(), cheat.value()), (0x91D9, 0xAD));
+        assert_eq!(cheat.code(), "SXIOPO");
+
+        let cheat = Cheat::new("00075A09").expect("valid par code");
+        assert_eq!(cheat.kind(), CheatKind::ProActionReplay);
+        assert_eq!((cheat.addr(), cheat.value()), (0x075A, 0x09));
+        assert!(!cheat.patches_rom());
+
+        let cheat = Cheat::new("9F41:77:22").expect("valid raw code");
+        assert_eq!(cheat.kind(), CheatKind::Raw);
+        assert_eq!(cheat.compare(), Some(0x22));
+        assert_eq!((cheat.read(0x22), cheat.read(0x23)), (0x77, 0x23));
+        assert_eq!(
+            cheat.to_genie_code().expect("rom address").code(),
+            "YYKPOYZZ"
+        );
+
+        assert!(Cheat::new("0075").is_err());
+        assert!(Cheat::new("10000:01").is_err());
+        assert!(Cheat::new("2006:01").is_err(), "ppu register");
+        assert!(Cheat::new("00401401").is_err(), "oam dma register");
+        assert!(Cheat::new("0F075A09").is_err(), "par prefix");
+        assert!(Cheat::new("QQQQQQ").is_err());
+    }
+
+    #[test]
+    fn apply_cheats() {
+        let mut deck = load_program(&LOOP);
+        deck.add_cheat(Cheat::new("0075:09").expect("valid raw code"));
+        deck.add_cheat(Cheat::new("8001:05").expect("valid raw code"));
+        let _ = deck.clock_frame().expect("valid clock");
+        assert_eq!(deck.cpu().peek(0x0075, Access::Dummy), 0x09, "ram cheat");
+        assert_eq!(deck.cpu().peek(0x8001, Access::Dummy), 0x05, "rom cheat");
+
+        deck.cpu_mut().poke(0x0075, 0x02);
+        deck.cheat_mut("0075:09").expect("cheat").set_enabled(false);
+        deck.remove_cheat("8001:05");
+        let _ = deck.clock_frame().expect("valid clock");
+        assert_eq!(
+            deck.cpu().peek(0x0075, Access::Dummy),
+            0x02,
+            "disabled ram cheat"
+        );
+        assert_eq!(
+            deck.cpu().peek(0x8001, Access::Dummy),
+            0x00,
+            "removed rom cheat"
+        );
+        assert_eq!(deck.cheats().len(), 1);
+    }
+
+    #[test]
+    fn load_save_cht() {
+        let file = "SC:9F41:77:22:8 hearts\n\n075A:09:Infinite lives: SMB\n";
+        let cheats = load_cht(file.as_bytes()).expect("valid cht");
+        assert_eq!(cheats.len(), 2);
+        assert!(!cheats[0].enabled());
+        assert_eq!(cheats[0].compare(), Some(0x22));
+        assert_eq!(cheats[0].description(), "8 hearts");
+        assert!(cheats[1].enabled());
+        assert_eq!(cheats[1].description(), "Infinite lives: SMB");
+
+        let mut saved = vec![];
+        save_cht(&mut saved, &cheats).expect("saved cht");
+        assert_eq!(
+            String::from_utf8_lossy(&saved),
+            "C:9F41:77:22:8 hearts\n075A:09:Infinite lives: SMB\n"
+        );
+        assert!(load_cht("075A\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn load_state_with_genie_code() {
+        // Saved by v0.8.0 two frames into an `INX`/`STX $10` loop, with Game Genie code `SXIOPO`
+        let file = File::open("test_roms/save_state/genie_code_v0.8.0.state").expect("valid file");
+        let mut state = vec![];
+        DeflateDecoder::new(BufReader::new(file))
+            .read_to_end(&mut state)
+            .expect("valid data");
+        let cpu: Cpu = bincode::deserialize(&state).expect("valid save state");
+        let mut deck = ControlDeck::default();
+        deck.load_cpu(cpu);
+        assert_eq!(deck.frame_number(), 2);
+        assert_eq!((deck.cpu().pc(), deck.cpu().x()), (0x8000, 0xDC));
+        let codes: Vec<&str> = deck.cheats().iter().map(|cheat| cheat.code()).collect();
+        assert_eq!(codes, ["SXIOPO"]);
+        assert_eq!(deck.cpu().peek(0x91D9, Access::Dummy), 0xAD, "genie code");
+
+        let state = bincode::serialize(deck.cpu()).expect("serialized");
+        let mut deck = ControlDeck::default();
+        deck.load_cpu(bincode::deserialize(&state).expect("deserialized"));
+        assert_eq!(deck.cheats().len(), 1, "saved with the state");
+    }
+}
@@ -9,6 +9,7 @@ use std::path::{Path, PathBuf};
 pub const CONFIG_DIR: &str = ".config/tetanes";
 pub const SAVE_DIR: &str = "save";
 pub const SRAM_DIR: &str = "sram";
+pub const CHEAT_DIR: &str = "cheats";
 
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[must_use]
@@ -369,7 +370,7 @@ pub(crate) mod tests {
             log::debug!("{} - {:?}", test_frame.number, deck.joypad_mut(Slot::One));
 
             while deck.frame_number() < test_frame.number {
-                deck.clock_frame().expect("valid frame clock");
+                let _ = deck.clock_frame().expect("valid frame clock");
                 deck.clear_audio_samples();
                 deck.joypad_mut(Slot::One).reset(Kind::Soft);
                 deck.joypad_mut(Slot::Two).reset(Kind::Soft);
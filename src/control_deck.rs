@@ -1,21 +1,33 @@
 use crate::{
     apu::{Apu, Channel},
+    audio::{stems::Stem, ChannelMix},
     bus::CpuBus,
     cart::Cart,
+    cheat::Cheat,
     common::{Clock, Kind, NesRegion, Regional, Reset},
     cpu::Cpu,
+    debugger::{
+        cdl::CodeDataLog,
+        events::EventLog,
+        history::History,
+        memory::{find_pattern_with, MemAccess, MemRegion},
+        profiler::Profiler,
+        symbols::Symbols,
+        trace::TraceLogger,
+        AddrSpace, Breakpoint, Condition, Watchpoint, WatchpointHit,
+    },
     input::{FourPlayer, Joypad, Slot},
     mapper::Mapper,
     mem::RamState,
     ppu::Ppu,
-    video::{Video, VideoFilter},
+    video::{palette::Palette, Overscan, Video, VideoFilter},
     NesResult,
 };
 use anyhow::anyhow;
 use std::{io::Read, ops::ControlFlow};
 
 /// Represents an NES Control Deck
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 #[must_use]
 pub struct ControlDeck {
     running: bool,
@@ -25,6 +37,35 @@ pub struct ControlDeck {
     loaded_rom: Option<String>,
     cycles_remaining: f32,
     cpu: Cpu,
+    breakpoints: Vec<Breakpoint>,
+    run_to: Option<u16>,
+    fresh_start: bool, // No instruction has executed since a reset or load
+    watchpoint_hit: Option<WatchpointHit>,
+    tracer: Option<TraceLogger>,
+    history: Option<History>,
+    symbols: Symbols,
+}
+
+impl Clone for ControlDeck {
+    /// Clones the control deck. An active trace log and execution history are not cloned.
+    fn clone(&self) -> Self {
+        Self {
+            running: self.running,
+            ram_state: self.ram_state,
+            region: self.region,
+            video: self.video.clone(),
+            loaded_rom: self.loaded_rom.clone(),
+            cycles_remaining: self.cycles_remaining,
+            cpu: self.cpu.clone(),
+            breakpoints: self.breakpoints.clone(),
+            run_to: self.run_to,
+            fresh_start: self.fresh_start,
+            watchpoint_hit: self.watchpoint_hit,
+            tracer: None,
+            history: None,
+            symbols: self.symbols.clone(),
+        }
+    }
 }
 
 impl Default for ControlDeck {
@@ -45,6 +86,13 @@ impl ControlDeck {
             loaded_rom: None,
             cycles_remaining: 0.0,
             cpu,
+            breakpoints: vec![],
+            run_to: None,
+            fresh_start: false,
+            watchpoint_hit: None,
+            tracer: None,
+            history: None,
+            symbols: Symbols::new(),
         }
     }
 
@@ -55,6 +103,7 @@ impl ControlDeck {
     /// If there is any issue loading the ROM, then an error is returned.
     pub fn load_rom<S: ToString, F: Read>(&mut self, name: S, rom: &mut F) -> NesResult<()> {
         self.loaded_rom = Some(name.to_string());
+        self.symbols.clear();
         let cart = Cart::from_rom(name, rom, self.ram_state)?;
         self.set_region(cart.region());
         self.cpu.load_cart(cart);
@@ -64,7 +113,9 @@ impl ControlDeck {
 
     #[inline]
     pub fn load_cpu(&mut self, cpu: Cpu) {
-        self.cpu = cpu;
+        self.cpu.load_state(cpu);
+        self.discard_loaded_history();
+        self.fresh_start = true;
     }
 
     #[inline]
@@ -105,6 +156,16 @@ impl ControlDeck {
         self.video.output()
     }
 
+    /// Get a frame worth of pixels with overscan cropped, `Overscan::width` by
+    /// `Overscan::height` pixels.
+    #[inline]
+    #[must_use]
+    pub fn cropped_frame_buffer(&mut self) -> &[u8] {
+        self.video
+            .apply_filter(self.cpu.frame_buffer(), self.cpu.frame_number());
+        self.video.cropped_output()
+    }
+
     /// Get the current frame number.
     #[inline]
     #[must_use]
@@ -112,11 +173,17 @@ impl ControlDeck {
         self.cpu.frame_number()
     }
 
-    /// Audio sample rate.
+    /// Audio sample rate. Defaults to 44.1 kHz.
     #[inline]
     #[must_use]
     pub const fn sample_rate(&self) -> f32 {
-        self.cpu.clock_rate()
+        self.cpu.sample_rate()
+    }
+
+    /// Set the audio sample rate. Output is band-limited, so any rate is free of aliasing.
+    #[inline]
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.cpu.set_sample_rate(sample_rate);
     }
 
     /// Get audio samples.
@@ -132,20 +199,92 @@ impl ControlDeck {
         self.cpu.clear_audio_samples();
     }
 
+    /// Whether per-channel stems are recorded alongside audio samples.
+    #[inline]
+    #[must_use]
+    pub const fn stems_enabled(&self) -> bool {
+        self.cpu.stems_enabled()
+    }
+
+    /// Enable or disable recording per-channel stems alongside audio samples.
+    #[inline]
+    pub fn set_stems_enabled(&mut self, enabled: bool) {
+        self.cpu.set_stems_enabled(enabled);
+    }
+
+    /// Get stem samples, with the output of each `Stem` interleaved for every audio sample. Cleared
+    /// along with audio samples.
+    #[inline]
+    #[must_use]
+    pub fn stem_samples(&self) -> &[f32] {
+        self.cpu.stem_samples()
+    }
+
+    /// Stems with output for the loaded ROM, including expansion audio.
+    #[inline]
+    #[must_use]
+    pub fn audio_stems(&self) -> Vec<Stem> {
+        self.cpu.audio_stems()
+    }
+
+    /// Number of interleaved channels in audio samples, `2` if stereo is enabled.
+    #[inline]
+    #[must_use]
+    pub const fn audio_channels(&self) -> usize {
+        self.cpu.audio_channels()
+    }
+
+    /// Whether audio samples are interleaved left and right stereo channels.
+    #[inline]
+    #[must_use]
+    pub const fn stereo(&self) -> bool {
+        self.cpu.stereo()
+    }
+
+    /// Enable or disable stereo output, with channels placed using their `ChannelMix` panning.
+    /// Clears audio samples.
+    #[inline]
+    pub fn set_stereo(&mut self, stereo: bool) {
+        self.cpu.set_stereo(stereo);
+    }
+
+    /// Volume and panning of an audio channel.
+    #[inline]
+    pub const fn channel_mix(&self, stem: Stem) -> ChannelMix {
+        self.cpu.channel_mix(stem)
+    }
+
+    /// Set the volume and panning of an audio channel, including expansion audio channels.
+    #[inline]
+    pub fn set_channel_mix(&mut self, stem: Stem, mix: ChannelMix) {
+        self.cpu.set_channel_mix(stem, mix);
+    }
+
     #[inline]
     pub fn clock_rate(&mut self) -> f32 {
         self.cpu.clock_rate()
     }
 
-    /// Steps the control deck one CPU clock.
+    /// Steps the control deck one CPU clock. Returns `ControlFlow::Break` if the instruction
+    /// triggered a watchpoint or the next instruction to be executed triggers a breakpoint. The
+    /// first instruction after a reset or load breaks before executing if it triggers a
+    /// breakpoint.
     ///
     /// # Errors
     ///
     /// If CPU encounteres an invalid opcode, an error is returned.
     pub fn clock_instr(&mut self) -> NesResult<ControlFlow<usize, usize>> {
+        if self.start_breakpoint_triggered() {
+            return Ok(ControlFlow::Break(0));
+        }
+        self.record_history()?;
+        self.trace_instr()?;
+        let (pc, cycle) = (self.cpu.pc(), self.cpu.cycle());
         let cycles = self.clock();
         if self.cpu_corrupted() {
             Err(anyhow!("cpu corrupted"))
+        } else if self.watchpoint_triggered(pc, cycle) || self.breakpoint_triggered() {
+            Ok(ControlFlow::Break(cycles))
         } else {
             Ok(ControlFlow::Continue(cycles))
         }
@@ -163,7 +302,7 @@ impl ControlDeck {
             match self.clock_instr()? {
                 ControlFlow::Break(cycles) => {
                     total_cycles += cycles;
-                    self.cycles_remaining -= cycles as f32;
+                    self.cycles_remaining = 0.0;
                     return Ok(ControlFlow::Break(total_cycles));
                 }
                 ControlFlow::Continue(cycles) => {
@@ -180,7 +319,7 @@ impl ControlDeck {
     ///
     /// # Errors
     ///
-    /// This function will return an error if .
+    /// If CPU encounteres an invalid opcode, an error is returned.
     pub fn clock_seconds_inspect<F>(
         &mut self,
         seconds: f32,
@@ -189,12 +328,24 @@ impl ControlDeck {
     where
         F: FnMut(&mut Cpu),
     {
+        if self.start_breakpoint_triggered() {
+            return Ok(ControlFlow::Break(0));
+        }
         self.cycles_remaining += self.clock_rate() * seconds;
         let mut total_cycles = 0;
         while self.cycles_remaining > 0.0 {
+            self.record_history()?;
+            self.trace_instr()?;
+            let (pc, cycle) = (self.cpu.pc(), self.cpu.cycle());
             let cycles = self.cpu.clock_inspect(&mut inspect);
             total_cycles += cycles;
             self.cycles_remaining -= cycles as f32;
+            if self.cpu_corrupted() {
+                return Err(anyhow!("cpu corrupted"));
+            } else if self.watchpoint_triggered(pc, cycle) || self.breakpoint_triggered() {
+                self.cycles_remaining = 0.0;
+                return Ok(ControlFlow::Break(total_cycles));
+            }
         }
         Ok(ControlFlow::Continue(total_cycles))
     }
@@ -221,6 +372,35 @@ impl ControlDeck {
         Ok(ControlFlow::Continue(total_cycles))
     }
 
+    /// Steps the control deck an entire frame with an inspection function, executed on every CPU
+    /// clock.
+    ///
+    /// # Errors
+    ///
+    /// If CPU encounteres an invalid opcode, an error is returned.
+    pub fn clock_frame_inspect<F>(&mut self, mut inspect: F) -> NesResult<ControlFlow<usize, usize>>
+    where
+        F: FnMut(&mut Cpu),
+    {
+        if self.start_breakpoint_triggered() {
+            return Ok(ControlFlow::Break(0));
+        }
+        let mut total_cycles = 0;
+        let frame = self.frame_number();
+        while frame == self.frame_number() {
+            self.record_history()?;
+            self.trace_instr()?;
+            let (pc, cycle) = (self.cpu.pc(), self.cpu.cycle());
+            total_cycles += self.cpu.clock_inspect(&mut inspect);
+            if self.cpu_corrupted() {
+                return Err(anyhow!("cpu corrupted"));
+            } else if self.watchpoint_triggered(pc, cycle) || self.breakpoint_triggered() {
+                return Ok(ControlFlow::Break(total_cycles));
+            }
+        }
+        Ok(ControlFlow::Continue(total_cycles))
+    }
+
     /// Steps the control deck a single scanline.
     ///
     /// # Errors
@@ -243,6 +423,490 @@ impl ControlDeck {
         Ok(ControlFlow::Continue(total_cycles))
     }
 
+    /// Add a breakpoint, returning its index.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.breakpoints.len() - 1
+    }
+
+    /// Remove the breakpoint at the given index.
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index))
+    }
+
+    /// Enable/Disable the breakpoint at the given index.
+    #[inline]
+    pub fn set_breakpoint_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(breakpoint) = self.breakpoints.get_mut(index) {
+            breakpoint.set_enabled(enabled);
+        }
+    }
+
+    /// Remove all breakpoints.
+    #[inline]
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    #[inline]
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Add a breakpoint on a symbol by name, returning its index.
+    ///
+    /// # Errors
+    ///
+    /// If the symbol is not loaded, an error is returned.
+    pub fn add_symbol_breakpoint(
+        &mut self,
+        name: &str,
+        conditions: Vec<Condition>,
+    ) -> NesResult<usize> {
+        let addr = self
+            .symbols
+            .address(name)
+            .ok_or_else(|| anyhow!("unknown symbol: {name}"))?;
+        Ok(self.add_breakpoint(Breakpoint::new(addr, conditions)))
+    }
+
+    /// Returns whether any enabled breakpoint triggers for the next instruction.
+    #[must_use]
+    pub fn breakpoint_hit(&self) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|breakpoint| breakpoint.is_hit(&self.cpu))
+    }
+
+    /// Sets a temporary breakpoint at `addr`, cleared once hit, to run to a given instruction.
+    #[inline]
+    pub fn set_run_to(&mut self, addr: Option<u16>) {
+        self.run_to = addr;
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn run_to(&self) -> Option<u16> {
+        self.run_to
+    }
+
+    /// Returns whether a breakpoint triggers for the next instruction, starting any trace log
+    /// waiting on one.
+    fn breakpoint_triggered(&mut self) -> bool {
+        let run_to_hit = self.run_to == Some(self.cpu.pc());
+        if run_to_hit {
+            self.run_to = None;
+        }
+        let hit = run_to_hit || self.breakpoint_hit();
+        if hit {
+            if let Some(tracer) = &mut self.tracer {
+                tracer.arm();
+            }
+        }
+        hit
+    }
+
+    /// Checks breakpoints against the first instruction after a reset or load, which no previously
+    /// executed instruction has checked.
+    #[inline]
+    fn start_breakpoint_triggered(&mut self) -> bool {
+        std::mem::take(&mut self.fresh_start) && self.breakpoint_triggered()
+    }
+
+    /// Add a memory watchpoint.
+    #[inline]
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.cpu
+            .watchpoints_mut(watchpoint.space())
+            .push(watchpoint);
+    }
+
+    /// Remove the watchpoint at the given index for an address space.
+    pub fn remove_watchpoint(&mut self, space: AddrSpace, index: usize) -> Option<Watchpoint> {
+        let watchpoints = self.cpu.watchpoints_mut(space);
+        (index < watchpoints.len()).then(|| watchpoints.remove(index))
+    }
+
+    /// Enable/Disable the watchpoint at the given index for an address space.
+    #[inline]
+    pub fn set_watchpoint_enabled(&mut self, space: AddrSpace, index: usize, enabled: bool) {
+        if let Some(watchpoint) = self.cpu.watchpoints_mut(space).get_mut(index) {
+            watchpoint.set_enabled(enabled);
+        }
+    }
+
+    /// Remove all watchpoints.
+    #[inline]
+    pub fn clear_watchpoints(&mut self) {
+        self.cpu.watchpoints_mut(AddrSpace::Cpu).clear();
+        self.cpu.watchpoints_mut(AddrSpace::Ppu).clear();
+    }
+
+    #[inline]
+    pub fn watchpoints(&self, space: AddrSpace) -> &[Watchpoint] {
+        self.cpu.watchpoints(space)
+    }
+
+    /// Returns the watchpoint hit which halted execution, if the last instruction triggered one.
+    #[inline]
+    #[must_use]
+    pub const fn watchpoint_hit(&self) -> Option<&WatchpointHit> {
+        self.watchpoint_hit.as_ref()
+    }
+
+    /// Records any watchpoint hit for the instruction at `pc`, returning whether one occurred.
+    fn watchpoint_triggered(&mut self, pc: u16, cycle: usize) -> bool {
+        self.watchpoint_hit = self.cpu.take_watchpoint_hit().map(|mut hit| {
+            hit.pc = pc;
+            hit.cycle = cycle;
+            hit
+        });
+        self.watchpoint_hit.is_some()
+    }
+
+    /// Load debug symbols for the current ROM, replacing any existing symbols.
+    #[inline]
+    pub fn load_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    #[inline]
+    pub const fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// Returns the label for a CPU address given the current mapper banking.
+    #[inline]
+    #[must_use]
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.symbols.label(self.cpu.mapper(), addr)
+    }
+
+    /// Disassembles the instruction at `pc` using any loaded symbols, advancing `pc` to the next
+    /// instruction.
+    pub fn disassemble(&mut self, pc: &mut u16) -> &str {
+        self.cpu.disassemble_symbols(pc, Some(&self.symbols));
+        self.cpu.disasm()
+    }
+
+    /// Start logging executed instructions, replacing any existing trace log.
+    #[inline]
+    pub fn start_trace(&mut self, tracer: TraceLogger) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stop logging executed instructions, returning the flushed trace log if one was active.
+    ///
+    /// # Errors
+    ///
+    /// If flushing the trace log fails, an error is returned.
+    pub fn stop_trace(&mut self) -> NesResult<Option<TraceLogger>> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.flush()?;
+        }
+        Ok(self.tracer.take())
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn tracer(&self) -> Option<&TraceLogger> {
+        self.tracer.as_ref()
+    }
+
+    /// Starts logging which PRG-ROM and CHR-ROM bytes are used as code or data, continuing from
+    /// a previously saved log if provided.
+    ///
+    /// # Errors
+    ///
+    /// If the provided log doesn't match the loaded ROM sizes, an error is returned.
+    #[inline]
+    pub fn start_cdl(&mut self, cdl: Option<CodeDataLog>) -> NesResult<()> {
+        self.cpu.start_cdl(cdl)
+    }
+
+    /// Returns a snapshot of the current Code/Data log, if logging.
+    #[inline]
+    #[must_use]
+    pub fn code_data_log(&self) -> Option<CodeDataLog> {
+        self.cpu.code_data_log()
+    }
+
+    /// Stops logging and returns the Code/Data log, if logging.
+    #[inline]
+    pub fn stop_cdl(&mut self) -> Option<CodeDataLog> {
+        self.cpu.stop_cdl()
+    }
+
+    /// Starts profiling subroutine calls, discarding any previous profile.
+    #[inline]
+    pub fn start_profiler(&mut self) {
+        self.cpu.start_profiler();
+    }
+
+    /// Returns the current profile, if profiling.
+    #[inline]
+    pub const fn profiler(&self) -> Option<&Profiler> {
+        self.cpu.profiler()
+    }
+
+    /// Stops profiling and returns the profile, if profiling.
+    #[inline]
+    pub fn stop_profiler(&mut self) -> Option<Profiler> {
+        self.cpu.stop_profiler()
+    }
+
+    /// Starts recording PPU register writes and interrupts, discarding any previous recording.
+    #[inline]
+    pub fn start_event_log(&mut self) {
+        self.cpu.ppu_mut().start_event_log();
+    }
+
+    /// Returns the recorded PPU events, if recording.
+    #[inline]
+    #[must_use]
+    pub const fn event_log(&self) -> Option<&EventLog> {
+        self.cpu.ppu().event_log()
+    }
+
+    /// Stops recording and returns the recorded PPU events, if recording.
+    #[inline]
+    pub fn stop_event_log(&mut self) -> Option<EventLog> {
+        self.cpu.ppu_mut().stop_event_log()
+    }
+
+    /// Starts recording the frame each byte of memory was last read or written on, for
+    /// highlighting recent accesses in a memory viewer.
+    #[inline]
+    pub fn start_access_log(&mut self) {
+        self.cpu.start_access_log();
+    }
+
+    #[inline]
+    pub fn stop_access_log(&mut self) {
+        self.cpu.stop_access_log();
+    }
+
+    /// Returns the frames a byte in a memory region was last read and written on, if the access
+    /// log is running.
+    #[inline]
+    #[must_use]
+    pub fn last_access(&self, region: MemRegion, index: usize) -> Option<MemAccess> {
+        self.cpu.last_access(region, index)
+    }
+
+    /// Size of a memory region, 0 if the loaded cartridge doesn't have it.
+    #[inline]
+    #[must_use]
+    pub fn mem_size(&self, region: MemRegion) -> usize {
+        self.cpu.mem_size(region)
+    }
+
+    /// Reads a byte from a memory region without side effects.
+    #[inline]
+    #[must_use]
+    pub fn peek_mem(&self, region: MemRegion, index: usize) -> u8 {
+        self.cpu.peek_mem(region, index)
+    }
+
+    /// Writes a byte to a memory region without side effects. Writes to CPU or PPU addresses
+    /// which aren't backed by memory are ignored.
+    #[inline]
+    pub fn poke_mem(&mut self, region: MemRegion, index: usize, val: u8) {
+        self.cpu.poke_mem(region, index, val);
+    }
+
+    /// Returns the index of the next match of `pattern` in a memory region at or after `start`,
+    /// wrapping around to the beginning.
+    #[must_use]
+    pub fn find_mem(&self, region: MemRegion, pattern: &[u8], start: usize) -> Option<usize> {
+        let peek = |index| self.peek_mem(region, index);
+        find_pattern_with(self.mem_size(region), peek, pattern, start)
+    }
+
+    /// Starts recording execution history for stepping backwards, discarding any previous
+    /// history.
+    #[inline]
+    pub fn start_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    /// Returns the recorded execution history, if recording.
+    #[inline]
+    #[must_use]
+    pub const fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Stops recording and returns the execution history, if recording.
+    #[inline]
+    pub fn stop_history(&mut self) -> Option<History> {
+        self.history.take()
+    }
+
+    /// Steps back to the previous instruction.
+    ///
+    /// # Errors
+    ///
+    /// If history isn't being recorded or doesn't reach back far enough, an error is returned.
+    pub fn step_back(&mut self) -> NesResult<()> {
+        self.rewind_history(|deck, history| {
+            let end_cycle = deck.cpu.cycle();
+            let index = history
+                .snapshot_before(end_cycle)
+                .ok_or_else(|| anyhow!("no earlier history"))?;
+            let mut target = end_cycle;
+            deck.replay(history, index, end_cycle, |deck| {
+                target = deck.cpu.cycle();
+                ControlFlow::Continue(())
+            })?;
+            deck.replay(history, index, target, |_| ControlFlow::Continue(()))
+        })
+    }
+
+    /// Steps back to the first instruction of the previous frame.
+    ///
+    /// # Errors
+    ///
+    /// If history isn't being recorded or doesn't reach back far enough, an error is returned.
+    pub fn step_back_frame(&mut self) -> NesResult<()> {
+        self.rewind_history(|deck, history| {
+            let frame = deck.frame_number().wrapping_sub(1);
+            let index = history
+                .snapshot_before_frame(frame)
+                .ok_or_else(|| anyhow!("no earlier history"))?;
+            deck.replay(history, index, deck.cpu.cycle(), |deck| {
+                if deck.frame_number() == frame {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+        })
+    }
+
+    /// Runs backwards to the last instruction which hit a breakpoint. Returns `false` if no
+    /// breakpoint was hit, stopping at the start of history.
+    ///
+    /// # Errors
+    ///
+    /// If history isn't being recorded or doesn't reach back far enough, an error is returned.
+    pub fn run_back(&mut self) -> NesResult<bool> {
+        self.rewind_history(|deck, history| {
+            let mut end_cycle = deck.cpu.cycle();
+            let mut index = history
+                .snapshot_before(end_cycle)
+                .ok_or_else(|| anyhow!("no earlier history"))?;
+            loop {
+                let mut hit = None;
+                deck.replay(history, index, end_cycle, |deck| {
+                    if deck.breakpoint_hit() {
+                        hit = Some(deck.cpu.cycle());
+                    }
+                    ControlFlow::Continue(())
+                })?;
+                if let Some(cycle) = hit {
+                    deck.replay(history, index, cycle, |_| ControlFlow::Continue(()))?;
+                    return Ok(true);
+                }
+                if index == 0 {
+                    deck.cpu.restore(history.snapshot(0)?);
+                    return Ok(false);
+                }
+                end_cycle = history.snapshot_cycle(index);
+                index -= 1;
+            }
+        })
+    }
+
+    /// Runs a rewind with the execution history, discarding any history after the resulting
+    /// state.
+    fn rewind_history<T, F>(&mut self, rewind: F) -> NesResult<T>
+    where
+        F: FnOnce(&mut Self, &History) -> NesResult<T>,
+    {
+        let mut history = self
+            .history
+            .take()
+            .ok_or_else(|| anyhow!("execution history is not being recorded"))?;
+        // Suspend script memory hooks, the profiler, event log and access log so re-executed
+        // instructions don't fire hooks or get recorded a second time
+        let mem_hooks = std::mem::take(self.cpu.mem_hooks_mut());
+        let profiler = self.cpu.stop_profiler();
+        let event_log = self.cpu.ppu_mut().stop_event_log();
+        let access_log = self.cpu.suspend_access_log();
+        let result = rewind(self, &history);
+        history.truncate(&self.cpu);
+        self.history = Some(history);
+        *self.cpu.mem_hooks_mut() = mem_hooks;
+        self.cpu.resume_profiler(profiler);
+        self.cpu.ppu_mut().resume_event_log(event_log);
+        self.cpu.resume_access_log(access_log);
+        // Drop watchpoint hits latched while replaying so the next instruction doesn't break
+        while self.cpu.take_watchpoint_hit().is_some() {}
+        self.watchpoint_hit = None;
+        self.cpu.clear_audio_samples();
+        result
+    }
+
+    /// Restores a snapshot and re-executes instructions before `end_cycle` with the recorded
+    /// input, calling `visit` before each one. Stops early if `visit` returns
+    /// `ControlFlow::Break`.
+    fn replay<F>(
+        &mut self,
+        history: &History,
+        index: usize,
+        end_cycle: usize,
+        mut visit: F,
+    ) -> NesResult<()>
+    where
+        F: FnMut(&Self) -> ControlFlow<()>,
+    {
+        self.cpu.restore(history.snapshot(index)?);
+        while self.cpu.cycle() < end_cycle {
+            if let Some(input) = history.input_at(self.cpu.cycle()) {
+                self.cpu.set_input_state(input);
+            }
+            if visit(self).is_break() {
+                break;
+            }
+            let _ = self.cpu.clock();
+            if self.cpu_corrupted() {
+                return Err(anyhow!("cpu corrupted"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Records input and snapshots for the next instruction if recording history.
+    #[inline]
+    fn record_history(&mut self) -> NesResult<()> {
+        self.discard_loaded_history();
+        match &mut self.history {
+            Some(history) => history.record(&self.cpu),
+            None => Ok(()),
+        }
+    }
+
+    /// Discards execution history if a save state was loaded, e.g. by a script, since it
+    /// leads up to a different state.
+    #[inline]
+    fn discard_loaded_history(&mut self) {
+        if self.cpu.take_state_loaded() {
+            if let Some(history) = &mut self.history {
+                history.clear();
+            }
+        }
+    }
+
+    /// Logs the next instruction to be executed if tracing is active.
+    #[inline]
+    fn trace_instr(&mut self) -> NesResult<()> {
+        match &mut self.tracer {
+            Some(tracer) => tracer.log(&self.cpu, &self.symbols),
+            None => Ok(()),
+        }
+    }
+
     /// Returns whether the CPU is corrupted or not.
     #[inline]
     #[must_use]
@@ -336,6 +1000,38 @@ impl ControlDeck {
         self.video.set_filter(filter);
     }
 
+    /// Set the palette used by the Pixellate filter for a given region.
+    #[inline]
+    pub fn set_palette(&mut self, region: NesRegion, palette: Palette) {
+        self.video.set_palette(region, palette);
+    }
+
+    /// Whether every sprite in range is drawn instead of only the first 8 on each scanline.
+    #[inline]
+    #[must_use]
+    pub const fn unlimited_sprites(&self) -> bool {
+        self.cpu.ppu().unlimited_sprites()
+    }
+
+    /// Remove the 8 sprites per scanline limit to reduce flicker, without changing how games
+    /// behave.
+    #[inline]
+    pub fn set_unlimited_sprites(&mut self, enabled: bool) {
+        self.cpu.ppu_mut().set_unlimited_sprites(enabled);
+    }
+
+    /// Get the overscan cropped by `ControlDeck::cropped_frame_buffer`.
+    #[inline]
+    pub const fn overscan(&self) -> Overscan {
+        self.video.overscan()
+    }
+
+    /// Set the overscan cropped by `ControlDeck::cropped_frame_buffer`.
+    #[inline]
+    pub fn set_overscan(&mut self, overscan: Overscan) {
+        self.video.set_overscan(overscan);
+    }
+
     /// Enable Zapper gun.
     #[inline]
     pub fn connect_zapper(&mut self, enabled: bool) {
@@ -357,6 +1053,34 @@ impl ControlDeck {
         self.cpu.remove_genie_code(genie_code);
     }
 
+    /// Add a cheat, replacing any existing cheat for the same address.
+    #[inline]
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cpu.add_cheat(cheat);
+    }
+
+    #[inline]
+    pub fn remove_cheat(&mut self, code: &str) {
+        self.cpu.remove_cheat(code);
+    }
+
+    #[inline]
+    pub fn clear_cheats(&mut self) {
+        self.cpu.clear_cheats();
+    }
+
+    /// Returns all cheats, ordered by address.
+    #[inline]
+    #[must_use]
+    pub fn cheats(&self) -> Vec<&Cheat> {
+        self.cpu.cheats()
+    }
+
+    #[inline]
+    pub fn cheat_mut(&mut self, code: &str) -> Option<&mut Cheat> {
+        self.cpu.cheat_mut(code)
+    }
+
     /// Returns whether a given API audio channel is enabled.
     #[inline]
     #[must_use]
@@ -397,6 +1121,7 @@ impl Regional for ControlDeck {
     fn set_region(&mut self, region: NesRegion) {
         self.region = region;
         self.cpu.set_region(region);
+        self.video.set_region(region);
     }
 }
 
@@ -404,6 +1129,10 @@ impl Reset for ControlDeck {
     /// Resets the console.
     fn reset(&mut self, kind: Kind) {
         self.cpu.reset(kind);
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        self.fresh_start = true;
         self.running = true;
     }
 }
@@ -4,10 +4,20 @@
 
 use crate::{
     apu::{Apu, Channel},
+    audio::{stems::Stem, ChannelMix},
     bus::CpuBus,
     cart::Cart,
+    cheat::Cheat,
     common::{Clock, Kind, NesRegion, Regional, Reset},
-    input::{FourPlayer, Joypad, Slot, Zapper},
+    debugger::{
+        cdl::{CodeDataLog, PrgFlags},
+        events::PpuEventKind,
+        memory::{AccessLog, MemAccess, MemRegion},
+        profiler::Profiler,
+        symbols::{SymbolAddr, Symbols},
+        AddrSpace, Watchpoint, WatchpointHit,
+    },
+    input::{FourPlayer, InputState, Joypad, Slot, Zapper},
     mapper::Mapper,
     mem::{Access, Mem},
     ppu::Ppu,
@@ -109,6 +119,10 @@ pub struct Cpu {
     dummy_read: bool,
     cycle_accurate: bool,
     disasm: String,
+    #[serde(skip)]
+    profiler: Option<Profiler>,
+    #[serde(skip)]
+    state_loaded: bool,
 }
 
 impl Cpu {
@@ -162,6 +176,8 @@ impl Cpu {
             dummy_read: false,
             cycle_accurate: true,
             disasm: String::with_capacity(100),
+            profiler: None,
+            state_loaded: false,
         };
         cpu.set_region(cpu.region);
         cpu
@@ -224,6 +240,53 @@ impl Cpu {
         self.status
     }
 
+    #[inline]
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    #[inline]
+    pub fn set_sp(&mut self, sp: u8) {
+        self.sp = sp;
+    }
+
+    #[inline]
+    pub fn set_a(&mut self, a: u8) {
+        self.acc = a;
+    }
+
+    #[inline]
+    pub fn set_x(&mut self, x: u8) {
+        self.x = x;
+    }
+
+    #[inline]
+    pub fn set_y(&mut self, y: u8) {
+        self.y = y;
+    }
+
+    #[inline]
+    pub fn set_status(&mut self, status: Status) {
+        self.status = status;
+    }
+
+    /// Writes a value to the bus without clocking the CPU, e.g. from a debugger.
+    #[inline]
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.bus.write(addr, val, Access::Dummy);
+    }
+
+    #[inline]
+    pub const fn irqs_pending(&self) -> Irq {
+        self.irq
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn nmi_pending(&self) -> bool {
+        self.nmi
+    }
+
     #[inline]
     #[must_use]
     pub const fn corrupted(&self) -> bool {
@@ -271,6 +334,163 @@ impl Cpu {
         self.bus.joypad(slot)
     }
 
+    #[inline]
+    pub fn watchpoints(&self, space: AddrSpace) -> &[Watchpoint] {
+        self.bus.watchpoints(space)
+    }
+
+    #[inline]
+    pub fn watchpoints_mut(&mut self, space: AddrSpace) -> &mut Vec<Watchpoint> {
+        self.bus.watchpoints_mut(space)
+    }
+
+    #[inline]
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.bus.take_watchpoint_hit()
+    }
+
+    #[inline]
+    pub fn mem_hooks_mut(&mut self) -> &mut Vec<Watchpoint> {
+        self.bus.mem_hooks_mut()
+    }
+
+    #[inline]
+    pub fn take_mem_hook_hits(&mut self) -> Vec<WatchpointHit> {
+        self.bus.take_mem_hook_hits()
+    }
+
+    /// Starts the Code/Data Logger, continuing from an existing log if provided.
+    ///
+    /// # Errors
+    ///
+    /// If the provided log doesn't match the loaded ROM sizes, an error is returned.
+    #[inline]
+    pub fn start_cdl(&mut self, cdl: Option<CodeDataLog>) -> NesResult<()> {
+        self.bus.start_cdl(cdl)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn code_data_log(&self) -> Option<CodeDataLog> {
+        self.bus.code_data_log()
+    }
+
+    #[inline]
+    pub fn stop_cdl(&mut self) -> Option<CodeDataLog> {
+        self.bus.stop_cdl()
+    }
+
+    /// Moves the code/data log from another CPU, e.g. when loading a save state.
+    #[inline]
+    pub(crate) fn take_cdl(&mut self, other: &mut Self) {
+        self.bus.take_cdl(&mut other.bus);
+    }
+
+    /// Starts profiling subroutine calls, discarding any previous profile.
+    #[inline]
+    pub fn start_profiler(&mut self) {
+        let mut profiler = Profiler::new();
+        profiler.clear_stack(self.cycle);
+        self.profiler = Some(profiler);
+    }
+
+    #[inline]
+    pub const fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    #[inline]
+    pub fn stop_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Starts recording the frame each byte of memory was last read or written on.
+    #[inline]
+    pub fn start_access_log(&mut self) {
+        self.bus.start_access_log();
+    }
+
+    #[inline]
+    pub fn stop_access_log(&mut self) {
+        self.bus.stop_access_log();
+    }
+
+    /// Stops the access log, returning it to be resumed with `resume_access_log`.
+    #[inline]
+    pub(crate) fn suspend_access_log(&mut self) -> (Option<AccessLog>, Option<AccessLog>) {
+        self.bus.suspend_access_log()
+    }
+
+    #[inline]
+    pub(crate) fn resume_access_log(&mut self, logs: (Option<AccessLog>, Option<AccessLog>)) {
+        self.bus.resume_access_log(logs);
+    }
+
+    /// Moves the access log from another CPU, e.g. when loading a save state.
+    #[inline]
+    pub(crate) fn take_access_log(&mut self, other: &mut Self) {
+        self.bus.take_access_log(&mut other.bus);
+    }
+
+    /// Moves audio synthesis state from another CPU, e.g. when loading a save state.
+    #[inline]
+    pub(crate) fn take_audio(&mut self, other: &mut Self) {
+        self.bus.take_audio(&mut other.bus);
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn last_access(&self, region: MemRegion, index: usize) -> Option<MemAccess> {
+        self.bus.last_access(region, index)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn mem_size(&self, region: MemRegion) -> usize {
+        self.bus.mem_size(region)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn peek_mem(&self, region: MemRegion, index: usize) -> u8 {
+        self.bus.peek_mem(region, index)
+    }
+
+    #[inline]
+    pub fn poke_mem(&mut self, region: MemRegion, index: usize, val: u8) {
+        self.bus.poke_mem(region, index, val);
+    }
+
+    /// Moves the profiler from another CPU, e.g. when loading a save state.
+    #[inline]
+    pub(crate) fn take_profiler(&mut self, other: &mut Self) {
+        self.resume_profiler(other.profiler.take());
+    }
+
+    /// Resumes a profiler returned by `stop_profiler`, counting from the current cycle.
+    #[inline]
+    pub(crate) fn resume_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.clear_stack(self.cycle);
+        }
+    }
+
+    /// Records a call to the routine at the program counter. `return_sp` is the stack pointer
+    /// before the return address was pushed.
+    fn profile_call(&mut self, return_sp: u8) {
+        if let Some(profiler) = &mut self.profiler {
+            let routine = SymbolAddr::from_cpu(self.bus.mapper(), self.pc);
+            profiler.call(routine, self.pc, return_sp, self.cycle);
+        }
+    }
+
+    fn profile_return(&mut self) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.ret(self.sp, self.cycle);
+        }
+    }
+
     #[inline]
     pub fn joypad_mut(&mut self, slot: Slot) -> &mut Joypad {
         self.bus.joypad_mut(slot)
@@ -291,6 +511,16 @@ impl Cpu {
         self.bus.zapper_mut()
     }
 
+    #[inline]
+    pub fn input_state(&self) -> InputState {
+        self.bus.input_state()
+    }
+
+    #[inline]
+    pub fn set_input_state(&mut self, state: InputState) {
+        self.bus.set_input_state(state);
+    }
+
     #[inline]
     pub fn load_cart(&mut self, cart: Cart) {
         self.bus.load_cart(cart);
@@ -334,6 +564,70 @@ impl Cpu {
         self.bus.remove_genie_code(genie_code);
     }
 
+    #[inline]
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.bus.add_cheat(cheat);
+    }
+
+    #[inline]
+    pub fn remove_cheat(&mut self, code: &str) {
+        self.bus.remove_cheat(code);
+    }
+
+    #[inline]
+    pub fn clear_cheats(&mut self) {
+        self.bus.clear_cheats();
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn cheats(&self) -> Vec<&Cheat> {
+        self.bus.cheats()
+    }
+
+    #[inline]
+    pub fn cheat_mut(&mut self, code: &str) -> Option<&mut Cheat> {
+        self.bus.cheat_mut(code)
+    }
+
+    #[inline]
+    pub(crate) fn take_cheats(&mut self, other: &mut Self) {
+        self.bus.take_cheats(&mut other.bus);
+    }
+
+    /// Replaces the CPU with a restored state, keeping any memory hooks registered by a running
+    /// script, watchpoints, cheats, the profiler, event log, access log, code/data log and audio
+    /// synthesis.
+    pub(crate) fn restore(&mut self, mut cpu: Self) {
+        std::mem::swap(cpu.mem_hooks_mut(), self.mem_hooks_mut());
+        for space in [AddrSpace::Cpu, AddrSpace::Ppu] {
+            std::mem::swap(cpu.watchpoints_mut(space), self.watchpoints_mut(space));
+        }
+        cpu.take_cheats(self);
+        cpu.take_profiler(self);
+        cpu.ppu_mut().take_event_log(self.ppu_mut());
+        cpu.take_access_log(self);
+        cpu.take_cdl(self);
+        cpu.ppu_mut()
+            .set_unlimited_sprites(self.ppu().unlimited_sprites());
+        cpu.take_audio(self);
+        *self = cpu;
+    }
+
+    /// Loads a save state the same way as `restore`, flagging it so any execution history
+    /// recorded before the load is discarded.
+    #[inline]
+    pub fn load_state(&mut self, cpu: Self) {
+        self.restore(cpu);
+        self.state_loaded = true;
+    }
+
+    /// Returns whether a save state was loaded since the last call.
+    #[inline]
+    pub(crate) fn take_state_loaded(&mut self) -> bool {
+        std::mem::take(&mut self.state_loaded)
+    }
+
     #[inline]
     #[must_use]
     pub const fn ppu_cycle(&self) -> u32 {
@@ -380,6 +674,67 @@ impl Cpu {
         self.bus.clear_audio_samples();
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn sample_rate(&self) -> f32 {
+        self.bus.sample_rate()
+    }
+
+    #[inline]
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.bus.set_sample_rate(sample_rate);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn stems_enabled(&self) -> bool {
+        self.bus.stems_enabled()
+    }
+
+    #[inline]
+    pub fn set_stems_enabled(&mut self, enabled: bool) {
+        self.bus.set_stems_enabled(enabled);
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn stem_samples(&self) -> &[f32] {
+        self.bus.stem_samples()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn audio_stems(&self) -> Vec<Stem> {
+        self.bus.audio_stems()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn audio_channels(&self) -> usize {
+        self.bus.audio_channels()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn stereo(&self) -> bool {
+        self.bus.stereo()
+    }
+
+    #[inline]
+    pub fn set_stereo(&mut self, stereo: bool) {
+        self.bus.set_stereo(stereo);
+    }
+
+    #[inline]
+    pub const fn channel_mix(&self, stem: Stem) -> ChannelMix {
+        self.bus.channel_mix(stem)
+    }
+
+    #[inline]
+    pub fn set_channel_mix(&mut self, stem: Stem, mix: ChannelMix) {
+        self.bus.set_channel_mix(stem, mix);
+    }
+
     #[inline]
     #[must_use]
     pub const fn four_player(&self) -> FourPlayer {
@@ -425,12 +780,18 @@ impl Cpu {
 
             self.pc = self.read_u16(Self::NMI_VECTOR);
             log::trace!("NMI: {}", self.cycle);
+            self.bus
+                .ppu_mut()
+                .log_event(PpuEventKind::Nmi, self.pc, 0x00);
         } else {
             self.push(status);
             self.status.set(Status::I, true);
 
             self.pc = self.read_u16(Self::IRQ_VECTOR);
             log::trace!("IRQ: {}", self.cycle);
+            self.bus
+                .ppu_mut()
+                .log_event(PpuEventKind::Irq, self.pc, 0x00);
         }
     }
 
@@ -526,8 +887,10 @@ impl Cpu {
                 if self.dmc_dma && !self.halt && !self.dummy_read {
                     // DMC DMA ready to read a byte (halt and dummy read done before)
                     self.process_dma_cycle();
-                    read_val = self.bus.read(self.bus.dmc_dma_addr(), Access::Dummy);
+                    let dmc_addr = self.bus.dmc_dma_addr();
+                    read_val = self.bus.read(dmc_addr, Access::Dummy);
                     self.end_cycle(Cycle::Read);
+                    self.bus.log_prg(dmc_addr, PrgFlags::PCM);
                     self.bus.load_dmc_buffer(read_val);
                     self.dmc_dma = false;
                 } else if self.bus.oam_dma() {
@@ -644,6 +1007,7 @@ impl Cpu {
         let mode = self.instr.addr_mode();
         self.fetched_data = match mode {
             IMP | ACC => self.acc,
+            IMM => self.read(self.abs_addr, Access::Execute), // Operand is part of the instruction
             ABX | ABY | IDY => {
                 // Read instructions may have crossed a page boundary and need to be re-read
                 match self.instr.op() {
@@ -687,7 +1051,7 @@ impl Cpu {
     #[must_use]
     #[inline]
     fn read_instr(&mut self) -> u8 {
-        let val = self.read(self.pc, Access::Read);
+        let val = self.read(self.pc, Access::Execute);
         self.pc = self.pc.wrapping_add(1);
         val
     }
@@ -738,6 +1102,21 @@ impl Cpu {
     }
 
     pub fn disassemble(&mut self, pc: &mut u16) {
+        self.disassemble_symbols(pc, None);
+    }
+
+    /// Formats an operand address, replacing it with a label if one exists.
+    fn operand_label(&self, symbols: Option<&Symbols>, addr: u16, zero_page: bool) -> String {
+        match symbols.and_then(|symbols| symbols.label(self.mapper(), addr)) {
+            Some(label) => label.to_string(),
+            None if zero_page => format!("${addr:02X}"),
+            None => format!("${addr:04X}"),
+        }
+    }
+
+    /// Disassembles the instruction at `pc`, replacing operand addresses with labels from
+    /// `symbols`.
+    pub fn disassemble_symbols(&mut self, pc: &mut u16, symbols: Option<&Symbols>) {
         let opcode = self.peek(*pc, Access::Dummy);
         let instr = Cpu::INSTRUCTIONS[opcode as usize];
         let mut bytes = Vec::with_capacity(3);
@@ -755,21 +1134,30 @@ impl Cpu {
                 bytes.push(self.peek(addr, Access::Dummy));
                 addr = addr.wrapping_add(1);
                 let val = self.peek(bytes[1].into(), Access::Dummy);
-                format!(" ${:02X} = #${val:02X}", bytes[1])
+                format!(
+                    " {} = #${val:02X}",
+                    self.operand_label(symbols, bytes[1].into(), true)
+                )
             }
             ZPX => {
                 bytes.push(self.peek(addr, Access::Dummy));
                 addr = addr.wrapping_add(1);
                 let x_offset = bytes[1].wrapping_add(self.x);
                 let val = self.peek(x_offset.into(), Access::Dummy);
-                format!(" ${:02X},X @ ${x_offset:02X} = #${val:02X}", bytes[1])
+                format!(
+                    " {},X @ ${x_offset:02X} = #${val:02X}",
+                    self.operand_label(symbols, bytes[1].into(), true)
+                )
             }
             ZPY => {
                 bytes.push(self.peek(addr, Access::Dummy));
                 addr = addr.wrapping_add(1);
                 let y_offset = bytes[1].wrapping_add(self.y);
                 let val = self.peek(y_offset.into(), Access::Dummy);
-                format!(" ${:02X},Y @ ${y_offset:02X} = #${val:02X}", bytes[1])
+                format!(
+                    " {},Y @ ${y_offset:02X} = #${val:02X}",
+                    self.operand_label(symbols, bytes[1].into(), true)
+                )
             }
             ABS => {
                 bytes.push(self.peek(addr, Access::Dummy));
@@ -777,10 +1165,13 @@ impl Cpu {
                 let abs_addr = self.peek_u16(addr);
                 addr = addr.wrapping_add(2);
                 if instr.op() == JMP || instr.op() == JSR {
-                    format!(" ${abs_addr:04X}")
+                    format!(" {}", self.operand_label(symbols, abs_addr, false))
                 } else {
                     let val = self.peek(abs_addr, Access::Dummy);
-                    format!(" ${abs_addr:04X} = #${val:02X}")
+                    format!(
+                        " {} = #${val:02X}",
+                        self.operand_label(symbols, abs_addr, false)
+                    )
                 }
             }
             ABX => {
@@ -790,7 +1181,10 @@ impl Cpu {
                 addr = addr.wrapping_add(2);
                 let x_offset = abs_addr.wrapping_add(self.x.into());
                 let val = self.peek(x_offset, Access::Dummy);
-                format!(" ${abs_addr:04X},X @ ${x_offset:04X} = #${val:02X}")
+                format!(
+                    " {},X @ ${x_offset:04X} = #${val:02X}",
+                    self.operand_label(symbols, abs_addr, false)
+                )
             }
             ABY => {
                 bytes.push(self.peek(addr, Access::Dummy));
@@ -799,7 +1193,10 @@ impl Cpu {
                 addr = addr.wrapping_add(2);
                 let y_offset = abs_addr.wrapping_add(self.y.into());
                 let val = self.peek(y_offset, Access::Dummy);
-                format!(" ${abs_addr:04X},Y @ ${y_offset:04X} = #${val:02X}")
+                format!(
+                    " {},Y @ ${y_offset:04X} = #${val:02X}",
+                    self.operand_label(symbols, abs_addr, false)
+                )
             }
             IND => {
                 bytes.push(self.peek(addr, Access::Dummy));
@@ -813,7 +1210,10 @@ impl Cpu {
                     self.peek(abs_addr + 1, Access::Dummy)
                 };
                 let val = u16::from_le_bytes([lo, hi]);
-                format!(" (${abs_addr:04X}) = ${val:04X}")
+                format!(
+                    " ({}) = ${val:04X}",
+                    self.operand_label(symbols, abs_addr, false)
+                )
             }
             IDX => {
                 bytes.push(self.peek(addr, Access::Dummy));
@@ -821,7 +1221,10 @@ impl Cpu {
                 let x_offset = bytes[1].wrapping_add(self.x);
                 let abs_addr = self.peek_zp_u16(x_offset);
                 let val = self.peek(abs_addr, Access::Dummy);
-                format!(" (${:02X},X) @ ${abs_addr:04X} = #${val:02X}", bytes[1])
+                format!(
+                    " ({},X) @ ${abs_addr:04X} = #${val:02X}",
+                    self.operand_label(symbols, bytes[1].into(), true)
+                )
             }
             IDY => {
                 bytes.push(self.peek(addr, Access::Dummy));
@@ -829,7 +1232,10 @@ impl Cpu {
                 let abs_addr = self.peek_zp_u16(bytes[1]);
                 let y_offset = abs_addr.wrapping_add(self.y.into());
                 let val = self.peek(y_offset, Access::Dummy);
-                format!(" (${:02X}),Y @ ${y_offset:04X} = #${val:02X}", bytes[1])
+                format!(
+                    " ({}),Y @ ${y_offset:04X} = #${val:02X}",
+                    self.operand_label(symbols, bytes[1].into(), true)
+                )
             }
             REL => {
                 bytes.push(self.peek(addr, Access::Dummy));
@@ -839,7 +1245,10 @@ impl Cpu {
                     // If address is negative, extend sign to 16-bits
                     rel_addr |= 0xFF00;
                 }
-                format!(" ${:04X}", addr.wrapping_add(rel_addr))
+                format!(
+                    " {}",
+                    self.operand_label(symbols, addr.wrapping_add(rel_addr), false)
+                )
             }
             ACC | IMP => "".to_string(),
         };
@@ -899,15 +1308,22 @@ impl Cpu {
     where
         F: FnMut(&mut Cpu),
     {
-        let start_cycle = self.cycle;
-
         if log::log_enabled!(log::Level::Trace) {
             self.trace_instr();
         }
         inspect(self);
+        // Inspect may load a different state
+        let start_cycle = self.cycle;
+        self.bus.apply_cheats();
 
+        // Instructions reached through `JMP ($nnnn)` are logged as indirect code
+        let indirect_jump = self.instr.op() == JMP && self.instr.addr_mode() == IND;
+        let (pc, return_sp) = (self.pc, self.sp);
         let opcode = self.read_instr(); // Cycle 1 of instruction
         self.instr = Cpu::INSTRUCTIONS[opcode as usize];
+        if indirect_jump {
+            self.bus.log_prg(pc, PrgFlags::INDIRECT_CODE);
+        }
 
         match self.instr.addr_mode() {
             IMM => self.imm(),
@@ -1005,8 +1421,18 @@ impl Cpu {
             XXX => self.xxx(), // Unimplemented opcode
         }
 
+        if self.profiler.is_some() {
+            match self.instr.op() {
+                JSR | BRK => self.profile_call(return_sp),
+                RTS | RTI => self.profile_return(),
+                _ => (),
+            }
+        }
+
         if self.prev_run_irq || self.prev_nmi {
+            let return_sp = self.sp;
             self.irq();
+            self.profile_call(return_sp);
         }
 
         if !self.cycle_accurate {
@@ -1038,6 +1464,21 @@ impl Mem for Cpu {
         self.start_cycle(Cycle::Read);
         let val = self.bus.read(addr, access);
         self.end_cycle(Cycle::Read);
+        if self.bus.cdl_enabled() {
+            let flags = match access {
+                Access::Execute => PrgFlags::CODE,
+                Access::Read
+                    if matches!(self.instr.addr_mode(), IDX | IDY) && addr == self.abs_addr =>
+                {
+                    PrgFlags::DATA | PrgFlags::INDIRECT_DATA
+                }
+                Access::Read => PrgFlags::DATA,
+                _ => PrgFlags::empty(),
+            };
+            if !flags.is_empty() {
+                self.bus.log_prg(addr, flags);
+            }
+        }
         val
     }
 
@@ -65,6 +65,16 @@ impl Instr {
     pub const fn cycles(&self) -> usize {
         self.3
     }
+    /// Number of bytes taken by the opcode and its operand.
+    #[inline]
+    #[must_use]
+    pub const fn size(&self) -> u16 {
+        match self.1 {
+            ACC | IMP => 1,
+            IMM | ZP0 | ZPX | ZPY | IDX | IDY | REL => 2,
+            ABS | ABX | ABY | IND => 3,
+        }
+    }
 }
 
 /// CPU Addressing Modes
@@ -102,7 +112,7 @@ impl Cpu {
     //  2    PC     R  read next instruction byte (and throw it away)
     #[inline]
     pub(super) fn acc(&mut self) {
-        let _ = self.read(self.pc, Access::Read); // Cycle 2, Read and throw away
+        let _ = self.read(self.pc, Access::Dummy); // Cycle 2, Read and throw away
     }
 
     /// Implied
@@ -113,7 +123,7 @@ impl Cpu {
     //    2    PC     R  read next instruction byte (and throw it away)
     #[inline]
     pub(super) fn imp(&mut self) {
-        let _ = self.read(self.pc, Access::Read); // Cycle 2, Read and throw away
+        let _ = self.read(self.pc, Access::Dummy); // Cycle 2, Read and throw away
     }
 
     /// Immediate
@@ -854,7 +864,7 @@ impl Cpu {
             self.run_irq = false;
         }
 
-        self.read(self.pc, Access::Read); // Dummy read
+        self.read(self.pc, Access::Dummy); // Dummy read
 
         self.abs_addr = if self.rel_addr & 0x80 == 0x80 {
             self.pc.wrapping_add(self.rel_addr | 0xFF00)
@@ -862,7 +872,7 @@ impl Cpu {
             self.pc.wrapping_add(self.rel_addr)
         };
         if Self::pages_differ(self.abs_addr, self.pc) {
-            self.read(self.pc, Access::Read); // Dummy read
+            self.read(self.pc, Access::Dummy); // Dummy read
         }
         self.pc = self.abs_addr;
     }
@@ -982,7 +992,7 @@ impl Cpu {
     pub(super) fn rts(&mut self) {
         let _ = self.read(Self::SP_BASE | u16::from(self.sp), Access::Read); // Cycle 3
         self.pc = self.pop_u16().wrapping_add(1); // Cycles 4 & 5
-        let _ = self.read(self.pc, Access::Read); // Cycle 6
+        let _ = self.read(self.pc, Access::Dummy); // Cycle 6
     }
 
     ///  Register opcodes
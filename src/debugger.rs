@@ -1,32 +1,785 @@
-use crate::mem::Access;
-use std::ops::RangeInclusive;
+use crate::{
+    cpu::{
+        instr::{
+            AddrMode::{ABS, IND, REL},
+            Operation::{JMP, JSR},
+        },
+        Cpu, Status,
+    },
+    mapper::Mapper,
+    mem::{Access, Mem},
+};
+use std::{fmt, ops::RangeInclusive};
+use symbols::SymbolAddr;
 
-// TODO: Use Address
-#[allow(dead_code)]
+pub mod cdl;
+pub mod disasm;
+pub mod events;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gdb;
+pub mod history;
+pub mod memory;
+pub mod profiler;
+pub mod sprites;
+pub mod symbols;
+pub mod trace;
+
+/// A single address or an inclusive range of addresses.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub(crate) enum Address {
+#[must_use]
+pub enum Address {
     Addr(u16),
     AddrRange(RangeInclusive<u16>),
+    /// An offset into PRG-ROM, matching only while its bank is mapped in.
+    PrgRom(usize),
+    /// An offset into PRG-RAM, matching only while its bank is mapped in.
+    PrgRam(usize),
+}
+
+impl Address {
+    /// Whether the given CPU address matches. PRG offsets never match without a mapper, see
+    /// [`Address::contains_mapped`].
+    #[inline]
+    #[must_use]
+    pub fn contains(&self, addr: u16) -> bool {
+        match self {
+            Self::Addr(a) => *a == addr,
+            Self::AddrRange(range) => range.contains(&addr),
+            Self::PrgRom(_) | Self::PrgRam(_) => false,
+        }
+    }
+
+    /// Whether the given CPU address matches given the current mapper banking.
+    #[must_use]
+    pub fn contains_mapped(&self, addr: u16, mapper: &Mapper) -> bool {
+        match self {
+            Self::PrgRom(offset) => {
+                SymbolAddr::from_cpu(mapper, addr) == SymbolAddr::PrgRom(*offset)
+            }
+            Self::PrgRam(offset) => {
+                SymbolAddr::from_cpu(mapper, addr) == SymbolAddr::PrgRam(*offset)
+            }
+            _ => self.contains(addr),
+        }
+    }
+}
+
+impl From<u16> for Address {
+    fn from(addr: u16) -> Self {
+        Self::Addr(addr)
+    }
+}
+
+impl From<RangeInclusive<u16>> for Address {
+    fn from(range: RangeInclusive<u16>) -> Self {
+        Self::AddrRange(range)
+    }
+}
+
+/// Comparison operator used when evaluating a [`Condition`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    #[inline]
+    #[must_use]
+    pub fn eval<T: PartialOrd>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+        }
+    }
 }
 
-// Conditions:
-// - A/X/Y/P/SP
-// - PC
-// - Opcode
-// - Scanline
-// - Cycle
-// - Memory value
-// - Branched
-// - IRQ/NMI
-// - Spr0 Hit/Spr Overflow
-// - VBlank
+/// A condition that must hold for a [`Breakpoint`] to trigger. Conditions are evaluated against
+/// the CPU state prior to executing the instruction at `PC`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) struct Condition {}
+#[must_use]
+pub enum Condition {
+    A(Cmp, u8),
+    X(Cmp, u8),
+    Y(Cmp, u8),
+    P(Cmp, u8),
+    Sp(Cmp, u8),
+    Pc(Cmp, u16),
+    /// Status flag is set (`true`) or cleared (`false`).
+    Flag(Status, bool),
+    Opcode(u8),
+    Scanline(Cmp, u32),
+    /// PPU cycle (dot) within the current scanline.
+    Cycle(Cmp, u32),
+    /// Value at a CPU address, read without side-effects.
+    Mem(u16, Cmp, u8),
+    /// An IRQ is pending.
+    Irq,
+    /// An NMI is pending.
+    Nmi,
+    Spr0Hit,
+    SprOverflow,
+    VBlank,
+}
+
+impl Condition {
+    /// Evaluate this condition against the current CPU state.
+    #[must_use]
+    pub fn is_met(&self, cpu: &Cpu) -> bool {
+        match *self {
+            Self::A(cmp, val) => cmp.eval(cpu.a(), val),
+            Self::X(cmp, val) => cmp.eval(cpu.x(), val),
+            Self::Y(cmp, val) => cmp.eval(cpu.y(), val),
+            Self::P(cmp, val) => cmp.eval(cpu.status().bits(), val),
+            Self::Sp(cmp, val) => cmp.eval(cpu.sp(), val),
+            Self::Pc(cmp, val) => cmp.eval(cpu.pc(), val),
+            Self::Flag(flag, set) => cpu.status().contains(flag) == set,
+            Self::Opcode(opcode) => cpu.peek(cpu.pc(), Access::Dummy) == opcode,
+            Self::Scanline(cmp, val) => cmp.eval(cpu.ppu().scanline(), val),
+            Self::Cycle(cmp, val) => cmp.eval(cpu.ppu().cycle(), val),
+            Self::Mem(addr, cmp, val) => cmp.eval(cpu.peek(addr, Access::Dummy), val),
+            Self::Irq => !cpu.irqs_pending().is_empty(),
+            Self::Nmi => cpu.nmi_pending(),
+            Self::Spr0Hit => cpu.ppu().status().spr_zero_hit(),
+            Self::SprOverflow => cpu.ppu().status().spr_overflow(),
+            Self::VBlank => cpu.ppu().status().in_vblank(),
+        }
+    }
+}
 
+/// A CPU breakpoint which halts execution when `PC` matches `addr` and all `conditions` are met.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub(crate) struct Breakpoint {
+#[must_use]
+pub struct Breakpoint {
     pub(crate) addr: Address,
     pub(crate) access: Vec<Access>,
     pub(crate) conditions: Vec<Condition>,
     pub(crate) enabled: bool,
 }
+
+impl Breakpoint {
+    /// Creates a new, enabled execution `Breakpoint`.
+    pub fn new<A: Into<Address>>(addr: A, conditions: Vec<Condition>) -> Self {
+        Self {
+            addr: addr.into(),
+            access: vec![Access::Execute],
+            conditions,
+            enabled: true,
+        }
+    }
+
+    #[inline]
+    pub const fn addr(&self) -> &Address {
+        &self.addr
+    }
+
+    #[inline]
+    pub fn access(&self) -> &[Access] {
+        &self.access
+    }
+
+    #[inline]
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[inline]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Whether this breakpoint triggers for the instruction about to be executed.
+    #[must_use]
+    pub fn is_hit(&self, cpu: &Cpu) -> bool {
+        self.enabled
+            && self.access.contains(&Access::Execute)
+            && self.addr.contains_mapped(cpu.pc(), cpu.mapper())
+            && self
+                .conditions
+                .iter()
+                .all(|condition| condition.is_met(cpu))
+    }
+}
+
+/// Address space a [`Watchpoint`] applies to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum AddrSpace {
+    Cpu,
+    Ppu,
+}
+
+/// A memory watchpoint which halts execution when an address in `addr` is accessed with one of
+/// the given `access` kinds and, for writes, optionally only when `value` is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Watchpoint {
+    pub(crate) space: AddrSpace,
+    pub(crate) addr: Address,
+    pub(crate) access: Vec<Access>,
+    pub(crate) value: Option<u8>,
+    pub(crate) enabled: bool,
+}
+
+impl Watchpoint {
+    /// Creates a new, enabled `Watchpoint`.
+    pub fn new<A: Into<Address>>(
+        space: AddrSpace,
+        addr: A,
+        access: Vec<Access>,
+        value: Option<u8>,
+    ) -> Self {
+        Self {
+            space,
+            addr: addr.into(),
+            access,
+            value,
+            enabled: true,
+        }
+    }
+
+    #[inline]
+    pub const fn space(&self) -> AddrSpace {
+        self.space
+    }
+
+    #[inline]
+    pub const fn addr(&self) -> &Address {
+        &self.addr
+    }
+
+    #[inline]
+    pub fn access(&self) -> &[Access] {
+        &self.access
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn value(&self) -> Option<u8> {
+        self.value
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[inline]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Whether this watchpoint triggers for the given memory access. Opcode and operand fetches
+    /// count as reads, while dummy reads and DMA transfers only trigger watchpoints that include
+    /// [`Access::Dummy`].
+    #[must_use]
+    pub fn is_hit(&self, addr: u16, access: Access, val: u8, mapper: &Mapper) -> bool {
+        let access_hit = self.access.contains(&access)
+            || (access == Access::Execute && self.access.contains(&Access::Read));
+        self.enabled
+            && access_hit
+            && self.addr.contains_mapped(addr, mapper)
+            && self.value.map_or(true, |value| value == val)
+    }
+}
+
+/// Details of the memory access which triggered a [`Watchpoint`]. `pc` and `cycle` refer to the
+/// instruction that was executing when the access occurred.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct WatchpointHit {
+    pub space: AddrSpace,
+    pub addr: u16,
+    pub access: Access,
+    pub pc: u16,
+    pub cycle: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl WatchpointHit {
+    /// Checks a list of watchpoints against a memory access, returning the first hit.
+    pub(crate) fn check(
+        watchpoints: &[Watchpoint],
+        mapper: &Mapper,
+        addr: u16,
+        access: Access,
+        old: u8,
+        new: u8,
+    ) -> Option<Self> {
+        watchpoints
+            .iter()
+            .find(|watchpoint| watchpoint.is_hit(addr, access, new, mapper))
+            .map(|watchpoint| Self {
+                space: watchpoint.space,
+                addr,
+                access,
+                pc: 0x0000,
+                cycle: 0,
+                old,
+                new,
+            })
+    }
+}
+
+impl fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {:?} ${:04X}: ${:02X} -> ${:02X} at PC ${:04X}, cycle {}",
+            self.space, self.access, self.addr, self.old, self.new, self.pc, self.cycle
+        )
+    }
+}
+
+/// Returns the size in bytes of the instruction at `addr`.
+#[inline]
+#[must_use]
+pub fn instr_size(cpu: &Cpu, addr: u16) -> u16 {
+    Cpu::INSTRUCTIONS[cpu.peek(addr, Access::Dummy) as usize].size()
+}
+
+/// Returns the address of the instruction before `addr`. Instructions vary in size, so this
+/// disassembles forward from several earlier addresses and picks the instruction most of them
+/// line up on.
+#[must_use]
+pub fn prev_instr_addr(cpu: &Cpu, addr: u16) -> u16 {
+    const LOOKBACK: u16 = 24;
+    let mut votes = [0u8; 3];
+    for lookback in 1..=LOOKBACK {
+        let mut pc = addr.wrapping_sub(lookback);
+        let mut remaining = lookback;
+        while remaining > 0 {
+            let size = instr_size(cpu, pc);
+            if size > remaining {
+                break;
+            }
+            pc = pc.wrapping_add(size);
+            remaining -= size;
+            if remaining == 0 {
+                votes[size as usize - 1] += 1;
+            }
+        }
+    }
+    // Ties go to the longer instruction
+    let size = (1..=3).max_by_key(|&size| votes[size - 1]).unwrap_or(1);
+    addr.wrapping_sub(size as u16)
+}
+
+/// Returns the target of the branch, jump or call at `addr`, if it is one.
+#[must_use]
+pub fn branch_target(cpu: &Cpu, addr: u16) -> Option<u16> {
+    let instr = Cpu::INSTRUCTIONS[cpu.peek(addr, Access::Dummy) as usize];
+    let operand = addr.wrapping_add(1);
+    match (instr.op(), instr.addr_mode()) {
+        (JMP | JSR, ABS) => Some(cpu.peek_u16(operand)),
+        (JMP, IND) => {
+            let ptr = cpu.peek_u16(operand);
+            // JMP ($xxFF) reads the high byte from $xx00
+            let hi_ptr = (ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF);
+            Some(u16::from_le_bytes([
+                cpu.peek(ptr, Access::Dummy),
+                cpu.peek(hi_ptr, Access::Dummy),
+            ]))
+        }
+        (_, REL) => {
+            let offset = cpu.peek(operand, Access::Dummy) as i8;
+            Some(addr.wrapping_add(2).wrapping_add(offset as u16))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the return addresses of `JSR` calls found on the stack, innermost call first. Values
+/// pushed on the stack are only considered return addresses if they follow a `JSR`.
+#[must_use]
+pub fn stack_return_addrs(cpu: &Cpu) -> Vec<u16> {
+    let mut addrs = vec![];
+    let mut offset = u16::from(cpu.sp()) + 1;
+    while offset < 0xFF {
+        let lo = cpu.peek(0x0100 | offset, Access::Dummy);
+        let hi = cpu.peek(0x0100 | (offset + 1), Access::Dummy);
+        // JSR pushes the address of its last byte
+        let return_addr = u16::from_le_bytes([lo, hi]).wrapping_add(1);
+        let call = Cpu::INSTRUCTIONS[cpu.peek(return_addr.wrapping_sub(3), Access::Dummy) as usize];
+        if call.op() == JSR {
+            addrs.push(return_addr);
+            offset += 2;
+        } else {
+            offset += 1;
+        }
+    }
+    addrs
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        cart::Cart,
+        common::{Kind, Reset},
+        control_deck::ControlDeck,
+        debugger::symbols::Symbols,
+    };
+    use std::ops::ControlFlow;
+
+    pub(crate) fn load_program(program: &[u8]) -> ControlDeck {
+        let mut cart = Cart::empty();
+        cart.prg_rom[..program.len()].copy_from_slice(program);
+        // Reset vector -> $8000
+        cart.prg_rom[0x3FFC] = 0x00;
+        cart.prg_rom[0x3FFD] = 0x80;
+        let mut deck = ControlDeck::default();
+        deck.cpu_mut().load_cart(cart);
+        deck.reset(Kind::Hard);
+        deck
+    }
+
+    // $8000 LDX #$00
+    // $8002 INX
+    // $8003 JMP $8002
+    pub(crate) const LOOP: [u8; 6] = [0xA2, 0x00, 0xE8, 0x4C, 0x02, 0x80];
+
+    #[test]
+    fn address() {
+        assert!(Address::from(0x8000).contains(0x8000));
+        assert!(!Address::from(0x8000).contains(0x8001));
+        assert!(Address::from(0x8000..=0x80FF).contains(0x80FF));
+        assert!(!Address::from(0x8000..=0x80FF).contains(0x8100));
+    }
+
+    #[test]
+    fn conditions() {
+        let deck = load_program(&LOOP);
+        let cpu = deck.cpu();
+        assert!(Condition::Pc(Cmp::Eq, 0x8000).is_met(cpu), "pc");
+        assert!(Condition::Opcode(0xA2).is_met(cpu), "opcode");
+        assert!(Condition::Mem(0x8003, Cmp::Eq, 0x4C).is_met(cpu), "mem");
+        assert!(Condition::Flag(Status::I, true).is_met(cpu), "flag");
+        assert!(Condition::Sp(Cmp::Le, 0xFD).is_met(cpu), "sp");
+        assert!(!Condition::X(Cmp::Gt, 0x00).is_met(cpu), "x");
+    }
+
+    #[test]
+    fn breakpoint_hit() {
+        let mut deck = load_program(&LOOP);
+        deck.add_breakpoint(Breakpoint::new(0x8003, vec![Condition::X(Cmp::Eq, 0x05)]));
+
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "breaks");
+        assert_eq!(deck.cpu().pc(), 0x8003, "pc");
+        assert_eq!(deck.cpu().x(), 0x05, "x");
+
+        // Resuming runs past the breakpoint until the condition is met again
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "breaks again");
+        assert_eq!(deck.cpu().x(), 0x05, "x wraps around");
+    }
+
+    #[test]
+    fn reset_vector_breakpoint() {
+        let mut deck = load_program(&LOOP);
+        deck.add_breakpoint(Breakpoint::new(0x8000, vec![]));
+        let state = bincode::serialize(deck.cpu()).expect("serialized");
+
+        let result = deck.clock_frame().expect("valid clock");
+        assert_eq!(result, ControlFlow::Break(0), "breaks before executing");
+        assert_eq!(deck.cpu().pc(), 0x8000, "pc");
+
+        let result = deck.clock_instr().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Continue(_)), "resumes");
+        assert_eq!(deck.cpu().pc(), 0x8002, "pc");
+
+        deck.load_cpu(bincode::deserialize(&state).expect("deserialized"));
+        let result = deck.clock_instr().expect("valid clock");
+        assert_eq!(result, ControlFlow::Break(0), "breaks after loading");
+        assert_eq!(deck.cpu().pc(), 0x8000, "pc");
+    }
+
+    #[test]
+    fn symbol_breakpoint() {
+        let mut deck = load_program(&LOOP);
+        let mut symbols = Symbols::new();
+        symbols.insert("loop", SymbolAddr::PrgRom(0x0002));
+        deck.load_symbols(symbols);
+        assert!(deck.add_symbol_breakpoint("missing", vec![]).is_err());
+        deck.add_symbol_breakpoint("loop", vec![Condition::X(Cmp::Eq, 0x03)])
+            .expect("valid symbol");
+
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "breaks");
+        assert_eq!(deck.cpu().pc(), 0x8002, "pc");
+        assert_eq!(deck.label(deck.cpu().pc()), Some("loop"));
+
+        let mut pc = 0x8003;
+        assert_eq!(deck.disassemble(&mut pc), "8003 4C 02 80  JMP loop");
+    }
+
+    #[test]
+    fn cpu_watchpoint() {
+        // $8000 LDA #$42
+        // $8002 STA $10
+        // $8004 LDA #$07
+        // $8006 STA $0810 ; WRAM mirror of $10
+        // $8009 JMP $8009
+        let mut deck = load_program(&[
+            0xA9, 0x42, 0x85, 0x10, 0xA9, 0x07, 0x8D, 0x10, 0x08, 0x4C, 0x09, 0x80,
+        ]);
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x0010,
+            vec![Access::Write],
+            Some(0x07),
+        ));
+
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "breaks");
+        let hit = deck.watchpoint_hit().expect("watchpoint hit");
+        assert_eq!(hit.addr, 0x0010, "addr");
+        assert_eq!(hit.access, Access::Write, "access");
+        assert_eq!(hit.pc, 0x8006, "pc");
+        assert_eq!((hit.old, hit.new), (0x42, 0x07), "values");
+
+        deck.set_watchpoint_enabled(AddrSpace::Cpu, 0, false);
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Continue(_)), "continues");
+        assert!(deck.watchpoint_hit().is_none(), "no watchpoint hit");
+    }
+
+    #[test]
+    fn ppu_watchpoint() {
+        // $8000 BIT $2002 ; Wait for PPU warm up
+        // $8003 BPL $8000
+        // $8005 BIT $2002
+        // $8008 BPL $8005
+        // $800A LDA #$23
+        // $800C STA $2006
+        // $800F LDA #$05
+        // $8011 STA $2006
+        // $8014 LDA #$66
+        // $8016 STA $2007
+        // $8019 JMP $8019
+        let mut deck = load_program(&[
+            0x2C, 0x02, 0x20, 0x10, 0xFB, 0x2C, 0x02, 0x20, 0x10, 0xFB, 0xA9, 0x23, 0x8D, 0x06,
+            0x20, 0xA9, 0x05, 0x8D, 0x06, 0x20, 0xA9, 0x66, 0x8D, 0x07, 0x20, 0x4C, 0x19, 0x80,
+        ]);
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Ppu,
+            0x2300..=0x23FF,
+            vec![Access::Write],
+            None,
+        ));
+
+        let mut frames = 0;
+        while deck.clock_frame().expect("valid clock").is_continue() {
+            frames += 1;
+            assert!(frames < 5, "breaks");
+        }
+        let hit = deck.watchpoint_hit().expect("watchpoint hit");
+        assert_eq!(hit.space, AddrSpace::Ppu, "space");
+        assert_eq!(hit.addr, 0x2305, "addr");
+        assert_eq!(hit.pc, 0x8016, "pc");
+        assert_eq!((hit.old, hit.new), (0x00, 0x66), "values");
+    }
+
+    #[test]
+    fn read_watchpoint_access() {
+        let mut deck = load_program(&LOOP);
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x8001,
+            vec![Access::Read],
+            None,
+        ));
+
+        // Operand fetch of LDX #$00
+        let result = deck.clock_instr().expect("valid clock");
+        assert!(
+            matches!(result, ControlFlow::Break(_)),
+            "operand fetch breaks"
+        );
+        let hit = deck.watchpoint_hit().expect("watchpoint hit");
+        assert_eq!((hit.addr, hit.access), (0x8001, Access::Execute));
+
+        deck.set_watchpoint_enabled(AddrSpace::Cpu, 0, false);
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x8003,
+            vec![Access::Read],
+            None,
+        ));
+        // INX only performs a dummy read of $8003
+        let result = deck.clock_instr().expect("valid clock");
+        assert!(
+            matches!(result, ControlFlow::Continue(_)),
+            "dummy read continues"
+        );
+        // Opcode fetch of JMP $8002
+        let result = deck.clock_instr().expect("valid clock");
+        assert!(
+            matches!(result, ControlFlow::Break(_)),
+            "opcode fetch breaks"
+        );
+        let hit = deck.watchpoint_hit().expect("watchpoint hit");
+        assert_eq!((hit.addr, hit.access), (0x8003, Access::Execute));
+
+        deck.set_watchpoint_enabled(AddrSpace::Cpu, 1, false);
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x8003,
+            vec![Access::Dummy],
+            None,
+        ));
+        let result = deck.clock_instr().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "dummy read breaks");
+        let hit = deck.watchpoint_hit().expect("watchpoint hit");
+        assert_eq!((hit.addr, hit.access), (0x8003, Access::Dummy));
+    }
+
+    #[test]
+    fn symbol_watchpoint() {
+        // $8000 LDA $8010
+        // $8003 JMP $8003
+        let mut deck = load_program(&[0xAD, 0x10, 0x80, 0x4C, 0x03, 0x80]);
+        let mut symbols = Symbols::new();
+        symbols.insert("table", SymbolAddr::PrgRom(0x0010));
+        deck.load_symbols(symbols);
+        let addr = deck.symbols().address("table").expect("valid symbol");
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            addr,
+            vec![Access::Read],
+            None,
+        ));
+
+        let result = deck.clock_instr().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "breaks");
+        let hit = deck.watchpoint_hit().expect("watchpoint hit");
+        assert_eq!((hit.addr, hit.access), (0x8010, Access::Read));
+    }
+
+    #[test]
+    fn mirrored_watchpoint() {
+        // $8000 LDA $0810
+        // $8003 JMP $8003
+        let mut deck = load_program(&[0xAD, 0x10, 0x08, 0x4C, 0x03, 0x80]);
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x0010,
+            vec![Access::Read],
+            None,
+        ));
+        deck.cpu_mut().mem_hooks_mut().push(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x0000..=0x1FFF,
+            vec![Access::Read],
+            None,
+        ));
+
+        let result = deck.clock_instr().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "breaks");
+        let hit = deck.watchpoint_hit().expect("watchpoint hit");
+        assert_eq!((hit.addr, hit.access), (0x0010, Access::Read));
+        let hits = deck.cpu_mut().take_mem_hook_hits();
+        assert_eq!(hits.len(), 1, "hooked once");
+        assert_eq!(hits[0].addr, 0x0010);
+    }
+
+    #[test]
+    fn watchpoints_survive_load_state() {
+        let mut deck = load_program(&LOOP);
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x0010,
+            vec![Access::Write],
+            None,
+        ));
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Ppu,
+            0x2000..=0x23FF,
+            vec![Access::Write],
+            None,
+        ));
+
+        let state = bincode::serialize(deck.cpu()).expect("serialized");
+        deck.load_cpu(bincode::deserialize(&state).expect("deserialized"));
+        assert_eq!(deck.watchpoints(AddrSpace::Cpu).len(), 1, "cpu watchpoints");
+        assert_eq!(deck.watchpoints(AddrSpace::Ppu).len(), 1, "ppu watchpoints");
+    }
+
+    #[test]
+    fn breakpoint_disabled() {
+        let mut deck = load_program(&LOOP);
+        deck.add_breakpoint(Breakpoint::new(0x8002..=0x8003, vec![]));
+        deck.set_breakpoint_enabled(0, false);
+
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Continue(_)), "continues");
+
+        deck.set_breakpoint_enabled(0, true);
+        let result = deck.clock_instr().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "breaks");
+
+        assert!(deck.remove_breakpoint(0).is_some(), "removed");
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Continue(_)), "continues");
+    }
+
+    #[test]
+    fn run_to() {
+        let mut deck = load_program(&LOOP);
+        deck.set_run_to(Some(0x8003));
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Break(_)), "breaks");
+        assert_eq!(deck.cpu().pc(), 0x8003);
+        assert_eq!(deck.run_to(), None, "cleared once hit");
+
+        let result = deck.clock_frame().expect("valid clock");
+        assert!(matches!(result, ControlFlow::Continue(_)), "continues");
+    }
+
+    #[test]
+    fn navigate_disassembly() {
+        // $8000 JSR $8007
+        // $8003 LDA #$01
+        // $8005 BNE $8000
+        // $8007 JSR $800B
+        // $800A RTS
+        // $800B NOP
+        // $800C RTS
+        let mut deck = load_program(&[
+            0x20, 0x07, 0x80, 0xA9, 0x01, 0xD0, 0xF9, 0x20, 0x0B, 0x80, 0x60, 0xEA, 0x60,
+        ]);
+        let cpu = deck.cpu();
+        assert_eq!(instr_size(cpu, 0x8000), 3);
+        assert_eq!(prev_instr_addr(cpu, 0x800C), 0x800B);
+        assert_eq!(prev_instr_addr(cpu, 0x800B), 0x800A);
+        assert_eq!(prev_instr_addr(cpu, 0x800A), 0x8007);
+        assert_eq!(prev_instr_addr(cpu, 0x8007), 0x8005);
+        assert_eq!(branch_target(cpu, 0x8000), Some(0x8007));
+        assert_eq!(branch_target(cpu, 0x8005), Some(0x8000));
+        assert_eq!(branch_target(cpu, 0x8003), None);
+
+        for _ in 0..3 {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+        assert_eq!(deck.cpu().pc(), 0x800C);
+        assert_eq!(stack_return_addrs(deck.cpu()), vec![0x800A, 0x8003]);
+    }
+}
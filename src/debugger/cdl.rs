@@ -0,0 +1,236 @@
+//! Code/Data Logger compatible with FCEUX `.cdl` files.
+//!
+//! <https://fceux.com/web/help/CodeDataLogger.html>
+//!
+//! A `.cdl` file contains one flag byte for every byte of PRG-ROM followed by one for every byte
+//! of CHR-ROM.
+
+use crate::NesResult;
+use anyhow::anyhow;
+use bitflags::bitflags;
+use std::io::{Read, Write};
+
+bitflags! {
+    /// PRG-ROM flags, `xPdcAADC`.
+    #[derive(Default)]
+    #[must_use]
+    pub struct PrgFlags: u8 {
+        /// Executed as code.
+        const CODE = 0x01;
+        /// Read as data.
+        const DATA = 0x02;
+        /// CPU bank (`$8000`, `$A000`, `$C000` or `$E000`) the byte was last accessed through.
+        const BANK = 0x0C;
+        /// Executed as the target of an indirect jump.
+        const INDIRECT_CODE = 0x10;
+        /// Read as data through an indirect pointer.
+        const INDIRECT_DATA = 0x20;
+        /// Read as a DMC PCM audio sample.
+        const PCM = 0x40;
+    }
+}
+
+bitflags! {
+    /// CHR-ROM flags, `xxxxxxRD`.
+    #[derive(Default)]
+    #[must_use]
+    pub struct ChrFlags: u8 {
+        /// Fetched while rendering.
+        const DRAWN = 0x01;
+        /// Read through `PPUDATA`.
+        const READ = 0x02;
+    }
+}
+
+impl PrgFlags {
+    /// Returns the `BANK` bits for a CPU address.
+    #[inline]
+    pub const fn bank(addr: u16) -> Self {
+        Self::from_bits_truncate((((addr >> 13) & 0x03) as u8) << 2)
+    }
+}
+
+/// Flags for every logged PRG-ROM and CHR-ROM byte.
+#[derive(Default, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct CodeDataLog {
+    pub(crate) prg: Vec<u8>,
+    pub(crate) chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    /// Creates an empty log for the given PRG-ROM and CHR-ROM sizes.
+    pub fn new(prg_rom_len: usize, chr_rom_len: usize) -> Self {
+        Self {
+            prg: vec![0x00; prg_rom_len],
+            chr: vec![0x00; chr_rom_len],
+        }
+    }
+
+    /// Loads an FCEUX `.cdl` file for a ROM with the given PRG-ROM and CHR-ROM sizes.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be read or doesn't match the ROM sizes, an error is returned.
+    pub fn load<R: Read>(
+        reader: &mut R,
+        prg_rom_len: usize,
+        chr_rom_len: usize,
+    ) -> NesResult<Self> {
+        let mut data = Vec::with_capacity(prg_rom_len + chr_rom_len);
+        reader.read_to_end(&mut data)?;
+        if data.len() != prg_rom_len + chr_rom_len {
+            return Err(anyhow!(
+                "invalid cdl size: {}, expected {}",
+                data.len(),
+                prg_rom_len + chr_rom_len
+            ));
+        }
+        let chr = data.split_off(prg_rom_len);
+        Ok(Self { prg: data, chr })
+    }
+
+    /// Saves the log as an FCEUX `.cdl` file.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be written, an error is returned.
+    pub fn save<W: Write>(&self, writer: &mut W) -> NesResult<()> {
+        writer.write_all(&self.prg)?;
+        writer.write_all(&self.chr)?;
+        Ok(())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    /// Returns the flags for a PRG-ROM offset.
+    #[inline]
+    pub fn prg_flags(&self, offset: usize) -> PrgFlags {
+        self.prg.get(offset).map_or_else(PrgFlags::empty, |&flags| {
+            PrgFlags::from_bits_truncate(flags)
+        })
+    }
+
+    /// Returns the flags for a CHR-ROM offset.
+    #[inline]
+    pub fn chr_flags(&self, offset: usize) -> ChrFlags {
+        self.chr.get(offset).map_or_else(ChrFlags::empty, |&flags| {
+            ChrFlags::from_bits_truncate(flags)
+        })
+    }
+
+    /// Returns the number of PRG-ROM bytes logged as code and as data.
+    #[must_use]
+    pub fn prg_coverage(&self) -> (usize, usize) {
+        let code_flags = PrgFlags::CODE | PrgFlags::INDIRECT_CODE;
+        let data_flags = PrgFlags::DATA | PrgFlags::INDIRECT_DATA | PrgFlags::PCM;
+        self.prg.iter().fold((0, 0), |(code, data), &flags| {
+            let flags = PrgFlags::from_bits_truncate(flags);
+            (
+                code + usize::from(flags.intersects(code_flags)),
+                data + usize::from(flags.intersects(data_flags)),
+            )
+        })
+    }
+
+    /// Returns the inclusive PRG-ROM offset ranges which were never accessed.
+    #[must_use]
+    pub fn unused_prg(&self) -> Vec<(usize, usize)> {
+        unused_ranges(&self.prg)
+    }
+
+    /// Returns the inclusive CHR-ROM offset ranges which were never accessed.
+    #[must_use]
+    pub fn unused_chr(&self) -> Vec<(usize, usize)> {
+        unused_ranges(&self.chr)
+    }
+}
+
+impl std::fmt::Debug for CodeDataLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CodeDataLog")
+            .field("prg_len", &self.prg.len())
+            .field("chr_len", &self.chr.len())
+            .finish()
+    }
+}
+
+fn unused_ranges(flags: &[u8]) -> Vec<(usize, usize)> {
+    let mut ranges = vec![];
+    let mut start = None;
+    for (offset, &flag) in flags.iter().enumerate() {
+        match (flag == 0x00, start) {
+            (true, None) => start = Some(offset),
+            (false, Some(begin)) => {
+                ranges.push((begin, offset - 1));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(begin) = start {
+        ranges.push((begin, flags.len() - 1));
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::tests::{load_program, LOOP};
+
+    #[test]
+    fn load_save() {
+        let mut cdl = CodeDataLog::new(4, 2);
+        cdl.prg[1] = (PrgFlags::CODE | PrgFlags::bank(0xC000)).bits();
+        cdl.chr[0] = ChrFlags::DRAWN.bits();
+
+        let mut file = vec![];
+        cdl.save(&mut file).expect("saved cdl");
+        assert_eq!(file, [0x00, 0x09, 0x00, 0x00, 0x01, 0x00]);
+
+        let loaded = CodeDataLog::load(&mut file.as_slice(), 4, 2).expect("loaded cdl");
+        assert_eq!(loaded, cdl);
+        assert_eq!(loaded.prg_flags(1), PrgFlags::CODE | PrgFlags::bank(0xC000));
+        assert_eq!(loaded.chr_flags(0), ChrFlags::DRAWN);
+        assert!(CodeDataLog::load(&mut file.as_slice(), 8, 0).is_err());
+    }
+
+    #[test]
+    fn log_code() {
+        let mut deck = load_program(&LOOP);
+        deck.start_cdl(None).expect("started cdl");
+        for _ in 0..4 {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+        let cdl = deck.stop_cdl().expect("cdl log");
+        for offset in 0..LOOP.len() {
+            assert_eq!(cdl.prg_flags(offset), PrgFlags::CODE, "offset {offset}");
+        }
+        assert_eq!(cdl.prg_coverage(), (LOOP.len(), 0));
+        assert_eq!(cdl.unused_prg(), [(LOOP.len(), cdl.prg().len() - 1)]);
+        assert!(deck.code_data_log().is_none());
+    }
+
+    #[test]
+    fn log_across_load_state() {
+        let mut deck = load_program(&LOOP);
+        let state = bincode::serialize(deck.cpu()).expect("serialized");
+        deck.start_cdl(None).expect("started cdl");
+        let _ = deck.clock_instr().expect("valid clock");
+
+        deck.load_cpu(bincode::deserialize(&state).expect("deserialized"));
+        let cdl = deck.code_data_log().expect("still logging");
+        assert_eq!(cdl.prg_flags(0), PrgFlags::CODE, "keeps logged code");
+    }
+}
@@ -0,0 +1,693 @@
+//! Full PRG-ROM disassembler producing ca65 source.
+//!
+//! PRG-ROM is split into fixed-size banks, each disassembled into its own segment. Code is told
+//! apart from data with a [`CodeDataLog`] if one is given, otherwise by recursive descent from
+//! the reset, NMI and IRQ vectors. Only official opcodes are emitted as instructions, everything
+//! else is emitted as `.byte` runs so the source reassembles byte-identically with `ca65` and
+//! `ld65` using the linker config from [`Disassembly::write_linker_config`].
+//!
+//! Without a mapper to consult, banks are placed the way most mappers lay them out: the last bank
+//! is fixed at the top of the address space and the others switch in at `$8000`. A code/data log
+//! records the CPU bank each byte was accessed through, which is used instead when available.
+
+use crate::{
+    cpu::{
+        instr::{AddrMode, Instr, Operation},
+        Cpu,
+    },
+    debugger::cdl::{CodeDataLog, PrgFlags},
+    NesResult,
+};
+use anyhow::anyhow;
+use std::{collections::BTreeSet, io::Write};
+
+const DATA_BYTES_PER_LINE: usize = 16;
+const VECTORS_LEN: usize = 6;
+
+/// Classification of a byte of PRG-ROM.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Byte {
+    Data,
+    /// First byte of an instruction.
+    Opcode,
+    /// Operand byte of the preceding instruction.
+    Operand,
+}
+
+#[derive(Debug, Clone)]
+struct Bank {
+    base: u16,
+    bytes: Vec<Byte>,
+    /// Offsets referenced by branches, jumps and absolute operands.
+    labels: BTreeSet<usize>,
+}
+
+/// A single line of source covering `len` bytes of a bank.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line {
+    offset: usize,
+    len: usize,
+    text: String,
+}
+
+/// PRG-ROM split into banks with code, data and labels identified.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct Disassembly {
+    prg_rom: Vec<u8>,
+    bank_size: usize,
+    banks: Vec<Bank>,
+}
+
+impl Disassembly {
+    /// Disassembles PRG-ROM in banks of `bank_size` bytes. A `bank_size` of 0 picks one bank for
+    /// 32K or less and 16K banks otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If PRG-ROM is empty, the bank size isn't a power of two between 8K and 32K or doesn't
+    /// divide PRG-ROM evenly, or the code/data log doesn't match PRG-ROM, an error is returned.
+    pub fn new(prg_rom: &[u8], bank_size: usize, cdl: Option<&CodeDataLog>) -> NesResult<Self> {
+        if prg_rom.is_empty() {
+            return Err(anyhow!("empty PRG-ROM"));
+        }
+        let bank_size = match bank_size {
+            0 if prg_rom.len() <= 0x8000 => prg_rom.len(),
+            0 => 0x4000,
+            size => size,
+        };
+        if !bank_size.is_power_of_two()
+            || !(0x2000..=0x8000).contains(&bank_size)
+            || prg_rom.len() % bank_size != 0
+        {
+            return Err(anyhow!(
+                "invalid bank size ${bank_size:04X} for ${:X} bytes of PRG-ROM",
+                prg_rom.len()
+            ));
+        }
+        if let Some(cdl) = cdl {
+            if cdl.prg().len() != prg_rom.len() {
+                return Err(anyhow!(
+                    "invalid cdl PRG-ROM size: {}, expected {}",
+                    cdl.prg().len(),
+                    prg_rom.len()
+                ));
+            }
+        }
+
+        let bank_count = prg_rom.len() / bank_size;
+        let banks = (0..bank_count)
+            .map(|index| {
+                let fixed = index + 1 == bank_count;
+                let default_base = if fixed {
+                    (0x10000 - bank_size) as u16
+                } else {
+                    0x8000
+                };
+                Bank {
+                    base: cdl
+                        .and_then(|cdl| Self::logged_base(cdl, index * bank_size, bank_size))
+                        .unwrap_or(default_base),
+                    bytes: vec![Byte::Data; bank_size],
+                    labels: BTreeSet::new(),
+                }
+            })
+            .collect();
+        let mut disasm = Self {
+            prg_rom: prg_rom.to_vec(),
+            bank_size,
+            banks,
+        };
+        match cdl {
+            Some(cdl) => disasm.mark_logged_code(cdl),
+            None => disasm.trace_vectors(),
+        }
+        disasm.find_labels();
+        Ok(disasm)
+    }
+
+    /// Number of PRG-ROM banks.
+    #[inline]
+    #[must_use]
+    pub fn bank_count(&self) -> usize {
+        self.banks.len()
+    }
+
+    /// Size of each PRG-ROM bank in bytes.
+    #[inline]
+    #[must_use]
+    pub const fn bank_size(&self) -> usize {
+        self.bank_size
+    }
+
+    /// CPU address a bank is assembled at.
+    #[inline]
+    #[must_use]
+    pub fn bank_base(&self, bank: usize) -> u16 {
+        self.banks[bank].base
+    }
+
+    /// Number of PRG-ROM bytes disassembled as instructions.
+    #[must_use]
+    pub fn code_len(&self) -> usize {
+        self.banks
+            .iter()
+            .flat_map(|bank| bank.bytes.iter())
+            .filter(|&&byte| byte != Byte::Data)
+            .count()
+    }
+
+    /// Writes ca65 source for the iNES `header`, every PRG-ROM bank and, if given, an `.incbin`
+    /// of the CHR-ROM file.
+    ///
+    /// # Errors
+    ///
+    /// If the source fails to be written, an error is returned.
+    pub fn write_source<W: Write>(
+        &self,
+        writer: &mut W,
+        header: &[u8],
+        chr_file: Option<&str>,
+    ) -> NesResult<()> {
+        writeln!(writer, "; Disassembled by TetaNES")?;
+        writeln!(writer, ".setcpu \"6502\"")?;
+        writeln!(writer)?;
+        writeln!(writer, ".segment \"HEADER\"")?;
+        for chunk in header.chunks(DATA_BYTES_PER_LINE) {
+            writeln!(writer, "    {}", Self::byte_directive(chunk))?;
+        }
+        for (index, bank) in self.banks.iter().enumerate() {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                ".segment \"{}\" ; ${:04X}",
+                Self::segment_name(index),
+                bank.base
+            )?;
+            for line in self.bank_lines(index) {
+                if bank.labels.contains(&line.offset) {
+                    writeln!(writer, "{}:", self.label(index, line.offset))?;
+                }
+                writeln!(writer, "    {}", line.text)?;
+            }
+        }
+        if let Some(chr_file) = chr_file {
+            writeln!(writer)?;
+            writeln!(writer, ".segment \"CHR\"")?;
+            writeln!(writer, "    .incbin \"{chr_file}\"")?;
+        }
+        Ok(())
+    }
+
+    /// Writes an `ld65` linker config placing the header, every PRG-ROM bank at its CPU address
+    /// and `chr_len` bytes of CHR-ROM into a single iNES file.
+    ///
+    /// # Errors
+    ///
+    /// If the config fails to be written, an error is returned.
+    pub fn write_linker_config<W: Write>(
+        &self,
+        writer: &mut W,
+        header_len: usize,
+        chr_len: usize,
+    ) -> NesResult<()> {
+        writeln!(writer, "MEMORY {{")?;
+        writeln!(
+            writer,
+            "    HEADER: start = $0000, size = ${header_len:04X}, file = %O, fill = yes;"
+        )?;
+        for (index, bank) in self.banks.iter().enumerate() {
+            writeln!(
+                writer,
+                "    {}: start = ${:04X}, size = ${:04X}, file = %O, fill = yes;",
+                Self::segment_name(index),
+                bank.base,
+                self.bank_size
+            )?;
+        }
+        if chr_len > 0 {
+            writeln!(
+                writer,
+                "    CHR: start = $0000, size = ${chr_len:04X}, file = %O, fill = yes;"
+            )?;
+        }
+        writeln!(writer, "}}")?;
+        writeln!(writer, "SEGMENTS {{")?;
+        writeln!(writer, "    HEADER: load = HEADER, type = ro;")?;
+        for index in 0..self.banks.len() {
+            let name = Self::segment_name(index);
+            writeln!(writer, "    {name}: load = {name}, type = ro;")?;
+        }
+        if chr_len > 0 {
+            writeln!(writer, "    CHR: load = CHR, type = ro;")?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    fn segment_name(bank: usize) -> String {
+        format!("PRG{bank:02}")
+    }
+
+    /// Returns the CPU bank base a code/data log recorded for the first logged byte of a bank.
+    fn logged_base(cdl: &CodeDataLog, start: usize, bank_size: usize) -> Option<u16> {
+        (start..start + bank_size).find_map(|offset| {
+            let flags = cdl.prg_flags(offset);
+            if flags.is_empty() {
+                return None;
+            }
+            let window = 0x8000 + ((flags & PrgFlags::BANK).bits() as usize >> 2) * 0x2000;
+            let base = window.checked_sub((offset - start) & !0x1FFF)?;
+            (base >= 0x8000 && base + bank_size <= 0x10000).then_some(base as u16)
+        })
+    }
+
+    /// Returns whether an opcode is official and reassembles to the same bytes.
+    fn is_official(instr: Instr) -> bool {
+        use Operation::*;
+        match instr.op() {
+            SKB | IGN | ISB | DCP | AXS | LAS | LAX | AHX | SAX | XAA | SXA | RRA | TAS | SYA
+            | ARR | SRE | ALR | RLA | ANC | SLO | XXX => false,
+            // 0xEA is the only official NOP
+            NOP => instr.opcode() == 0xEA,
+            SBC => instr.opcode() != 0xEB,
+            _ => true,
+        }
+    }
+
+    #[inline]
+    fn bank_data(&self, bank: usize) -> &[u8] {
+        let start = bank * self.bank_size;
+        &self.prg_rom[start..start + self.bank_size]
+    }
+
+    fn instr_at(&self, bank: usize, offset: usize) -> Instr {
+        Cpu::INSTRUCTIONS[self.bank_data(bank)[offset] as usize]
+    }
+
+    fn word_at(&self, bank: usize, offset: usize) -> u16 {
+        let data = self.bank_data(bank);
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+
+    #[inline]
+    fn operand_u16(&self, bank: usize, offset: usize) -> u16 {
+        self.word_at(bank, offset + 1)
+    }
+
+    /// Returns the bank and offset of each interrupt vector target, if the fixed bank holds the
+    /// vectors.
+    fn vector_targets(&self) -> Vec<(usize, usize)> {
+        let fixed = self.banks.len() - 1;
+        if self.banks[fixed].base as usize + self.bank_size != 0x10000 {
+            return vec![];
+        }
+        let vectors = self.bank_size - VECTORS_LEN;
+        (0..3)
+            .filter_map(|i| self.resolve(fixed, self.word_at(fixed, vectors + 2 * i)))
+            .collect()
+    }
+
+    /// Returns the bank and offset an address falls in as seen from `bank`. Addresses in the
+    /// same bank win, then the fixed last bank. Addresses in other switchable banks can't be
+    /// resolved without knowing which bank is mapped.
+    fn resolve(&self, bank: usize, addr: u16) -> Option<(usize, usize)> {
+        let fixed = self.banks.len() - 1;
+        [bank, fixed].into_iter().find_map(|bank| {
+            let base = self.banks[bank].base as usize;
+            let addr = addr as usize;
+            (base..base + self.bank_size)
+                .contains(&addr)
+                .then(|| (bank, addr - base))
+        })
+    }
+
+    /// Returns the target of the instruction at `offset` if it branches, jumps or reads an
+    /// absolute address.
+    fn target(&self, bank: usize, offset: usize) -> Option<u16> {
+        let instr = self.instr_at(bank, offset);
+        let addr = self.banks[bank].base.wrapping_add(offset as u16);
+        match instr.addr_mode() {
+            AddrMode::REL => {
+                let rel = self.bank_data(bank)[offset + 1] as i8;
+                Some(addr.wrapping_add(2).wrapping_add(rel as u16))
+            }
+            AddrMode::ABS | AddrMode::ABX | AddrMode::ABY | AddrMode::IND => {
+                Some(self.operand_u16(bank, offset))
+            }
+            _ => None,
+        }
+    }
+
+    /// Marks an instruction at `offset` if it's official, fits in the bank and doesn't overlap
+    /// another instruction. Returns whether it's marked.
+    fn mark_instr(&mut self, bank: usize, offset: usize) -> bool {
+        let instr = self.instr_at(bank, offset);
+        let size = instr.size() as usize;
+        let bytes = &mut self.banks[bank].bytes;
+        if !Self::is_official(instr)
+            || offset + size > bytes.len()
+            || bytes[offset..offset + size]
+                .iter()
+                .any(|&byte| byte != Byte::Data)
+        {
+            return false;
+        }
+        bytes[offset] = Byte::Opcode;
+        for byte in &mut bytes[offset + 1..offset + size] {
+            *byte = Byte::Operand;
+        }
+        true
+    }
+
+    /// Marks instructions where every byte was logged as executed.
+    fn mark_logged_code(&mut self, cdl: &CodeDataLog) {
+        let code_flags = PrgFlags::CODE | PrgFlags::INDIRECT_CODE;
+        for bank in 0..self.banks.len() {
+            let start = bank * self.bank_size;
+            let mut offset = 0;
+            while offset < self.bank_size {
+                let size = self.instr_at(bank, offset).size() as usize;
+                let logged = (offset..offset + size).all(|offset| {
+                    offset < self.bank_size && cdl.prg_flags(start + offset).intersects(code_flags)
+                });
+                if logged && self.mark_instr(bank, offset) {
+                    offset += size;
+                } else {
+                    offset += 1;
+                }
+            }
+        }
+    }
+
+    /// Marks instructions reachable from the interrupt vectors in the fixed bank.
+    fn trace_vectors(&mut self) {
+        let mut pending = self.vector_targets();
+        while let Some((bank, mut offset)) = pending.pop() {
+            while offset < self.bank_size
+                && self.banks[bank].bytes[offset] == Byte::Data
+                && self.mark_instr(bank, offset)
+            {
+                let instr = self.instr_at(bank, offset);
+                let jump_target = match (instr.op(), instr.addr_mode()) {
+                    (_, AddrMode::REL) | (Operation::JMP | Operation::JSR, AddrMode::ABS) => {
+                        self.target(bank, offset)
+                    }
+                    _ => None,
+                };
+                if let Some(target) = jump_target.and_then(|addr| self.resolve(bank, addr)) {
+                    pending.push(target);
+                }
+                match instr.op() {
+                    Operation::JMP | Operation::RTS | Operation::RTI | Operation::BRK => break,
+                    _ => offset += instr.size() as usize,
+                }
+            }
+        }
+    }
+
+    /// Labels interrupt handlers and targets that land on the start of an instruction or on data.
+    fn find_labels(&mut self) {
+        let mut labels = self.vector_targets();
+        for (bank, data) in self.banks.iter().enumerate() {
+            for offset in 0..self.bank_size {
+                if data.bytes[offset] != Byte::Opcode {
+                    continue;
+                }
+                if let Some((target_bank, target)) = self
+                    .target(bank, offset)
+                    .and_then(|addr| self.resolve(bank, addr))
+                {
+                    labels.push((target_bank, target));
+                }
+            }
+        }
+        for (bank, offset) in labels {
+            if self.banks[bank].bytes[offset] != Byte::Operand {
+                self.banks[bank].labels.insert(offset);
+            }
+        }
+    }
+
+    fn label(&self, bank: usize, offset: usize) -> String {
+        let prefix = match self.banks[bank].bytes[offset] {
+            Byte::Data => 'D',
+            _ => 'L',
+        };
+        let addr = self.banks[bank].base as usize + offset;
+        if self.banks.len() == 1 {
+            format!("{prefix}{addr:04X}")
+        } else {
+            format!("{prefix}{bank:02}_{addr:04X}")
+        }
+    }
+
+    fn byte_directive(bytes: &[u8]) -> String {
+        let bytes = bytes
+            .iter()
+            .map(|byte| format!("${byte:02X}"))
+            .collect::<Vec<_>>();
+        format!(".byte {}", bytes.join(", "))
+    }
+
+    /// Returns the operand of an absolute address, using a label if there is one. Addresses in
+    /// zero page are forced to absolute addressing so they reassemble to the same opcode.
+    fn abs_operand(&self, bank: usize, addr: u16) -> String {
+        match self.resolve(bank, addr) {
+            Some((bank, offset)) if self.banks[bank].labels.contains(&offset) => {
+                self.label(bank, offset)
+            }
+            _ if addr < 0x0100 => format!("a:${addr:04X}"),
+            _ => format!("${addr:04X}"),
+        }
+    }
+
+    fn instr_text(&self, bank: usize, offset: usize) -> String {
+        let instr = self.instr_at(bank, offset);
+        let data = self.bank_data(bank);
+        let mnemonic = format!("{:?}", instr.op()).to_lowercase();
+        let byte = || data[offset + 1];
+        let operand = match instr.addr_mode() {
+            // ca65 only assembles a bare BRK, so keep its padding byte separate
+            _ if instr.op() == Operation::BRK => {
+                return format!("brk\n    .byte ${:02X}", byte());
+            }
+            AddrMode::IMP => return mnemonic,
+            AddrMode::ACC => "a".to_string(),
+            AddrMode::IMM => format!("#${:02X}", byte()),
+            AddrMode::ZP0 => format!("${:02X}", byte()),
+            AddrMode::ZPX => format!("${:02X},x", byte()),
+            AddrMode::ZPY => format!("${:02X},y", byte()),
+            AddrMode::IDX => format!("(${:02X},x)", byte()),
+            AddrMode::IDY => format!("(${:02X}),y", byte()),
+            AddrMode::ABS => self.abs_operand(bank, self.operand_u16(bank, offset)),
+            AddrMode::ABX => format!(
+                "{},x",
+                self.abs_operand(bank, self.operand_u16(bank, offset))
+            ),
+            AddrMode::ABY => format!(
+                "{},y",
+                self.abs_operand(bank, self.operand_u16(bank, offset))
+            ),
+            AddrMode::IND => format!(
+                "({})",
+                self.abs_operand(bank, self.operand_u16(bank, offset))
+            ),
+            AddrMode::REL => {
+                let target = self.target(bank, offset).unwrap_or_default();
+                match self.resolve(bank, target) {
+                    Some((target_bank, target))
+                        if target_bank == bank && self.banks[bank].labels.contains(&target) =>
+                    {
+                        self.label(bank, target)
+                    }
+                    _ => {
+                        let rel = 2 + i32::from(byte() as i8);
+                        if rel < 0 {
+                            format!("*-{}", -rel)
+                        } else {
+                            format!("*+{rel}")
+                        }
+                    }
+                }
+            }
+        };
+        format!("{mnemonic} {operand}")
+    }
+
+    /// Splits a bank into lines of instructions and `.byte` runs. Data runs are broken at labels
+    /// so every label lands on the start of a line.
+    fn bank_lines(&self, bank: usize) -> Vec<Line> {
+        let data = self.bank_data(bank);
+        let info = &self.banks[bank];
+        let fixed = bank + 1 == self.banks.len();
+        let vectors = self.bank_size - VECTORS_LEN;
+        let mut lines = vec![];
+        let mut offset = 0;
+        while offset < self.bank_size {
+            if info.bytes[offset] == Byte::Opcode {
+                let len = self.instr_at(bank, offset).size() as usize;
+                lines.push(Line {
+                    offset,
+                    len,
+                    text: self.instr_text(bank, offset),
+                });
+                offset += len;
+                continue;
+            }
+            let vectors_are_data = fixed
+                && offset == vectors
+                && info.base as usize + self.bank_size == 0x10000
+                && (offset..self.bank_size).all(|i| {
+                    info.bytes[i] == Byte::Data && (i == offset || !info.labels.contains(&i))
+                });
+            if vectors_are_data {
+                let addrs = (0..3)
+                    .map(|i| {
+                        let addr = self.word_at(bank, offset + 2 * i);
+                        match self.resolve(bank, addr) {
+                            Some((bank, offset)) if self.banks[bank].labels.contains(&offset) => {
+                                self.label(bank, offset)
+                            }
+                            _ => format!("${addr:04X}"),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                lines.push(Line {
+                    offset,
+                    len: VECTORS_LEN,
+                    text: format!(".addr {} ; NMI, Reset, IRQ", addrs.join(", ")),
+                });
+                break;
+            }
+            let mut end = offset + 1;
+            while end < self.bank_size
+                && end - offset < DATA_BYTES_PER_LINE
+                && info.bytes[end] == Byte::Data
+                && !info.labels.contains(&end)
+                && !(fixed && end == vectors)
+            {
+                end += 1;
+            }
+            lines.push(Line {
+                offset,
+                len: end - offset,
+                text: Self::byte_directive(&data[offset..end]),
+            });
+            offset = end;
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // $C000 SEI
+    // $C001 LDX #$00
+    // $C003 LDA $C010,X
+    // $C006 STA $0010
+    // $C009 INX
+    // $C00A BNE $C003
+    // $C00C JSR $C014
+    // $C00F RTS
+    // $C010 .byte $01, $02, $03, $04
+    // $C014 JMP $C00C
+    const PROGRAM: [u8; 23] = [
+        0x78, 0xA2, 0x00, 0xBD, 0x10, 0xC0, 0x8D, 0x10, 0x00, 0xE8, 0xD0, 0xF7, 0x20, 0x14, 0xC0,
+        0x60, 0x01, 0x02, 0x03, 0x04, 0x4C, 0x0C, 0xC0,
+    ];
+
+    fn prg_rom() -> Vec<u8> {
+        let mut prg_rom = vec![0x00; 0x4000];
+        prg_rom[..PROGRAM.len()].copy_from_slice(&PROGRAM);
+        // NMI and IRQ -> $C00F, Reset -> $C000
+        prg_rom[0x3FFA..].copy_from_slice(&[0x0F, 0xC0, 0x00, 0xC0, 0x0F, 0xC0]);
+        prg_rom
+    }
+
+    fn source(disasm: &Disassembly) -> String {
+        let mut source = vec![];
+        disasm
+            .write_source(&mut source, b"NES\x1A", Some("game.chr"))
+            .expect("valid source");
+        String::from_utf8(source).expect("valid utf8")
+    }
+
+    #[test]
+    fn recursive_descent() {
+        let prg_rom = prg_rom();
+        let disasm = Disassembly::new(&prg_rom, 0, None).expect("valid disassembly");
+        assert_eq!(disasm.bank_count(), 1);
+        assert_eq!(disasm.bank_base(0), 0xC000);
+        assert_eq!(disasm.code_len(), PROGRAM.len() - 4);
+
+        let lines = disasm.bank_lines(0);
+        assert_eq!(
+            lines.iter().map(|line| line.len).sum::<usize>(),
+            prg_rom.len(),
+            "every byte emitted once"
+        );
+        assert!(lines
+            .windows(2)
+            .all(|w| w[0].offset + w[0].len == w[1].offset));
+
+        let source = source(&disasm);
+        for expected in [
+            "L_START:\n    sei",
+            "L_LOOP:\n    lda D_DATA,x\n    sta a:$0010\n    inx\n    bne L_LOOP",
+            "L_CALL:\n    jsr L_JUMP\nL_RTS:\n    rts\nD_DATA:\n    .byte $01, $02, $03, $04\nL_JUMP:\n    jmp L_CALL",
+            ".addr L_RTS, L_START, L_RTS",
+            ".incbin \"game.chr\"",
+        ] {
+            let expected = expected
+                .replace("L_START", "LC000")
+                .replace("L_RTS", "LC00F")
+                .replace("L_LOOP", "LC003")
+                .replace("D_DATA", "DC010")
+                .replace("L_CALL", "LC00C")
+                .replace("L_JUMP", "LC014");
+            assert!(source.contains(&expected), "{expected} in\n{source}");
+        }
+    }
+
+    #[test]
+    fn code_data_log() {
+        let mut prg_rom = prg_rom();
+        prg_rom.extend(prg_rom.clone());
+        let mut cdl = CodeDataLog::new(prg_rom.len(), 0);
+        // Only the loop at $8003 in the first bank ran, through the $8000 window
+        for offset in 0x03..0x0C {
+            cdl.prg[offset] = (PrgFlags::CODE | PrgFlags::bank(0x8000)).bits();
+        }
+        // An unofficial NOP executed in the fixed bank stays as data
+        prg_rom[0x4000] = 0x1A;
+        cdl.prg[0x4000] = (PrgFlags::CODE | PrgFlags::bank(0xC000)).bits();
+
+        let disasm = Disassembly::new(&prg_rom, 0x4000, Some(&cdl)).expect("valid disassembly");
+        assert_eq!(disasm.bank_count(), 2);
+        assert_eq!(disasm.bank_base(0), 0x8000);
+        assert_eq!(disasm.bank_base(1), 0xC000);
+        assert_eq!(disasm.code_len(), 9);
+        let source = source(&disasm);
+        for expected in [
+            ".segment \"PRG00\" ; $8000\n    .byte $78, $A2, $00\nL00_8003:\n    lda D01_C010,x",
+            "bne L00_8003",
+            ".segment \"PRG01\" ; $C000\nD01_C000:\n    .byte $1A,",
+        ] {
+            assert!(source.contains(expected), "{expected} in\n{source}");
+        }
+
+        let mut config = vec![];
+        disasm
+            .write_linker_config(&mut config, 16, 0x2000)
+            .expect("valid config");
+        let config = String::from_utf8(config).expect("valid utf8");
+        assert!(config.contains("PRG01: start = $C000, size = $4000, file = %O, fill = yes;"));
+        assert!(config.contains("CHR: load = CHR, type = ro;"));
+
+        assert!(Disassembly::new(&prg_rom, 0x3000, None).is_err());
+        assert!(Disassembly::new(&prg_rom[..0x4000], 0, Some(&cdl)).is_err());
+    }
+}
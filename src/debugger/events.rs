@@ -0,0 +1,228 @@
+//! PPU event recording for diagnosing raster effects.
+//!
+//! Records CPU writes to PPU registers, OAM DMA and mapper registers along with NMI, IRQ and
+//! sprite 0 hit events, each tagged with the PPU scanline and cycle it happened on. Events are
+//! grouped by frame, starting from scanline 0.
+
+/// Type of a recorded [`PpuEvent`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum PpuEventKind {
+    /// CPU write to `$2000-$2007`. Writes to mirrors are recorded as the mirrored register.
+    PpuWrite,
+    /// CPU write to `$4014`, starting OAM DMA.
+    OamDma,
+    /// CPU write to a mapper register.
+    MapperWrite,
+    /// NMI handled by the CPU, with the handler address.
+    Nmi,
+    /// IRQ handled by the CPU, with the handler address.
+    Irq,
+    /// Sprite 0 hit flag set.
+    SpriteZeroHit,
+}
+
+impl PpuEventKind {
+    #[inline]
+    pub const fn as_slice() -> &'static [Self] {
+        &[
+            Self::PpuWrite,
+            Self::OamDma,
+            Self::MapperWrite,
+            Self::Nmi,
+            Self::Irq,
+            Self::SpriteZeroHit,
+        ]
+    }
+}
+
+impl AsRef<str> for PpuEventKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::PpuWrite => "PPU Register Write",
+            Self::OamDma => "OAM DMA",
+            Self::MapperWrite => "Mapper Register Write",
+            Self::Nmi => "NMI",
+            Self::Irq => "IRQ",
+            Self::SpriteZeroHit => "Sprite 0 Hit",
+        }
+    }
+}
+
+/// A single recorded event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct PpuEvent {
+    pub kind: PpuEventKind,
+    pub scanline: u32,
+    pub cycle: u32,
+    /// Register written or interrupt handler address. Unused for sprite 0 hits.
+    pub addr: u16,
+    /// Value written. Unused for interrupts and sprite 0 hits.
+    pub val: u8,
+}
+
+impl std::fmt::Display for PpuEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>3},{:>3} {}",
+            self.scanline,
+            self.cycle,
+            self.kind.as_ref()
+        )?;
+        match self.kind {
+            PpuEventKind::PpuWrite | PpuEventKind::OamDma | PpuEventKind::MapperWrite => {
+                write!(f, ": ${:04X} = ${:02X}", self.addr, self.val)
+            }
+            PpuEventKind::Nmi | PpuEventKind::Irq => write!(f, ": ${:04X}", self.addr),
+            PpuEventKind::SpriteZeroHit => Ok(()),
+        }
+    }
+}
+
+/// Events recorded for the current and previous frames.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub struct EventLog {
+    current: Vec<PpuEvent>,
+    previous: Vec<PpuEvent>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub(crate) fn log(&mut self, event: PpuEvent) {
+        self.current.push(event);
+    }
+
+    /// Moves events for the frame in progress to the previous frame.
+    #[inline]
+    pub(crate) fn end_frame(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
+    }
+
+    /// Returns events for the last complete frame, in the order they happened.
+    #[inline]
+    pub fn events(&self) -> &[PpuEvent] {
+        &self.previous
+    }
+
+    /// Returns events recorded so far for the frame in progress.
+    #[inline]
+    pub fn current_events(&self) -> &[PpuEvent] {
+        &self.current
+    }
+
+    /// Returns the last event recorded at or before the given scanline and cycle in the last
+    /// complete frame, within `radius` cycles.
+    #[must_use]
+    pub fn event_near(&self, scanline: u32, cycle: u32, radius: u32) -> Option<&PpuEvent> {
+        self.previous.iter().rev().find(|event| {
+            event.scanline == scanline && event.cycle <= cycle && cycle - event.cycle <= radius
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cart::Cart,
+        common::{Kind, Reset},
+        control_deck::ControlDeck,
+        mapper::Uxrom,
+    };
+
+    // $8000 LDA #$90
+    // $8002 STA $2000
+    // $8005 JMP $8000
+    // $8008 RTI
+    const ENABLE_NMI: [u8; 9] = [0xA9, 0x90, 0x8D, 0x00, 0x20, 0x4C, 0x00, 0x80, 0x40];
+
+    #[test]
+    fn record_events() {
+        let mut cart = Cart::empty();
+        cart.prg_rom[..ENABLE_NMI.len()].copy_from_slice(&ENABLE_NMI);
+        // NMI vector -> $8008, Reset vector -> $8000
+        cart.prg_rom[0x3FFA..0x3FFE].copy_from_slice(&[0x08, 0x80, 0x00, 0x80]);
+        let mut deck = ControlDeck::default();
+        deck.cpu_mut().load_cart(cart);
+        deck.reset(Kind::Hard);
+
+        deck.start_event_log();
+        for _ in 0..2 {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+        let log = deck.event_log().expect("event log");
+        let write = log.current_events()[0];
+        assert_eq!(write.kind, PpuEventKind::PpuWrite);
+        assert_eq!((write.addr, write.val), (0x2000, 0x90));
+
+        for _ in 0..3 {
+            let _ = deck.clock_frame().expect("valid clock");
+        }
+        let log = deck.event_log().expect("event log");
+        let nmi = log
+            .events()
+            .iter()
+            .find(|event| event.kind == PpuEventKind::Nmi)
+            .expect("nmi event");
+        assert_eq!((nmi.scanline, nmi.addr), (241, 0x8008));
+        assert_eq!(log.event_near(nmi.scanline, nmi.cycle + 2, 4), Some(nmi));
+
+        assert!(deck.stop_event_log().is_some());
+        assert!(deck.event_log().is_none());
+    }
+
+    // $8000 LDA #$01
+    // $8002 STA $2009
+    // $8005 STA $8000
+    // $8008 JMP $8008
+    const REGISTER_WRITES: [u8; 11] = [
+        0xA9, 0x01, 0x8D, 0x09, 0x20, 0x8D, 0x00, 0x80, 0x4C, 0x08, 0x80,
+    ];
+
+    fn register_writes(uxrom: bool) -> Vec<PpuEvent> {
+        let mut cart = Cart::empty();
+        cart.prg_rom[..REGISTER_WRITES.len()].copy_from_slice(&REGISTER_WRITES);
+        cart.prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+        if uxrom {
+            cart.mapper = Uxrom::load(&mut cart);
+        }
+        let mut deck = ControlDeck::default();
+        deck.cpu_mut().load_cart(cart);
+        deck.reset(Kind::Hard);
+
+        deck.start_event_log();
+        for _ in 0..3 {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+        deck.event_log()
+            .expect("event log")
+            .current_events()
+            .iter()
+            .filter(|event| event.kind != PpuEventKind::Nmi)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn record_mirrored_and_mapper_writes() {
+        // Writes to PPU register mirrors are recorded once as the mirrored register and ROM
+        // writes the mapper ignores are not recorded
+        let events = register_writes(false);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, PpuEventKind::PpuWrite);
+        assert_eq!((events[0].addr, events[0].val), (0x2001, 0x01));
+
+        let events = register_writes(true);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].kind, PpuEventKind::MapperWrite);
+        assert_eq!((events[1].addr, events[1].val), (0x8000, 0x01));
+    }
+}
@@ -0,0 +1,638 @@
+//! GDB Remote Serial Protocol stub for the 6502 core.
+//!
+//! <https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html>
+//!
+//! GDB has no 6502 target, so registers are exposed in the following order, each encoded as
+//! little-endian hex in `g`/`G` packets and numbered for `p`/`P` packets:
+//!
+//! | # | Register | Size    |
+//! |---|----------|---------|
+//! | 0 | `pc`     | 16 bits |
+//! | 1 | `sp`     | 8 bits  |
+//! | 2 | `a`      | 8 bits  |
+//! | 3 | `x`      | 8 bits  |
+//! | 4 | `y`      | 8 bits  |
+//! | 5 | `status` | 8 bits  |
+//!
+//! Supported packets: `?`, `g`, `G`, `p`, `P`, `m`, `M`, `s`, `c`, `Z0`-`Z4`, `z0`-`z4`, `D`,
+//! `k`, `qSupported`, `QStartNoAckMode` and `qAttached`. Continuing can be interrupted by sending
+//! `0x03`. Memory reads use `peek` and writes do not clock the CPU. Stop replies use `S05` for
+//! breakpoints and steps, `T05watch:addr;` (or `rwatch`/`awatch`) for watchpoints, `S02` for
+//! interrupts and `S04` if the CPU encounters an invalid opcode.
+
+use crate::{
+    control_deck::ControlDeck,
+    cpu::Status,
+    debugger::{AddrSpace, Address, Breakpoint, Watchpoint},
+    mem::{Access, Mem},
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    ops::ControlFlow,
+};
+
+const PACKET_SIZE: usize = 0x1000;
+const INTERRUPT: u8 = 0x03;
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+/// Listens for GDB remote connections.
+#[derive(Debug)]
+#[must_use]
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    /// Binds a TCP listener to the given address.
+    ///
+    /// # Errors
+    ///
+    /// If the address can not be bound, an error is returned.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> NesResult<Self> {
+        let listener = TcpListener::bind(addr).context("failed to bind gdb server")?;
+        Ok(Self { listener })
+    }
+
+    /// Returns the address the server is listening on.
+    ///
+    /// # Errors
+    ///
+    /// If the address can not be determined, an error is returned.
+    pub fn local_addr(&self) -> NesResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Waits for a single client connection and serves requests against `deck` until the client
+    /// detaches, kills the session or disconnects.
+    ///
+    /// # Errors
+    ///
+    /// If the connection fails, an error is returned.
+    pub fn serve(&self, deck: &mut ControlDeck) -> NesResult<()> {
+        let (stream, addr) = self.listener.accept()?;
+        log::info!("gdb client connected from {addr}");
+        GdbSession::new(stream).run(deck)
+    }
+}
+
+/// Stop reason reported to the client.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stop {
+    Signal(u8),
+    Watch(WatchKind, u16),
+}
+
+/// Kind of watchpoint set by a `Z2`-`Z4` packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+impl Stop {
+    fn reply(self) -> String {
+        match self {
+            Self::Signal(signal) => format!("S{signal:02x}"),
+            Self::Watch(kind, addr) => {
+                let kind = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T{SIGTRAP:02x}{kind}:{addr:04x};")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Packet<'a> {
+    Command(&'a str),
+    Interrupt,
+}
+
+/// A single client connection.
+#[derive(Debug)]
+struct GdbSession {
+    stream: TcpStream,
+    pending: VecDeque<u8>,
+    packet: String,
+    last_response: String,
+    no_ack: bool,
+}
+
+impl GdbSession {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            pending: VecDeque::with_capacity(PACKET_SIZE),
+            packet: String::with_capacity(PACKET_SIZE),
+            last_response: String::new(),
+            no_ack: false,
+        }
+    }
+
+    fn run(&mut self, deck: &mut ControlDeck) -> NesResult<()> {
+        loop {
+            let response = match self.read_packet()? {
+                None => return Ok(()),
+                // Already stopped
+                Some(Packet::Interrupt) => Some(Stop::Signal(SIGINT).reply()),
+                Some(Packet::Command(command)) => {
+                    let command = command.to_string();
+                    match command.as_str() {
+                        "k" => return Ok(()),
+                        "D" => {
+                            self.send_packet("OK")?;
+                            return Ok(());
+                        }
+                        _ => self.handle_command(deck, &command)?,
+                    }
+                }
+            };
+            self.send_packet(response.as_deref().unwrap_or_default())?;
+        }
+    }
+
+    /// Handles a command packet, returning the response. An empty response indicates an
+    /// unsupported command.
+    fn handle_command(
+        &mut self,
+        deck: &mut ControlDeck,
+        command: &str,
+    ) -> NesResult<Option<String>> {
+        let (kind, args) = command.split_at(command.chars().next().map_or(0, char::len_utf8));
+        let response = match kind {
+            "?" => Stop::Signal(SIGTRAP).reply(),
+            "g" => Self::read_registers(deck),
+            "G" => Self::result(Self::write_registers(deck, args)),
+            "p" => Self::result(Self::read_register(deck, args)),
+            "P" => Self::result(Self::write_register(deck, args)),
+            "m" => Self::result(Self::read_memory(deck, args)),
+            "M" => Self::result(Self::write_memory(deck, args)),
+            "s" => Self::step(deck).reply(),
+            "c" => self.resume(deck)?.reply(),
+            "Z" | "z" => Self::result(Self::set_breakpoint(deck, args, kind == "Z")),
+            "H" => "OK".to_string(),
+            "q" => match args.split(':').next().unwrap_or_default() {
+                "Supported" => format!("PacketSize={PACKET_SIZE:x};QStartNoAckMode+"),
+                "Attached" => "1".to_string(),
+                _ => return Ok(None),
+            },
+            "Q" if args == "StartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(response))
+    }
+
+    /// Converts a command result into a response, replying with `E01` on error.
+    fn result(result: NesResult<String>) -> String {
+        result.unwrap_or_else(|err| {
+            log::warn!("gdb command failed: {err:?}");
+            "E01".to_string()
+        })
+    }
+
+    fn read_registers(deck: &ControlDeck) -> String {
+        let cpu = deck.cpu();
+        let [pc_lo, pc_hi] = cpu.pc().to_le_bytes();
+        let mut response = String::with_capacity(14);
+        for val in [
+            pc_lo,
+            pc_hi,
+            cpu.sp(),
+            cpu.a(),
+            cpu.x(),
+            cpu.y(),
+            cpu.status().bits(),
+        ] {
+            let _ = write!(response, "{val:02x}");
+        }
+        response
+    }
+
+    fn write_registers(deck: &mut ControlDeck, args: &str) -> NesResult<String> {
+        let bytes = parse_hex_bytes(args)?;
+        match bytes[..] {
+            [pc_lo, pc_hi, sp, a, x, y, status] => {
+                let cpu = deck.cpu_mut();
+                cpu.set_pc(u16::from_le_bytes([pc_lo, pc_hi]));
+                cpu.set_sp(sp);
+                cpu.set_a(a);
+                cpu.set_x(x);
+                cpu.set_y(y);
+                cpu.set_status(Status::from_bits_truncate(status));
+                Ok("OK".to_string())
+            }
+            _ => Err(anyhow!("invalid register data: {args}")),
+        }
+    }
+
+    fn read_register(deck: &ControlDeck, args: &str) -> NesResult<String> {
+        let cpu = deck.cpu();
+        Ok(match parse_hex(args)? {
+            0 => {
+                let [lo, hi] = cpu.pc().to_le_bytes();
+                format!("{lo:02x}{hi:02x}")
+            }
+            1 => format!("{:02x}", cpu.sp()),
+            2 => format!("{:02x}", cpu.a()),
+            3 => format!("{:02x}", cpu.x()),
+            4 => format!("{:02x}", cpu.y()),
+            5 => format!("{:02x}", cpu.status().bits()),
+            reg => return Err(anyhow!("invalid register: {reg}")),
+        })
+    }
+
+    fn write_register(deck: &mut ControlDeck, args: &str) -> NesResult<String> {
+        let (reg, val) = args
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid register write: {args}"))?;
+        let bytes = parse_hex_bytes(val)?;
+        let cpu = deck.cpu_mut();
+        match (parse_hex(reg)?, &bytes[..]) {
+            (0, &[lo, hi]) => cpu.set_pc(u16::from_le_bytes([lo, hi])),
+            (1, &[val]) => cpu.set_sp(val),
+            (2, &[val]) => cpu.set_a(val),
+            (3, &[val]) => cpu.set_x(val),
+            (4, &[val]) => cpu.set_y(val),
+            (5, &[val]) => cpu.set_status(Status::from_bits_truncate(val)),
+            _ => return Err(anyhow!("invalid register write: {args}")),
+        }
+        Ok("OK".to_string())
+    }
+
+    fn read_memory(deck: &ControlDeck, args: &str) -> NesResult<String> {
+        let (addr, len) = parse_addr_len(args)?;
+        let cpu = deck.cpu();
+        let mut response = String::with_capacity(2 * len);
+        for offset in 0..len {
+            let val = cpu.peek(addr.wrapping_add(offset as u16), Access::Dummy);
+            let _ = write!(response, "{val:02x}");
+        }
+        Ok(response)
+    }
+
+    fn write_memory(deck: &mut ControlDeck, args: &str) -> NesResult<String> {
+        let (addr_len, data) = args
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid memory write: {args}"))?;
+        let (addr, len) = parse_addr_len(addr_len)?;
+        let bytes = parse_hex_bytes(data)?;
+        if bytes.len() != len {
+            return Err(anyhow!("invalid memory write length: {args}"));
+        }
+        let cpu = deck.cpu_mut();
+        for (offset, val) in bytes.into_iter().enumerate() {
+            cpu.poke(addr.wrapping_add(offset as u16), val);
+        }
+        Ok("OK".to_string())
+    }
+
+    fn set_breakpoint(deck: &mut ControlDeck, args: &str, insert: bool) -> NesResult<String> {
+        let mut parts = args.split(',');
+        let (kind, addr, len) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(kind), Some(addr), Some(len)) => (kind, addr, len),
+            _ => return Err(anyhow!("invalid breakpoint: {args}")),
+        };
+        let addr = u16::try_from(parse_hex(addr)?)?;
+        let access = match kind {
+            // Software and hardware breakpoints are equivalent
+            "0" | "1" => {
+                let address = Address::Addr(addr);
+                let index = deck.breakpoints().iter().position(|breakpoint| {
+                    breakpoint.addr() == &address && breakpoint.conditions().is_empty()
+                });
+                match (insert, index) {
+                    (true, None) => {
+                        deck.add_breakpoint(Breakpoint::new(address, vec![]));
+                    }
+                    (false, Some(index)) => {
+                        deck.remove_breakpoint(index);
+                    }
+                    _ => (),
+                }
+                return Ok("OK".to_string());
+            }
+            "2" => vec![Access::Write],
+            "3" => vec![Access::Read],
+            "4" => vec![Access::Read, Access::Write],
+            _ => return Err(anyhow!("invalid breakpoint kind: {kind}")),
+        };
+        let len = parse_hex(len)?.max(1);
+        let end = addr.saturating_add(u16::try_from(len - 1)?);
+        let address = Address::from(addr..=end);
+        let index = deck
+            .watchpoints(AddrSpace::Cpu)
+            .iter()
+            .position(|watchpoint| watchpoint.addr() == &address && watchpoint.access() == access);
+        match (insert, index) {
+            (true, None) => {
+                deck.add_watchpoint(Watchpoint::new(AddrSpace::Cpu, address, access, None));
+            }
+            (false, Some(index)) => {
+                deck.remove_watchpoint(AddrSpace::Cpu, index);
+            }
+            _ => (),
+        }
+        Ok("OK".to_string())
+    }
+
+    fn stop_reason(deck: &ControlDeck) -> Stop {
+        deck.watchpoint_hit().map_or(Stop::Signal(SIGTRAP), |hit| {
+            // GDB expects the kind of watchpoint that was set, not the access that triggered it
+            let mapper = deck.cpu().mapper();
+            let access = deck
+                .watchpoints(hit.space)
+                .iter()
+                .find(|watchpoint| watchpoint.is_hit(hit.addr, hit.access, hit.new, mapper))
+                .map_or(vec![hit.access], |watchpoint| watchpoint.access().to_vec());
+            let kind = match (
+                access.contains(&Access::Read),
+                access.contains(&Access::Write),
+            ) {
+                (true, true) => WatchKind::Access,
+                (true, false) => WatchKind::Read,
+                _ => WatchKind::Write,
+            };
+            Stop::Watch(kind, hit.addr)
+        })
+    }
+
+    fn step(deck: &mut ControlDeck) -> Stop {
+        match deck.clock_instr() {
+            Ok(_) => Self::stop_reason(deck),
+            Err(_) => Stop::Signal(SIGILL),
+        }
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, or the client interrupts.
+    fn resume(&mut self, deck: &mut ControlDeck) -> NesResult<Stop> {
+        loop {
+            match deck.clock_frame() {
+                Ok(ControlFlow::Break(_)) => return Ok(Self::stop_reason(deck)),
+                Ok(ControlFlow::Continue(_)) => (),
+                Err(_) => return Ok(Stop::Signal(SIGILL)),
+            }
+            if self.poll_interrupt()? {
+                return Ok(Stop::Signal(SIGINT));
+            }
+        }
+    }
+
+    /// Reads any available input without blocking, returning whether an interrupt was received.
+    fn poll_interrupt(&mut self) -> NesResult<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0x00; 64];
+        let result = loop {
+            match self.stream.read(&mut buf) {
+                // Disconnected, so stop running
+                Ok(0) => break Ok(true),
+                Ok(len) => self.pending.extend(&buf[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break Ok(false),
+                Err(err) => break Err(err),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        let interrupted = match self.pending.iter().position(|&byte| byte == INTERRUPT) {
+            Some(index) => {
+                self.pending.remove(index);
+                true
+            }
+            None => false,
+        };
+        Ok(result? || interrupted)
+    }
+
+    fn read_byte(&mut self) -> NesResult<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut buf = [0x00; 1];
+        match self.stream.read(&mut buf) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(buf[0])),
+            Err(err) if err.kind() == io::ErrorKind::ConnectionReset => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Reads the next packet, returning `None` if the client disconnected.
+    fn read_packet(&mut self) -> NesResult<Option<Packet<'_>>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(INTERRUPT) => return Ok(Some(Packet::Interrupt)),
+                Some(b'-') if !self.no_ack => {
+                    let response = self.last_response.clone();
+                    self.send_packet(&response)?;
+                }
+                Some(b'$') => {
+                    self.packet.clear();
+                    let mut checksum = 0u8;
+                    loop {
+                        match self.read_byte()? {
+                            None => return Ok(None),
+                            Some(b'#') => break,
+                            Some(byte) => {
+                                checksum = checksum.wrapping_add(byte);
+                                self.packet.push(char::from(byte));
+                            }
+                        }
+                    }
+                    let mut expected = [0x00; 2];
+                    for byte in &mut expected {
+                        *byte = match self.read_byte()? {
+                            Some(byte) => byte,
+                            None => return Ok(None),
+                        };
+                    }
+                    let valid = std::str::from_utf8(&expected)
+                        .ok()
+                        .and_then(|expected| u8::from_str_radix(expected, 16).ok())
+                        == Some(checksum);
+                    if !self.no_ack {
+                        self.stream.write_all(if valid { b"+" } else { b"-" })?;
+                    }
+                    if valid && !self.packet.is_empty() {
+                        return Ok(Some(Packet::Command(&self.packet)));
+                    }
+                }
+                // Acks and noise between packets
+                Some(_) => (),
+            }
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> NesResult<()> {
+        let checksum = data.bytes().fold(0u8, u8::wrapping_add);
+        write!(self.stream, "${data}#{checksum:02x}")?;
+        self.stream.flush()?;
+        if !self.no_ack {
+            self.last_response.clear();
+            self.last_response.push_str(data);
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(val: &str) -> NesResult<usize> {
+    usize::from_str_radix(val, 16).with_context(|| format!("invalid hex value: {val}"))
+}
+
+fn parse_hex_bytes(val: &str) -> NesResult<Vec<u8>> {
+    if val.len() % 2 != 0 {
+        return Err(anyhow!("invalid hex data: {val}"));
+    }
+    (0..val.len())
+        .step_by(2)
+        .map(|i| {
+            let byte = val.get(i..i + 2).unwrap_or_default();
+            u8::from_str_radix(byte, 16).with_context(|| format!("invalid hex data: {val}"))
+        })
+        .collect()
+}
+
+fn parse_addr_len(val: &str) -> NesResult<(u16, usize)> {
+    let (addr, len) = val
+        .split_once(',')
+        .ok_or_else(|| anyhow!("invalid address and length: {val}"))?;
+    let addr = u16::try_from(parse_hex(addr)?)?;
+    let len = parse_hex(len)?;
+    if len > PACKET_SIZE / 2 {
+        return Err(anyhow!("invalid length: {len}"));
+    }
+    Ok((addr, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::tests::{load_program, LOOP};
+    use std::{io::BufReader, thread};
+
+    struct Client {
+        stream: BufReader<TcpStream>,
+    }
+
+    impl Client {
+        fn connect() -> (Self, thread::JoinHandle<NesResult<()>>) {
+            let server = GdbServer::bind("127.0.0.1:0").expect("valid bind");
+            let addr = server.local_addr().expect("valid addr");
+            let handle = thread::spawn(move || {
+                let mut deck = load_program(&LOOP);
+                server.serve(&mut deck)
+            });
+            let stream = TcpStream::connect(addr).expect("valid connection");
+            let client = Self {
+                stream: BufReader::new(stream),
+            };
+            (client, handle)
+        }
+
+        fn send(&mut self, data: &str) {
+            let checksum = data.bytes().fold(0u8, u8::wrapping_add);
+            let stream = self.stream.get_mut();
+            write!(stream, "${data}#{checksum:02x}").expect("valid write");
+        }
+
+        fn recv(&mut self) -> String {
+            let mut byte = [0x00; 1];
+            let mut packet = String::new();
+            loop {
+                self.stream.read_exact(&mut byte).expect("valid read");
+                match byte[0] {
+                    b'$' => packet.clear(),
+                    b'#' => {
+                        let mut checksum = [0x00; 2];
+                        self.stream.read_exact(&mut checksum).expect("valid read");
+                        return packet;
+                    }
+                    b'+' if packet.is_empty() => (),
+                    byte => packet.push(char::from(byte)),
+                }
+            }
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.recv()
+        }
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let (mut client, handle) = Client::connect();
+        assert_eq!(
+            client.request("qSupported:swbreak+"),
+            "PacketSize=1000;QStartNoAckMode+"
+        );
+        assert_eq!(client.request("QStartNoAckMode"), "OK");
+        assert_eq!(client.request("?"), "S05");
+        // pc = $8000, sp = $FD, a = x = y = 0, status = $24
+        assert_eq!(client.request("g"), "0080fd00000024");
+        assert_eq!(client.request("p0"), "0080");
+        assert_eq!(client.request("P2=42"), "OK");
+        assert_eq!(client.request("p2"), "42");
+        assert_eq!(client.request("p9"), "E01");
+
+        assert_eq!(client.request("m8000,3"), "a200e8");
+        assert_eq!(client.request("M0010,2:beef"), "OK");
+        assert_eq!(client.request("m0010,2"), "beef");
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p0"), "0280");
+        client.send("k");
+        handle.join().expect("valid join").expect("valid session");
+    }
+
+    #[test]
+    fn breakpoints_and_interrupt() {
+        let (mut client, handle) = Client::connect();
+        assert_eq!(client.request("Z0,8003,1"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p0"), "0380");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p0"), "0380");
+        assert_eq!(client.request("z0,8003,1"), "OK");
+
+        assert_eq!(client.request("Z2,0010,1"), "OK");
+        assert_eq!(
+            client.request("M0010,1:07"),
+            "OK",
+            "debugger writes don't trigger"
+        );
+        assert_eq!(client.request("z2,0010,1"), "OK");
+
+        // $8003 is only read as the opcode of JMP $8002
+        assert_eq!(client.request("Z4,8003,1"), "OK");
+        assert_eq!(client.request("c"), "T05awatch:8003;");
+        assert_eq!(client.request("z4,8003,1"), "OK");
+        assert_eq!(client.request("Z3,8004,1"), "OK");
+        assert_eq!(client.request("c"), "T05rwatch:8004;");
+        assert_eq!(client.request("z3,8004,1"), "OK");
+
+        // Runs forever until interrupted
+        client.send("c");
+        client
+            .stream
+            .get_mut()
+            .write_all(&[INTERRUPT])
+            .expect("valid write");
+        assert_eq!(client.recv(), "S02");
+        assert_eq!(client.request("D"), "OK");
+        handle.join().expect("valid join").expect("valid session");
+    }
+}
@@ -0,0 +1,338 @@
+//! Execution history for stepping backwards in the debugger.
+//!
+//! A snapshot of the [`Cpu`] is taken every few frames, along with every input change in between
+//! tagged with the CPU cycle it was made on. Stepping backwards restores the nearest earlier
+//! snapshot and re-executes up to the target instruction, replaying recorded input on the same
+//! cycles so execution is identical to the first time around.
+
+use crate::{cpu::Cpu, input::InputState, NesResult};
+use std::collections::VecDeque;
+
+/// A serialized [`Cpu`]. Debugger state such as the code/data log, access log, profiler and audio
+/// synthesis isn't serialized, so snapshots don't hold a copy of it.
+#[derive(Debug, Clone)]
+#[must_use]
+struct Snapshot {
+    cycle: usize,
+    frame: u32,
+    state: Vec<u8>,
+}
+
+/// Snapshots and input recorded while executing.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct History {
+    interval: u32,
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+    inputs: VecDeque<(usize, InputState)>,
+    last_input: InputState,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_INTERVAL, Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl History {
+    /// Frames between snapshots.
+    pub const DEFAULT_INTERVAL: u32 = 30;
+    /// Snapshots kept, about 30 seconds of history with the default interval.
+    pub const DEFAULT_CAPACITY: usize = 60;
+
+    /// Creates a history taking a snapshot every `interval` frames, keeping up to `capacity`
+    /// snapshots.
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            snapshots: VecDeque::new(),
+            inputs: VecDeque::new(),
+            last_input: InputState::default(),
+        }
+    }
+
+    /// Number of snapshots taken.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// CPU cycle of the oldest instruction that can be stepped back to.
+    #[inline]
+    #[must_use]
+    pub fn start_cycle(&self) -> Option<usize> {
+        self.snapshots.front().map(|snapshot| snapshot.cycle)
+    }
+
+    /// Discards all snapshots and input.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.inputs.clear();
+    }
+
+    /// Records any input change and takes a snapshot if one is due. Called before each
+    /// instruction is executed.
+    ///
+    /// # Errors
+    ///
+    /// If the snapshot fails to serialize, an error is returned.
+    pub(crate) fn record(&mut self, cpu: &Cpu) -> NesResult<()> {
+        let input = cpu.input_state();
+        if input.changed_since(&self.last_input) {
+            self.inputs.push_back((cpu.cycle(), input));
+        }
+        self.last_input = input;
+
+        let snapshot_due = self.snapshots.back().map_or(true, |snapshot| {
+            cpu.frame_number().wrapping_sub(snapshot.frame) >= self.interval
+        });
+        if snapshot_due {
+            if self.snapshots.len() == self.capacity {
+                self.snapshots.pop_front();
+            }
+            self.snapshots.push_back(Snapshot {
+                cycle: cpu.cycle(),
+                frame: cpu.frame_number(),
+                state: bincode::serialize(cpu)?,
+            });
+            let start_cycle = self.start_cycle().unwrap_or_default();
+            while matches!(self.inputs.front(), Some(&(cycle, _)) if cycle < start_cycle) {
+                self.inputs.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    /// Discards history recorded after the current CPU cycle, since execution may diverge after
+    /// stepping back.
+    pub(crate) fn truncate(&mut self, cpu: &Cpu) {
+        let current = cpu.cycle();
+        while matches!(self.snapshots.back(), Some(snapshot) if snapshot.cycle > current) {
+            self.snapshots.pop_back();
+        }
+        while matches!(self.inputs.back(), Some(&(cycle, _)) if cycle > current) {
+            self.inputs.pop_back();
+        }
+        self.last_input = cpu.input_state();
+    }
+
+    /// Deserializes the snapshot at the given index.
+    ///
+    /// # Errors
+    ///
+    /// If the snapshot fails to deserialize, an error is returned.
+    #[inline]
+    pub(crate) fn snapshot(&self, index: usize) -> NesResult<Cpu> {
+        Ok(bincode::deserialize(&self.snapshots[index].state)?)
+    }
+
+    #[inline]
+    #[must_use]
+    pub(crate) fn snapshot_cycle(&self, index: usize) -> usize {
+        self.snapshots[index].cycle
+    }
+
+    /// Index of the latest snapshot taken before the given CPU cycle.
+    #[must_use]
+    pub(crate) fn snapshot_before(&self, cycle: usize) -> Option<usize> {
+        self.snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.cycle < cycle)
+    }
+
+    /// Index of the latest snapshot taken during or before the given frame.
+    #[must_use]
+    pub(crate) fn snapshot_before_frame(&self, frame: u32) -> Option<usize> {
+        self.snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.frame <= frame)
+    }
+
+    /// Input changed by the player right before the instruction at the given CPU cycle.
+    pub(crate) fn input_at(&self, cycle: usize) -> Option<InputState> {
+        self.inputs
+            .binary_search_by_key(&cycle, |&(cycle, _)| cycle)
+            .ok()
+            .map(|index| self.inputs[index].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control_deck::ControlDeck,
+        debugger::{profiler::Profiler, tests::load_program, AddrSpace, Breakpoint, Watchpoint},
+        input::{JoypadBtnState, Slot},
+        mem::{Access, Mem},
+    };
+
+    // $8000 LDA #$01
+    // $8002 STA $4016
+    // $8005 LDA #$00
+    // $8007 STA $4016
+    // $800A LDA $4016
+    // $800D AND #$01
+    // $800F BEQ $8017
+    // $8011 INC $00
+    // $8013 BNE $8017
+    // $8015 INC $01
+    // $8017 JMP $8000
+    const COUNT_A: [u8; 26] = [
+        0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xAD, 0x16, 0x40, 0x29, 0x01,
+        0xF0, 0x06, 0xE6, 0x00, 0xD0, 0x02, 0xE6, 0x01, 0x4C, 0x00, 0x80,
+    ];
+
+    fn state(deck: &ControlDeck) -> (u32, usize, u16) {
+        let cpu = deck.cpu();
+        let count = u16::from_le_bytes([
+            cpu.peek(0x0000, Access::Dummy),
+            cpu.peek(0x0001, Access::Dummy),
+        ]);
+        (deck.frame_number(), cpu.cycle(), count)
+    }
+
+    #[test]
+    fn step_back() {
+        let mut deck = load_program(&COUNT_A);
+        // Only snapshot at the start so stepping back replays the button press
+        deck.start_history(History::new(60, 10));
+        let _ = deck.clock_frame().expect("valid clock");
+        let frame_start = state(&deck);
+        let _ = deck.clock_frame().expect("valid clock");
+        deck.joypad_mut(Slot::One)
+            .set_button(JoypadBtnState::A, true);
+        let press_start = state(&deck);
+        let _ = deck.clock_frame().expect("valid clock");
+        deck.joypad_mut(Slot::One)
+            .set_button(JoypadBtnState::A, false);
+        let release_start = state(&deck);
+        assert!(release_start.2 > press_start.2, "a presses counted");
+        for _ in 0..10 {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+
+        let (before, pc) = (state(&deck), deck.cpu().pc());
+        let _ = deck.clock_instr().expect("valid clock");
+        deck.step_back().expect("step back");
+        assert_eq!(state(&deck), before, "previous instruction");
+        assert_eq!(deck.cpu().pc(), pc);
+
+        deck.add_breakpoint(Breakpoint::new(0x8011, vec![]));
+        assert!(deck.run_back().expect("run back"), "breakpoint hit");
+        let hit = state(&deck);
+        assert_eq!(deck.cpu().pc(), 0x8011);
+        assert!(press_start.1 < hit.1 && hit.1 < before.1);
+        assert_eq!(hit.2, before.2 - 1, "last a press");
+
+        // The last press may be counted just after the release
+        let frame_starts = [frame_start, press_start, release_start];
+        deck.step_back_frame().expect("step back frame");
+        assert_eq!(
+            Some(&state(&deck)),
+            frame_starts.iter().find(|start| start.0 == hit.0 - 1),
+            "previous frame"
+        );
+
+        assert!(!deck.run_back().expect("run back"), "start of history");
+        assert_eq!(
+            Some(deck.cpu().cycle()),
+            deck.history().and_then(History::start_cycle)
+        );
+        assert!(deck.step_back().is_err(), "no earlier history");
+    }
+
+    #[test]
+    fn replay_side_effects() {
+        // $8000 JSR $8006
+        // $8003 JMP $8000
+        // $8006 INX
+        // $8007 STX $10
+        // $8009 RTS
+        let mut deck = load_program(&[0x20, 0x06, 0x80, 0x4C, 0x00, 0x80, 0xE8, 0x86, 0x10, 0x60]);
+        deck.start_history(History::new(60, 10));
+        deck.start_profiler();
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x0010,
+            vec![Access::Write],
+            Some(0x05),
+        ));
+        deck.cpu_mut().mem_hooks_mut().push(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x0010,
+            vec![Access::Write],
+            None,
+        ));
+
+        while deck.clock_instr().expect("valid clock").is_continue() {}
+        // RTS, JMP, JSR, INX and STX $10 of 6
+        for _ in 0..5 {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+        assert_eq!(deck.cpu().peek(0x0010, Access::Dummy), 0x06);
+        assert!(
+            !deck.cpu_mut().take_mem_hook_hits().is_empty(),
+            "hooks fired"
+        );
+        let profiled = deck.profiler().map(Profiler::total_cycles);
+        // Added after the snapshot, which shouldn't roll it back
+        deck.add_watchpoint(Watchpoint::new(
+            AddrSpace::Cpu,
+            0x0020,
+            vec![Access::Read],
+            None,
+        ));
+
+        deck.step_back().expect("step back");
+        assert_eq!(deck.cpu().peek(0x0010, Access::Dummy), 0x05);
+        assert!(
+            deck.cpu_mut().take_mem_hook_hits().is_empty(),
+            "hooks suspended while replaying"
+        );
+        assert_eq!(
+            deck.profiler().map(Profiler::total_cycles),
+            profiled,
+            "replay not profiled"
+        );
+        assert_eq!(deck.cpu_mut().mem_hooks_mut().len(), 1, "hooks restored");
+        assert_eq!(
+            deck.watchpoints(AddrSpace::Cpu).len(),
+            2,
+            "watchpoints kept"
+        );
+        assert!(
+            deck.clock_instr().expect("valid clock").is_continue(),
+            "no stale watchpoint hit"
+        );
+    }
+
+    #[test]
+    fn replay_not_logged() {
+        // $8000 INX
+        // $8001 STX $2006
+        // $8004 JMP $8000
+        let mut deck = load_program(&[0xE8, 0x8E, 0x06, 0x20, 0x4C, 0x00, 0x80]);
+        deck.start_history(History::new(60, 10));
+        deck.start_event_log();
+        for _ in 0..30 {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+        let event_count =
+            |deck: &ControlDeck| deck.event_log().map(|log| log.current_events().len());
+        let logged = event_count(&deck);
+        assert!(logged > Some(0), "ppu writes logged");
+
+        deck.step_back().expect("step back");
+        assert_eq!(event_count(&deck), logged, "replay not logged");
+    }
+}
@@ -0,0 +1,275 @@
+//! Memory regions for viewing, editing and searching memory in the debugger.
+//!
+//! Regions are indexed from 0, e.g. `Palette` index 0 is PPU address `$3F00`. Recent accesses are
+//! tracked per region by the frame they last happened on while an [`AccessLog`] is running.
+//! CPU and PPU address space, PRG-RAM, CHR-RAM, palette RAM and OAM accesses are tracked.
+//! Secondary OAM and EX-RAM aren't.
+
+use crate::{mem::Access, NesResult};
+use anyhow::anyhow;
+
+/// A block of memory that can be viewed in the memory editor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum MemRegion {
+    /// CPU address space as read with `peek`, without side effects.
+    Cpu,
+    /// PPU address space, with CHR and nametables mapped as the PPU sees them.
+    Ppu,
+    /// Primary OAM, 64 sprites of 4 bytes each.
+    Oam,
+    /// Secondary OAM, the 8 sprites selected for the next scanline.
+    SecondaryOam,
+    /// Palette RAM at `$3F00-$3F1F`.
+    Palette,
+    /// Cartridge PRG-RAM, including battery-backed SRAM.
+    PrgRam,
+    /// Cartridge CHR-RAM.
+    ChrRam,
+    /// MMC5 extended RAM, or the extra nametable RAM of four-screen cartridges.
+    ExRam,
+}
+
+impl MemRegion {
+    #[inline]
+    pub const fn as_slice() -> &'static [Self] {
+        &[
+            Self::Cpu,
+            Self::Ppu,
+            Self::Oam,
+            Self::SecondaryOam,
+            Self::Palette,
+            Self::PrgRam,
+            Self::ChrRam,
+            Self::ExRam,
+        ]
+    }
+}
+
+impl AsRef<str> for MemRegion {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Cpu => "CPU Memory",
+            Self::Ppu => "PPU Memory",
+            Self::Oam => "OAM",
+            Self::SecondaryOam => "Secondary OAM",
+            Self::Palette => "Palette RAM",
+            Self::PrgRam => "PRG-RAM",
+            Self::ChrRam => "CHR-RAM",
+            Self::ExRam => "EX-RAM",
+        }
+    }
+}
+
+/// Frames a byte was last read and written on.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct MemAccess {
+    pub read: Option<u32>,
+    pub write: Option<u32>,
+}
+
+#[derive(Default, Debug, Clone)]
+struct RegionLog {
+    // Frame number + 1 of the last access, 0 if never accessed
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+}
+
+/// Records the frame each byte of memory was last read or written on.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct AccessLog {
+    frame: u32,
+    regions: Vec<RegionLog>,
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AccessLog {
+    pub fn new() -> Self {
+        Self {
+            frame: 0,
+            regions: vec![RegionLog::default(); MemRegion::as_slice().len()],
+        }
+    }
+
+    /// Sets the frame number subsequent accesses are recorded on.
+    #[inline]
+    pub(crate) fn set_frame(&mut self, frame: u32) {
+        self.frame = frame;
+    }
+
+    /// Records an access to a byte in a region. Dummy accesses are ignored.
+    pub(crate) fn log(&mut self, region: MemRegion, index: usize, access: Access) {
+        let log = &mut self.regions[region as usize];
+        let accesses = match access {
+            Access::Read | Access::Execute => &mut log.reads,
+            Access::Write => &mut log.writes,
+            Access::Dummy => return,
+        };
+        if index >= accesses.len() {
+            accesses.resize(index + 1, 0);
+        }
+        accesses[index] = self.frame.wrapping_add(1);
+    }
+
+    /// Returns the frames a byte in a region was last read and written on.
+    pub fn last_access(&self, region: MemRegion, index: usize) -> MemAccess {
+        let log = &self.regions[region as usize];
+        let frame = |accesses: &[u32]| {
+            accesses
+                .get(index)
+                .filter(|&&frame| frame > 0)
+                .map(|frame| frame - 1)
+        };
+        MemAccess {
+            read: frame(&log.reads),
+            write: frame(&log.writes),
+        }
+    }
+}
+
+/// Parses a search pattern of space separated values. `$` prefixed hex values and `#` prefixed
+/// decimal values over 255 match as little-endian words. Bare hex digits match bytes in order,
+/// e.g. `A9 01` and `A901` both match `LDA #$01`.
+///
+/// # Errors
+///
+/// If the pattern is empty or a value is invalid, an error is returned.
+pub fn parse_pattern(pattern: &str) -> NesResult<Vec<u8>> {
+    let mut bytes = vec![];
+    for token in pattern.split(|c: char| c.is_whitespace() || c == ',') {
+        if token.is_empty() {
+            continue;
+        }
+        let value = if let Some(hex) = token.strip_prefix('$') {
+            u16::from_str_radix(hex, 16).ok()
+        } else if let Some(dec) = token.strip_prefix('#') {
+            dec.parse::<u16>().ok()
+        } else {
+            if token.len() > 1 && token.len() % 2 == 1 {
+                return Err(anyhow!("odd number of hex digits in `{token}`"));
+            }
+            for i in (0..token.len()).step_by(2) {
+                let byte = token
+                    .get(i..(i + 2).min(token.len()))
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(|| anyhow!("invalid hex byte in `{token}`"))?;
+                bytes.push(byte);
+            }
+            continue;
+        };
+        let value = value.ok_or_else(|| anyhow!("invalid value `{token}`"))?;
+        match u8::try_from(value) {
+            Ok(byte) => bytes.push(byte),
+            Err(_) => bytes.extend(value.to_le_bytes()),
+        }
+    }
+    if bytes.is_empty() {
+        return Err(anyhow!("empty search pattern"));
+    }
+    Ok(bytes)
+}
+
+/// Returns the index of the next match of `pattern` in `data` at or after `start`, wrapping
+/// around to the beginning.
+#[must_use]
+pub fn find_pattern(data: &[u8], pattern: &[u8], start: usize) -> Option<usize> {
+    find_pattern_with(data.len(), |index| data[index], pattern, start)
+}
+
+/// Same as [`find_pattern`], but reads each byte of memory `len` bytes long through `peek` as
+/// it's compared instead of copying it first.
+#[must_use]
+pub fn find_pattern_with(
+    len: usize,
+    peek: impl Fn(usize) -> u8,
+    pattern: &[u8],
+    start: usize,
+) -> Option<usize> {
+    if pattern.is_empty() || pattern.len() > len {
+        return None;
+    }
+    let last = len - pattern.len();
+    let start = if start > last { 0 } else { start };
+    (start..=last).chain(0..start).find(|&index| {
+        pattern
+            .iter()
+            .enumerate()
+            .all(|(offset, &val)| peek(index + offset) == val)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::tests::{load_program, LOOP};
+
+    #[test]
+    fn search() {
+        assert_eq!(parse_pattern("A9 01").expect("valid"), [0xA9, 0x01]);
+        assert_eq!(parse_pattern("A901").expect("valid"), [0xA9, 0x01]);
+        assert_eq!(
+            parse_pattern("$8002, #16").expect("valid"),
+            [0x02, 0x80, 0x10]
+        );
+        assert!(parse_pattern("A90").is_err());
+        assert!(parse_pattern("ZZ").is_err());
+        assert!(parse_pattern(" ").is_err());
+
+        let data = [0xA9, 0x01, 0x00, 0xA9, 0x01];
+        assert_eq!(find_pattern(&data, &[0xA9, 0x01], 0), Some(0));
+        assert_eq!(find_pattern(&data, &[0xA9, 0x01], 1), Some(3));
+        assert_eq!(
+            find_pattern(&data, &[0xA9, 0x01], 4),
+            Some(0),
+            "wraps around"
+        );
+        assert_eq!(find_pattern(&data, &[0xA9, 0x02], 0), None);
+    }
+
+    #[test]
+    fn edit_and_log_regions() {
+        let mut deck = load_program(&LOOP);
+        deck.poke_mem(MemRegion::Cpu, 0x0010, 0x42);
+        assert_eq!(deck.peek_mem(MemRegion::Cpu, 0x0810), 0x42, "mirrored");
+        deck.poke_mem(MemRegion::Palette, 0x01, 0x21);
+        assert_eq!(deck.peek_mem(MemRegion::Ppu, 0x3F01), 0x21);
+        deck.poke_mem(MemRegion::Oam, 0x04, 0x80);
+        assert_eq!(deck.peek_mem(MemRegion::Oam, 0x04), 0x80);
+        assert_eq!(deck.mem_size(MemRegion::Cpu), 0x10000);
+        assert_eq!(deck.mem_size(MemRegion::ExRam), 0, "no MMC5");
+        assert_eq!(
+            deck.find_mem(MemRegion::Cpu, &[0x4C, 0x02, 0x80], 0),
+            Some(0x8003)
+        );
+
+        assert_eq!(
+            deck.last_access(MemRegion::Cpu, 0x8002),
+            None,
+            "not logging"
+        );
+        deck.start_access_log();
+        let _ = deck.clock_frame().expect("valid clock");
+        let frame = deck.frame_number();
+        let access = deck
+            .last_access(MemRegion::Cpu, 0x8002)
+            .expect("access log");
+        assert!(
+            matches!(access.read, Some(read) if frame - read <= 1),
+            "INX executed"
+        );
+        assert_eq!(access.write, None);
+        assert_eq!(
+            deck.last_access(MemRegion::Cpu, 0x0010),
+            Some(MemAccess::default())
+        );
+        deck.stop_access_log();
+        assert_eq!(deck.last_access(MemRegion::Cpu, 0x8002), None);
+    }
+}
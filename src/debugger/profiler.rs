@@ -0,0 +1,345 @@
+//! Subroutine profiler attributing CPU cycles to each routine.
+//!
+//! Routines are entered by `JSR`, `BRK` or an interrupt and left by `RTS` or `RTI`. Returns are
+//! matched by stack pointer rather than by pairing each `RTS` with a call, so jump tables which
+//! push an address and `RTS` to it, or code which resets the stack, don't unbalance the call
+//! stack.
+//!
+//! Exclusive cycles are spent in the routine itself, while inclusive cycles also count any
+//! routines it calls. Cycles spent outside any routine are attributed to `[main]`.
+
+use crate::{
+    debugger::symbols::{SymbolAddr, Symbols},
+    NesResult,
+};
+use std::{collections::HashMap, io::Write};
+
+/// Name used for cycles spent outside of any routine.
+const ROOT_NAME: &str = "[main]";
+
+/// Cycle counts for a single routine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct RoutineStats {
+    /// Symbol name if one is loaded, otherwise the CPU address and, for PRG-ROM, the PRG-ROM offset,
+    /// e.g. `$C123@1C123`.
+    pub name: String,
+    /// Bank-resolved location of the routine.
+    pub addr: SymbolAddr,
+    /// CPU address the routine was first called at.
+    pub cpu_addr: u16,
+    pub calls: usize,
+    pub exclusive: usize,
+    pub inclusive: usize,
+    /// Longest inclusive time of a single call.
+    pub max_call: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Routine {
+    cpu_addr: u16,
+    calls: usize,
+    exclusive: usize,
+    inclusive: usize,
+    max_call: usize,
+}
+
+/// A unique call stack, stored as a tree so each call only has to look up its child.
+#[derive(Debug, Clone)]
+struct Node {
+    routine: Option<SymbolAddr>,
+    parent: usize,
+    children: HashMap<SymbolAddr, usize>,
+    cycles: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Frame {
+    routine: SymbolAddr,
+    node: usize,
+    /// Stack pointer once the routine has returned.
+    return_sp: u8,
+    start_cycle: usize,
+}
+
+/// Tracks calls and returns to attribute CPU cycles to routines.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct Profiler {
+    routines: HashMap<SymbolAddr, Routine>,
+    nodes: Vec<Node>,
+    stack: Vec<Frame>,
+    last_cycle: usize,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            routines: HashMap::new(),
+            nodes: vec![Node {
+                routine: None,
+                parent: 0,
+                children: HashMap::new(),
+                cycles: 0,
+            }],
+            stack: vec![],
+            last_cycle: 0,
+        }
+    }
+
+    /// Records a call to a routine. `return_sp` is the stack pointer before the return address was
+    /// pushed.
+    pub(crate) fn call(&mut self, routine: SymbolAddr, cpu_addr: u16, return_sp: u8, cycle: usize) {
+        self.attribute(cycle);
+        self.unwind(return_sp, cycle);
+
+        let parent = self.stack.last().map_or(0, |frame| frame.node);
+        let node = match self.nodes[parent].children.get(&routine) {
+            Some(&node) => node,
+            None => {
+                let node = self.nodes.len();
+                self.nodes.push(Node {
+                    routine: Some(routine),
+                    parent,
+                    children: HashMap::new(),
+                    cycles: 0,
+                });
+                self.nodes[parent].children.insert(routine, node);
+                node
+            }
+        };
+        self.routines
+            .entry(routine)
+            .or_insert(Routine {
+                cpu_addr,
+                calls: 0,
+                exclusive: 0,
+                inclusive: 0,
+                max_call: 0,
+            })
+            .calls += 1;
+        self.stack.push(Frame {
+            routine,
+            node,
+            return_sp,
+            start_cycle: cycle,
+        });
+    }
+
+    /// Records a return, leaving every routine whose return address was popped off the stack.
+    pub(crate) fn ret(&mut self, sp: u8, cycle: usize) {
+        self.attribute(cycle);
+        self.unwind(sp, cycle);
+    }
+
+    /// Forgets the current call stack and starts counting from `cycle`, keeping collected stats.
+    /// Used when profiling starts or a save state is loaded.
+    pub(crate) fn clear_stack(&mut self, cycle: usize) {
+        self.stack.clear();
+        self.last_cycle = cycle;
+    }
+
+    /// Total cycles profiled.
+    #[must_use]
+    pub fn total_cycles(&self) -> usize {
+        self.nodes.iter().map(|node| node.cycles).sum()
+    }
+
+    /// Returns stats for every routine called, sorted by exclusive cycles, highest first.
+    #[must_use]
+    pub fn routines(&self, symbols: &Symbols) -> Vec<RoutineStats> {
+        let mut routines: Vec<RoutineStats> = self
+            .routines
+            .iter()
+            .map(|(&addr, routine)| RoutineStats {
+                name: routine_name(symbols, addr, routine.cpu_addr),
+                addr,
+                cpu_addr: routine.cpu_addr,
+                calls: routine.calls,
+                exclusive: routine.exclusive,
+                inclusive: routine.inclusive,
+                max_call: routine.max_call,
+            })
+            .collect();
+        routines.sort_by(|a, b| {
+            b.exclusive
+                .cmp(&a.exclusive)
+                .then_with(|| a.cpu_addr.cmp(&b.cpu_addr))
+        });
+        routines
+    }
+
+    /// Writes a table of routines sorted by exclusive cycles.
+    ///
+    /// # Errors
+    ///
+    /// If the table fails to be written, an error is returned.
+    pub fn write_table<W: Write>(&self, writer: &mut W, symbols: &Symbols) -> NesResult<()> {
+        let total = self.total_cycles().max(1);
+        writeln!(
+            writer,
+            "{:<32} {:>8} {:>12} {:>7} {:>12} {:>7} {:>10}",
+            "Routine", "Calls", "Exclusive", "%", "Inclusive", "%", "Max Call"
+        )?;
+        for routine in self.routines(symbols) {
+            writeln!(
+                writer,
+                "{:<32} {:>8} {:>12} {:>6.2}% {:>12} {:>6.2}% {:>10}",
+                routine.name,
+                routine.calls,
+                routine.exclusive,
+                100.0 * routine.exclusive as f64 / total as f64,
+                routine.inclusive,
+                100.0 * routine.inclusive as f64 / total as f64,
+                routine.max_call,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes exclusive cycles per call stack in the folded format used by `flamegraph.pl` and
+    /// `inferno`, e.g. `[main];nmi;update_sprites 1234`.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be written, an error is returned.
+    pub fn write_folded<W: Write>(&self, writer: &mut W, symbols: &Symbols) -> NesResult<()> {
+        let mut lines = vec![];
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.cycles == 0 {
+                continue;
+            }
+            let mut names = vec![];
+            let mut index = index;
+            loop {
+                let node = &self.nodes[index];
+                match node.routine {
+                    Some(addr) => {
+                        let cpu_addr = self.routines.get(&addr).map_or(0, |r| r.cpu_addr);
+                        names.push(routine_name(symbols, addr, cpu_addr));
+                    }
+                    None => {
+                        names.push(ROOT_NAME.to_string());
+                        break;
+                    }
+                }
+                index = node.parent;
+            }
+            names.reverse();
+            lines.push((names.join(";"), node.cycles));
+        }
+        lines.sort();
+        for (stack, cycles) in lines {
+            writeln!(writer, "{stack} {cycles}")?;
+        }
+        Ok(())
+    }
+
+    /// Adds the cycles since the last event to the routine currently executing.
+    fn attribute(&mut self, cycle: usize) {
+        let elapsed = cycle.saturating_sub(self.last_cycle);
+        self.last_cycle = cycle;
+        match self.stack.last() {
+            Some(frame) => {
+                self.nodes[frame.node].cycles += elapsed;
+                if let Some(routine) = self.routines.get_mut(&frame.routine) {
+                    routine.exclusive += elapsed;
+                }
+            }
+            None => self.nodes[0].cycles += elapsed,
+        }
+    }
+
+    /// Leaves every routine whose return address is above the stack pointer.
+    fn unwind(&mut self, sp: u8, cycle: usize) {
+        while let Some(frame) = self.stack.last().copied() {
+            if frame.return_sp > sp {
+                break;
+            }
+            self.stack.pop();
+            let elapsed = cycle.saturating_sub(frame.start_cycle);
+            // Recursive calls are already counted by the outermost call
+            let recursive = self.stack.iter().any(|f| f.routine == frame.routine);
+            if let Some(routine) = self.routines.get_mut(&frame.routine) {
+                if !recursive {
+                    routine.inclusive += elapsed;
+                }
+                routine.max_call = routine.max_call.max(elapsed);
+            }
+        }
+    }
+}
+
+fn routine_name(symbols: &Symbols, addr: SymbolAddr, cpu_addr: u16) -> String {
+    match symbols.label_at(addr) {
+        Some(label) => label.to_string(),
+        None => match addr {
+            SymbolAddr::PrgRom(offset) => format!("${cpu_addr:04X}@{offset:05X}"),
+            SymbolAddr::Cpu(_) | SymbolAddr::PrgRam(_) => format!("${cpu_addr:04X}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::tests::load_program;
+
+    // $8000 JSR $8008
+    // $8003 JSR $800C
+    // $8006 BNE $8000
+    // $8008 JSR $800C
+    // $800B RTS
+    // $800C LDX #$01
+    // $800E RTS
+    const CALLS: [u8; 15] = [
+        0x20, 0x08, 0x80, 0x20, 0x0C, 0x80, 0xD0, 0xF8, 0x20, 0x0C, 0x80, 0x60, 0xA2, 0x01, 0x60,
+    ];
+
+    #[test]
+    fn attribute_cycles() {
+        let mut deck = load_program(&CALLS);
+        deck.start_profiler();
+        // One pass through the loop
+        for _ in 0..8 {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+        let profiler = deck.stop_profiler().expect("profiler");
+
+        let mut symbols = Symbols::new();
+        symbols.insert("outer", SymbolAddr::PrgRom(0x0008));
+        let routines = profiler.routines(&symbols);
+        assert_eq!(routines.len(), 2);
+
+        // LDX #$01 + RTS
+        let inner = &routines[0];
+        assert_eq!(inner.name, "$800C@0000C");
+        assert_eq!(inner.calls, 2);
+        assert_eq!(inner.exclusive, 2 * (2 + 6));
+        assert_eq!(inner.inclusive, inner.exclusive);
+
+        // JSR $800C + RTS
+        let outer = &routines[1];
+        assert_eq!(outer.name, "outer");
+        assert_eq!(outer.calls, 1);
+        assert_eq!(outer.exclusive, 6 + 6);
+        assert_eq!(outer.inclusive, 6 + 6 + 2 + 6);
+        assert_eq!(outer.max_call, outer.inclusive);
+
+        let mut folded = vec![];
+        profiler
+            .write_folded(&mut folded, &symbols)
+            .expect("folded stacks");
+        assert_eq!(
+            String::from_utf8_lossy(&folded),
+            "[main] 12\n[main];$800C@0000C 8\n[main];outer 12\n[main];outer;$800C@0000C 8\n"
+        );
+        assert_eq!(profiler.total_cycles(), 40);
+    }
+}
@@ -0,0 +1,185 @@
+//! Sprite evaluation snapshots for the sprite viewer.
+//!
+//! Sprites are evaluated into secondary OAM on cycles 65-256 of a scanline to be drawn on the
+//! next one. Only the first 8 sprites in range make it, the rest are dropped and set the sprite
+//! overflow flag (with the hardware's false positives and negatives).
+
+use crate::ppu::{ctrl::PpuCtrl, Ppu};
+
+/// A sprite entry in OAM.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct OamSprite {
+    /// Index in OAM, 0-63.
+    pub index: usize,
+    pub y: u8,
+    pub tile: u8,
+    pub attr: u8,
+    pub x: u8,
+}
+
+impl OamSprite {
+    /// Number of sprites in OAM.
+    pub const COUNT: usize = 64;
+
+    /// Reads the sprite at `index` from OAM.
+    pub fn from_oam(oam: &[u8], index: usize) -> Self {
+        let entry = |offset| oam.get(4 * index + offset).copied().unwrap_or(0xFF);
+        Self {
+            index,
+            y: entry(0),
+            tile: entry(1),
+            attr: entry(2),
+            x: entry(3),
+        }
+    }
+
+    /// Sprite palette, 0-3.
+    #[inline]
+    #[must_use]
+    pub const fn palette(&self) -> u8 {
+        self.attr & 0x03
+    }
+
+    /// Whether the sprite is drawn behind the background.
+    #[inline]
+    #[must_use]
+    pub const fn bg_priority(&self) -> bool {
+        self.attr & 0x20 == 0x20
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn flip_horizontal(&self) -> bool {
+        self.attr & 0x40 == 0x40
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn flip_vertical(&self) -> bool {
+        self.attr & 0x80 == 0x80
+    }
+
+    /// PPU address of the top tile. 8x16 sprites select the pattern table with bit 0 of the tile
+    /// number and continue with the next tile for the bottom half.
+    #[must_use]
+    pub fn tile_addr(&self, ctrl: PpuCtrl) -> u16 {
+        let tile = u16::from(self.tile);
+        if ctrl.spr_height() == 16 {
+            ((tile & 0x01) * 0x1000) | ((tile & 0xFE) * 16)
+        } else {
+            ctrl.spr_select() | (tile * 16)
+        }
+    }
+
+    /// Whether the sprite is in range for evaluation on `scanline`.
+    #[must_use]
+    pub fn in_range(&self, scanline: u32, height: u32) -> bool {
+        let y = u32::from(self.y);
+        (y..y + height).contains(&scanline)
+    }
+}
+
+/// OAM and the result of sprite evaluation captured on a scanline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct SpriteEval {
+    pub scanline: u32,
+    /// Sprite height, 8 or 16.
+    pub height: u32,
+    pub sprites: Vec<OamSprite>,
+    /// OAM indexes of sprites copied into secondary OAM, in evaluation order.
+    pub evaluated: Vec<usize>,
+    /// OAM indexes of sprites in range but dropped by the 8 sprites per scanline limit.
+    pub dropped: Vec<usize>,
+    /// Sprite overflow flag in `PPUSTATUS`.
+    pub overflow: bool,
+}
+
+impl SpriteEval {
+    /// Captures sprite evaluation for the current scanline. Only valid once evaluation finishes
+    /// after cycle 256.
+    pub fn capture(ppu: &Ppu) -> Self {
+        let oam = ppu.oamdata();
+        let sprites = (0..OamSprite::COUNT)
+            .map(|index| OamSprite::from_oam(oam, index))
+            .collect::<Vec<_>>();
+        let scanline = ppu.scanline();
+        let height = ppu.ctrl().spr_height();
+
+        // Secondary OAM holds copies, so match them back up with OAM in order
+        let mut evaluated = Vec::with_capacity(8);
+        let mut next = 0;
+        for entry in ppu.secondary_oamdata().chunks(4).take(ppu.spr_count()) {
+            let found = (next..sprites.len())
+                .chain(0..next)
+                .find(|&index| oam[4 * index..4 * index + 4] == *entry);
+            if let Some(index) = found {
+                evaluated.push(index);
+                next = index + 1;
+            }
+        }
+        let dropped = sprites
+            .iter()
+            .filter(|sprite| {
+                sprite.in_range(scanline, height) && !evaluated.contains(&sprite.index)
+            })
+            .map(|sprite| sprite.index)
+            .collect();
+
+        Self {
+            scanline,
+            height,
+            sprites,
+            evaluated,
+            dropped,
+            overflow: ppu.status().spr_overflow(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{common::Clock, debugger::memory::MemRegion, ppu::PpuRegisters};
+
+    #[test]
+    fn capture_evaluation() {
+        let mut ppu = Ppu::default();
+        ppu.load_chr_ram(vec![0x00; 0x2000]);
+        // 10 sprites on scanlines 20-27
+        for i in 0..10 {
+            let index = 4 * i;
+            for (offset, val) in [20, i as u8, 0x41, 8 * i as u8].into_iter().enumerate() {
+                ppu.poke_mem(MemRegion::Oam, index + offset, val);
+            }
+        }
+        ppu.write_mask(0x18);
+        while !(ppu.scanline() == 21 && ppu.cycle() > 256) {
+            let _ = ppu.clock();
+        }
+
+        let eval = SpriteEval::capture(&ppu);
+        assert_eq!(eval.scanline, 21);
+        assert_eq!(eval.height, 8);
+        assert_eq!(eval.evaluated, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(eval.dropped, [8, 9]);
+        assert!(eval.overflow);
+
+        let sprite = eval.sprites[1];
+        assert_eq!((sprite.x, sprite.y, sprite.tile), (8, 20, 1));
+        assert_eq!(sprite.palette(), 1);
+        assert!(sprite.flip_horizontal() && !sprite.flip_vertical() && !sprite.bg_priority());
+        assert_eq!(sprite.tile_addr(PpuCtrl::new()), 0x0010);
+        ppu.write_ctrl(0x20);
+        assert_eq!(
+            OamSprite {
+                tile: 0x25,
+                ..sprite
+            }
+            .tile_addr(ppu.ctrl()),
+            0x1240,
+            "8x16"
+        );
+    }
+}
@@ -0,0 +1,438 @@
+//! Debug symbol loading for ca65 `.dbg`, FCEUX `.nl` and Mesen `.mlb` files.
+//!
+//! Labels located in PRG-ROM or PRG-RAM are stored by their offset into that memory, so that a
+//! CPU address resolves to the correct label for whichever bank the mapper has currently switched
+//! in.
+
+use crate::{
+    debugger::Address,
+    mapper::{MappedRead, Mapper, MemMap},
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+/// Size of the iNES header preceding PRG-ROM in ca65 output files.
+const NES_HEADER_LEN: usize = 16;
+/// Size of a PRG-ROM bank in FCEUX `.nl` files.
+const NL_BANK_SIZE: usize = 0x4000;
+
+/// Location of a symbol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum SymbolAddr {
+    /// A fixed CPU address, e.g. internal RAM or a hardware register.
+    Cpu(u16),
+    /// An offset into PRG-ROM.
+    PrgRom(usize),
+    /// An offset into PRG-RAM.
+    PrgRam(usize),
+}
+
+impl SymbolAddr {
+    /// Determines the location of a CPU address given the current mapper banking.
+    pub fn from_cpu(mapper: &Mapper, addr: u16) -> Self {
+        // Mappers also handle PPU addresses below $2000, so only ask about cartridge space
+        if addr < 0x4020 {
+            return Self::Cpu(addr);
+        }
+        match mapper.map_peek(addr) {
+            MappedRead::PrgRom(offset) => Self::PrgRom(offset),
+            MappedRead::PrgRam(offset) => Self::PrgRam(offset),
+            _ => Self::Cpu(addr),
+        }
+    }
+}
+
+impl From<SymbolAddr> for Address {
+    fn from(addr: SymbolAddr) -> Self {
+        match addr {
+            SymbolAddr::Cpu(addr) => Self::Addr(addr),
+            SymbolAddr::PrgRom(offset) => Self::PrgRom(offset),
+            SymbolAddr::PrgRam(offset) => Self::PrgRam(offset),
+        }
+    }
+}
+
+/// A table of debug symbols for a loaded ROM.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub struct Symbols {
+    labels: HashMap<SymbolAddr, String>,
+    names: HashMap<String, SymbolAddr>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads all symbol files found next to a ROM. Looks for `game.dbg`, `game.mlb` and FCEUX
+    /// `game.nes.ram.nl` and `game.nes.<bank>.nl` files.
+    ///
+    /// # Errors
+    ///
+    /// If any symbol file fails to be read or parsed, an error is returned.
+    pub fn from_rom_path<P: AsRef<Path>>(rom_path: P) -> NesResult<Self> {
+        let rom_path = rom_path.as_ref();
+        let mut symbols = Self::new();
+        for ext in ["dbg", "mlb"] {
+            let path = rom_path.with_extension(ext);
+            if path.is_file() {
+                symbols.load_path(&path)?;
+            }
+        }
+        if let (Some(dir), Some(rom_name)) = (
+            rom_path.parent(),
+            rom_path.file_name().and_then(OsStr::to_str),
+        ) {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let prefix = format!("{rom_name}.");
+            let mut nl_paths = fs::read_dir(dir)
+                .with_context(|| format!("failed to read directory {dir:?}"))?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(OsStr::to_str)
+                        .map_or(false, |name| {
+                            name.starts_with(&prefix) && name.ends_with(".nl")
+                        })
+                })
+                .collect::<Vec<_>>();
+            nl_paths.sort();
+            for path in nl_paths {
+                symbols.load_path(&path)?;
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Loads a symbol file, detecting the format from the file extension.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be read or parsed, or the format is unsupported, an error is
+    /// returned.
+    pub fn load_path<P: AsRef<Path>>(&mut self, path: P) -> NesResult<()> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        let reader = BufReader::new(file);
+        match path.extension().and_then(OsStr::to_str) {
+            Some("dbg") => self.load_dbg(reader),
+            Some("mlb") => self.load_mlb(reader),
+            Some("nl") => {
+                // FCEUX names files `game.nes.ram.nl` or `game.nes.<bank>.nl`
+                let bank = path
+                    .file_stem()
+                    .map(Path::new)
+                    .and_then(Path::extension)
+                    .and_then(OsStr::to_str)
+                    .ok_or_else(|| anyhow!("invalid .nl filename {path:?}"))?;
+                let bank = if bank.eq_ignore_ascii_case("ram") {
+                    None
+                } else {
+                    Some(
+                        usize::from_str_radix(bank, 16)
+                            .with_context(|| format!("invalid .nl bank in {path:?}"))?,
+                    )
+                };
+                self.load_nl(reader, bank)
+            }
+            _ => Err(anyhow!("unsupported symbol file {path:?}")),
+        }
+        .with_context(|| format!("failed to load symbols from {path:?}"))
+    }
+
+    /// Loads symbols from a ca65/ld65 debug info file.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be read or parsed, an error is returned.
+    pub fn load_dbg<R: BufRead>(&mut self, reader: R) -> NesResult<()> {
+        // Segment id -> (start address, output file offset)
+        let mut segments = HashMap::new();
+        let mut syms = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let (kind, fields) = match line.split_once('\t') {
+                Some(split) => split,
+                None => continue,
+            };
+            let fields = Self::parse_dbg_fields(fields);
+            let field = |key: &str| {
+                fields
+                    .iter()
+                    .find_map(|(k, v)| (*k == key).then_some(*v))
+                    .ok_or_else(|| anyhow!("missing `{key}` on line {}", i + 1))
+            };
+            match kind {
+                "seg" => {
+                    let id = Self::parse_num(field("id")?)?;
+                    let start = Self::parse_num(field("start")?)?;
+                    let ooffs = field("ooffs").ok().map(Self::parse_num).transpose()?;
+                    segments.insert(id, (start, ooffs));
+                }
+                "sym" => {
+                    // Only labels are addresses, equates are usually constants
+                    if field("type").ok() != Some("lab") {
+                        continue;
+                    }
+                    let name = field("name")?.trim_matches('"').to_string();
+                    let val = Self::parse_num(field("val")?)?;
+                    let seg = field("seg").ok().map(Self::parse_num).transpose()?;
+                    syms.push((name, val, seg));
+                }
+                _ => (),
+            }
+        }
+
+        for (name, val, seg) in syms {
+            let addr = match seg.and_then(|seg| segments.get(&seg)) {
+                Some(&(start, Some(ooffs))) if ooffs >= NES_HEADER_LEN && val >= start => {
+                    SymbolAddr::PrgRom(ooffs - NES_HEADER_LEN + (val - start))
+                }
+                _ => SymbolAddr::Cpu(
+                    u16::try_from(val).with_context(|| format!("invalid address for {name}"))?,
+                ),
+            };
+            self.insert(name, addr);
+        }
+        Ok(())
+    }
+
+    /// Loads symbols from an FCEUX `.nl` file. `bank` is the 16K PRG-ROM bank for
+    /// `game.nes.<bank>.nl` files or `None` for `game.nes.ram.nl`.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be read or parsed, an error is returned.
+    pub fn load_nl<R: BufRead>(&mut self, reader: R, bank: Option<usize>) -> NesResult<()> {
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            // $C000#Label#Comment or $0200/10#Label#Comment for arrays
+            let mut parts = line.splitn(3, '#');
+            let (addr, name) = match (parts.next(), parts.next()) {
+                (Some(addr), Some(name)) if addr.starts_with('$') && !name.is_empty() => {
+                    (addr, name)
+                }
+                _ => continue,
+            };
+            let addr = addr[1..].split('/').next().unwrap_or_default();
+            let addr = u16::from_str_radix(addr, 16)
+                .with_context(|| format!("invalid address on line {}", i + 1))?;
+            let addr = match bank {
+                Some(bank) if addr >= 0x8000 => {
+                    SymbolAddr::PrgRom(bank * NL_BANK_SIZE + (usize::from(addr) % NL_BANK_SIZE))
+                }
+                _ => SymbolAddr::Cpu(addr),
+            };
+            self.insert(name, addr);
+        }
+        Ok(())
+    }
+
+    /// Loads symbols from a Mesen `.mlb` label file.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be read or parsed, an error is returned.
+    pub fn load_mlb<R: BufRead>(&mut self, reader: R) -> NesResult<()> {
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            // P:8000:Label:Comment or NesPrgRom:8000-8001:Label:Comment
+            let mut parts = line.trim().splitn(4, ':');
+            let (kind, addr, name) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(kind), Some(addr), Some(name)) if !name.is_empty() => (kind, addr, name),
+                _ => continue,
+            };
+            let addr = addr.split('-').next().unwrap_or_default();
+            let addr = usize::from_str_radix(addr, 16)
+                .with_context(|| format!("invalid address on line {}", i + 1))?;
+            let cpu_addr = || {
+                u16::try_from(addr).with_context(|| format!("invalid address on line {}", i + 1))
+            };
+            let addr = match kind {
+                "P" | "NesPrgRom" => SymbolAddr::PrgRom(addr),
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => SymbolAddr::PrgRam(addr),
+                "R" | "G" | "NesInternalRam" | "NesMemory" => SymbolAddr::Cpu(cpu_addr()?),
+                _ => continue,
+            };
+            self.insert(name, addr);
+        }
+        Ok(())
+    }
+
+    /// Add a symbol. The first label added for a location is used for display.
+    pub fn insert<S: Into<String>>(&mut self, name: S, addr: SymbolAddr) {
+        let name = name.into();
+        self.labels.entry(addr).or_insert_with(|| name.clone());
+        self.names.entry(name).or_insert(addr);
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Remove all symbols.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.labels.clear();
+        self.names.clear();
+    }
+
+    /// Returns the label for a CPU address given the current mapper banking.
+    #[must_use]
+    pub fn label(&self, mapper: &Mapper, addr: u16) -> Option<&str> {
+        if self.labels.is_empty() {
+            return None;
+        }
+        self.labels
+            .get(&SymbolAddr::from_cpu(mapper, addr))
+            .or_else(|| self.labels.get(&SymbolAddr::Cpu(addr)))
+            .map(String::as_str)
+    }
+
+    /// Returns the label for a bank-resolved location.
+    #[must_use]
+    pub fn label_at(&self, addr: SymbolAddr) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    /// Returns the location of a symbol by name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<SymbolAddr> {
+        self.names.get(name).copied()
+    }
+
+    /// Returns a breakpoint [`Address`] for a symbol by name.
+    pub fn address(&self, name: &str) -> Option<Address> {
+        self.get(name).map(Address::from)
+    }
+
+    /// Splits `key=value` pairs, ignoring commas within quoted strings.
+    fn parse_dbg_fields(fields: &str) -> Vec<(&str, &str)> {
+        let mut pairs = Vec::new();
+        let mut start = 0;
+        let mut quoted = false;
+        for (i, c) in fields
+            .char_indices()
+            .chain(std::iter::once((fields.len(), ',')))
+        {
+            match c {
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    if let Some(pair) = fields[start..i].split_once('=') {
+                        pairs.push(pair);
+                    }
+                    start = i + 1;
+                }
+                _ => (),
+            }
+        }
+        pairs
+    }
+
+    fn parse_num(val: &str) -> NesResult<usize> {
+        match val.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => val.parse(),
+        }
+        .with_context(|| format!("invalid number `{val}`"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cart::Cart, mapper::Nrom};
+
+    const DBG: &str = r#"version	major=2,minor=0
+info	csym=0,file=1,lib=0,line=0,mod=1,scope=1,seg=3,span=0,sym=4,type=0
+seg	id=0,name="HEADER",start=0x000000,size=0x0010,addrsize=absolute,type=ro,oname="game.nes",ooffs=0
+seg	id=1,name="CODE",start=0x00C000,size=0x4000,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+seg	id=2,name="ZEROPAGE",start=0x000000,size=0x0010,addrsize=zeropage,type=rw
+sym	id=0,name="reset",addrsize=absolute,scope=0,def=1,val=0xC010,seg=1,type=lab
+sym	id=1,name="player_x",addrsize=zeropage,scope=0,def=2,val=0x04,seg=2,type=lab
+sym	id=2,name="BUTTON_A",addrsize=zeropage,scope=0,def=3,val=0x80,type=equ
+sym	id=3,name="main",addrsize=absolute,scope=0,def=4,val=0xC100,seg=1,type=lab
+"#;
+
+    fn nrom_32k() -> Mapper {
+        let mut cart = Cart::empty();
+        cart.prg_rom.resize(0x8000, 0x00);
+        Nrom::load(&mut cart)
+    }
+
+    #[test]
+    fn load_dbg() {
+        let mut symbols = Symbols::new();
+        symbols.load_dbg(DBG.as_bytes()).expect("valid dbg");
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.get("reset"), Some(SymbolAddr::PrgRom(0x0010)));
+        assert_eq!(symbols.get("player_x"), Some(SymbolAddr::Cpu(0x0004)));
+        assert_eq!(symbols.get("BUTTON_A"), None);
+        assert_eq!(symbols.address("main"), Some(Address::PrgRom(0x0100)));
+    }
+
+    #[test]
+    fn load_nl() {
+        let mut symbols = Symbols::new();
+        symbols
+            .load_nl("$0300#buffer#\n$0200/10#oam#sprites\n".as_bytes(), None)
+            .expect("valid nl");
+        symbols
+            .load_nl("$C000#nmi#\n$FFFA#vectors#\n".as_bytes(), Some(1))
+            .expect("valid nl");
+        assert_eq!(symbols.get("buffer"), Some(SymbolAddr::Cpu(0x0300)));
+        assert_eq!(symbols.get("oam"), Some(SymbolAddr::Cpu(0x0200)));
+        assert_eq!(symbols.get("nmi"), Some(SymbolAddr::PrgRom(0x4000)));
+        assert_eq!(symbols.get("vectors"), Some(SymbolAddr::PrgRom(0x7FFA)));
+    }
+
+    #[test]
+    fn load_mlb() {
+        let mut symbols = Symbols::new();
+        let mlb = "P:0010:reset:entry\nR:0004:player_x\nNesPrgRom:4000-4001:table\nS:0000:save\nP:0020::comment only\n";
+        symbols.load_mlb(mlb.as_bytes()).expect("valid mlb");
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.get("reset"), Some(SymbolAddr::PrgRom(0x0010)));
+        assert_eq!(symbols.get("player_x"), Some(SymbolAddr::Cpu(0x0004)));
+        assert_eq!(symbols.get("table"), Some(SymbolAddr::PrgRom(0x4000)));
+        assert_eq!(symbols.get("save"), Some(SymbolAddr::PrgRam(0x0000)));
+    }
+
+    #[test]
+    fn bank_aware_labels() {
+        let mapper = nrom_32k();
+        let mut symbols = Symbols::new();
+        symbols.insert("bank0", SymbolAddr::PrgRom(0x0000));
+        symbols.insert("bank1", SymbolAddr::PrgRom(0x4000));
+        symbols.insert("ram", SymbolAddr::Cpu(0x0010));
+        symbols.insert("sram", SymbolAddr::PrgRam(0x0000));
+        assert_eq!(symbols.label(&mapper, 0x8000), Some("bank0"));
+        assert_eq!(symbols.label(&mapper, 0xC000), Some("bank1"));
+        assert_eq!(symbols.label(&mapper, 0x0010), Some("ram"));
+        assert_eq!(symbols.label(&mapper, 0x6000), Some("sram"));
+        assert_eq!(symbols.label(&mapper, 0x8001), None);
+    }
+}
@@ -0,0 +1,563 @@
+//! Instruction trace logging.
+//!
+//! Supports output compatible with `nestest.log`, the FCEUX Trace Logger and the Mesen Trace
+//! Logger for diffing against other emulators.
+
+use crate::{
+    cpu::{
+        instr::{
+            AddrMode::{self, ABS, ABX, ABY, ACC, IDX, IDY, IMM, IMP, IND, REL, ZP0, ZPX, ZPY},
+            Operation::{JMP, JSR},
+        },
+        Cpu, Status,
+    },
+    debugger::symbols::Symbols,
+    mapper::Mapper,
+    mem::{Access, Mem},
+    NesResult,
+};
+use std::{
+    fmt::{self, Write as _},
+    io::Write,
+    ops::RangeInclusive,
+};
+
+/// Trace log line format.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum TraceFormat {
+    /// `C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`
+    Nestest,
+    /// `A:00 X:00 Y:00 S:FD P:nvubdIzc  $C000:4C F5 C5  JMP $C5F5`
+    Fceux,
+    /// `C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC: 21 SL:  0 CPU Cycle:7`
+    Mesen,
+}
+
+/// Decoded operand of an instruction, read without side-effects.
+#[derive(Debug, Copy, Clone)]
+struct Operand {
+    mode: AddrMode,
+    bytes: [u8; 3],
+    len: usize,
+    arg: u16,
+    ptr: u16,
+    addr: u16,
+    val: u8,
+}
+
+impl Operand {
+    fn decode(cpu: &Cpu, pc: u16) -> Self {
+        let peek = |addr: u16| cpu.peek(addr, Access::Dummy);
+        // Zero Page pointers wrap around at $FF
+        let peek_zp_u16 =
+            |addr: u8| u16::from_le_bytes([peek(addr.into()), peek(addr.wrapping_add(1).into())]);
+
+        let opcode = peek(pc);
+        let instr = Cpu::INSTRUCTIONS[opcode as usize];
+        let mode = instr.addr_mode();
+        let lo = peek(pc.wrapping_add(1));
+        let hi = peek(pc.wrapping_add(2));
+        let word = u16::from_le_bytes([lo, hi]);
+        let (len, arg, ptr, addr) = match mode {
+            IMM => (2, lo.into(), 0x0000, 0x0000),
+            ZP0 => (2, lo.into(), 0x0000, lo.into()),
+            ZPX => (2, lo.into(), 0x0000, lo.wrapping_add(cpu.x()).into()),
+            ZPY => (2, lo.into(), 0x0000, lo.wrapping_add(cpu.y()).into()),
+            ABS => (3, word, 0x0000, word),
+            ABX => (3, word, 0x0000, word.wrapping_add(cpu.x().into())),
+            ABY => (3, word, 0x0000, word.wrapping_add(cpu.y().into())),
+            IND => {
+                // Replicates the 6502 page boundary bug
+                let hi_addr = if word & 0x00FF == 0x00FF {
+                    word & 0xFF00
+                } else {
+                    word.wrapping_add(1)
+                };
+                let addr = u16::from_le_bytes([peek(word), peek(hi_addr)]);
+                (3, word, 0x0000, addr)
+            }
+            IDX => {
+                let ptr = lo.wrapping_add(cpu.x());
+                (2, lo.into(), ptr.into(), peek_zp_u16(ptr))
+            }
+            IDY => {
+                let ptr = peek_zp_u16(lo);
+                (2, lo.into(), ptr, ptr.wrapping_add(cpu.y().into()))
+            }
+            REL => {
+                let offset = lo as i8;
+                let addr = pc.wrapping_add(2).wrapping_add(offset as u16);
+                (2, lo.into(), 0x0000, addr)
+            }
+            ACC | IMP => (1, 0x0000, 0x0000, 0x0000),
+        };
+        let bytes = [opcode, lo, hi];
+        let val = peek(addr);
+        Self {
+            mode,
+            bytes,
+            len,
+            arg,
+            ptr,
+            addr,
+            val,
+        }
+    }
+
+    /// Formats the operand address, replacing it with a label if one exists.
+    fn arg_label(&self, symbols: &Symbols, mapper: &Mapper) -> String {
+        let (addr, zero_page) = match self.mode {
+            ZP0 | ZPX | ZPY | IDX | IDY => (self.arg, true),
+            ABS | ABX | ABY | IND => (self.arg, false),
+            REL => (self.addr, false),
+            IMM | ACC | IMP => return String::new(),
+        };
+        match symbols.label(mapper, addr) {
+            Some(label) => label.to_string(),
+            None if zero_page => format!("${addr:02X}"),
+            None => format!("${addr:04X}"),
+        }
+    }
+
+    fn write_bytes(&self, out: &mut String) -> fmt::Result {
+        let mut bytes = String::with_capacity(9);
+        for byte in &self.bytes[..self.len] {
+            write!(bytes, "{byte:02X} ")?;
+        }
+        write!(out, "{bytes:<9}")
+    }
+
+    fn write_nestest(&self, out: &mut String, label: &str, jump: bool) -> fmt::Result {
+        let Self {
+            arg,
+            ptr,
+            addr,
+            val,
+            ..
+        } = *self;
+        match self.mode {
+            IMM => write!(out, "#${arg:02X}"),
+            ZP0 => write!(out, "{label} = {val:02X}"),
+            ZPX => write!(out, "{label},X @ {addr:02X} = {val:02X}"),
+            ZPY => write!(out, "{label},Y @ {addr:02X} = {val:02X}"),
+            ABS if jump => write!(out, "{label}"),
+            ABS => write!(out, "{label} = {val:02X}"),
+            ABX => write!(out, "{label},X @ {addr:04X} = {val:02X}"),
+            ABY => write!(out, "{label},Y @ {addr:04X} = {val:02X}"),
+            IND => write!(out, "({label}) = {addr:04X}"),
+            IDX => write!(out, "({label},X) @ {ptr:02X} = {addr:04X} = {val:02X}"),
+            IDY => write!(out, "({label}),Y = {ptr:04X} @ {addr:04X} = {val:02X}"),
+            REL => write!(out, "{label}"),
+            ACC => write!(out, "A"),
+            IMP => Ok(()),
+        }
+    }
+
+    fn write_fceux(&self, out: &mut String, label: &str, jump: bool) -> fmt::Result {
+        let Self { arg, addr, val, .. } = *self;
+        match self.mode {
+            IMM => write!(out, "#${arg:02X}"),
+            ZP0 => write!(out, "{label} = #${val:02X}"),
+            ZPX => write!(out, "{label},X @ ${addr:04X} = #${val:02X}"),
+            ZPY => write!(out, "{label},Y @ ${addr:04X} = #${val:02X}"),
+            ABS if jump => write!(out, "{label}"),
+            ABS => write!(out, "{label} = #${val:02X}"),
+            ABX => write!(out, "{label},X @ ${addr:04X} = #${val:02X}"),
+            ABY => write!(out, "{label},Y @ ${addr:04X} = #${val:02X}"),
+            IND => write!(out, "({label}) = ${addr:04X}"),
+            IDX => write!(out, "({label},X) @ ${addr:04X} = #${val:02X}"),
+            IDY => write!(out, "({label}),Y @ ${addr:04X} = #${val:02X}"),
+            REL => write!(out, "{label}"),
+            ACC | IMP => Ok(()),
+        }
+    }
+
+    fn write_mesen(&self, out: &mut String, label: &str, jump: bool) -> fmt::Result {
+        let Self { arg, addr, val, .. } = *self;
+        match self.mode {
+            IMM => write!(out, "#${arg:02X}"),
+            ZP0 => write!(out, "{label} = ${val:02X}"),
+            ZPX => write!(out, "{label},X [${addr:04X}] = ${val:02X}"),
+            ZPY => write!(out, "{label},Y [${addr:04X}] = ${val:02X}"),
+            ABS if jump => write!(out, "{label}"),
+            ABS => write!(out, "{label} = ${val:02X}"),
+            ABX => write!(out, "{label},X [${addr:04X}] = ${val:02X}"),
+            ABY => write!(out, "{label},Y [${addr:04X}] = ${val:02X}"),
+            IND => write!(out, "({label}) [${addr:04X}]"),
+            IDX => write!(out, "({label},X) [${addr:04X}] = ${val:02X}"),
+            IDY => write!(out, "({label}),Y [${addr:04X}] = ${val:02X}"),
+            REL => write!(out, "{label}"),
+            ACC => write!(out, "A"),
+            IMP => Ok(()),
+        }
+    }
+}
+
+/// Logs each executed instruction to an output stream.
+///
+/// Logging can be restricted to a range of `PC` addresses and/or frame numbers and can wait to
+/// start until a breakpoint is hit.
+#[must_use]
+pub struct TraceLogger {
+    output: Box<dyn Write>,
+    format: TraceFormat,
+    ppu_columns: bool,
+    cycle_columns: bool,
+    pc_range: Option<RangeInclusive<u16>>,
+    frame_range: Option<RangeInclusive<u32>>,
+    wait_for_breakpoint: bool,
+    armed: bool,
+    line: String,
+}
+
+impl TraceLogger {
+    /// Creates a new `TraceLogger` writing to `output`. PPU and cycle columns are enabled by
+    /// default.
+    pub fn new<W: Write + 'static>(output: W, format: TraceFormat) -> Self {
+        Self {
+            output: Box::new(output),
+            format,
+            ppu_columns: true,
+            cycle_columns: true,
+            pc_range: None,
+            frame_range: None,
+            wait_for_breakpoint: false,
+            armed: true,
+            line: String::with_capacity(128),
+        }
+    }
+
+    /// Include PPU scanline and dot columns.
+    pub fn ppu_columns(&mut self, enabled: bool) -> &mut Self {
+        self.ppu_columns = enabled;
+        self
+    }
+
+    /// Include total CPU cycle columns.
+    pub fn cycle_columns(&mut self, enabled: bool) -> &mut Self {
+        self.cycle_columns = enabled;
+        self
+    }
+
+    /// Only log instructions with a `PC` within the given range.
+    pub fn pc_range(&mut self, range: Option<RangeInclusive<u16>>) -> &mut Self {
+        self.pc_range = range;
+        self
+    }
+
+    /// Only log instructions executed during the given range of frames.
+    pub fn frame_range(&mut self, range: Option<RangeInclusive<u32>>) -> &mut Self {
+        self.frame_range = range;
+        self
+    }
+
+    /// Wait to start logging until a breakpoint is hit.
+    pub fn wait_for_breakpoint(&mut self, enabled: bool) -> &mut Self {
+        self.wait_for_breakpoint = enabled;
+        self.armed = !enabled;
+        self
+    }
+
+    #[inline]
+    pub const fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    /// Returns whether logging has started, or is waiting on a breakpoint.
+    #[inline]
+    #[must_use]
+    pub const fn armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Start logging when waiting on a breakpoint.
+    #[inline]
+    pub(crate) fn arm(&mut self) {
+        self.armed = true;
+    }
+
+    /// Whether the instruction about to be executed should be logged.
+    #[must_use]
+    pub fn should_log(&self, cpu: &Cpu) -> bool {
+        self.armed
+            && self
+                .pc_range
+                .as_ref()
+                .map_or(true, |range| range.contains(&cpu.pc()))
+            && self
+                .frame_range
+                .as_ref()
+                .map_or(true, |range| range.contains(&cpu.frame_number()))
+    }
+
+    /// Logs the instruction about to be executed, if the start/stop conditions are met. Operand
+    /// addresses are replaced with labels from `symbols`.
+    ///
+    /// # Errors
+    ///
+    /// If writing to the output fails, an error is returned.
+    pub fn log(&mut self, cpu: &Cpu, symbols: &Symbols) -> NesResult<()> {
+        if self.should_log(cpu) {
+            self.line.clear();
+            self.format_line(cpu, symbols)?;
+            self.line.push('\n');
+            self.output.write_all(self.line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Flush any buffered output.
+    ///
+    /// # Errors
+    ///
+    /// If flushing the output fails, an error is returned.
+    pub fn flush(&mut self) -> NesResult<()> {
+        Ok(self.output.flush()?)
+    }
+
+    fn format_line(&mut self, cpu: &Cpu, symbols: &Symbols) -> fmt::Result {
+        let pc = cpu.pc();
+        let instr = Cpu::INSTRUCTIONS[cpu.peek(pc, Access::Dummy) as usize];
+        let jump = matches!(instr.op(), JMP | JSR);
+        let operand = Operand::decode(cpu, pc);
+        let label = operand.arg_label(symbols, cpu.mapper());
+        let out = &mut self.line;
+
+        let mut disasm = String::with_capacity(32);
+        match self.format {
+            TraceFormat::Nestest => {
+                write!(out, "{pc:04X}  ")?;
+                operand.write_bytes(out)?;
+                // Unofficial opcodes are prefixed with `*`
+                write!(disasm, "{instr:?} ")?;
+                operand.write_nestest(&mut disasm, &label, jump)?;
+                write!(out, "{disasm:<33}")?;
+                write!(
+                    out,
+                    "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+                    cpu.a(),
+                    cpu.x(),
+                    cpu.y(),
+                    cpu.status().bits(),
+                    cpu.sp()
+                )?;
+                if self.ppu_columns {
+                    let ppu = cpu.ppu();
+                    write!(out, " PPU:{:3},{:3}", ppu.scanline(), ppu.cycle())?;
+                }
+                if self.cycle_columns {
+                    write!(out, " CYC:{}", cpu.cycle())?;
+                }
+            }
+            TraceFormat::Fceux => {
+                // FCEUX logs its counters ahead of the registers
+                if self.ppu_columns {
+                    let ppu = cpu.ppu();
+                    write!(out, "PPU:{:3},{:3} ", ppu.scanline(), ppu.cycle())?;
+                }
+                if self.cycle_columns {
+                    write!(out, "CYC:{} ", cpu.cycle())?;
+                }
+                write!(
+                    out,
+                    "A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:",
+                    cpu.a(),
+                    cpu.x(),
+                    cpu.y(),
+                    cpu.sp()
+                )?;
+                let status = cpu.status();
+                // FCEUX doesn't keep the unused flag in its status register, so it's always clear
+                for (flag, set, clear) in [
+                    (Status::N, 'N', 'n'),
+                    (Status::V, 'V', 'v'),
+                    (Status::U, 'u', 'u'),
+                    (Status::B, 'B', 'b'),
+                    (Status::D, 'D', 'd'),
+                    (Status::I, 'I', 'i'),
+                    (Status::Z, 'Z', 'z'),
+                    (Status::C, 'C', 'c'),
+                ] {
+                    out.push(if status.contains(flag) { set } else { clear });
+                }
+                write!(out, "  ${pc:04X}:")?;
+                operand.write_bytes(out)?;
+                write!(disasm, "{instr:?} ")?;
+                operand.write_fceux(&mut disasm, &label, jump)?;
+                out.push_str(disasm.trim_end());
+            }
+            TraceFormat::Mesen => {
+                write!(out, "{pc:04X}  ")?;
+                operand.write_bytes(out)?;
+                write!(disasm, "{instr:?} ")?;
+                operand.write_mesen(&mut disasm, &label, jump)?;
+                write!(out, "{disasm:<33}")?;
+                write!(
+                    out,
+                    "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+                    cpu.a(),
+                    cpu.x(),
+                    cpu.y(),
+                    cpu.status().bits(),
+                    cpu.sp()
+                )?;
+                if self.ppu_columns {
+                    let ppu = cpu.ppu();
+                    write!(out, " CYC:{:3} SL:{:3}", ppu.cycle(), ppu.scanline())?;
+                }
+                if self.cycle_columns {
+                    write!(out, " CPU Cycle:{}", cpu.cycle())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TraceLogger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceLogger")
+            .field("format", &self.format)
+            .field("ppu_columns", &self.ppu_columns)
+            .field("cycle_columns", &self.cycle_columns)
+            .field("pc_range", &self.pc_range)
+            .field("frame_range", &self.frame_range)
+            .field("wait_for_breakpoint", &self.wait_for_breakpoint)
+            .field("armed", &self.armed)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control_deck::ControlDeck,
+        debugger::{tests::load_program, Breakpoint, Condition},
+    };
+    use std::{cell::RefCell, io, rc::Rc};
+
+    #[derive(Default, Clone)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.0.borrow())
+                .lines()
+                .map(ToString::to_string)
+                .collect()
+        }
+    }
+
+    // $8000 LDX #$00
+    // $8002 STX $10
+    // $8004 INX
+    // $8005 JMP $8004
+    const PROGRAM: [u8; 8] = [0xA2, 0x00, 0x86, 0x10, 0xE8, 0x4C, 0x04, 0x80];
+
+    fn trace(deck: &mut ControlDeck, tracer: TraceLogger, instrs: usize) {
+        deck.start_trace(tracer);
+        for _ in 0..instrs {
+            let _ = deck.clock_instr().expect("valid clock");
+        }
+        deck.stop_trace().expect("valid flush");
+    }
+
+    #[test]
+    fn nestest_format() {
+        let mut deck = load_program(&PROGRAM);
+        let buf = SharedBuf::default();
+        let mut tracer = TraceLogger::new(buf.clone(), TraceFormat::Nestest);
+        tracer.ppu_columns(false);
+        trace(&mut deck, tracer, 4);
+        let lines = buf.lines();
+        assert_eq!(
+            lines[0],
+            "8000  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD CYC:7"
+        );
+        assert_eq!(
+            lines[1],
+            "8002  86 10     STX $10 = 00                    A:00 X:00 Y:00 P:26 SP:FD CYC:9"
+        );
+        assert_eq!(
+            lines[3],
+            "8005  4C 04 80  JMP $8004                       A:00 X:01 Y:00 P:24 SP:FD CYC:14"
+        );
+    }
+
+    #[test]
+    fn fceux_format() {
+        let mut deck = load_program(&PROGRAM);
+        let buf = SharedBuf::default();
+        let mut tracer = TraceLogger::new(buf.clone(), TraceFormat::Fceux);
+        tracer.ppu_columns(false).cycle_columns(false);
+        trace(&mut deck, tracer, 4);
+        let lines = buf.lines();
+        assert_eq!(
+            lines[1],
+            "A:00 X:00 Y:00 S:FD P:nvubdIZc  $8002:86 10     STX $10 = #$00"
+        );
+        assert_eq!(
+            lines[3],
+            "A:00 X:01 Y:00 S:FD P:nvubdIzc  $8005:4C 04 80  JMP $8004"
+        );
+    }
+
+    #[test]
+    fn mesen_format() {
+        let mut deck = load_program(&PROGRAM);
+        let buf = SharedBuf::default();
+        let tracer = TraceLogger::new(buf.clone(), TraceFormat::Mesen);
+        trace(&mut deck, tracer, 2);
+        let lines = buf.lines();
+        assert_eq!(
+            lines[0],
+            "8000  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD CYC: 20 SL:  0 CPU Cycle:7"
+        );
+        assert_eq!(
+            lines[1],
+            "8002  86 10     STX $10 = $00                   A:00 X:00 Y:00 P:26 SP:FD CYC: 26 SL:  0 CPU Cycle:9"
+        );
+    }
+
+    #[test]
+    fn pc_range() {
+        let mut deck = load_program(&PROGRAM);
+        let buf = SharedBuf::default();
+        let mut tracer = TraceLogger::new(buf.clone(), TraceFormat::Nestest);
+        tracer.pc_range(Some(0x8004..=0x8004));
+        trace(&mut deck, tracer, 10);
+        let lines = buf.lines();
+        assert_eq!(lines.len(), 4, "INX executed 4 times");
+        assert!(lines.iter().all(|line| line.starts_with("8004")));
+    }
+
+    #[test]
+    fn wait_for_breakpoint() {
+        let mut deck = load_program(&PROGRAM);
+        deck.add_breakpoint(Breakpoint::new(
+            0x8004,
+            vec![Condition::X(crate::debugger::Cmp::Eq, 0x03)],
+        ));
+        let buf = SharedBuf::default();
+        let mut tracer = TraceLogger::new(buf.clone(), TraceFormat::Nestest);
+        tracer.ppu_columns(false).wait_for_breakpoint(true);
+        trace(&mut deck, tracer, 10);
+        let lines = buf.lines();
+        assert!(
+            lines[0].starts_with("8004  E8        INX                             A:00 X:03"),
+            "{}",
+            lines[0]
+        );
+    }
+}
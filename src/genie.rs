@@ -13,6 +13,11 @@ static GENIE_MAP: Lazy<HashMap<char, u8>> = Lazy::new(|| {
     }
 });
 
+// Letters indexed by the value they represent
+const GENIE_LETTERS: [char; 16] = [
+    'A', 'P', 'Z', 'L', 'G', 'I', 'T', 'Y', 'E', 'O', 'X', 'U', 'K', 'S', 'V', 'N',
+];
+
 /// Game Genie Code
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenieCode {
@@ -66,6 +71,38 @@ impl GenieCode {
         })
     }
 
+    /// Encodes an address, value and optional compare value as a `GenieCode`. Codes with a compare
+    /// value are 8 letters long, otherwise they are 6 letters long.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the address is below `$8000`, since the Game Genie can
+    /// only patch PRG-ROM.
+    pub fn encode(addr: u16, data: u8, compare: Option<u8>) -> NesResult<Self> {
+        if addr < 0x8000 {
+            return Err(anyhow!("invalid game genie address: ${:04X}", addr));
+        }
+        let a = addr & 0x7FFF;
+        let mut hex = vec![
+            (data & 7) | ((data >> 4) & 8),
+            ((data >> 4) & 7) | ((a >> 4) & 8) as u8,
+            ((a >> 4) & 7) as u8 | if compare.is_some() { 8 } else { 0 },
+            (((a >> 12) & 7) | (a & 8)) as u8,
+            ((a & 7) | ((a >> 8) & 8)) as u8,
+            ((a >> 8) & 7) as u8 | (compare.unwrap_or(data) & 8),
+        ];
+        if let Some(compare) = compare {
+            hex.push((compare & 7) | ((compare >> 4) & 8));
+            hex.push(((compare >> 4) & 7) | (data & 8));
+        }
+        Ok(Self {
+            code: hex.iter().map(|&h| GENIE_LETTERS[h as usize]).collect(),
+            addr,
+            data,
+            compare,
+        })
+    }
+
     #[inline]
     #[must_use]
     pub fn code(&self) -> &str {
@@ -78,6 +115,18 @@ impl GenieCode {
         self.addr
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn data(&self) -> u8 {
+        self.data
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn compare(&self) -> Option<u8> {
+        self.compare
+    }
+
     #[inline]
     #[must_use]
     pub const fn read(&self, val: u8) -> u8 {
@@ -98,3 +147,26 @@ impl std::fmt::Display for GenieCode {
         write!(f, "{}", &self.code)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        for code in ["SXIOPO", "YYKPOYZZ"] {
+            let genie_code = GenieCode::new(code.to_string()).expect("valid genie code");
+            let encoded =
+                GenieCode::encode(genie_code.addr(), genie_code.data(), genie_code.compare())
+                    .expect("valid address");
+            assert_eq!(encoded.code(), code);
+        }
+        assert_eq!(
+            GenieCode::encode(0x9F41, 0x77, Some(0x22))
+                .expect("valid address")
+                .code(),
+            "YYKPOYZZ"
+        );
+        assert!(GenieCode::encode(0x0075, 0x09, None).is_err());
+    }
+}
@@ -138,6 +138,46 @@ impl Input {
         self.four_player = four_player;
         self.reset(Kind::Hard);
     }
+
+    /// Returns the input set by the player.
+    #[inline]
+    pub fn state(&self) -> InputState {
+        InputState {
+            buttons: self.joypads.map(|joypad| joypad.buttons),
+            zapper: self.zapper,
+        }
+    }
+
+    /// Restores input set by the player, leaving controller shift registers unchanged.
+    #[inline]
+    pub fn set_state(&mut self, state: InputState) {
+        for (joypad, buttons) in self.joypads.iter_mut().zip(state.buttons) {
+            joypad.buttons = buttons;
+        }
+        self.zapper = state.zapper;
+    }
+}
+
+/// Input set by the player rather than by emulation, recorded so execution can be replayed
+/// deterministically.
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct InputState {
+    buttons: [JoypadBtnState; 4],
+    zapper: Zapper,
+}
+
+impl InputState {
+    /// Whether the player changed input since `previous`. The zapper trigger counting down isn't a
+    /// change, only pulling it again.
+    #[must_use]
+    pub fn changed_since(&self, previous: &Self) -> bool {
+        self.buttons != previous.buttons
+            || self.zapper.x != previous.zapper.x
+            || self.zapper.y != previous.zapper.y
+            || self.zapper.connected != previous.zapper.connected
+            || self.zapper.triggered > previous.zapper.triggered
+    }
 }
 
 impl InputRegisters for Input {
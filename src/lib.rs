@@ -42,6 +42,7 @@
 )]
 
 pub mod audio;
+pub mod cheat;
 pub mod genie;
 
 pub mod apu;
@@ -51,7 +52,6 @@ pub mod cart;
 pub mod common;
 pub mod control_deck;
 pub mod cpu;
-#[cfg(not(target_arch = "wasm32"))]
 pub mod debugger;
 pub mod input;
 pub mod mapper;
@@ -59,6 +59,9 @@ pub mod mem;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod nes;
 pub mod ppu;
+pub mod ram_search;
+#[cfg(feature = "scripting")]
+pub mod script;
 pub mod video;
 
 pub type NesError = anyhow::Error;
@@ -37,6 +37,7 @@ fn main() -> NesResult<()> {
         .speed(opt.speed)
         .genie_codes(opt.genie_codes)
         .debug(opt.debug)
+        .script(opt.script)
         .build()?
         .run()
 }
@@ -80,4 +81,9 @@ struct Opt {
     genie_codes: Vec<String>,
     #[structopt(long = "debug", help = "Start debugging")]
     debug: bool,
+    #[structopt(
+        long = "script",
+        help = "A Rhai script to run for automation. Requires the `scripting` feature."
+    )]
+    script: Option<PathBuf>,
 }
@@ -89,6 +89,7 @@ pub enum MappedRead {
 #[must_use]
 pub enum MappedWrite {
     None,
+    Bus,
     Chr(usize, u8),
     CIRam(usize, u8),
     ExRam(usize, u8),
@@ -261,7 +261,7 @@ impl MemMap for Sxrom {
                         self.update_banks(addr);
                     }
                 }
-                MappedWrite::None
+                MappedWrite::Bus
             }
             _ => MappedWrite::None,
         }
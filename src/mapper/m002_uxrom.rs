@@ -54,7 +54,7 @@ impl MemMap for Uxrom {
             0x0000..=0x1FFF => MappedWrite::Chr(addr.into(), val),
             0x8000..=0xFFFF => {
                 self.prg_rom_banks.set(0, val.into());
-                MappedWrite::None
+                MappedWrite::Bus
             }
             _ => MappedWrite::None,
         }
@@ -51,10 +51,13 @@ impl MemMap for Cnrom {
     }
 
     fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
-        if matches!(addr, 0x8000..=0xFFFF) {
-            self.chr_banks.set(0, val.into());
+        match addr {
+            0x8000..=0xFFFF => {
+                self.chr_banks.set(0, val.into());
+                MappedWrite::Bus
+            }
+            _ => MappedWrite::None,
         }
-        MappedWrite::None
     }
 }
 
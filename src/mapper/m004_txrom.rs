@@ -300,7 +300,7 @@ impl MemMap for Txrom {
                     0xE001 => self.regs.irq_enabled = true,
                     _ => unreachable!("impossible address"),
                 }
-                MappedWrite::None
+                MappedWrite::Bus
             }
             _ => MappedWrite::None,
         }
@@ -477,6 +477,17 @@ impl Exrom {
         };
     }
 
+    #[inline]
+    #[must_use]
+    pub fn exram(&self) -> &[u8] {
+        &self.exram
+    }
+
+    #[inline]
+    pub fn exram_mut(&mut self) -> &mut [u8] {
+        &mut self.exram
+    }
+
     #[inline]
     fn read_exram(&self, addr: u16) -> u8 {
         self.exram[(addr & 0x03FF) as usize]
@@ -511,6 +522,18 @@ impl Exrom {
     const fn nametable_select(&self, addr: u16) -> Nametable {
         self.regs.nametable_mapping.select[((addr >> 10) & 0x03) as usize]
     }
+
+    /// Outputs of the pulse 1, pulse 2 and PCM channels, on the same scale as the mixed output.
+    #[must_use]
+    pub fn channel_outputs(&self) -> [f32; 3] {
+        let pulse_scale = PULSE_TABLE[PULSE_TABLE.len() - 1] / 15.0;
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.dmc.output(),
+        ]
+        .map(|out| pulse_scale * -out)
+    }
 }
 
 impl Mapped for Exrom {
@@ -783,8 +806,9 @@ impl MemMap for Exrom {
                 }
                 Nametable::ExRam if self.regs.exram_mode.nametable => {
                     self.write_exram(addr, val);
+                    return MappedWrite::None;
                 }
-                _ => (),
+                _ => return MappedWrite::None,
             },
             0x5000 => self.pulse1.write_ctrl(val),
             // 0x5001 Has no effect since there is no Sweep unit
@@ -963,9 +987,9 @@ impl MemMap for Exrom {
             0x6000..=0xDFFF if !self.rom_select(addr) => {
                 return MappedWrite::PrgRam(self.prg_ram_banks.translate(addr), val);
             }
-            _ => (),
+            _ => return MappedWrite::None,
         }
-        MappedWrite::None
+        MappedWrite::Bus
     }
 }
 
@@ -69,7 +69,7 @@ impl MemMap for Axrom {
                 } else {
                     Mirroring::SingleScreenA
                 };
-                MappedWrite::None
+                MappedWrite::Bus
             }
             _ => MappedWrite::None,
         }
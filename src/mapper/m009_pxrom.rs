@@ -108,12 +108,12 @@ impl MemMap for Pxrom {
             0x6000..=0x7FFF => MappedWrite::PrgRam((addr & 0x1FFF).into(), val),
             0xA000..=0xAFFF => {
                 self.prg_rom_banks.set(0, (val & 0x0F).into());
-                MappedWrite::None
+                MappedWrite::Bus
             }
             0xB000..=0xEFFF => {
                 self.latch_banks[((addr - 0xB000) >> 12) as usize] = val & 0x1F;
                 self.update_banks();
-                MappedWrite::None
+                MappedWrite::Bus
             }
             0xF000..=0xFFFF => {
                 self.mirroring = match val & Self::MIRRORING_MASK {
@@ -121,7 +121,7 @@ impl MemMap for Pxrom {
                     1 => Mirroring::Horizontal,
                     _ => unreachable!("impossible mirroring mode"),
                 };
-                MappedWrite::None
+                MappedWrite::Bus
             }
             _ => MappedWrite::None,
         }
@@ -69,6 +69,14 @@ impl Vrc6 {
         vrc6.into()
     }
 
+    /// Outputs of the pulse 1, pulse 2 and sawtooth channels, on the same scale as the mixed
+    /// output.
+    #[inline]
+    #[must_use]
+    pub fn channel_outputs(&self) -> [f32; 3] {
+        self.audio.channel_outputs()
+    }
+
     #[inline]
     #[must_use]
     const fn prg_ram_enabled(&self) -> bool {
@@ -328,9 +336,9 @@ impl MemMap for Vrc6 {
             0xF000 => self.irq.write_reload(val),
             0xF001 => self.irq.write_control(val),
             0xF002 => self.irq.acknowledge(),
-            _ => (),
+            _ => return MappedWrite::None,
         }
-        MappedWrite::None
+        MappedWrite::Bus
     }
 }
 
@@ -395,6 +403,17 @@ impl Vrc6Audio {
         pulse_scale * self.out
     }
 
+    #[must_use]
+    fn channel_outputs(&self) -> [f32; 3] {
+        let pulse_scale = PULSE_TABLE[PULSE_TABLE.len() - 1] / 15.0;
+        [
+            self.pulse1.volume(),
+            self.pulse2.volume(),
+            self.saw.volume(),
+        ]
+        .map(|volume| pulse_scale * volume)
+    }
+
     fn write_register(&mut self, addr: u16, val: u8) {
         // Only A0, A1 and A12-15 are used for registers, remaining addresses are mirrored.
         match addr & 0xF003 {
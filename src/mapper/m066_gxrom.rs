@@ -49,12 +49,15 @@ impl MemMap for Gxrom {
     }
 
     fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
-        if matches!(addr, 0x8000..=0xFFFF) {
-            self.chr_banks.set(0, (val & Self::CHR_BANK_MASK).into());
-            self.prg_rom_banks
-                .set(0, ((val & Self::PRG_BANK_MASK) >> 4).into());
+        match addr {
+            0x8000..=0xFFFF => {
+                self.chr_banks.set(0, (val & Self::CHR_BANK_MASK).into());
+                self.prg_rom_banks
+                    .set(0, ((val & Self::PRG_BANK_MASK) >> 4).into());
+                MappedWrite::Bus
+            }
+            _ => MappedWrite::None,
         }
-        MappedWrite::None
     }
 }
 
@@ -92,7 +92,7 @@ impl MemMap for Bf909x {
                         Mirroring::SingleScreenB
                     };
                 }
-                MappedWrite::None
+                MappedWrite::Bus
             }
             _ => MappedWrite::None,
         }
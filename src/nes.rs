@@ -1,7 +1,9 @@
 //! User Interface representing the the NES Control Deck
 
+#[cfg(feature = "scripting")]
+use crate::script::Script;
 use crate::{
-    audio::AudioMixer,
+    audio::{stems::StemRecorder, AudioMixer},
     common::Regional,
     control_deck::ControlDeck,
     input::Slot,
@@ -9,18 +11,24 @@ use crate::{
     nes::{
         apu_viewer::ApuViewer,
         debug::Debugger,
+        event_viewer::EventViewer,
+        memory_viewer::MemoryViewer,
         ppu_viewer::PpuViewer,
+        sprite_viewer::SpriteViewer,
         state::{Replay, ReplayMode},
     },
     ppu::Ppu,
     NesResult,
 };
 use config::Config;
-use menu::Menu;
+use menu::{CheatEditor, CheatSearch, Menu, PaletteEditor};
 use pix_engine::prelude::*;
 use std::{
     collections::{hash_map::Entry, HashMap, VecDeque},
     env,
+    fs::File,
+    io::BufWriter,
+    ops::ControlFlow,
     path::PathBuf,
     time::Instant,
 };
@@ -29,19 +37,19 @@ pub(crate) mod apu_viewer;
 pub(crate) mod config;
 pub(crate) mod debug;
 pub(crate) mod event;
+pub(crate) mod event_viewer;
 pub(crate) mod filesystem;
+pub(crate) mod memory_viewer;
 pub(crate) mod menu;
 pub(crate) mod ppu_viewer;
+pub(crate) mod sprite_viewer;
 pub(crate) mod state;
 
 const APP_NAME: &str = "TetaNES";
 #[cfg(not(target_arch = "wasm32"))]
 const ICON: &[u8] = include_bytes!("../assets/tetanes_icon.png");
-const WINDOW_WIDTH_NTSC: f32 = Ppu::WIDTH as f32 * 8.0 / 7.0 + 0.5; // for 8:7 Aspect Ratio
-const WINDOW_WIDTH_PAL: f32 = Ppu::WIDTH as f32 * 18.0 / 13.0 + 0.5; // for 18:13 Aspect Ratio
-const WINDOW_HEIGHT: f32 = Ppu::HEIGHT as f32;
-// Trim top and bottom 8 scanlines
-pub(crate) const NES_FRAME_SRC: Rect<i32> = rect![0, 8, Ppu::WIDTH as i32, Ppu::HEIGHT as i32 - 16];
+const PIXEL_ASPECT_NTSC: f32 = 8.0 / 7.0; // for 8:7 Aspect Ratio
+const PIXEL_ASPECT_PAL: f32 = 18.0 / 13.0; // for 18:13 Aspect Ratio
 
 #[derive(Debug, Clone)]
 #[must_use]
@@ -54,6 +62,7 @@ pub struct NesBuilder {
     speed: Option<f32>,
     genie_codes: Vec<String>,
     debug: bool,
+    script: Option<PathBuf>,
 }
 
 impl NesBuilder {
@@ -68,6 +77,7 @@ impl NesBuilder {
             speed: None,
             genie_codes: vec![],
             debug: false,
+            script: None,
         }
     }
 
@@ -124,6 +134,15 @@ impl NesBuilder {
         self
     }
 
+    /// A script to run for each loaded ROM. Requires the `scripting` feature.
+    pub fn script<P>(&mut self, path: Option<P>) -> &mut Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.script = path.map(Into::into);
+        self
+    }
+
     /// Creates an Nes instance from an `NesBuilder`.
     ///
     /// # Errors
@@ -141,15 +160,18 @@ impl NesBuilder {
         let mut control_deck = ControlDeck::new(config.ram_state);
         control_deck.set_region(config.region);
         control_deck.set_filter(config.filter);
+        control_deck.set_overscan(config.overscan);
         control_deck.set_four_player(config.four_player);
         control_deck.connect_zapper(config.zapper);
+        control_deck.set_stereo(config.stereo);
+        for (&stem, &mix) in &config.channel_mix {
+            control_deck.set_channel_mix(stem, mix);
+        }
 
-        Ok(Nes::new(
-            control_deck,
-            config,
-            self.replay.clone(),
-            self.debug,
-        ))
+        let mut nes = Nes::new(control_deck, config, self.replay.clone(), self.debug);
+        nes.load_palettes();
+        nes.script_path = self.script.clone();
+        Ok(nes)
     }
 }
 
@@ -180,15 +202,18 @@ impl Default for Mode {
 pub struct Nes {
     control_deck: ControlDeck,
     audio: AudioMixer,
+    stem_recording: Option<StemRecorder<BufWriter<File>>>,
     players: HashMap<Slot, ControllerId>,
     emulation: Option<(WindowId, TextureId)>,
     debugger: Option<Debugger>,
     ppu_viewer: Option<PpuViewer>,
     apu_viewer: Option<ApuViewer>,
+    event_viewer: Option<EventViewer>,
+    memory_viewer: Option<MemoryViewer>,
+    sprite_viewer: Option<SpriteViewer>,
     config: Config,
     mode: Mode,
     replay_path: Option<PathBuf>,
-    record_sound: bool,
     debug: bool,
     rewind_frame: u32,
     rewind_buffer: VecDeque<Vec<u8>>,
@@ -198,34 +223,45 @@ pub struct Nes {
     selected_path: usize,
     error: Option<String>,
     confirm_quit: Option<(String, bool)>,
+    script_path: Option<PathBuf>,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+    cheat_editor: CheatEditor,
+    cheat_search: CheatSearch,
+    palette_editor: PaletteEditor,
     #[cfg(feature = "profile-rate-control")]
     stats: std::io::BufWriter<std::fs::File>,
 }
 
 impl Nes {
     pub(crate) fn new(
-        control_deck: ControlDeck,
+        mut control_deck: ControlDeck,
         config: Config,
         replay_path: Option<PathBuf>,
         debug: bool,
     ) -> Self {
-        let audio = AudioMixer::new(
-            control_deck.sample_rate(),
+        let mut audio = AudioMixer::new(
             config.audio_sample_rate / config.speed,
             config.audio_buffer_size,
         );
+        audio.set_channels(control_deck.audio_channels());
+        audio.set_filter_profile(config.audio_filter);
+        control_deck.set_sample_rate(audio.sample_rate());
         Self {
             control_deck,
             audio,
+            stem_recording: None,
             players: HashMap::new(),
             emulation: None,
             debugger: None,
             ppu_viewer: None,
             apu_viewer: None,
+            event_viewer: None,
+            memory_viewer: None,
+            sprite_viewer: None,
             config,
             mode: if debug { Mode::Paused } else { Mode::default() },
             replay_path,
-            record_sound: false,
             debug,
             rewind_frame: 0,
             rewind_buffer: VecDeque::new(),
@@ -235,6 +271,12 @@ impl Nes {
             selected_path: 0,
             error: None,
             confirm_quit: None,
+            script_path: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+            cheat_editor: CheatEditor::default(),
+            cheat_search: CheatSearch::default(),
+            palette_editor: PaletteEditor::default(),
             #[cfg(feature = "profile-rate-control")]
             stats: std::io::BufWriter::new(std::fs::File::create("./stats.dat").unwrap()),
         }
@@ -289,10 +331,31 @@ impl Nes {
                 s.line([x, y - 8, x, y + 8])?;
                 s.clear_texture_target();
             }
-            s.texture(texture_id, NES_FRAME_SRC, None)?;
+            #[cfg(feature = "scripting")]
+            {
+                if let Some(ref script) = self.script {
+                    let overlays = script.overlays();
+                    if !overlays.is_empty() {
+                        s.set_texture_target(texture_id)?;
+                        s.push();
+                        s.stroke(None);
+                        s.fill(Color::WHITE);
+                        for overlay in overlays.iter() {
+                            s.set_cursor_pos([overlay.x, overlay.y]);
+                            s.text(&overlay.text)?;
+                        }
+                        s.pop();
+                        s.clear_texture_target();
+                    }
+                }
+            }
+            s.texture(texture_id, self.config.frame_src(), None)?;
         }
         self.render_debugger(s)?;
         self.render_ppu_viewer(s)?;
+        self.render_event_viewer(s)?;
+        self.render_memory_viewer(s)?;
+        self.render_sprite_viewer(s)?;
         Ok(())
     }
 }
@@ -305,13 +368,6 @@ impl PixEngine for Nes {
         }
         self.audio.open_playback(s)?;
         self.set_scale(s, self.config.scale);
-        for code in self.config.genie_codes.clone() {
-            if let Err(err) = self.control_deck.add_genie_code(code.clone()) {
-                log::warn!("{}", err);
-                self.add_message(format!("Invalid Genie Code: '{code}'"));
-                break;
-            }
-        }
 
         self.emulation = Some((
             s.window_id(),
@@ -338,7 +394,9 @@ impl PixEngine for Nes {
             let seconds_to_run = (self.config.speed * s.delta_time().as_secs_f32())
                 .clamp(0.0, self.config.speed * (1.0 / 20.0));
             let prev_frame = self.control_deck.frame_number();
-            match self
+            #[cfg(feature = "scripting")]
+            let mut script_result = Ok(());
+            let result = self
                 .control_deck
                 .clock_seconds_inspect(seconds_to_run, |cpu| {
                     if let Some(ref mut viewer) = self.ppu_viewer {
@@ -348,8 +406,44 @@ impl PixEngine for Nes {
                             viewer.load_palettes(cpu.ppu());
                         }
                     }
-                }) {
-                Ok(_) => {
+                    if let Some(ref mut viewer) = self.sprite_viewer {
+                        // Secondary OAM holds the evaluated sprites until the next scanline
+                        if cpu.ppu().cycle() > 256 && cpu.ppu().scanline() == viewer.scanline() {
+                            viewer.load_sprites(cpu.ppu());
+                        }
+                    }
+                    #[cfg(feature = "scripting")]
+                    {
+                        if let Some(ref mut script) = self.script {
+                            if script_result.is_ok() {
+                                script_result = script.inspect(cpu);
+                            }
+                        }
+                    }
+                });
+            #[cfg(feature = "scripting")]
+            {
+                if let Err(err) = script_result {
+                    log::error!("{:?}", err);
+                    self.add_message("Script error, see log for details");
+                    self.script = None;
+                } else if self.script.as_ref().map_or(false, Script::quit_requested) {
+                    s.quit();
+                }
+            }
+            match result {
+                Ok(res) => {
+                    if let ControlFlow::Break(_) = res {
+                        self.pause_play();
+                        let message = match self.control_deck.watchpoint_hit() {
+                            Some(hit) => format!("Watchpoint hit: {hit}"),
+                            None => {
+                                let pc = self.control_deck.cpu().pc();
+                                format!("Breakpoint hit at ${pc:04X}")
+                            }
+                        };
+                        self.add_message(message);
+                    }
                     if prev_frame != self.control_deck.frame_number() {
                         self.update_rewind();
                         if self.config.sound {
@@ -371,7 +465,9 @@ impl PixEngine for Nes {
                                 self.config.dynamic_rate_delta,
                             );
                         }
+                        self.record_stems();
                         self.control_deck.clear_audio_samples();
+                        self.control_deck.set_sample_rate(self.audio.sample_rate());
                     }
                 }
                 Err(err) => return self.handle_emulation_error(s, &err),
@@ -432,11 +528,22 @@ impl PixEngine for Nes {
                 self.stop_replay();
             }
         }
+        if let Err(err) = self.audio.stop_recording() {
+            log::error!("{:?}", err);
+        }
+        self.stop_stem_recording();
         self.save_config();
         Ok(())
     }
 
     fn on_key_pressed(&mut self, s: &mut PixState, event: KeyEvent) -> PixResult<bool> {
+        if let Some(ref mut viewer) = self.memory_viewer {
+            if s.focused_window(viewer.window_id())
+                && viewer.handle_key(&mut self.control_deck, event.key)
+            {
+                return Ok(true);
+            }
+        }
         Ok(self.handle_key_event(s, event, true))
     }
 
@@ -462,6 +569,33 @@ impl PixEngine for Nes {
         Ok(self.handle_mouse_motion(pos))
     }
 
+    fn on_mouse_wheel(&mut self, s: &mut PixState, pos: Point<i32>) -> PixResult<bool> {
+        if let Some(ref mut debugger) = self.debugger {
+            if s.focused_window(debugger.window_id()) {
+                // Scrolling up moves to earlier instructions
+                debugger.scroll(self.control_deck.cpu(), -3 * pos.y());
+                return Ok(true);
+            }
+        }
+        if let Some(ref mut viewer) = self.memory_viewer {
+            if s.focused_window(viewer.window_id()) {
+                viewer.scroll(&self.control_deck, -3 * pos.y());
+                return Ok(true);
+            }
+        }
+        if let Some(ref mut viewer) = self.sprite_viewer {
+            if s.focused_window(viewer.window_id()) {
+                // Scrolling up moves to earlier scanlines
+                match pos.y() {
+                    y if y > 0 => viewer.dec_scanline(y.unsigned_abs()),
+                    y => viewer.inc_scanline(y.unsigned_abs()),
+                }
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     fn on_controller_update(
         &mut self,
         _s: &mut PixState,
@@ -527,12 +661,24 @@ impl PixEngine for Nes {
                 } else if matches!(&self.debugger, Some(ref debugger) if debugger.window_id() == window_id)
                 {
                     self.debugger = None;
+                    self.control_deck.stop_history();
                 } else if matches!(self.ppu_viewer, Some(ref viewer) if viewer.window_id() == window_id)
                 {
                     self.ppu_viewer = None;
                 } else if matches!(self.apu_viewer, Some(ref view) if view.window_id() == window_id)
                 {
                     self.apu_viewer = None;
+                } else if matches!(self.event_viewer, Some(ref view) if view.window_id() == window_id)
+                {
+                    self.event_viewer = None;
+                    self.control_deck.stop_event_log();
+                } else if matches!(self.memory_viewer, Some(ref view) if view.window_id() == window_id)
+                {
+                    self.memory_viewer = None;
+                    self.control_deck.stop_access_log();
+                } else if matches!(self.sprite_viewer, Some(ref view) if view.window_id() == window_id)
+                {
+                    self.sprite_viewer = None;
                 }
             }
             WindowEvent::Hidden | WindowEvent::FocusLost => {
@@ -1,20 +1,28 @@
 use crate::{
+    audio::{
+        filter::FilterProfile,
+        stems::{Stem, StemLayout},
+        wav::WavFormat,
+        ChannelMix,
+    },
     common::{config_dir, config_path, NesRegion},
     input::FourPlayer,
     mem::RamState,
     nes::{
         event::{Input, InputBindings, InputMapping},
-        Nes, WINDOW_HEIGHT, WINDOW_WIDTH_NTSC, WINDOW_WIDTH_PAL,
+        Nes, PIXEL_ASPECT_NTSC, PIXEL_ASPECT_PAL,
     },
-    video::VideoFilter,
+    video::{Overscan, VideoFilter},
 };
 use anyhow::Context;
 use pix_engine::{
     point,
-    prelude::{PixResult, PixState},
+    prelude::{PixResult, PixState, Rect},
+    rect,
 };
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     fs::{self, File},
     io::{BufReader, BufWriter},
     path::PathBuf,
@@ -34,7 +42,19 @@ pub(crate) struct Config {
     pub(crate) fullscreen: bool,
     pub(crate) vsync: bool,
     pub(crate) filter: VideoFilter,
+    /// Custom `.pal` palette files by region, using the built-in palette otherwise.
+    #[serde(default)]
+    pub(crate) palettes: HashMap<NesRegion, PathBuf>,
+    /// Pixels cropped from each edge of the frame.
+    #[serde(default)]
+    pub(crate) overscan: Overscan,
     pub(crate) concurrent_dpad: bool,
+    /// Draw every sprite on a scanline instead of the first 8 to reduce flicker.
+    #[serde(default)]
+    pub(crate) unlimited_sprites: bool,
+    /// Per-game overrides of `unlimited_sprites` by ROM file name.
+    #[serde(default)]
+    pub(crate) game_unlimited_sprites: HashMap<String, bool>,
     pub(crate) region: NesRegion,
     pub(crate) ram_state: RamState,
     pub(crate) save_slot: u8,
@@ -49,6 +69,18 @@ pub(crate) struct Config {
     pub(crate) audio_buffer_size: usize,
     pub(crate) dynamic_rate_control: bool,
     pub(crate) dynamic_rate_delta: f32,
+    #[serde(default)]
+    pub(crate) audio_filter: FilterProfile,
+    /// Output left and right channels, using the panning in `channel_mix`.
+    #[serde(default)]
+    pub(crate) stereo: bool,
+    /// Volume and panning by audio channel, using the default mix for any missing.
+    #[serde(default)]
+    pub(crate) channel_mix: HashMap<Stem, ChannelMix>,
+    #[serde(default)]
+    pub(crate) sound_recording_format: WavFormat,
+    #[serde(default)]
+    pub(crate) stem_layout: StemLayout,
     pub(crate) genie_codes: Vec<String>,
     pub(crate) bindings: InputBindings,
     #[serde(skip)]
@@ -64,7 +96,11 @@ impl Default for Config {
             fullscreen: false,
             vsync: true,
             filter: VideoFilter::default(),
+            palettes: HashMap::new(),
+            overscan: Overscan::default(),
             concurrent_dpad: false,
+            unlimited_sprites: false,
+            game_unlimited_sprites: HashMap::new(),
             region: NesRegion::default(),
             ram_state: RamState::default(),
             save_slot: 1,
@@ -79,6 +115,11 @@ impl Default for Config {
             audio_buffer_size: 4096,
             dynamic_rate_control: true,
             dynamic_rate_delta: 0.005,
+            audio_filter: FilterProfile::default(),
+            stereo: false,
+            channel_mix: HashMap::new(),
+            sound_recording_format: WavFormat::default(),
+            stem_layout: StemLayout::default(),
             genie_codes: vec![],
             bindings: InputBindings::default(),
             input_map: InputMapping::default(),
@@ -153,15 +194,37 @@ impl Config {
     //     self.bindings.update_from_map(&self.input_map);
     // }
 
+    pub(crate) fn unlimited_sprites(&self, rom: &str) -> bool {
+        self.game_unlimited_sprites
+            .get(rom)
+            .copied()
+            .unwrap_or(self.unlimited_sprites)
+    }
+
+    pub(crate) fn pixel_aspect(&self) -> f32 {
+        match self.region {
+            NesRegion::Ntsc => PIXEL_ASPECT_NTSC,
+            NesRegion::Pal | NesRegion::Dendy => PIXEL_ASPECT_PAL,
+        }
+    }
+
     pub(crate) fn get_dimensions(&self) -> (u32, u32) {
-        let width = match self.region {
-            NesRegion::Ntsc => WINDOW_WIDTH_NTSC,
-            NesRegion::Pal | NesRegion::Dendy => WINDOW_WIDTH_PAL,
-        };
+        let width = self.overscan.width() as f32 * self.pixel_aspect() + 0.5;
         let width = (self.scale * width) as u32;
-        let height = (self.scale * WINDOW_HEIGHT) as u32;
+        let height = (self.scale * self.overscan.height() as f32) as u32;
         (width, height)
     }
+
+    /// The area of the frame shown after cropping overscan.
+    pub(crate) fn frame_src(&self) -> Rect<i32> {
+        let overscan = self.overscan.clamped();
+        rect![
+            overscan.left as i32,
+            overscan.top as i32,
+            overscan.width() as i32,
+            overscan.height() as i32
+        ]
+    }
 }
 
 impl Nes {
@@ -205,6 +268,46 @@ impl Nes {
             .set_output_frequency(self.config.audio_sample_rate / self.config.speed);
     }
 
+    /// Switches between mono and stereo output, reopening playback for the new channel count.
+    pub(crate) fn set_stereo(&mut self, s: &mut PixState, stereo: bool) -> PixResult<()> {
+        self.config.stereo = stereo;
+        // Recordings can't change channel count part way through
+        if self.audio.is_recording() {
+            self.toggle_sound_recording(s);
+        }
+        self.control_deck.set_stereo(stereo);
+        self.audio.set_channels(self.control_deck.audio_channels());
+        self.audio.reset(self.config.audio_buffer_size);
+        self.audio.open_playback(s)?;
+        Ok(())
+    }
+
+    pub(crate) fn set_channel_mix(&mut self, stem: Stem, mix: ChannelMix) {
+        if mix == ChannelMix::default() {
+            self.config.channel_mix.remove(&stem);
+        } else {
+            self.config.channel_mix.insert(stem, mix);
+        }
+        self.control_deck.set_channel_mix(stem, mix);
+    }
+
+    /// Enables stereo with pulse 1 panned left and pulse 2 panned right, keeping their volumes.
+    pub(crate) fn set_pulse_split(&mut self, s: &mut PixState) -> PixResult<()> {
+        if !self.config.stereo {
+            self.set_stereo(s, true)?;
+        }
+        for (stem, pan) in [(Stem::Pulse1, -1.0), (Stem::Pulse2, 1.0)] {
+            let mix = self.control_deck.channel_mix(stem);
+            self.set_channel_mix(stem, ChannelMix::new(mix.volume, pan));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn update_unlimited_sprites(&mut self) {
+        let enabled = self.config.unlimited_sprites(self.rom_filename());
+        self.control_deck.set_unlimited_sprites(enabled);
+    }
+
     pub(crate) fn update_frame_rate(&mut self, s: &mut PixState) -> PixResult<()> {
         match self.config.region {
             NesRegion::Ntsc => s.frame_rate(60),
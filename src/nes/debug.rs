@@ -1,23 +1,85 @@
 use crate::{
-    cpu::Status,
+    cpu::{Cpu, Status},
+    debugger::{
+        branch_target, history::History, instr_size, prev_instr_addr, stack_return_addrs,
+        Breakpoint,
+    },
     mem::{Access, Mem},
-    nes::Nes,
+    nes::{event::DebugAction, Nes},
 };
 use pix_engine::prelude::*;
 
 #[derive(Debug)]
 pub(crate) struct Debugger {
     window_id: WindowId,
+    /// Address of the first instruction shown in the disassembly pane.
+    disasm_addr: u16,
+    /// Whether the disassembly pane scrolls to keep the PC in view.
+    follow_pc: bool,
+    cursor: Option<u16>,
+    jump_addr: String,
 }
 
 impl Debugger {
-    const fn new(window_id: WindowId) -> Self {
-        Self { window_id }
+    const DISASM_ROWS: usize = 24;
+    const DISASM_X: i32 = 320;
+    const GUTTER_WIDTH: i32 = 24;
+
+    fn new(window_id: WindowId, pc: u16) -> Self {
+        Self {
+            window_id,
+            disasm_addr: pc,
+            follow_pc: true,
+            cursor: None,
+            jump_addr: String::new(),
+        }
     }
 
     pub(crate) const fn window_id(&self) -> WindowId {
         self.window_id
     }
+
+    /// Scrolls the disassembly pane by a number of instructions, negative scrolling up.
+    pub(crate) fn scroll(&mut self, cpu: &Cpu, lines: i32) {
+        self.follow_pc = false;
+        for _ in 0..lines.unsigned_abs() {
+            self.disasm_addr = if lines < 0 {
+                prev_instr_addr(cpu, self.disasm_addr)
+            } else {
+                self.disasm_addr
+                    .wrapping_add(instr_size(cpu, self.disasm_addr))
+            };
+        }
+    }
+
+    /// Shows `addr` a few rows from the top of the disassembly pane.
+    fn goto(&mut self, cpu: &Cpu, addr: u16) {
+        self.disasm_addr = addr;
+        for _ in 0..Self::DISASM_ROWS / 4 {
+            self.disasm_addr = prev_instr_addr(cpu, self.disasm_addr);
+        }
+    }
+
+    /// Returns the addresses of each instruction shown in the disassembly pane.
+    fn visible_addrs(&self, cpu: &Cpu) -> Vec<u16> {
+        let mut addr = self.disasm_addr;
+        (0..Self::DISASM_ROWS)
+            .map(|_| {
+                let current = addr;
+                addr = addr.wrapping_add(instr_size(cpu, addr));
+                current
+            })
+            .collect()
+    }
+}
+
+/// Actions requested by the disassembly pane, handled once rendering is done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DisasmAction {
+    Debug(DebugAction),
+    ToggleBreakpoint(u16),
+    RunToCursor(u16),
+    Message(String),
 }
 
 impl Nes {
@@ -25,19 +87,23 @@ impl Nes {
         match self.debugger {
             None => {
                 let (w, h) = s.dimensions()?;
+                // Leave room for the disassembly pane
                 let window_id = s
                     .window()
-                    .dimensions(w, h)
+                    .dimensions(w.max(800), h.max(720))
                     .title("Debugger")
                     .position(10, 10)
                     .resizable()
                     .build()?;
-                self.debugger = Some(Debugger::new(window_id));
+                let pc = self.control_deck.cpu().pc();
+                self.debugger = Some(Debugger::new(window_id, pc));
+                self.control_deck.start_history(History::default());
                 self.pause_play();
             }
             Some(ref debugger) => {
                 s.close_window(debugger.window_id())?;
                 self.debugger = None;
+                self.control_deck.stop_history();
             }
         }
         Ok(())
@@ -143,19 +209,184 @@ impl Nes {
                 }
             }
 
-            {
-                let cpu = self.control_deck.cpu_mut();
+            let action = self.render_disassembly(s)?;
+            s.reset_window_target();
 
-                s.spacing()?;
-                let mut pc = cpu.pc();
-                for _ in 0..10 {
-                    cpu.disassemble(&mut pc);
-                    s.text(cpu.disasm())?;
+            match action {
+                Some(DisasmAction::Debug(action)) => {
+                    let _ = self.handle_debug(s, action, false)?;
                 }
+                Some(DisasmAction::ToggleBreakpoint(addr)) => self.toggle_breakpoint(addr),
+                Some(DisasmAction::RunToCursor(addr)) => {
+                    self.control_deck.set_run_to(Some(addr));
+                    self.resume_play();
+                }
+                Some(DisasmAction::Message(message)) => self.add_message(message),
+                None => (),
             }
-
-            s.reset_window_target();
         }
         Ok(())
     }
+
+    /// Renders the disassembly pane to the right of the CPU state. Clicking the gutter toggles a
+    /// breakpoint and clicking an instruction moves the cursor.
+    fn render_disassembly(&mut self, s: &mut PixState) -> PixResult<Option<DisasmAction>> {
+        let debugger = match self.debugger {
+            Some(ref mut debugger) => debugger,
+            None => return Ok(None),
+        };
+        let mut action = None;
+        let x = Debugger::DISASM_X;
+        s.set_cursor_pos([x, 10]);
+        s.set_column_offset(x);
+
+        // Controls
+
+        for (label, debug_action) in [
+            ("Step Into", DebugAction::StepInto),
+            ("Step Over", DebugAction::StepOver),
+            ("Step Out", DebugAction::StepOut),
+            ("Step Frame", DebugAction::StepFrame),
+            ("Step Back", DebugAction::StepBack),
+        ] {
+            if s.button(label)? {
+                action = Some(DisasmAction::Debug(debug_action));
+            }
+            s.same_line(None);
+        }
+        s.spacing()?;
+
+        let cpu = self.control_deck.cpu();
+        let pc = cpu.pc();
+        if s.button("Go to PC")? {
+            debugger.follow_pc = true;
+            debugger.goto(cpu, pc);
+        }
+        s.same_line(None);
+        if let Some(cursor) = debugger.cursor {
+            if s.button("Run to Cursor")? {
+                action = Some(DisasmAction::RunToCursor(cursor));
+            }
+            s.same_line(None);
+            if s.button("Toggle Breakpoint")? {
+                action = Some(DisasmAction::ToggleBreakpoint(cursor));
+            }
+            s.same_line(None);
+            if let Some(target) = branch_target(cpu, cursor) {
+                if s.button("Follow Branch")? {
+                    debugger.follow_pc = false;
+                    debugger.cursor = Some(target);
+                    debugger.goto(cpu, target);
+                }
+            }
+        }
+        s.spacing()?;
+        s.text_field("Address", &mut debugger.jump_addr)?;
+        s.same_line(None);
+        if s.button("Jump")? {
+            let addr = debugger.jump_addr.trim().trim_start_matches('$');
+            match u16::from_str_radix(addr, 16) {
+                Ok(addr) => {
+                    debugger.follow_pc = false;
+                    debugger.cursor = Some(addr);
+                    debugger.goto(cpu, addr);
+                }
+                Err(_) => {
+                    action = Some(DisasmAction::Message(format!(
+                        "Invalid address: {}",
+                        debugger.jump_addr
+                    )));
+                }
+            }
+        }
+        s.spacing()?;
+
+        // Instructions
+
+        if debugger.follow_pc && !debugger.visible_addrs(cpu).contains(&pc) {
+            debugger.goto(cpu, pc);
+        }
+        let return_addrs = stack_return_addrs(cpu);
+        let breakpoints: Vec<u16> = debugger
+            .visible_addrs(cpu)
+            .into_iter()
+            .filter(|&addr| {
+                self.control_deck
+                    .breakpoints()
+                    .iter()
+                    .any(|breakpoint| breakpoint.addr().contains_mapped(addr, cpu.mapper()))
+            })
+            .collect();
+        let clicked = s.focused_window(debugger.window_id()) && s.mouse_clicked(Mouse::Left);
+        let m = s.mouse_pos();
+        let (width, _) = s.dimensions()?;
+
+        s.push();
+        let mut addr = debugger.disasm_addr;
+        for _ in 0..Debugger::DISASM_ROWS {
+            if let Some(label) = self.control_deck.label(addr) {
+                s.fill(Color::GRAY);
+                s.text(&format!("    {label}:"))?;
+            }
+            let row_addr = addr;
+            let marker = match (
+                breakpoints.contains(&row_addr),
+                row_addr == pc,
+                return_addrs.contains(&row_addr),
+            ) {
+                (true, true, _) => "*>",
+                (true, false, true) => "*<",
+                (true, false, false) => "* ",
+                (false, true, _) => " >",
+                (false, false, true) => " <",
+                (false, false, false) => "  ",
+            };
+            let color = if row_addr == pc {
+                Color::GREEN
+            } else if debugger.cursor == Some(row_addr) {
+                Color::YELLOW
+            } else if breakpoints.contains(&row_addr) {
+                Color::RED
+            } else {
+                Color::WHITE
+            };
+
+            let top = s.cursor_pos().y();
+            s.fill(color);
+            let disasm = self.control_deck.disassemble(&mut addr);
+            s.text(&format!("{marker} {disasm}"))?;
+            let row = rect![x, top, width as i32 - x, s.cursor_pos().y() - top];
+            if clicked && row.contains(m) {
+                if m.x() < x + Debugger::GUTTER_WIDTH {
+                    action = Some(DisasmAction::ToggleBreakpoint(row_addr));
+                } else {
+                    debugger.cursor = Some(row_addr);
+                }
+            }
+        }
+        s.pop();
+        s.reset_column_offset();
+
+        Ok(action)
+    }
+
+    /// Removes any breakpoint covering `addr`, or adds one if there isn't one.
+    fn toggle_breakpoint(&mut self, addr: u16) {
+        let mapper = self.control_deck.cpu().mapper();
+        let index = self
+            .control_deck
+            .breakpoints()
+            .iter()
+            .position(|breakpoint| breakpoint.addr().contains_mapped(addr, mapper));
+        match index {
+            Some(index) => {
+                let _ = self.control_deck.remove_breakpoint(index);
+            }
+            None => {
+                let _ = self
+                    .control_deck
+                    .add_breakpoint(Breakpoint::new(addr, vec![]));
+            }
+        }
+    }
 }
@@ -8,7 +8,7 @@ use crate::{
     input::{JoypadBtn, JoypadBtnState, Slot},
     mapper::MapperRevision,
     mem::{Access, Mem},
-    nes::{menu::Menu, Mode, Nes, NesResult, ReplayMode, NES_FRAME_SRC},
+    nes::{menu::Menu, Mode, Nes, NesResult, ReplayMode},
     video::VideoFilter,
 };
 use pix_engine::prelude::*;
@@ -17,7 +17,7 @@ use std::{
     cmp::Ordering,
     collections::HashMap,
     fmt,
-    ops::{Deref, DerefMut},
+    ops::{ControlFlow, Deref, DerefMut},
     time::{Duration, Instant},
 };
 
@@ -150,6 +150,7 @@ pub(crate) enum NesState {
 pub(crate) enum Feature {
     ToggleGameplayRecording,
     ToggleSoundRecording,
+    ToggleStemRecording,
     Rewind,
     TakeScreenshot,
     SaveState,
@@ -180,11 +181,17 @@ pub(crate) enum DebugAction {
     ToggleCpuDebugger,
     TogglePpuDebugger,
     ToggleApuDebugger,
+    ToggleEventViewer,
+    ToggleMemoryViewer,
+    ToggleSpriteViewer,
     StepInto,
     StepOver,
     StepOut,
     StepFrame,
     StepScanline,
+    StepBack,
+    StepBackFrame,
+    RunBack,
     IncScanline,
     DecScanline,
 }
@@ -325,14 +332,14 @@ impl Nes {
     }
 
     pub fn set_zapper_pos(&mut self, pos: Point<i32>) {
-        let mut pos = pos / self.config.scale as i32;
-        pos.set_x((pos.x() as f32 * 8.0 / 7.0 + 0.5) as i32); // Adjust ratio
-        if pos.y() < NES_FRAME_SRC.top() {
-            pos.set_y(8);
-        } else if pos.y() >= NES_FRAME_SRC.bottom() {
-            pos.set_y(NES_FRAME_SRC.bottom() - 1);
-        }
-        self.control_deck.aim_zapper(pos.x(), pos.y());
+        // Map window coordinates back to the visible area of the frame
+        let src = self.config.frame_src();
+        let scale = self.config.scale;
+        let x = (pos.x() as f32 / (scale * self.config.pixel_aspect())) as i32;
+        let y = (pos.y() as f32 / scale) as i32;
+        let x = (src.left() + x).clamp(src.left(), src.right() - 1);
+        let y = (src.top() + y).clamp(src.top(), src.bottom() - 1);
+        self.control_deck.aim_zapper(x, y);
     }
 
     #[inline]
@@ -551,6 +558,7 @@ impl Nes {
                     ReplayMode::Recording | ReplayMode::Playback => self.stop_replay(),
                 },
                 Feature::ToggleSoundRecording => self.toggle_sound_recording(s),
+                Feature::ToggleStemRecording => self.toggle_stem_recording(s),
                 Feature::TakeScreenshot => self.save_screenshot(s),
                 Feature::SaveState => self.save_state(self.config.save_slot),
                 Feature::LoadState => self.load_state(self.config.save_slot),
@@ -648,7 +656,7 @@ impl Nes {
         true
     }
 
-    fn handle_debug(
+    pub(crate) fn handle_debug(
         &mut self,
         s: &mut PixState,
         action: DebugAction,
@@ -659,20 +667,32 @@ impl Nes {
             DebugAction::ToggleCpuDebugger if !repeat => self.toggle_debugger(s)?,
             DebugAction::TogglePpuDebugger if !repeat => self.toggle_ppu_viewer(s)?,
             DebugAction::ToggleApuDebugger if !repeat => self.toggle_apu_viewer(s)?,
+            DebugAction::ToggleEventViewer if !repeat => self.toggle_event_viewer(s)?,
+            DebugAction::ToggleMemoryViewer if !repeat => self.toggle_memory_viewer(s)?,
+            DebugAction::ToggleSpriteViewer if !repeat => self.toggle_sprite_viewer(s)?,
             DebugAction::StepInto if debugging => self.debug_step_into(s)?,
             DebugAction::StepOver if debugging => self.debug_step_over(s)?,
             DebugAction::StepOut if debugging => self.debug_step_out(s)?,
             DebugAction::StepFrame if debugging => self.debug_step_frame(s)?,
             DebugAction::StepScanline if debugging => self.debug_step_scanline(s)?,
+            DebugAction::StepBack if debugging => self.debug_step_back(),
+            DebugAction::StepBackFrame if debugging => self.debug_step_back_frame(),
+            DebugAction::RunBack if debugging => self.debug_run_back(),
             DebugAction::IncScanline => {
+                let increment = if s.keymod_down(KeyMod::SHIFT) { 10 } else { 1 };
                 if let Some(ref mut viewer) = self.ppu_viewer {
-                    let increment = if s.keymod_down(KeyMod::SHIFT) { 10 } else { 1 };
+                    viewer.inc_scanline(increment);
+                }
+                if let Some(ref mut viewer) = self.sprite_viewer {
                     viewer.inc_scanline(increment);
                 }
             }
             DebugAction::DecScanline => {
+                let decrement = if s.keymod_down(KeyMod::SHIFT) { 10 } else { 1 };
                 if let Some(ref mut viewer) = self.ppu_viewer {
-                    let decrement = if s.keymod_down(KeyMod::SHIFT) { 10 } else { 1 };
+                    viewer.dec_scanline(decrement);
+                }
+                if let Some(ref mut viewer) = self.sprite_viewer {
                     viewer.dec_scanline(decrement);
                 }
             }
@@ -704,9 +724,13 @@ impl Nes {
         if instr.op() == Operation::JSR {
             let rti_addr = self.control_deck.cpu().peek_stack_u16().wrapping_add(1);
             while self.control_deck.cpu().pc() != rti_addr {
-                if let Err(err) = self.control_deck.clock_instr() {
-                    self.handle_emulation_error(s, &err)?;
-                    break;
+                match self.control_deck.clock_instr() {
+                    Ok(ControlFlow::Continue(_)) => (),
+                    Ok(ControlFlow::Break(_)) => return Ok(()),
+                    Err(err) => {
+                        self.handle_emulation_error(s, &err)?;
+                        break;
+                    }
                 }
             }
         }
@@ -716,9 +740,13 @@ impl Nes {
     fn debug_step_out(&mut self, s: &mut PixState) -> NesResult<()> {
         let mut instr = self.next_instr();
         while !matches!(instr.op(), Operation::RTS | Operation::RTI) {
-            if let Err(err) = self.control_deck.clock_instr() {
-                self.handle_emulation_error(s, &err)?;
-                break;
+            match self.control_deck.clock_instr() {
+                Ok(ControlFlow::Continue(_)) => (),
+                Ok(ControlFlow::Break(_)) => return Ok(()),
+                Err(err) => {
+                    self.handle_emulation_error(s, &err)?;
+                    break;
+                }
             }
             instr = self.next_instr();
         }
@@ -744,4 +772,27 @@ impl Nes {
         }
         Ok(())
     }
+
+    fn debug_step_back(&mut self) {
+        self.pause_play();
+        if let Err(err) = self.control_deck.step_back() {
+            self.add_message(format!("Failed to step back: {err}"));
+        }
+    }
+
+    fn debug_step_back_frame(&mut self) {
+        self.pause_play();
+        if let Err(err) = self.control_deck.step_back_frame() {
+            self.add_message(format!("Failed to step back: {err}"));
+        }
+    }
+
+    fn debug_run_back(&mut self) {
+        self.pause_play();
+        match self.control_deck.run_back() {
+            Ok(true) => (),
+            Ok(false) => self.add_message("No breakpoint hit, stopped at start of history"),
+            Err(err) => self.add_message(format!("Failed to run back: {err}")),
+        }
+    }
 }
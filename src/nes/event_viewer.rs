@@ -0,0 +1,157 @@
+use crate::{
+    common::{NesRegion, Regional},
+    debugger::events::PpuEventKind,
+    nes::Nes,
+    ppu::Ppu,
+};
+use pix_engine::prelude::*;
+
+#[derive(Debug)]
+pub(crate) struct EventViewer {
+    window_id: WindowId,
+}
+
+impl EventViewer {
+    const DOTS: u32 = 341;
+    const SCALE: i32 = 2;
+    const PADDING: i32 = 10;
+    const INFO_WIDTH: u32 = 280;
+    // Hovering within this many dots to the right of an event selects it
+    const HOVER_RADIUS: u32 = 4;
+
+    const fn new(window_id: WindowId) -> Self {
+        Self { window_id }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    const fn scanlines(region: NesRegion) -> u32 {
+        match region {
+            NesRegion::Ntsc => 262,
+            NesRegion::Pal | NesRegion::Dendy => 312,
+        }
+    }
+
+    const fn color(kind: PpuEventKind) -> Color {
+        match kind {
+            PpuEventKind::PpuWrite => Color::RED,
+            PpuEventKind::OamDma => Color::ORANGE,
+            PpuEventKind::MapperWrite => Color::MAGENTA,
+            PpuEventKind::Nmi => Color::GREEN,
+            PpuEventKind::Irq => Color::YELLOW,
+            PpuEventKind::SpriteZeroHit => Color::CYAN,
+        }
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_event_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.event_viewer {
+            None => {
+                let scanlines = EventViewer::scanlines(self.control_deck.region());
+                let w = EventViewer::SCALE as u32 * EventViewer::DOTS
+                    + 2 * EventViewer::PADDING as u32
+                    + EventViewer::INFO_WIDTH;
+                let h = EventViewer::SCALE as u32 * scanlines + 2 * EventViewer::PADDING as u32;
+                let window_id = s
+                    .window()
+                    .dimensions(w, h)
+                    .title("Event Viewer")
+                    .position(10, 10)
+                    .build()?;
+                self.event_viewer = Some(EventViewer::new(window_id));
+                self.control_deck.start_event_log();
+            }
+            Some(ref viewer) => {
+                s.close_window(viewer.window_id())?;
+                self.event_viewer = None;
+                self.control_deck.stop_event_log();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn render_event_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        if let Some(ref viewer) = self.event_viewer {
+            let log = match self.control_deck.event_log() {
+                Some(log) => log,
+                None => return Ok(()),
+            };
+
+            s.set_window_target(viewer.window_id())?;
+            s.clear()?;
+            s.stroke(None);
+
+            let scale = EventViewer::SCALE;
+            let padding = EventViewer::PADDING;
+            let scanlines = EventViewer::scanlines(self.control_deck.region());
+            let grid = rect![
+                padding,
+                padding,
+                scale * EventViewer::DOTS as i32,
+                scale * scanlines as i32
+            ];
+            // Visible pixels are output on dots 1-256 of scanlines 0-239
+            let visible = rect![
+                grid.x() + scale,
+                grid.y(),
+                scale * Ppu::WIDTH as i32,
+                scale * Ppu::HEIGHT as i32
+            ];
+
+            s.fill(rgb!(30));
+            s.rect(grid)?;
+            s.fill(rgb!(60));
+            s.rect(visible)?;
+
+            for event in log.events() {
+                s.fill(EventViewer::color(event.kind));
+                s.rect([
+                    grid.x() + scale * event.cycle as i32 - 1,
+                    grid.y() + scale * event.scanline as i32 - 1,
+                    2 * scale,
+                    2 * scale,
+                ])?;
+            }
+
+            // Legend
+
+            s.set_cursor_pos([grid.right() + padding, padding]);
+            s.set_column_offset(grid.right() + padding);
+            s.fill(Color::WHITE);
+            for &kind in PpuEventKind::as_slice() {
+                let pos = s.cursor_pos();
+                s.push();
+                s.fill(EventViewer::color(kind));
+                s.rect([pos.x(), pos.y() + 4, 8, 8])?;
+                s.pop();
+                s.set_cursor_pos([pos.x() + 14, pos.y()]);
+                s.text(kind.as_ref())?;
+            }
+
+            // Event Info
+
+            s.spacing()?;
+            s.text(&format!("Events: {}", log.events().len()))?;
+            let m = s.mouse_pos();
+            if s.focused_window(viewer.window_id()) && grid.contains(m) {
+                let cycle = ((m.x() - grid.x()) / scale) as u32;
+                let scanline = ((m.y() - grid.y()) / scale) as u32;
+                s.text(&format!("Scanline: {scanline:3}  Cycle: {cycle:3}"))?;
+                match log.event_near(scanline, cycle, EventViewer::HOVER_RADIUS) {
+                    Some(event) => s.text(&event.to_string())?,
+                    None => s.text("No event")?,
+                };
+            } else {
+                s.text("Scanline:   0  Cycle:   0")?;
+                s.text("No event")?;
+            }
+
+            s.reset_column_offset();
+            s.reset_window_target();
+        }
+        Ok(())
+    }
+}
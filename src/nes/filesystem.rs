@@ -1,5 +1,14 @@
 use super::{Menu, Mode, Nes, NesResult};
-use crate::{audio::AudioMixer, cart::NesHeader, common::Regional};
+#[cfg(feature = "scripting")]
+use crate::script::Script;
+use crate::{
+    audio::AudioMixer,
+    cart::NesHeader,
+    cheat::{self, Cheat},
+    common::{NesRegion, Regional},
+    debugger::symbols::Symbols,
+    video::palette::Palette,
+};
 use anyhow::{anyhow, Context};
 use flate2::{bufread::DeflateDecoder, write::DeflateEncoder, Compression};
 use pix_engine::prelude::PixState;
@@ -148,6 +157,121 @@ impl Nes {
             })
     }
 
+    /// Loads Game Genie codes from the config and cheats from the per-game cheat file, falling
+    /// back to importing an FCEUX `.cht` file next to the ROM.
+    pub(crate) fn load_cheats(&mut self) {
+        self.control_deck.clear_cheats();
+        for code in self.config.genie_codes.clone() {
+            if let Err(err) = self.control_deck.add_genie_code(code.clone()) {
+                log::warn!("{}", err);
+                self.add_message(format!("Invalid Genie Code: '{code}'"));
+                break;
+            }
+        }
+
+        let path = match self.cheat_path() {
+            Ok(path) if path.exists() => path,
+            Ok(_) => self.config.rom_path.with_extension("cht"),
+            Err(err) => {
+                log::error!("{:?}", err);
+                return;
+            }
+        };
+        if !path.exists() {
+            return;
+        }
+        match File::open(&path)
+            .with_context(|| format!("failed to open {path:?}"))
+            .and_then(|file| cheat::load_cht(BufReader::new(file)))
+        {
+            Ok(cheats) => {
+                log::info!("loaded {} cheats from {:?}", cheats.len(), path);
+                for cheat in cheats {
+                    self.control_deck.add_cheat(cheat);
+                }
+            }
+            Err(err) => {
+                log::error!("{:?}: {:?}", path, err);
+                self.add_message("Failed to load cheats");
+            }
+        }
+    }
+
+    /// Saves cheats, except Game Genie codes from the config, to the per-game cheat file.
+    pub(crate) fn save_cheats(&mut self) {
+        let cheats: Vec<Cheat> = self
+            .control_deck
+            .cheats()
+            .into_iter()
+            .filter(|cheat| {
+                !self
+                    .config
+                    .genie_codes
+                    .iter()
+                    .any(|code| code == cheat.code())
+            })
+            .cloned()
+            .collect();
+        let result = self.cheat_path().and_then(|path| {
+            if let Some(directory) = path.parent() {
+                fs::create_dir_all(directory)
+                    .with_context(|| format!("failed to create directory {directory:?}"))?;
+            }
+            let mut writer = BufWriter::new(
+                File::create(&path).with_context(|| format!("failed to create file {path:?}"))?,
+            );
+            cheat::save_cht(&mut writer, &cheats)
+        });
+        if let Err(err) = result {
+            log::error!("{:?}", err);
+            self.add_message("Failed to save cheats");
+        }
+    }
+
+    /// Loads the custom palettes from the config, keeping the built-in palette for any that fail.
+    pub(crate) fn load_palettes(&mut self) {
+        for (region, path) in self.config.palettes.clone() {
+            if let Err(err) = self.load_palette(region, &path) {
+                log::error!("{:?}", err);
+            }
+        }
+    }
+
+    /// Loads a `.pal` palette file for a region.
+    pub(crate) fn load_palette(&mut self, region: NesRegion, path: &Path) -> NesResult<()> {
+        let mut file =
+            BufReader::new(File::open(path).with_context(|| format!("failed to open {path:?}"))?);
+        let palette =
+            Palette::load(&mut file).with_context(|| format!("failed to load palette {path:?}"))?;
+        self.control_deck.set_palette(region, palette);
+        log::info!("loaded {} palette from {:?}", region.as_ref(), path);
+        Ok(())
+    }
+
+    /// Compiles and starts the script, if any, for the loaded ROM.
+    pub(crate) fn start_script(&mut self) {
+        if let Some(path) = self.script_path.clone() {
+            #[cfg(feature = "scripting")]
+            {
+                match Script::from_path(&path).and_then(|mut script| {
+                    script.start(self.control_deck.cpu_mut())?;
+                    Ok(script)
+                }) {
+                    Ok(script) => self.script = Some(script),
+                    Err(err) => {
+                        log::error!("{:?}: {:?}", path, err);
+                        self.add_message("Failed to start script");
+                        self.script = None;
+                    }
+                }
+            }
+            #[cfg(not(feature = "scripting"))]
+            {
+                log::warn!("{:?}: scripting is not enabled in this build", path);
+            }
+        }
+    }
+
     /// Loads a ROM cartridge into memory
     pub(crate) fn load_rom(&mut self, s: &mut PixState) -> NesResult<()> {
         if self.config.rom_path.is_dir() {
@@ -186,20 +310,41 @@ impl Nes {
         let mut rom = BufReader::new(rom);
         match self.control_deck.load_rom(&name, &mut rom) {
             Ok(()) => {
+                self.stop_stem_recording();
                 self.config.region = self.control_deck.region();
                 s.set_window_dimensions(self.config.get_dimensions())?;
                 self.update_frame_rate(s)?;
-                self.audio = AudioMixer::new(
-                    self.control_deck.sample_rate(),
+                let mut audio = AudioMixer::new(
                     self.config.audio_sample_rate / self.config.speed,
                     self.config.audio_buffer_size,
                 );
+                audio.set_channels(self.control_deck.audio_channels());
+                audio.set_filter_profile(self.config.audio_filter);
+                audio.take_recording(&mut self.audio);
+                self.audio = audio;
                 self.audio.open_playback(s)?;
                 self.audio.resume();
                 if let Err(err) = self.load_sram() {
                     log::error!("{:?}: {:?}", self.config.rom_path, err);
                     self.add_message("Failed to load game state");
                 }
+                match Symbols::from_rom_path(&self.config.rom_path) {
+                    Ok(symbols) => {
+                        if !symbols.is_empty() {
+                            log::info!("loaded {} debug symbols", symbols.len());
+                        }
+                        self.control_deck.load_symbols(symbols);
+                    }
+                    Err(err) => {
+                        log::error!("{:?}: {:?}", self.config.rom_path, err);
+                        self.add_message("Failed to load debug symbols");
+                    }
+                }
+                self.update_unlimited_sprites();
+                self.load_cheats();
+                self.start_script();
+                self.cheat_search.search = None;
+                self.cheat_editor.selected = 0;
                 self.mode = Mode::Playing;
             }
             Err(err) => {
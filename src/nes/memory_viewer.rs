@@ -0,0 +1,301 @@
+use crate::{
+    control_deck::ControlDeck,
+    debugger::memory::{parse_pattern, MemRegion},
+    nes::Nes,
+};
+use pix_engine::prelude::*;
+
+#[derive(Debug)]
+pub(crate) struct MemoryViewer {
+    window_id: WindowId,
+    region: MemRegion,
+    /// First row shown.
+    row: usize,
+    cursor: Option<usize>,
+    /// Whether typing edits the byte under the cursor.
+    editing: bool,
+    /// High nibble typed while editing the byte under the cursor.
+    nibble: Option<u8>,
+    goto_addr: String,
+    search: String,
+}
+
+impl MemoryViewer {
+    const BYTES_PER_ROW: usize = 16;
+    const ROWS: usize = 24;
+    // Bytes accessed within this many frames are highlighted
+    const HIGHLIGHT_FRAMES: u32 = 60;
+
+    const fn new(window_id: WindowId) -> Self {
+        Self {
+            window_id,
+            region: MemRegion::Cpu,
+            row: 0,
+            cursor: None,
+            editing: false,
+            nibble: None,
+            goto_addr: String::new(),
+            search: String::new(),
+        }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    fn rows(deck: &ControlDeck, region: MemRegion) -> usize {
+        (deck.mem_size(region) + Self::BYTES_PER_ROW - 1) / Self::BYTES_PER_ROW
+    }
+
+    /// Scrolls by a number of rows, negative scrolling up.
+    pub(crate) fn scroll(&mut self, deck: &ControlDeck, rows: i32) {
+        let max_row = Self::rows(deck, self.region).saturating_sub(Self::ROWS);
+        self.row = if rows < 0 {
+            self.row.saturating_sub(rows.unsigned_abs() as usize)
+        } else {
+            (self.row + rows as usize).min(max_row)
+        };
+    }
+
+    /// Moves the cursor to `index`, scrolling it into view.
+    fn goto(&mut self, deck: &ControlDeck, index: usize) {
+        self.cursor = Some(index);
+        self.nibble = None;
+        let row = index / Self::BYTES_PER_ROW;
+        if row < self.row || row >= self.row + Self::ROWS {
+            self.row = 0;
+            self.scroll(deck, row.saturating_sub(Self::ROWS / 2) as i32);
+        }
+    }
+
+    /// Edits the byte under the cursor with hex digits and moves the cursor with the arrow keys.
+    /// Returns whether the key was handled.
+    pub(crate) fn handle_key(&mut self, deck: &mut ControlDeck, key: Key) -> bool {
+        let cursor = match self.cursor {
+            Some(cursor) if self.editing => cursor,
+            _ => return false,
+        };
+        let size = deck.mem_size(self.region);
+        let digit = match key {
+            Key::Num0 => 0x0,
+            Key::Num1 => 0x1,
+            Key::Num2 => 0x2,
+            Key::Num3 => 0x3,
+            Key::Num4 => 0x4,
+            Key::Num5 => 0x5,
+            Key::Num6 => 0x6,
+            Key::Num7 => 0x7,
+            Key::Num8 => 0x8,
+            Key::Num9 => 0x9,
+            Key::A => 0xA,
+            Key::B => 0xB,
+            Key::C => 0xC,
+            Key::D => 0xD,
+            Key::E => 0xE,
+            Key::F => 0xF,
+            Key::Left if cursor > 0 => {
+                self.goto(deck, cursor - 1);
+                return true;
+            }
+            Key::Right if cursor + 1 < size => {
+                self.goto(deck, cursor + 1);
+                return true;
+            }
+            Key::Up if cursor >= Self::BYTES_PER_ROW => {
+                self.goto(deck, cursor - Self::BYTES_PER_ROW);
+                return true;
+            }
+            Key::Down if cursor + Self::BYTES_PER_ROW < size => {
+                self.goto(deck, cursor + Self::BYTES_PER_ROW);
+                return true;
+            }
+            Key::Escape => {
+                self.editing = false;
+                self.nibble = None;
+                return true;
+            }
+            _ => return false,
+        };
+        match self.nibble.take() {
+            None => self.nibble = Some(digit),
+            Some(hi) => {
+                deck.poke_mem(self.region, cursor, (hi << 4) | digit);
+                if cursor + 1 < size {
+                    self.goto(deck, cursor + 1);
+                }
+            }
+        }
+        true
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_memory_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.memory_viewer {
+            None => {
+                let window_id = s
+                    .window()
+                    .dimensions(640, 640)
+                    .title("Memory Viewer")
+                    .position(10, 10)
+                    .build()?;
+                self.memory_viewer = Some(MemoryViewer::new(window_id));
+                self.control_deck.start_access_log();
+            }
+            Some(ref viewer) => {
+                s.close_window(viewer.window_id())?;
+                self.memory_viewer = None;
+                self.control_deck.stop_access_log();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn render_memory_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        if let Some(ref mut viewer) = self.memory_viewer {
+            let deck = &self.control_deck;
+            let mut message = None;
+
+            s.set_window_target(viewer.window_id())?;
+            s.clear()?;
+            s.fill(Color::WHITE);
+            s.stroke(None);
+
+            let clicked = s.focused_window(viewer.window_id()) && s.mouse_clicked(Mouse::Left);
+            if clicked {
+                // Clicking anywhere but a byte stops editing so typing goes to the text fields
+                viewer.editing = false;
+                viewer.nibble = None;
+            }
+
+            // Controls
+
+            for (i, &region) in MemRegion::as_slice().iter().enumerate() {
+                if i % 4 > 0 {
+                    s.same_line(None);
+                }
+                if s.button(region.as_ref())? && region != viewer.region {
+                    viewer.region = region;
+                    viewer.row = 0;
+                    viewer.cursor = None;
+                }
+            }
+            let size = deck.mem_size(viewer.region);
+            s.text(&format!("{}: {size} bytes", viewer.region.as_ref()))?;
+            if size == 0 {
+                s.text("Not present on this cartridge")?;
+                s.reset_window_target();
+                return Ok(());
+            }
+
+            s.text_field("Address", &mut viewer.goto_addr)?;
+            s.same_line(None);
+            if s.button("Go To")? {
+                let addr = viewer.goto_addr.trim().trim_start_matches('$');
+                match usize::from_str_radix(addr, 16) {
+                    Ok(index) if index < size => viewer.goto(deck, index),
+                    _ => message = Some(format!("Invalid address: {}", viewer.goto_addr)),
+                }
+            }
+            s.text_field("Search", &mut viewer.search)?;
+            s.same_line(None);
+            if s.button("Find Next")? {
+                match parse_pattern(&viewer.search) {
+                    Ok(pattern) => {
+                        let start = viewer.cursor.map_or(0, |cursor| cursor + 1);
+                        match deck.find_mem(viewer.region, &pattern, start) {
+                            Some(index) => viewer.goto(deck, index),
+                            None => message = Some(format!("{} not found", viewer.search)),
+                        }
+                    }
+                    Err(err) => message = Some(format!("Invalid search: {err}")),
+                }
+            }
+            s.spacing()?;
+
+            // Bytes
+
+            let frame = deck.frame_number();
+            let recent = |accessed: Option<u32>| {
+                accessed.map_or(false, |accessed| {
+                    frame.wrapping_sub(accessed) < MemoryViewer::HIGHLIGHT_FRAMES
+                })
+            };
+            let m = s.mouse_pos();
+
+            s.push();
+            let end_row =
+                (viewer.row + MemoryViewer::ROWS).min(MemoryViewer::rows(deck, viewer.region));
+            for row in viewer.row..end_row {
+                let start = row * MemoryViewer::BYTES_PER_ROW;
+                let end = (start + MemoryViewer::BYTES_PER_ROW).min(size);
+                let top = s.cursor_pos().y();
+                s.fill(Color::GRAY);
+                s.monospace(format!("{start:04X}:"))?;
+
+                let mut byte_xs = [0; MemoryViewer::BYTES_PER_ROW + 1];
+                let mut ascii = String::with_capacity(MemoryViewer::BYTES_PER_ROW);
+                for index in start..end {
+                    s.same_line(None);
+                    byte_xs[index - start] = s.cursor_pos().x();
+                    let val = deck.peek_mem(viewer.region, index);
+                    let access = deck.last_access(viewer.region, index).unwrap_or_default();
+                    let color = if viewer.cursor == Some(index) {
+                        Color::YELLOW
+                    } else if recent(access.write) {
+                        Color::RED
+                    } else if recent(access.read) {
+                        Color::GREEN
+                    } else {
+                        Color::WHITE
+                    };
+                    s.fill(color);
+                    match viewer.nibble {
+                        Some(hi) if viewer.cursor == Some(index) => {
+                            s.monospace(format!("{hi:X}_"))?
+                        }
+                        _ => s.monospace(format!("{val:02X}"))?,
+                    };
+                    ascii.push(if val.is_ascii_graphic() {
+                        val as char
+                    } else {
+                        '.'
+                    });
+                }
+                s.same_line(None);
+                byte_xs[end - start] = s.cursor_pos().x();
+                s.fill(Color::GRAY);
+                s.monospace(&ascii)?;
+
+                let bottom = s.cursor_pos().y();
+                if clicked && (top..bottom).contains(&m.y()) {
+                    if let Some(i) =
+                        (0..end - start).find(|&i| (byte_xs[i]..byte_xs[i + 1]).contains(&m.x()))
+                    {
+                        viewer.goto(deck, start + i);
+                        viewer.editing = true;
+                    }
+                }
+            }
+            s.pop();
+
+            s.spacing()?;
+            s.push();
+            s.fill(Color::RED);
+            s.text("Written")?;
+            s.same_line(None);
+            s.fill(Color::GREEN);
+            s.text("Read")?;
+            s.same_line(None);
+            s.fill(Color::GRAY);
+            s.text("in the last second. Click a byte and type hex digits to edit it.")?;
+            s.pop();
+
+            s.reset_window_target();
+            if let Some(message) = message {
+                self.add_message(message);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,22 +1,29 @@
 use crate::{
     apu::Channel,
-    audio::AudioMixer,
+    audio::{
+        filter::FilterProfile,
+        stems::{Stem, StemLayout},
+        wav::WavFormat,
+        AudioMixer, ChannelMix,
+    },
+    cheat::{Cheat, CheatKind},
     common::{config_path, NesRegion, Regional, SAVE_DIR, SRAM_DIR},
     input::FourPlayer,
     mem::RamState,
     nes::{
         config::CONFIG,
         filesystem::is_nes_rom,
-        menu::types::{ConfigSection, EmuSpeed, SampleRate},
+        menu::types::{ConfigSection, EmuSpeed, SampleRate, SearchFilterKind},
         Mode, Nes,
     },
-    video::VideoFilter,
+    ram_search::{RamSearch, ValueSize},
+    video::{palette::Palette, Overscan, VideoFilter},
 };
 use pix_engine::prelude::*;
 use std::{borrow::Cow, ffi::OsStr, path::PathBuf};
 
 pub(crate) mod types;
-pub(crate) use types::{Menu, Player};
+pub(crate) use types::{CheatEditor, CheatSearch, Menu, PaletteEditor, Player};
 
 impl Nes {
     pub(crate) fn open_menu(&mut self, s: &mut PixState, menu: Menu) -> PixResult<()> {
@@ -56,6 +63,8 @@ impl Nes {
             Menu::Config(section) => self.render_config(s, section)?,
             Menu::Keybind(player) => self.render_keybinds(s, player)?,
             Menu::LoadRom => self.render_load_rom(s)?,
+            Menu::Cheats => self.render_cheats(s)?,
+            Menu::CheatSearch => self.render_cheat_search(s)?,
             Menu::About => self.render_about(s)?,
         }
 
@@ -119,14 +128,18 @@ impl Nes {
         s.next_width(150);
         if s.select_box("NES Region", &mut region, NesRegion::as_slice(), 3)? {
             self.config.region = NesRegion::from(region);
+            self.stop_stem_recording();
             self.control_deck.set_region(self.config.region);
             s.set_window_dimensions(self.config.get_dimensions())?;
             self.update_frame_rate(s)?;
-            self.audio = AudioMixer::new(
-                self.control_deck.sample_rate(),
+            let mut audio = AudioMixer::new(
                 self.config.audio_sample_rate / self.config.speed,
                 self.config.audio_buffer_size,
             );
+            audio.set_channels(self.control_deck.audio_channels());
+            audio.set_filter_profile(self.config.audio_filter);
+            audio.take_recording(&mut self.audio);
+            self.audio = audio;
             self.audio.open_playback(s)?;
         }
 
@@ -151,6 +164,36 @@ impl Nes {
         s.same_line(None);
         s.help_marker("Allow pressing U/D and L/R at the same time.")?;
 
+        if s.checkbox("Remove Sprite Limit", &mut self.config.unlimited_sprites)? {
+            self.update_unlimited_sprites();
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Draw more than 8 sprites per scanline to reduce flicker. Games still see the sprite overflow flag.",
+        )?;
+        if self.control_deck.is_running() {
+            let rom = self.rom_filename().to_owned();
+            let mut selected = match self.config.game_unlimited_sprites.get(&rom) {
+                None => 0,
+                Some(true) => 1,
+                Some(false) => 2,
+            };
+            s.next_width(150);
+            if s.select_box(
+                "Sprite Limit For This Game",
+                &mut selected,
+                &["Use Default", "Removed", "Kept"],
+                3,
+            )? {
+                match selected {
+                    1 => self.config.game_unlimited_sprites.insert(rom, true),
+                    2 => self.config.game_unlimited_sprites.insert(rom, false),
+                    _ => self.config.game_unlimited_sprites.remove(&rom),
+                };
+                self.update_unlimited_sprites();
+            }
+        }
+
         Ok(())
     }
 
@@ -191,6 +234,37 @@ impl Nes {
                 )?;
             }
 
+            let mut profile = self.config.audio_filter as usize;
+            s.next_width(150);
+            if s.select_box("Filter", &mut profile, FilterProfile::as_slice(), 3)? {
+                self.config.audio_filter = FilterProfile::from(profile);
+                audio.set_filter_profile(self.config.audio_filter);
+            }
+            s.same_line(None);
+            s.help_marker(
+                "Models the analog output filtering of an NES front-loader or a Famicom, or leaves output unfiltered.",
+            )?;
+
+            let mut format = self.config.sound_recording_format as usize;
+            s.next_width(150);
+            if s.select_box("Recording Format", &mut format, WavFormat::as_slice(), 2)? {
+                self.config.sound_recording_format = WavFormat::from(format);
+            }
+            s.same_line(None);
+            s.help_marker(
+                "WAV format used by Toggle Sound Recording (Shift-R) and Toggle Stem Recording (Ctrl-Shift-R).",
+            )?;
+
+            let mut layout = self.config.stem_layout as usize;
+            s.next_width(150);
+            if s.select_box("Stem Layout", &mut layout, StemLayout::as_slice(), 2)? {
+                self.config.stem_layout = StemLayout::from(layout);
+            }
+            s.same_line(None);
+            s.help_marker(
+                "Toggle Stem Recording (Ctrl-Shift-R) records each audio channel, including expansion audio, to its own WAV file or to one multichannel WAV file.",
+            )?;
+
             let deck = &mut self.control_deck;
             s.collapsing_tree("Channels", |s: &mut PixState| {
                 let mut pulse1 = deck.channel_enabled(Channel::Pulse1);
@@ -219,6 +293,45 @@ impl Nes {
                 }
                 Ok(())
             })?;
+
+            s.collapsing_tree("Mixer", |s: &mut PixState| {
+                let mut stereo = self.config.stereo;
+                if s.checkbox("Stereo", &mut stereo)? {
+                    self.set_stereo(s, stereo)?;
+                }
+                s.same_line(None);
+                if s.button("Pulse Split")? {
+                    self.set_pulse_split(s)?;
+                }
+                s.same_line(None);
+                if s.button("Reset Mixer")? {
+                    for &stem in Stem::as_slice() {
+                        self.set_channel_mix(stem, ChannelMix::default());
+                    }
+                }
+                s.same_line(None);
+                s.help_marker(
+                    "Sets the volume of each audio channel, including expansion audio, from 0% to 200%. With Stereo enabled, each channel can also be panned from left (-1) to right (1). Pulse Split pans pulse 1 left and pulse 2 right.",
+                )?;
+
+                for &stem in Stem::as_slice() {
+                    let mix = self.control_deck.channel_mix(stem);
+                    let mut volume = (mix.volume * 100.0).round() as u32;
+                    let mut pan = mix.pan;
+                    s.next_width(200);
+                    let mut changed =
+                        s.slider(&format!("{} Volume", stem.as_ref()), &mut volume, 0, 200)?;
+                    if self.config.stereo {
+                        s.next_width(200);
+                        changed |=
+                            s.slider(&format!("{} Pan", stem.as_ref()), &mut pan, -1.0, 1.0)?;
+                    }
+                    if changed {
+                        self.set_channel_mix(stem, ChannelMix::new(volume as f32 / 100.0, pan));
+                    }
+                }
+                Ok(())
+            })?;
         }
         Ok(())
     }
@@ -249,6 +362,80 @@ impl Nes {
             self.control_deck.set_filter(self.config.filter);
         }
 
+        s.collapsing_tree("Palettes", |s: &mut PixState| {
+            let mut region = self.palette_editor.region;
+            s.next_width(150);
+            if s.select_box("Palette Region", &mut region, NesRegion::as_slice(), 3)? {
+                self.palette_editor.region = region;
+                self.palette_editor.path.clear();
+                self.palette_editor.error = None;
+            }
+            let region = NesRegion::from(region);
+            let current = self
+                .config
+                .palettes
+                .get(&region)
+                .map_or_else(|| Cow::from("Built-in"), |path| path.to_string_lossy());
+            s.text(&format!("Current: {current}"))?;
+
+            s.next_width(300);
+            s.text_field("Palette File", &mut self.palette_editor.path)?;
+            s.same_line(None);
+            s.help_marker(
+                "A .pal file with 64 colors, or 512 colors including every color emphasis combination.",
+            )?;
+            if s.button("Load Palette")? {
+                let path = PathBuf::from(self.palette_editor.path.trim());
+                match self.load_palette(region, &path) {
+                    Ok(()) => {
+                        self.config.palettes.insert(region, path);
+                        self.palette_editor.path.clear();
+                        self.palette_editor.error = None;
+                    }
+                    Err(err) => {
+                        log::error!("{:?}", err);
+                        self.palette_editor.error = Some(format!("Failed to load {path:?}"));
+                    }
+                }
+            }
+            s.same_line(None);
+            if s.button("Use Built-in")? {
+                self.control_deck.set_palette(region, Palette::default());
+                self.config.palettes.remove(&region);
+                self.palette_editor.error = None;
+            }
+            if let Some(ref error) = self.palette_editor.error {
+                s.fill(s.theme().colors.error);
+                s.text(error)?;
+            }
+            Ok(())
+        })?;
+
+        s.collapsing_tree("Overscan", |s: &mut PixState| {
+            let overscan = &mut self.config.overscan;
+            let mut changed = false;
+            for (label, edge) in [
+                ("Top", &mut overscan.top),
+                ("Bottom", &mut overscan.bottom),
+                ("Left", &mut overscan.left),
+                ("Right", &mut overscan.right),
+            ] {
+                s.next_width(200);
+                changed |= s.slider(label, edge, 0, Overscan::MAX)?;
+            }
+            if changed {
+                self.control_deck.set_overscan(self.config.overscan);
+                let (width, height) = self.config.get_dimensions();
+                s.set_window_dimensions((width, height))?;
+                if let Some(debugger) = &self.debugger {
+                    s.set_window_target(debugger.window_id())?;
+                    s.set_window_dimensions((width, height))?;
+                    s.reset_window_target();
+                }
+            }
+            Ok(())
+        })?;
+
         if s.checkbox("Fullscreen", &mut self.config.fullscreen)? {
             s.fullscreen(self.config.fullscreen)?;
         }
@@ -272,6 +459,12 @@ impl Nes {
         if s.menu("Load ROM")? {
             self.mode = Mode::InMenu(Menu::LoadRom);
         }
+        if self.control_deck.is_running() && s.menu("Cheats")? {
+            self.mode = Mode::InMenu(Menu::Cheats);
+        }
+        if self.control_deck.is_running() && s.menu("Cheat Search")? {
+            self.mode = Mode::InMenu(Menu::CheatSearch);
+        }
         if s.menu("About")? {
             self.mode = Mode::InMenu(Menu::About);
         }
@@ -428,6 +621,147 @@ impl Nes {
         }
     }
 
+    fn render_cheats(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.render_heading(s, "Cheats")?;
+
+        let editor = &mut self.cheat_editor;
+        s.next_width(200);
+        s.text_field("Code", &mut editor.code)?;
+        s.same_line(None);
+        s.help_marker(
+            "Game Genie (SXIOPO), Pro Action Replay (00AAAAVV) or raw (AAAA:VV or AAAA:VV:CC) code.",
+        )?;
+        s.next_width(200);
+        s.text_field("Description", &mut editor.description)?;
+        if s.button("Add Cheat")? {
+            match Cheat::new(&editor.code) {
+                Ok(mut cheat) => {
+                    cheat.set_description(editor.description.trim());
+                    self.control_deck.add_cheat(cheat);
+                    editor.code.clear();
+                    editor.description.clear();
+                    self.save_cheats();
+                }
+                Err(err) => {
+                    log::warn!("{}", err);
+                    self.error = Some(format!("Invalid cheat code: '{}'", editor.code.trim()));
+                }
+            }
+        }
+        s.spacing()?;
+
+        let cheats = self.control_deck.cheats();
+        let list: Vec<String> = cheats
+            .iter()
+            .map(|cheat| format!("[{}] {cheat}", if cheat.enabled() { "x" } else { " " }))
+            .collect();
+        let selected = cheats.get(self.cheat_editor.selected).map(|&cheat| {
+            let genie_code = match cheat.kind() {
+                CheatKind::GameGenie => None,
+                _ => cheat.to_genie_code().ok(),
+            };
+            (cheat.code().to_string(), genie_code)
+        });
+        s.next_width(300);
+        s.select_list("Cheats", &mut self.cheat_editor.selected, &list, 10)?;
+
+        if let Some((code, genie_code)) = selected {
+            if s.button("Toggle")? {
+                if let Some(cheat) = self.control_deck.cheat_mut(&code) {
+                    cheat.set_enabled(!cheat.enabled());
+                }
+                self.save_cheats();
+            }
+            s.same_line(None);
+            if s.button("Remove")? {
+                self.control_deck.remove_cheat(&code);
+                self.cheat_editor.selected = 0;
+                self.save_cheats();
+            }
+            if let Some(genie_code) = genie_code {
+                s.text("Game Genie: ")?;
+                s.same_line(None);
+                s.monospace(genie_code.code())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_cheat_search(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.render_heading(s, "Cheat Search")?;
+
+        let mut new_cheats = vec![];
+        let cpu = self.control_deck.cpu();
+        let cheat_search = &mut self.cheat_search;
+        s.next_width(100);
+        s.select_box("Size", &mut cheat_search.size, ValueSize::as_slice(), 2)?;
+        s.checkbox("Signed", &mut cheat_search.signed)?;
+        if s.button("New Search")? {
+            let size = ValueSize::as_slice()[cheat_search.size];
+            cheat_search.search = Some(RamSearch::new(cpu, size, cheat_search.signed));
+            cheat_search.selected = 0;
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Start a search, play until the value changes, then filter. Repeat until only a few \
+            addresses are left.",
+        )?;
+        s.spacing()?;
+
+        if let Some(ref mut search) = cheat_search.search {
+            s.next_width(200);
+            s.select_box(
+                "Filter",
+                &mut cheat_search.filter,
+                SearchFilterKind::as_slice(),
+                3,
+            )?;
+            s.next_width(100);
+            s.text_field("Value", &mut cheat_search.value)?;
+            if s.button("Apply Filter")? {
+                let value = cheat_search.value.trim().parse().ok();
+                match SearchFilterKind::from(cheat_search.filter).filter(value) {
+                    Some(filter) => {
+                        search.filter(cpu, filter);
+                        cheat_search.selected = 0;
+                    }
+                    None => self.error = Some("A value is required for this filter".to_string()),
+                }
+            }
+
+            s.text(&format!("{} candidates", search.len()))?;
+            // Listing every candidate of a fresh search is too slow to be useful
+            if search.len() <= 1000 {
+                let candidates = search.candidates(cpu);
+                let list: Vec<String> = candidates.iter().map(ToString::to_string).collect();
+                s.next_width(300);
+                s.select_list("Candidates", &mut cheat_search.selected, &list, 10)?;
+                if let Some(candidate) = candidates.get(cheat_search.selected) {
+                    s.text("Cheat Codes: ")?;
+                    s.same_line(None);
+                    s.monospace(candidate.codes().join(" "))?;
+                    if s.button("Add Cheat")? {
+                        new_cheats = candidate.codes();
+                    }
+                }
+            }
+        }
+
+        if !new_cheats.is_empty() {
+            for code in new_cheats {
+                match Cheat::new(&code) {
+                    Ok(cheat) => self.control_deck.add_cheat(cheat),
+                    Err(err) => log::warn!("{}", err),
+                }
+            }
+            self.save_cheats();
+            self.add_message("Added cheat");
+        }
+
+        Ok(())
+    }
+
     fn render_about(&mut self, s: &mut PixState) -> PixResult<()> {
         self.render_heading(s, &format!("TetaNES {}", env!("CARGO_PKG_VERSION")))?;
 
@@ -1,3 +1,7 @@
+use crate::{
+    debugger::Cmp,
+    ram_search::{RamSearch, SearchFilter},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
@@ -33,6 +37,8 @@ pub(crate) enum Menu {
     Config(ConfigSection),
     Keybind(Player),
     LoadRom,
+    Cheats,
+    CheatSearch,
     About,
 }
 
@@ -213,3 +219,90 @@ impl From<f32> for EmuSpeed {
         Self::from(((4.0 * value) as usize).saturating_sub(1))
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum SearchFilterKind {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    ChangedBy,
+    EqualTo,
+}
+
+impl SearchFilterKind {
+    #[inline]
+    #[must_use]
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[
+            Self::Equal,
+            Self::NotEqual,
+            Self::Greater,
+            Self::Less,
+            Self::ChangedBy,
+            Self::EqualTo,
+        ]
+    }
+
+    /// Returns the filter to apply, or `None` if the filter requires a value and none was given.
+    pub(crate) const fn filter(self, value: Option<i32>) -> Option<SearchFilter> {
+        match (self, value) {
+            (Self::Equal, _) => Some(SearchFilter::Previous(Cmp::Eq)),
+            (Self::NotEqual, _) => Some(SearchFilter::Previous(Cmp::Ne)),
+            (Self::Greater, _) => Some(SearchFilter::Previous(Cmp::Gt)),
+            (Self::Less, _) => Some(SearchFilter::Previous(Cmp::Lt)),
+            (Self::ChangedBy, Some(value)) => Some(SearchFilter::ChangedBy(value)),
+            (Self::EqualTo, Some(value)) => Some(SearchFilter::Value(Cmp::Eq, value)),
+            (Self::ChangedBy | Self::EqualTo, None) => None,
+        }
+    }
+}
+
+impl AsRef<str> for SearchFilterKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Equal => "Equal to Previous",
+            Self::NotEqual => "Not Equal to Previous",
+            Self::Greater => "Greater than Previous",
+            Self::Less => "Less than Previous",
+            Self::ChangedBy => "Changed By",
+            Self::EqualTo => "Equal To",
+        }
+    }
+}
+
+impl From<usize> for SearchFilterKind {
+    fn from(value: usize) -> Self {
+        Self::as_slice().get(value).copied().unwrap_or(Self::Equal)
+    }
+}
+
+/// Cheat Search menu state.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct CheatSearch {
+    pub(crate) search: Option<RamSearch>,
+    pub(crate) size: usize,
+    pub(crate) signed: bool,
+    pub(crate) filter: usize,
+    pub(crate) value: String,
+    pub(crate) selected: usize,
+}
+
+/// Cheats menu state.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct CheatEditor {
+    pub(crate) code: String,
+    pub(crate) description: String,
+    pub(crate) selected: usize,
+}
+
+/// Video palette menu state.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct PaletteEditor {
+    pub(crate) region: usize,
+    pub(crate) path: String,
+    pub(crate) error: Option<String>,
+}
@@ -13,6 +13,13 @@ pub(crate) struct PpuViewer {
     scanline: u32,
     nametables: [Vec<u8>; 4],
     nametable_ids: Vec<u8>,
+    nametable_palettes: Vec<u8>,
+    bg_select: u16,
+    /// Top-left corner of the screen across all four nametables on the selected scanline.
+    viewport: (u32, u32),
+    show_viewport: bool,
+    show_attr_grid: bool,
+    show_tile_grid: bool,
     pattern_tables: [Vec<u8>; 2],
     palette: [u8; Self::PALETTE_SIZE],
     palette_ids: [u8; Self::PALETTE_SIZE],
@@ -38,6 +45,12 @@ impl PpuViewer {
                 vec![0x00; Self::NAMETABLE_SIZE],
             ],
             nametable_ids: vec![0; Self::NAMETABLE_IDS_SIZE],
+            nametable_palettes: vec![0; Self::NAMETABLE_IDS_SIZE],
+            bg_select: 0x0000,
+            viewport: (0, 0),
+            show_viewport: true,
+            show_attr_grid: false,
+            show_tile_grid: false,
             pattern_tables: [
                 vec![0x00; Self::PATTERN_SIZE],
                 vec![0x00; Self::PATTERN_SIZE],
@@ -69,6 +82,8 @@ impl PpuViewer {
 
     pub(crate) fn load_nametables(&mut self, ppu: &Ppu) {
         self.mirroring = ppu.mirroring();
+        self.bg_select = ppu.ctrl().bg_select();
+        self.viewport = ppu.viewport_origin();
         for (i, nametable) in self.nametables.iter_mut().enumerate() {
             let base_addr = Ppu::NT_START + (i as u16) * Ppu::NT_SIZE;
             for addr in base_addr..(base_addr + Ppu::NT_SIZE - 64) {
@@ -90,6 +105,7 @@ impl PpuViewer {
                 let tile_y = (tile_num / 32) * 8;
 
                 self.nametable_ids[(addr - Ppu::NT_START) as usize] = tile;
+                self.nametable_palettes[(addr - Ppu::NT_START) as usize] = palette as u8;
                 for y in 0..8 {
                     let lo = u16::from(ppu.peek(tile_addr + y, Access::Dummy));
                     let hi = u16::from(ppu.peek(tile_addr + y + 8, Access::Dummy));
@@ -185,7 +201,7 @@ impl Nes {
     }
 
     pub(crate) fn render_ppu_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
-        if let Some(ref viewer) = self.ppu_viewer {
+        if let Some(ref mut viewer) = self.ppu_viewer {
             s.set_window_target(viewer.window_id())?;
             s.clear()?;
             s.fill(Color::WHITE);
@@ -227,30 +243,90 @@ impl Nes {
             ])?;
             s.pop();
 
+            // Overlays
+
+            s.push();
+            s.fill(None);
+            let (nt_x, nt_y) = (nametable_dst.x(), nametable_dst.y());
+            for (show, step, color) in [
+                (viewer.show_tile_grid, 8, rgb!(255, 255, 255, 40)),
+                (viewer.show_attr_grid, 16, rgb!(255, 255, 0, 100)),
+            ] {
+                if show {
+                    s.stroke(color);
+                    for x in (step..2 * width).step_by(step as usize) {
+                        s.line([nt_x + x, nt_y, nt_x + x, nametable_dst.bottom()])?;
+                    }
+                    for y in (step..2 * height).step_by(step as usize) {
+                        s.line([nt_x, nt_y + y, nametable_dst.right(), nt_y + y])?;
+                    }
+                }
+            }
+            if viewer.show_viewport {
+                // The screen wraps around to the opposite nametables
+                let (x, y) = (viewer.viewport.0 as i32, viewer.viewport.1 as i32);
+                let wrap = |start: i32, len: i32, max: i32| {
+                    if start + len <= max {
+                        vec![(start, start + len)]
+                    } else {
+                        vec![(start, max), (0, start + len - max)]
+                    }
+                };
+                let xs = wrap(x, width, 2 * width);
+                let ys = wrap(y, height, 2 * height);
+                let (right, bottom) = ((x + width) % (2 * width), (y + height) % (2 * height));
+                s.stroke(Color::RED);
+                s.stroke_weight(2);
+                for &(x1, x2) in &xs {
+                    s.line([nt_x + x1, nt_y + y, nt_x + x2, nt_y + y])?;
+                    s.line([nt_x + x1, nt_y + bottom, nt_x + x2, nt_y + bottom])?;
+                }
+                for &(y1, y2) in &ys {
+                    s.line([nt_x + x, nt_y + y1, nt_x + x, nt_y + y2])?;
+                    s.line([nt_x + right, nt_y + y1, nt_x + right, nt_y + y2])?;
+                }
+            }
+            s.pop();
+
             // Nametable Info
 
             s.set_cursor_pos([s.cursor_pos().x(), nametable_dst.bottom() + 4]);
 
-            s.text(&format!("Scanline: {}", viewer.scanline))?;
+            s.text(&format!(
+                "Scanline: {}  Scroll: ({}, {})",
+                viewer.scanline, viewer.viewport.0, viewer.viewport.1
+            ))?;
             s.text(&format!("Mirroring: {:?}", viewer.mirroring))?;
 
-            if s.focused_window(viewer.window_id())
-                && rect![0, 0, 2 * width, 2 * height].contains(m)
-            {
-                let x = m.x() - nametable_src.x();
-                let y = m.y() - nametable_src.y();
-                let nt_addr = (x / width) * 0x0400 + (y / height) * 0x0800;
-                let ppu_addr = nt_addr + ((((y / 8) % 30) << 5) | ((x / 8) % 32));
-                let tile_id = viewer.nametable_ids.get(ppu_addr as usize).unwrap_or(&0x00);
-                s.text(&format!("Tile ID: ${tile_id:02X}"))?;
-                s.text(&format!("(X, Y): ({x}, {y})"))?;
-                s.text(&format!("Nametable: ${nt_addr:04X}"))?;
-                s.text(&format!("PPU Addr: ${ppu_addr:04X}"))?;
+            if s.focused_window(viewer.window_id()) && nametable_dst.contains(m) {
+                let x = m.x() - nametable_dst.x();
+                let y = m.y() - nametable_dst.y();
+                let (col, row) = (x / 8 % 32, y / 8 % 30);
+                let nt_addr = Ppu::NT_START + ((x / width) * 0x0400 + (y / height) * 0x0800) as u16;
+                let ppu_addr = nt_addr + ((row << 5) | col) as u16;
+                let attr_addr = nt_addr + 0x03C0 + ((row / 4) * 8 + col / 4) as u16;
+                let index = (ppu_addr - Ppu::NT_START) as usize;
+                let tile_id = viewer.nametable_ids.get(index).copied().unwrap_or(0x00);
+                let palette = viewer
+                    .nametable_palettes
+                    .get(index)
+                    .copied()
+                    .unwrap_or(0x00);
+                let chr_addr = viewer.bg_select + u16::from(tile_id) * 16;
+                s.text(&format!("Tile ID: ${tile_id:02X}  (X, Y): ({x}, {y})"))?;
+                s.text(&format!(
+                    "Nametable: ${nt_addr:04X}  PPU Addr: ${ppu_addr:04X}"
+                ))?;
+                s.text(&format!(
+                    "Attribute: ${attr_addr:04X}  Palette: {palette} (${:04X})",
+                    Ppu::PALETTE_START + u16::from(palette) * 4
+                ))?;
+                s.text(&format!("CHR Addr: ${chr_addr:04X}"))?;
             } else {
-                s.text("Tile ID: $00")?;
-                s.text("(X, Y): (0, 0)")?;
-                s.text("Nametable: $0000")?;
-                s.text("PPU Addr: $0000")?;
+                s.text("Tile ID: $00  (X, Y): (0, 0)")?;
+                s.text("Nametable: $0000  PPU Addr: $0000")?;
+                s.text("Attribute: $0000  Palette: 0 ($3F00)")?;
+                s.text("CHR Addr: $0000")?;
             }
 
             // Pattern Tables
@@ -332,6 +408,11 @@ impl Nes {
                 s.text("Palette: $00")?;
             }
 
+            s.spacing()?;
+            s.checkbox("Show Scroll Viewport", &mut viewer.show_viewport)?;
+            s.checkbox("Show Attribute Grid", &mut viewer.show_attr_grid)?;
+            s.checkbox("Show Tile Grid", &mut viewer.show_tile_grid)?;
+
             s.reset_column_offset();
             s.reset_window_target();
         }
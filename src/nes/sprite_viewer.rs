@@ -0,0 +1,263 @@
+use crate::{
+    debugger::sprites::{OamSprite, SpriteEval},
+    mem::{Access, Mem},
+    nes::Nes,
+    ppu::Ppu,
+};
+use pix_engine::prelude::*;
+
+#[derive(Debug)]
+pub(crate) struct SpriteViewer {
+    window_id: WindowId,
+    texture_id: TextureId,
+    scanline: u32,
+    eval: Option<SpriteEval>,
+    /// Every sprite as an 8x16 tile, 8 sprites per row.
+    tiles: Vec<u8>,
+}
+
+impl SpriteViewer {
+    const TILES_WIDTH: u32 = 8 * 8;
+    const TILES_HEIGHT: u32 = 8 * 16;
+    const PADDING: i32 = 10;
+    const ROW_HEIGHT: i32 = 18;
+    const COLUMN_WIDTH: i32 = 300;
+    const ROWS: usize = OamSprite::COUNT / 2;
+    const HEADER: &'static str = "##   X   Y Tile Attr Pal Flip Priority";
+    const TRANSPARENT: [u8; 3] = [40, 40, 40];
+
+    fn new(window_id: WindowId, texture_id: TextureId) -> Self {
+        Self {
+            window_id,
+            texture_id,
+            scanline: 0,
+            eval: None,
+            tiles: vec![0x00; (4 * Self::TILES_WIDTH * Self::TILES_HEIGHT) as usize],
+        }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    pub(crate) const fn scanline(&self) -> u32 {
+        self.scanline
+    }
+
+    pub(crate) fn inc_scanline(&mut self, increment: u32) {
+        self.scanline = (self.scanline + increment).clamp(0, Ppu::HEIGHT - 1);
+    }
+
+    pub(crate) fn dec_scanline(&mut self, decrement: u32) {
+        self.scanline = self.scanline.saturating_sub(decrement);
+    }
+
+    /// Captures OAM and sprite evaluation on the selected scanline, rendering every sprite with
+    /// the pattern tables and palettes loaded at the time.
+    pub(crate) fn load_sprites(&mut self, ppu: &Ppu) {
+        let eval = SpriteEval::capture(ppu);
+        for sprite in &eval.sprites {
+            let tile_addr = sprite.tile_addr(ppu.ctrl());
+            let tile_x = (sprite.index % 8) as u32 * 8;
+            let tile_y = (sprite.index / 8) as u32 * 16;
+            for y in 0..16 {
+                let row = if y < 8 { y } else { y + 8 };
+                let (lo, hi) = if y < eval.height as u16 {
+                    (
+                        ppu.peek(tile_addr + row, Access::Dummy),
+                        ppu.peek(tile_addr + row + 8, Access::Dummy),
+                    )
+                } else {
+                    (0x00, 0x00)
+                };
+                for x in 0..8 {
+                    let pix_type = ((lo >> (7 - x)) & 1) | (((hi >> (7 - x)) & 1) << 1);
+                    let [red, green, blue] = if pix_type == 0 {
+                        Self::TRANSPARENT
+                    } else {
+                        let palette_addr = Ppu::PALETTE_START
+                            + 0x10
+                            + u16::from(sprite.palette()) * 4
+                            + u16::from(pix_type);
+                        let (red, green, blue) =
+                            Ppu::system_palette(ppu.peek(palette_addr, Access::Dummy).into());
+                        [red, green, blue]
+                    };
+                    let idx =
+                        4 * ((tile_x + x) + (tile_y + u32::from(y)) * Self::TILES_WIDTH) as usize;
+                    self.tiles[idx..idx + 4].copy_from_slice(&[red, green, blue, 0xFF]);
+                }
+            }
+        }
+        self.eval = Some(eval);
+    }
+
+    fn color(eval: &SpriteEval, index: usize) -> Color {
+        if eval.evaluated.contains(&index) {
+            Color::GREEN
+        } else if eval.dropped.contains(&index) {
+            Color::RED
+        } else {
+            Color::WHITE
+        }
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_sprite_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.sprite_viewer {
+            None => {
+                let w = 2 * (SpriteViewer::COLUMN_WIDTH + SpriteViewer::PADDING) as u32;
+                let h = (SpriteViewer::ROWS as i32 + 6) * SpriteViewer::ROW_HEIGHT
+                    + 2 * SpriteViewer::PADDING;
+                let window_id = s
+                    .window()
+                    .dimensions(w, h as u32)
+                    .title("Sprite Viewer")
+                    .position(10, 10)
+                    .build()?;
+                s.set_window_target(window_id)?;
+                let texture_id = s.create_texture(
+                    SpriteViewer::TILES_WIDTH,
+                    SpriteViewer::TILES_HEIGHT,
+                    PixelFormat::Rgba,
+                )?;
+                self.sprite_viewer = Some(SpriteViewer::new(window_id, texture_id));
+                s.reset_window_target();
+            }
+            Some(ref viewer) => {
+                s.close_window(viewer.window_id())?;
+                self.sprite_viewer = None;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn render_sprite_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        if let Some(ref viewer) = self.sprite_viewer {
+            s.set_window_target(viewer.window_id)?;
+            s.clear()?;
+            s.fill(Color::WHITE);
+            s.stroke(None);
+
+            let padding = SpriteViewer::PADDING;
+            s.set_cursor_pos([padding, padding]);
+            s.text(&format!(
+                "Scanline: {} (scroll or Ctrl-Up/Down to change)",
+                viewer.scanline
+            ))?;
+            let eval = match viewer.eval {
+                Some(ref eval) if eval.scanline == viewer.scanline => eval,
+                _ => {
+                    s.text("Waiting for sprite evaluation on this scanline...")?;
+                    s.reset_window_target();
+                    return Ok(());
+                }
+            };
+
+            let list = |indexes: &[usize]| {
+                indexes
+                    .iter()
+                    .map(|index| format!("{index:02}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            s.text(&format!(
+                "8x{} sprites. Evaluated for scanline {}: {}",
+                eval.height,
+                eval.scanline + 1,
+                list(&eval.evaluated)
+            ))?;
+            s.push();
+            s.fill(Color::RED);
+            s.text(&format!(
+                "Dropped over the 8 sprite limit: {}{}",
+                if eval.dropped.is_empty() {
+                    "None".to_string()
+                } else {
+                    list(&eval.dropped)
+                },
+                if eval.overflow {
+                    " (overflow flag set)"
+                } else {
+                    ""
+                }
+            ))?;
+            s.pop();
+            s.spacing()?;
+
+            // OAM Entries
+
+            s.update_texture(
+                viewer.texture_id,
+                rect![
+                    0,
+                    0,
+                    SpriteViewer::TILES_WIDTH as i32,
+                    SpriteViewer::TILES_HEIGHT as i32
+                ],
+                &viewer.tiles,
+                4 * SpriteViewer::TILES_WIDTH as usize,
+            )?;
+            let top = s.cursor_pos().y();
+            let row_height = SpriteViewer::ROW_HEIGHT;
+            for column in 0..2 {
+                let x = padding + column * (SpriteViewer::COLUMN_WIDTH + padding);
+                s.set_cursor_pos([x + 20, top]);
+                s.fill(Color::GRAY);
+                s.monospace(SpriteViewer::HEADER)?;
+                for row in 0..SpriteViewer::ROWS {
+                    let sprite = eval.sprites[column as usize * SpriteViewer::ROWS + row];
+                    let y = top + (row as i32 + 1) * row_height;
+
+                    // 8x8 sprites are doubled so every preview is 16 pixels tall
+                    let (tile_w, tile_h) = if eval.height == 16 { (8, 16) } else { (16, 16) };
+                    let src = rect![
+                        (sprite.index % 8) as i32 * 8,
+                        (sprite.index / 8) as i32 * 16,
+                        8,
+                        eval.height as i32
+                    ];
+                    s.texture(viewer.texture_id, src, rect![x, y + 1, tile_w, tile_h])?;
+
+                    s.set_cursor_pos([x + 20, y]);
+                    s.fill(SpriteViewer::color(eval, sprite.index));
+                    s.monospace(format!(
+                        "{:02} {:3} {:3}  ${:02X}  ${:02X}   {}   {}{}  {}",
+                        sprite.index,
+                        sprite.x,
+                        sprite.y,
+                        sprite.tile,
+                        sprite.attr,
+                        sprite.palette(),
+                        if sprite.flip_horizontal() { 'H' } else { '-' },
+                        if sprite.flip_vertical() { 'V' } else { '-' },
+                        if sprite.bg_priority() {
+                            "Behind"
+                        } else {
+                            "Front"
+                        },
+                    ))?;
+                }
+            }
+
+            // Legend
+
+            s.set_cursor_pos([
+                padding,
+                top + (SpriteViewer::ROWS as i32 + 1) * row_height + padding,
+            ]);
+            s.fill(Color::GREEN);
+            s.text("Evaluated")?;
+            s.same_line(None);
+            s.fill(Color::RED);
+            s.text("Dropped")?;
+            s.same_line(None);
+            s.fill(Color::GRAY);
+            s.text("on the selected scanline.")?;
+
+            s.reset_window_target();
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    common::config_dir,
+    audio::stems::StemRecorder,
+    common::{config_dir, CHEAT_DIR},
     cpu::Cpu,
     nes::{
         event::ActionEvent,
@@ -84,6 +85,25 @@ impl Nes {
         }
     }
 
+    /// Returns the path where the per-game cheat file is stored
+    pub(crate) fn cheat_path(&self) -> NesResult<PathBuf> {
+        match self.control_deck.loaded_rom() {
+            Some(ref rom) => PathBuf::from(rom)
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .map_or_else(
+                    || Err(anyhow!("failed to create cheat path for `{rom:?}`")),
+                    |cheat_name| {
+                        Ok(config_dir()
+                            .join(CHEAT_DIR)
+                            .join(cheat_name)
+                            .with_extension("cht"))
+                    },
+                ),
+            None => Err(anyhow!("no rom is loaded")),
+        }
+    }
+
     /// Returns the path where Save states are stored
     pub(crate) fn save_path(&self, slot: u8) -> NesResult<PathBuf> {
         match self.control_deck.loaded_rom() {
@@ -322,8 +342,79 @@ impl Nes {
     }
 
     pub(crate) fn toggle_sound_recording(&mut self, _s: &mut PixState) {
-        self.record_sound = !self.record_sound;
-        // TODO
-        self.add_message("Toggle sound recording not implemented yet");
+        if self.audio.is_recording() {
+            match self.audio.stop_recording() {
+                Ok(()) => self.add_message("Sound Recording Stopped"),
+                Err(err) => {
+                    log::error!("{:?}", err);
+                    self.add_message("Failed to save sound recording");
+                }
+            }
+        } else {
+            let filename = Local::now()
+                .format("Sound_Recording_%Y-%m-%d_at_%H_%M_%S.wav")
+                .to_string();
+            match self
+                .audio
+                .start_recording(&filename, self.config.sound_recording_format)
+            {
+                Ok(()) => self.add_message(format!("Sound Recording Started: {filename}")),
+                Err(err) => {
+                    log::error!("{:?}", err);
+                    self.add_message("Failed to start sound recording");
+                }
+            }
+        }
+    }
+
+    pub(crate) fn toggle_stem_recording(&mut self, _s: &mut PixState) {
+        if self.stem_recording.is_some() {
+            self.stop_stem_recording();
+            self.add_message("Stem Recording Stopped");
+        } else {
+            let filename = Local::now()
+                .format("Stem_Recording_%Y-%m-%d_at_%H_%M_%S.wav")
+                .to_string();
+            match StemRecorder::create(
+                &filename,
+                &self.control_deck.audio_stems(),
+                self.config.stem_layout,
+                self.audio.output_frequency(),
+                self.config.sound_recording_format,
+                self.config.audio_filter,
+            ) {
+                Ok(recorder) => {
+                    self.stem_recording = Some(recorder);
+                    self.control_deck.set_stems_enabled(true);
+                    self.add_message(format!("Stem Recording Started: {filename}"));
+                }
+                Err(err) => {
+                    log::error!("{:?}", err);
+                    self.add_message("Failed to start stem recording");
+                }
+            }
+        }
+    }
+
+    /// Writes stems for the last frame, if recording.
+    pub(crate) fn record_stems(&mut self) {
+        if let Some(ref mut recorder) = self.stem_recording {
+            if let Err(err) = recorder.consume(self.control_deck.stem_samples()) {
+                log::error!("{:?}", err);
+                self.stop_stem_recording();
+                self.add_message("Failed to save stem recording");
+            }
+        }
+    }
+
+    /// Stops any stem recording, e.g. when the loaded ROM or region changes the available stems.
+    pub(crate) fn stop_stem_recording(&mut self) {
+        if let Some(mut recorder) = self.stem_recording.take() {
+            if let Err(err) = recorder.finish() {
+                log::error!("{:?}", err);
+                self.add_message("Failed to save stem recording");
+            }
+        }
+        self.control_deck.set_stems_enabled(false);
     }
 }
@@ -1,5 +1,11 @@
 use crate::{
     common::{Clock, Kind, NesRegion, Regional, Reset},
+    debugger::{
+        cdl::ChrFlags,
+        events::{EventLog, PpuEvent, PpuEventKind},
+        memory::{AccessLog, MemAccess, MemRegion},
+        Watchpoint, WatchpointHit,
+    },
     mapper::{Mapped, Mapper},
     mem::{Access, Mem},
     ppu::{bus::PpuBus, frame::Frame},
@@ -102,8 +108,14 @@ pub struct Ppu {
     spr_count: usize,
     sprites: [Sprite; 8], // Each scanline can hold 8 sprites at a time
     spr_present: Vec<bool>,
+    #[serde(skip)]
+    extra_sprites: Vec<Sprite>, // Sprites past the 8 sprite limit when it's removed
+    #[serde(skip)]
+    unlimited_sprites: bool,
 
     open_bus: u8,
+    #[serde(skip)]
+    event_log: Option<EventLog>,
 }
 
 impl Default for Ppu {
@@ -223,8 +235,11 @@ impl Ppu {
             spr_count: 0,
             sprites: [Sprite::new(); 8],
             spr_present: vec![false; Self::VISIBLE_END as usize],
+            extra_sprites: vec![],
+            unlimited_sprites: false,
 
             open_bus: 0x00,
+            event_log: None,
         };
         ppu.set_region(ppu.region);
         ppu
@@ -248,11 +263,185 @@ impl Ppu {
         self.scanline
     }
 
+    /// Starts recording PPU events, discarding any previous recording.
+    #[inline]
+    pub fn start_event_log(&mut self) {
+        self.event_log = Some(EventLog::new());
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn event_log(&self) -> Option<&EventLog> {
+        self.event_log.as_ref()
+    }
+
+    /// Stops recording PPU events, returning the recording.
+    #[inline]
+    pub fn stop_event_log(&mut self) -> Option<EventLog> {
+        self.event_log.take()
+    }
+
+    /// Resumes an event recording returned by `stop_event_log`.
+    #[inline]
+    pub(crate) fn resume_event_log(&mut self, log: Option<EventLog>) {
+        self.event_log = log;
+    }
+
+    /// Moves the event recording over from another `Ppu`, e.g. when loading a save state.
+    #[inline]
+    pub(crate) fn take_event_log(&mut self, ppu: &mut Self) {
+        self.event_log = ppu.event_log.take();
+    }
+
+    /// Records an event at the current scanline and cycle if recording.
+    #[inline]
+    pub(crate) fn log_event(&mut self, kind: PpuEventKind, addr: u16, val: u8) {
+        if let Some(log) = &mut self.event_log {
+            log.log(PpuEvent {
+                kind,
+                scanline: self.scanline,
+                cycle: self.cycle,
+                addr,
+                val,
+            });
+        }
+    }
+
+    /// Starts recording the frame each byte of PPU memory and OAM was last accessed on.
+    #[inline]
+    pub fn start_access_log(&mut self) {
+        self.bus.start_access_log(self.frame_number());
+    }
+
+    #[inline]
+    pub fn stop_access_log(&mut self) {
+        self.bus.stop_access_log();
+    }
+
+    /// Moves the access log over from another `Ppu`, e.g. when loading a save state.
+    #[inline]
+    pub(crate) fn take_access_log(&mut self, ppu: &mut Self) {
+        self.bus.take_access_log(&mut ppu.bus);
+    }
+
+    /// Stops the access log, returning it to be resumed with `resume_access_log`.
+    #[inline]
+    pub(crate) fn suspend_access_log(&mut self) -> Option<AccessLog> {
+        self.bus.suspend_access_log()
+    }
+
+    /// Resumes an access log returned by `suspend_access_log`, recording on the current frame.
+    #[inline]
+    pub(crate) fn resume_access_log(&mut self, log: Option<AccessLog>) {
+        self.bus.resume_access_log(log);
+        self.bus.set_access_frame(self.frame_number());
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn last_access(&self, region: MemRegion, index: usize) -> Option<MemAccess> {
+        self.bus.last_access(region, index)
+    }
+
+    /// Size of a memory region, 0 if the cartridge doesn't have it.
+    #[must_use]
+    pub fn mem_size(&self, region: MemRegion) -> usize {
+        match region {
+            MemRegion::Oam => Self::OAM_SIZE,
+            MemRegion::SecondaryOam => Self::SECONDARY_OAM_SIZE,
+            _ => self.bus.mem_size(region),
+        }
+    }
+
+    #[must_use]
+    pub fn peek_mem(&self, region: MemRegion, index: usize) -> u8 {
+        match region {
+            MemRegion::Oam => self.oamdata.get(index).copied().unwrap_or_default(),
+            MemRegion::SecondaryOam => self
+                .secondary_oamdata
+                .get(index)
+                .copied()
+                .unwrap_or_default(),
+            _ => self.bus.peek_mem(region, index),
+        }
+    }
+
+    /// Writes a byte without side effects.
+    pub fn poke_mem(&mut self, region: MemRegion, index: usize, val: u8) {
+        let byte = match region {
+            MemRegion::Oam => self.oamdata.get_mut(index),
+            MemRegion::SecondaryOam => self.secondary_oamdata.get_mut(index),
+            _ => return self.bus.poke_mem(region, index, val),
+        };
+        if let Some(byte) = byte {
+            *byte = val;
+        }
+    }
+
     #[inline]
     pub const fn ctrl(&self) -> PpuCtrl {
         self.ctrl
     }
 
+    #[inline]
+    pub const fn status(&self) -> PpuStatus {
+        self.status
+    }
+
+    #[inline]
+    pub const fn scroll(&self) -> PpuScroll {
+        self.scroll
+    }
+
+    /// Returns the top-left corner of the screen in the 512x480 space of all four nametables as
+    /// scrolled for the current scanline, including mid-frame scroll changes. Only meaningful at
+    /// the start of a visible scanline, before the first tile fetch.
+    #[must_use]
+    pub fn viewport_origin(&self) -> (u32, u32) {
+        let (x, y) = self.scroll.pixel_pos();
+        // The first two tiles were prefetched at the end of the previous scanline
+        let x = (u32::from(x) + 512 - 16) % 512;
+        let y = (u32::from(y) + 2 * 480 - self.scanline % 480) % 480;
+        (x, y)
+    }
+
+    /// Primary OAM, 64 sprites of 4 bytes each.
+    #[inline]
+    #[must_use]
+    pub fn oamdata(&self) -> &[u8] {
+        &self.oamdata
+    }
+
+    /// Secondary OAM, the sprites evaluated on the current scanline for the next one.
+    #[inline]
+    #[must_use]
+    pub fn secondary_oamdata(&self) -> &[u8] {
+        &self.secondary_oamdata
+    }
+
+    /// Number of sprites evaluated into secondary OAM, updated at the end of sprite evaluation.
+    #[inline]
+    #[must_use]
+    pub const fn spr_count(&self) -> usize {
+        self.spr_count
+    }
+
+    /// Whether every sprite in range is drawn instead of only the first 8 on each scanline.
+    #[inline]
+    #[must_use]
+    pub const fn unlimited_sprites(&self) -> bool {
+        self.unlimited_sprites
+    }
+
+    /// Draws every sprite in range on a scanline to remove flicker. Sprite evaluation, the
+    /// overflow flag and pattern fetches seen by the mapper are unchanged, so games behave the
+    /// same.
+    #[inline]
+    pub fn set_unlimited_sprites(&mut self, enabled: bool) {
+        self.unlimited_sprites = enabled;
+        self.extra_sprites.clear();
+    }
+
     #[inline]
     #[must_use]
     pub fn frame_buffer(&self) -> &[u16] {
@@ -311,6 +500,38 @@ impl Ppu {
         self.bus.mapper_mut()
     }
 
+    #[inline]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        self.bus.watchpoints()
+    }
+
+    #[inline]
+    pub fn watchpoints_mut(&mut self) -> &mut Vec<Watchpoint> {
+        self.bus.watchpoints_mut()
+    }
+
+    #[inline]
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.bus.take_watchpoint_hit()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr_rom_len(&self) -> usize {
+        self.bus.chr_rom_len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr_cdl(&self) -> Option<&[u8]> {
+        self.bus.chr_cdl()
+    }
+
+    #[inline]
+    pub fn chr_cdl_mut(&mut self) -> &mut Option<Vec<u8>> {
+        self.bus.chr_cdl_mut()
+    }
+
     #[must_use]
     #[inline]
     pub const fn nmi_pending(&self) -> bool {
@@ -424,8 +645,14 @@ impl Ppu {
         match self.cycle & 0x07 {
             1 => self.fetch_bg_nt_byte(),
             3 => self.fetch_bg_attr_byte(),
-            5 => self.tile_lo = self.bus.read(self.tile_addr, Access::Read),
-            7 => self.tile_hi = self.bus.read(self.tile_addr + 8, Access::Read),
+            5 => {
+                self.tile_lo = self.bus.read(self.tile_addr, Access::Read);
+                self.bus.log_chr(self.tile_addr, ChrFlags::DRAWN);
+            }
+            7 => {
+                self.tile_hi = self.bus.read(self.tile_addr + 8, Access::Read);
+                self.bus.log_chr(self.tile_addr + 8, ChrFlags::DRAWN);
+            }
             _ => (),
         }
     }
@@ -564,20 +791,7 @@ impl Ppu {
                 tile_number = 0xFF;
             }
 
-            let tile_addr = if height == 16 {
-                // Use bit 0 of tile index to determine pattern table
-                let sprite_select = if tile_number & 0x01 == 0x01 {
-                    0x1000
-                } else {
-                    0x0000
-                };
-                if line_offset >= 8 {
-                    line_offset += 8;
-                }
-                sprite_select | ((tile_number & 0xFE) << 4) | line_offset as u16
-            } else {
-                self.ctrl.spr_select() | (tile_number << 4) | line_offset as u16
-            };
+            let tile_addr = self.spr_tile_addr(tile_number, line_offset);
 
             if idx < self.spr_count {
                 let mut sprite = &mut self.sprites[idx];
@@ -585,6 +799,8 @@ impl Ppu {
                 sprite.y = y;
                 sprite.tile_lo = self.bus.read(tile_addr, Access::Read);
                 sprite.tile_hi = self.bus.read(tile_addr + 8, Access::Read);
+                self.bus.log_chr(tile_addr, ChrFlags::DRAWN);
+                self.bus.log_chr(tile_addr + 8, ChrFlags::DRAWN);
                 sprite.palette = palette;
                 sprite.bg_priority = bg_priority;
                 sprite.flip_horizontal = flip_horizontal;
@@ -599,6 +815,73 @@ impl Ppu {
                 let _ = self.bus.read(tile_addr + 8, Access::Read);
             }
         }
+
+        if idx == 7 && self.unlimited_sprites {
+            self.load_extra_sprites();
+        }
+    }
+
+    fn spr_tile_addr(&self, tile_number: u16, mut line_offset: u32) -> u16 {
+        if self.ctrl.spr_height() == 16 {
+            // Use bit 0 of tile index to determine pattern table
+            let sprite_select = if tile_number & 0x01 == 0x01 {
+                0x1000
+            } else {
+                0x0000
+            };
+            if line_offset >= 8 {
+                line_offset += 8;
+            }
+            sprite_select | ((tile_number & 0xFE) << 4) | line_offset as u16
+        } else {
+            self.ctrl.spr_select() | (tile_number << 4) | line_offset as u16
+        }
+    }
+
+    // Loads the sprites in range past the first 8 when the sprite limit is removed. Pattern data
+    // is peeked so mappers don't see any extra fetches. OAMADDR is reset to $00 every rendered
+    // scanline, so the first 8 sprites in range in OAM are the ones in secondary OAM.
+    fn load_extra_sprites(&mut self) {
+        self.extra_sprites.clear();
+        if self.spr_count < 8 {
+            return;
+        }
+        let height = self.ctrl.spr_height();
+        let mut in_range = 0;
+        for oam_idx in (0..Self::OAM_SIZE).step_by(4) {
+            let y = u32::from(self.oamdata[oam_idx]);
+            if !(y..y + height).contains(&self.scanline) {
+                continue;
+            }
+            in_range += 1;
+            if in_range <= 8 {
+                continue;
+            }
+
+            let tile_number = u16::from(self.oamdata[oam_idx + 1]);
+            let attr = self.oamdata[oam_idx + 2];
+            let x = u32::from(self.oamdata[oam_idx + 3]);
+            let flip_vertical = (attr & 0x80) == 0x80;
+            let mut line_offset = self.scanline - y;
+            if flip_vertical {
+                line_offset = height - 1 - line_offset;
+            }
+            let tile_addr = self.spr_tile_addr(tile_number, line_offset);
+
+            let mut sprite = Sprite::new();
+            sprite.x = x;
+            sprite.y = y;
+            sprite.tile_lo = self.bus.peek(tile_addr, Access::Dummy);
+            sprite.tile_hi = self.bus.peek(tile_addr + 8, Access::Dummy);
+            sprite.palette = ((attr & 0x03) << 2) | 0x10;
+            sprite.bg_priority = (attr & 0x20) == 0x20;
+            sprite.flip_horizontal = (attr & 0x40) == 0x40;
+            sprite.flip_vertical = flip_vertical;
+            for spr in self.spr_present.iter_mut().skip(x as usize).take(8) {
+                *spr = true;
+            }
+            self.extra_sprites.push(sprite);
+        }
     }
 
     // http://wiki.nesdev.com/w/index.php/PPU_OAM
@@ -632,7 +915,8 @@ impl Ppu {
 
         let left_clip_spr = x < 8 && !self.mask.show_left_spr();
         if self.mask.show_spr() && !left_clip_spr && self.spr_present[x as usize] {
-            for (i, sprite) in self.sprites.iter().take(self.spr_count).enumerate() {
+            let sprites = self.sprites.iter().take(self.spr_count);
+            for (i, sprite) in sprites.chain(&self.extra_sprites).enumerate() {
                 let shift = x as i16 - sprite.x as i16;
                 if (0..=7).contains(&shift) {
                     let spr_color = if sprite.flip_horizontal {
@@ -651,6 +935,15 @@ impl Ppu {
                             && !self.status.spr_zero_hit()
                         {
                             self.status.set_spr_zero_hit(true);
+                            if let Some(log) = &mut self.event_log {
+                                log.log(PpuEvent {
+                                    kind: PpuEventKind::SpriteZeroHit,
+                                    scanline: self.scanline,
+                                    cycle: self.cycle,
+                                    addr: 0x0000,
+                                    val: 0x00,
+                                });
+                            }
                         }
 
                         if bg_color == 0 || !sprite.bg_priority {
@@ -931,6 +1224,8 @@ impl PpuRegisters for Ppu {
     fn read_oamdata(&mut self) -> u8 {
         let val = self.peek_oamdata();
         self.open_bus = val;
+        self.bus
+            .log_access(MemRegion::Oam, self.oamaddr.into(), Access::Read);
         val
     }
 
@@ -977,6 +1272,8 @@ impl PpuRegisters for Ppu {
                 val &= 0xE3;
             }
             self.oamdata[self.oamaddr as usize] = val;
+            self.bus
+                .log_access(MemRegion::Oam, self.oamaddr.into(), Access::Write);
             self.oamaddr = self.oamaddr.wrapping_add(1);
         }
     }
@@ -1030,6 +1327,9 @@ impl PpuRegisters for Ppu {
         // Buffering quirk resulting in a dummy read for the CPU
         // for reading pre-palette data in $0000 - $3EFF
         let val = self.bus.read(addr, Access::Read);
+        if addr < 0x2000 {
+            self.bus.log_chr(addr, ChrFlags::READ);
+        }
         let val = if addr < Self::PALETTE_START {
             let buffer = self.vram_buffer;
             self.vram_buffer = val;
@@ -1103,8 +1403,12 @@ impl Clock for Ppu {
             // Post-render line
             if self.scanline == self.vblank_scanline - 1 {
                 self.frame.increment();
+                self.bus.set_access_frame(self.frame_number());
             } else if self.scanline > self.prerender_scanline {
                 self.scanline = 0;
+                if let Some(log) = &mut self.event_log {
+                    log.end_frame();
+                }
             }
         } else {
             // cycle > 0
@@ -1181,6 +1485,7 @@ impl Reset for Ppu {
         self.spr_zero_visible = false;
         self.spr_count = 0;
         self.sprites = [Sprite::new(); 8];
+        self.extra_sprites.clear();
         self.spr_present.fill(false);
         self.open_bus = 0x00;
         self.bus.reset(kind);
@@ -1231,7 +1536,10 @@ impl std::fmt::Debug for Ppu {
             .field("spr_count", &self.spr_count)
             .field("sprites", &self.sprites)
             .field("spr_present_len", &self.spr_present.len())
+            .field("extra_sprites", &self.extra_sprites)
+            .field("unlimited_sprites", &self.unlimited_sprites)
             .field("open_bus", &self.open_bus)
+            .field("event_log", &self.event_log.is_some())
             .finish()
     }
 }
@@ -1261,6 +1569,71 @@ mod tests {
         assert_eq!(ppu.bus.read(0x2305, Access::Read), 0x66);
     }
 
+    #[test]
+    fn unlimited_sprites() {
+        let render_line = |unlimited: bool| {
+            let mut ppu = Ppu::default();
+            let mut chr = vec![0x00; 0x2000];
+            chr[0x10..0x18].fill(0xFF); // Solid tile $01
+            ppu.load_chr_ram(chr);
+            ppu.write_addr(0x3F);
+            ppu.write_addr(0x11);
+            ppu.write_data(0x16);
+            // 10 sprites on scanlines 21-28
+            for i in 0..10 {
+                for val in [20, 0x01, 0x00, 8 * i] {
+                    ppu.write_oamdata(val);
+                }
+            }
+            ppu.set_unlimited_sprites(unlimited);
+            ppu.write_mask(0x1E);
+            while !(ppu.scanline() == 22 && ppu.cycle() == 0) {
+                ppu.clock();
+            }
+            assert!(ppu.status.spr_overflow());
+            ppu
+        };
+
+        let ppu = render_line(false);
+        assert_eq!(ppu.frame.pixel(7 * 8, 21), 0x16);
+        assert_ne!(ppu.frame.pixel(9 * 8, 21), 0x16, "9th sprite dropped");
+
+        let ppu = render_line(true);
+        assert_eq!(ppu.spr_count(), 8);
+        assert_eq!(ppu.frame.pixel(9 * 8, 21), 0x16, "9th sprite drawn");
+
+        // Extra sprites aren't part of save states
+        let state = bincode::serialize(&ppu).expect("serialized");
+        let limited = bincode::serialize(&render_line(false)).expect("serialized");
+        assert_eq!(state.len(), limited.len(), "save state size");
+    }
+
+    #[test]
+    fn viewport_origin() {
+        let mut ppu = Ppu::default();
+        ppu.load_chr_ram(vec![0x00; 0x2000]);
+        ppu.write_ctrl(0x01); // Nametable $2400
+        ppu.write_scroll(20);
+        ppu.write_scroll(32);
+        ppu.write_mask(0x08);
+        // Vertical scroll is copied on the pre-render scanline
+        while ppu.scanline() != ppu.prerender_scanline {
+            ppu.clock();
+        }
+        while !(ppu.scanline() == 10 && ppu.cycle() == 0) {
+            ppu.clock();
+        }
+        assert_eq!(ppu.viewport_origin(), (256 + 20, 32));
+
+        // Mid-frame horizontal scroll change takes effect on the next scanline
+        ppu.write_scroll(100);
+        ppu.write_scroll(0);
+        while !(ppu.scanline() == 11 && ppu.cycle() == 0) {
+            ppu.clock();
+        }
+        assert_eq!(ppu.viewport_origin(), (256 + 100, 32));
+    }
+
     #[test]
     fn vram_reads() {
         let mut ppu = Ppu::default();
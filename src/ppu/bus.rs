@@ -1,6 +1,11 @@
 use super::Ppu;
 use crate::{
     common::{Kind, NesRegion, Regional, Reset},
+    debugger::{
+        cdl::ChrFlags,
+        memory::{AccessLog, MemAccess, MemRegion},
+        Watchpoint, WatchpointHit,
+    },
     mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::{Access, Mem},
     ppu::Mirroring,
@@ -35,6 +40,14 @@ pub struct PpuBus {
     chr_ram: Vec<u8>,
     exram: Vec<u8>,
     open_bus: u8,
+    #[serde(skip)]
+    watchpoints: Vec<Watchpoint>,
+    #[serde(skip)]
+    watchpoint_hit: Option<WatchpointHit>,
+    #[serde(skip)]
+    chr_cdl: Option<Vec<u8>>,
+    #[serde(skip)]
+    access_log: Option<AccessLog>,
 }
 
 impl Default for PpuBus {
@@ -57,6 +70,10 @@ impl PpuBus {
             chr_ram: vec![],
             exram: vec![],
             open_bus: 0x00,
+            watchpoints: vec![],
+            watchpoint_hit: None,
+            chr_cdl: None,
+            access_log: None,
         }
     }
 
@@ -100,6 +117,193 @@ impl PpuBus {
         &mut self.mapper
     }
 
+    #[inline]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    #[inline]
+    pub fn watchpoints_mut(&mut self) -> &mut Vec<Watchpoint> {
+        &mut self.watchpoints
+    }
+
+    #[inline]
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.watchpoint_hit.take()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr_rom_len(&self) -> usize {
+        self.chr_rom.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr_cdl(&self) -> Option<&[u8]> {
+        self.chr_cdl.as_deref()
+    }
+
+    #[inline]
+    pub fn chr_cdl_mut(&mut self) -> &mut Option<Vec<u8>> {
+        &mut self.chr_cdl
+    }
+
+    /// Logs a CHR-ROM access for the Code/Data Logger, if enabled.
+    #[inline]
+    pub fn log_chr(&mut self, addr: u16, flags: ChrFlags) {
+        if let Some(chr_cdl) = &mut self.chr_cdl {
+            let offset = match self.mapper.map_peek(addr) {
+                MappedRead::Chr(offset) => offset,
+                _ => addr.into(),
+            };
+            if let Some(logged) = chr_cdl.get_mut(offset) {
+                *logged |= flags.bits();
+            }
+        }
+    }
+
+    #[inline]
+    pub fn start_access_log(&mut self, frame: u32) {
+        let mut log = AccessLog::new();
+        log.set_frame(frame);
+        self.access_log = Some(log);
+    }
+
+    #[inline]
+    pub fn stop_access_log(&mut self) {
+        self.access_log = None;
+    }
+
+    #[inline]
+    pub(crate) fn take_access_log(&mut self, bus: &mut Self) {
+        self.access_log = bus.access_log.take();
+    }
+
+    /// Stops the access log, returning it to be resumed with `resume_access_log`.
+    #[inline]
+    pub(crate) fn suspend_access_log(&mut self) -> Option<AccessLog> {
+        self.access_log.take()
+    }
+
+    #[inline]
+    pub(crate) fn resume_access_log(&mut self, log: Option<AccessLog>) {
+        self.access_log = log;
+    }
+
+    #[inline]
+    pub(crate) fn set_access_frame(&mut self, frame: u32) {
+        if let Some(log) = &mut self.access_log {
+            log.set_frame(frame);
+        }
+    }
+
+    /// Records an access for the memory editor, if enabled.
+    #[inline]
+    pub(crate) fn log_access(&mut self, region: MemRegion, index: usize, access: Access) {
+        if let Some(log) = &mut self.access_log {
+            log.log(region, index, access);
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn last_access(&self, region: MemRegion, index: usize) -> Option<MemAccess> {
+        self.access_log
+            .as_ref()
+            .map(|log| log.last_access(region, index))
+    }
+
+    /// Size of a memory region held by the bus, 0 if the cartridge doesn't have it.
+    #[must_use]
+    pub fn mem_size(&self, region: MemRegion) -> usize {
+        match region {
+            MemRegion::Ppu => 0x4000,
+            MemRegion::Palette => Self::PALETTE_SIZE,
+            MemRegion::ChrRam => self.chr_ram.len(),
+            MemRegion::ExRam => match self.mapper {
+                Mapper::Exrom(ref exrom) => exrom.exram().len(),
+                _ => self.exram.len(),
+            },
+            _ => 0,
+        }
+    }
+
+    #[must_use]
+    pub fn peek_mem(&self, region: MemRegion, index: usize) -> u8 {
+        match region {
+            MemRegion::Ppu => self.peek(index as u16 & 0x3FFF, Access::Dummy),
+            MemRegion::Palette => self.palette[index & 0x1F],
+            MemRegion::ChrRam => self.chr_ram.get(index).copied().unwrap_or_default(),
+            MemRegion::ExRam => match self.mapper {
+                Mapper::Exrom(ref exrom) => exrom.exram().get(index).copied(),
+                _ => self.exram.get(index).copied(),
+            }
+            .unwrap_or_default(),
+            _ => 0x00,
+        }
+    }
+
+    /// Writes a byte without side effects. CHR-ROM is writable through PPU addresses.
+    pub fn poke_mem(&mut self, region: MemRegion, index: usize, val: u8) {
+        let byte = match region {
+            MemRegion::Ppu => self.poke_addr(index as u16 & 0x3FFF),
+            MemRegion::Palette => self.palette.get_mut(index),
+            MemRegion::ChrRam => self.chr_ram.get_mut(index),
+            MemRegion::ExRam => match self.mapper {
+                Mapper::Exrom(ref mut exrom) => exrom.exram_mut().get_mut(index),
+                _ => self.exram.get_mut(index),
+            },
+            _ => None,
+        };
+        if let Some(byte) = byte {
+            *byte = val;
+        }
+    }
+
+    /// Returns the byte a PPU address is mapped to.
+    fn poke_addr(&mut self, addr: u16) -> Option<&mut u8> {
+        match addr {
+            0x0000..=0x1FFF => {
+                let addr = if let MappedRead::Chr(addr) = self.mapper.map_peek(addr) {
+                    addr
+                } else {
+                    addr.into()
+                };
+                if self.chr_ram.is_empty() {
+                    self.chr_rom.get_mut(addr)
+                } else {
+                    self.chr_ram.get_mut(addr)
+                }
+            }
+            0x2000..=0x3EFF => match self.mapper.map_peek(addr) {
+                MappedRead::CIRam(addr) => self.ciram.get_mut(addr & 0x07FF),
+                MappedRead::ExRam(addr) => self.exram.get_mut(addr & 0x03FF),
+                MappedRead::Data(_) => None,
+                _ => {
+                    if self.mirroring() == Mirroring::FourScreen {
+                        None
+                    } else {
+                        let addr = self.ciram_mirror(addr as usize);
+                        self.ciram.get_mut(addr)
+                    }
+                }
+            },
+            _ => {
+                let addr = self.palette_mirror(addr as usize);
+                self.palette.get_mut(addr)
+            }
+        }
+    }
+
+    #[inline]
+    fn check_watchpoints(&mut self, addr: u16, access: Access, old: u8, new: u8) {
+        if self.watchpoint_hit.is_none() {
+            self.watchpoint_hit =
+                WatchpointHit::check(&self.watchpoints, &self.mapper, addr, access, old, new);
+        }
+    }
+
     // Maps addresses to nametable pages based on mirroring mode
     //
     // Vram:            [ A ] [ B ]
@@ -132,10 +336,21 @@ impl PpuBus {
             addr
         }
     }
+
+    /// Resolves nametable and palette mirrors to the address they mirror, so watchpoints and the
+    /// access log see each access on a single address.
+    #[inline]
+    const fn canonical_addr(&self, addr: u16) -> u16 {
+        match addr {
+            0x3000..=0x3EFF => addr - 0x1000, // Nametable Mirrors
+            0x3F00..=0x3FFF => 0x3F00 | self.palette_mirror(addr as usize) as u16, // Palette Mirrors
+            _ => addr,
+        }
+    }
 }
 
 impl Mem for PpuBus {
-    fn read(&mut self, addr: u16, _access: Access) -> u8 {
+    fn read(&mut self, addr: u16, access: Access) -> u8 {
         let val = match addr {
             0x0000..=0x1FFF => {
                 let addr = if let MappedRead::Chr(addr) = self.mapper.map_read(addr) {
@@ -144,6 +359,7 @@ impl Mem for PpuBus {
                     addr.into()
                 };
                 if self.chr_rom.is_empty() {
+                    self.log_access(MemRegion::ChrRam, addr, access);
                     self.chr_ram[addr]
                 } else {
                     self.chr_rom[addr]
@@ -161,12 +377,21 @@ impl Mem for PpuBus {
                     }
                 }
             },
-            0x3F00..=0x3FFF => self.palette[self.palette_mirror(addr as usize)],
+            0x3F00..=0x3FFF => {
+                let addr = self.palette_mirror(addr as usize);
+                self.log_access(MemRegion::Palette, addr, access);
+                self.palette[addr]
+            }
             _ => {
                 log::error!("unexpected PPU memory access at ${:04X}", addr);
                 0x00
             }
         };
+        let canonical = self.canonical_addr(addr);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(canonical, access, val, val);
+        }
+        self.log_access(MemRegion::Ppu, canonical.into(), access);
         self.open_bus = val;
         val
     }
@@ -205,7 +430,12 @@ impl Mem for PpuBus {
         }
     }
 
-    fn write(&mut self, addr: u16, val: u8, _access: Access) {
+    fn write(&mut self, addr: u16, val: u8, access: Access) {
+        let canonical = self.canonical_addr(addr);
+        if !self.watchpoints.is_empty() {
+            let old = self.peek(addr, Access::Dummy);
+            self.check_watchpoints(canonical, access, old, val);
+        }
         match addr {
             0x2000..=0x3EFF => match self.mapper.map_write(addr, val) {
                 MappedWrite::CIRam(addr, val) => self.ciram[addr] = val,
@@ -220,15 +450,19 @@ impl Mem for PpuBus {
             0x0000..=0x1FFF => {
                 if !self.chr_ram.is_empty() {
                     if let MappedWrite::Chr(addr, val) = self.mapper.map_write(addr, val) {
+                        self.log_access(MemRegion::ChrRam, addr, access);
                         self.chr_ram[addr] = val;
                     }
                 }
             }
             0x3F00..=0x3FFF => {
-                self.palette[self.palette_mirror(addr as usize)] = val;
+                let addr = self.palette_mirror(addr as usize);
+                self.log_access(MemRegion::Palette, addr, access);
+                self.palette[addr] = val;
             }
             _ => log::error!("unexpected PPU memory access at ${:04X}", addr),
         }
+        self.log_access(MemRegion::Ppu, canonical.into(), access);
         self.mapper.ppu_bus_write(addr, val);
         self.open_bus = val;
     }
@@ -262,6 +496,7 @@ impl std::fmt::Debug for PpuBus {
             .field("chr_ram_len", &self.chr_ram.len())
             .field("ex_ram_len", &self.exram.len())
             .field("open_bus", &self.open_bus)
+            .field("watchpoints", &self.watchpoints)
             .finish()
     }
 }
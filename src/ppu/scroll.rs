@@ -157,6 +157,27 @@ impl PpuScroll {
         self.v >> 12
     }
 
+    /// Returns the pixel `v` and fine X point to in the 512x480 space of all four nametables, as
+    /// `(x, y)`.
+    #[inline]
+    #[must_use]
+    pub const fn pixel_pos(&self) -> (u16, u16) {
+        let nt_x = if self.v & Self::NT_X_MASK == Self::NT_X_MASK {
+            256
+        } else {
+            0
+        };
+        let nt_y = if self.v & Self::NT_Y_MASK == Self::NT_Y_MASK {
+            240
+        } else {
+            0
+        };
+        (
+            nt_x + self.coarse_x() * 8 + self.fine_x(),
+            nt_y + self.coarse_y() * 8 + self.fine_y(),
+        )
+    }
+
     // Increment PPUADDR v by either 1 (going across) or 32 (going down)
     // Address wraps around
     #[inline]
@@ -52,6 +52,12 @@ impl PpuStatus {
         self.bits
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn spr_overflow(&self) -> bool {
+        self.contains(Self::SPR_OVERFLOW)
+    }
+
     #[inline]
     pub fn set_spr_overflow(&mut self, val: bool) {
         self.set(Self::SPR_OVERFLOW, val);
@@ -0,0 +1,330 @@
+//! RAM search for narrowing down where a game stores values such as lives or health.
+//!
+//! A search starts with every WRAM and PRG-RAM location as a candidate and snapshots their values.
+//! Each filter removes candidates that don't match, comparing against the previous snapshot or a
+//! given value, and then takes a new snapshot so values can be tracked across frames.
+
+use crate::{
+    cpu::Cpu,
+    debugger::Cmp,
+    mapper::{MappedRead, MemMap},
+};
+use std::fmt;
+
+/// Memory region a [`Candidate`] is located in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum RamRegion {
+    Wram,
+    PrgRam,
+}
+
+/// Size of each searched value. Words are little-endian.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub enum ValueSize {
+    #[default]
+    Byte,
+    Word,
+}
+
+impl ValueSize {
+    #[inline]
+    #[must_use]
+    pub const fn as_slice() -> &'static [Self] {
+        &[Self::Byte, Self::Word]
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn bytes(self) -> usize {
+        match self {
+            Self::Byte => 1,
+            Self::Word => 2,
+        }
+    }
+
+    const fn mask(self) -> i64 {
+        match self {
+            Self::Byte => 0xFF,
+            Self::Word => 0xFFFF,
+        }
+    }
+}
+
+impl AsRef<str> for ValueSize {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Byte => "8-bit",
+            Self::Word => "16-bit",
+        }
+    }
+}
+
+/// Filter applied to the remaining candidates of a [`RamSearch`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub enum SearchFilter {
+    /// Compare the current value to the previous snapshot, e.g. `Previous(Cmp::Lt)` keeps values
+    /// that decreased.
+    Previous(Cmp),
+    /// Compare the current value to a given value.
+    Value(Cmp, i32),
+    /// Value changed by exactly the given amount since the previous snapshot, wrapping around.
+    ChangedBy(i32),
+}
+
+impl SearchFilter {
+    fn matches(self, size: ValueSize, current: i32, previous: i32) -> bool {
+        match self {
+            Self::Previous(cmp) => cmp.eval(current, previous),
+            Self::Value(cmp, val) => cmp.eval(current, val),
+            Self::ChangedBy(delta) => {
+                (i64::from(current) - i64::from(previous) - i64::from(delta)) & size.mask() == 0
+            }
+        }
+    }
+}
+
+/// A memory location matching all filters so far.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Candidate {
+    pub region: RamRegion,
+    pub offset: usize,
+    /// CPU address the location is currently mapped to, if any.
+    pub addr: Option<u16>,
+    pub size: ValueSize,
+    pub value: i32,
+    pub previous: i32,
+}
+
+impl Candidate {
+    /// Returns raw `AAAA:VV` cheat codes which hold this location at its current value, or
+    /// nothing if it isn't mapped into CPU memory.
+    #[must_use]
+    pub fn codes(&self) -> Vec<String> {
+        self.addr.map_or_else(Vec::new, |addr| {
+            (0..self.size.bytes())
+                .map(|i| {
+                    let val = (self.value >> (8 * i)) as u8;
+                    format!("{:04X}:{:02X}", addr.wrapping_add(i as u16), val)
+                })
+                .collect()
+        })
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            Some(addr) => write!(f, "${addr:04X}")?,
+            None => write!(f, "{:?}+${:04X}", self.region, self.offset)?,
+        }
+        write!(f, ": {} (was {})", self.value, self.previous)
+    }
+}
+
+/// Searches WRAM and PRG-RAM for locations whose values change in a given way.
+#[derive(Default, Clone)]
+#[must_use]
+pub struct RamSearch {
+    size: ValueSize,
+    signed: bool,
+    wram: Vec<u8>,
+    prg_ram: Vec<u8>,
+    candidates: Vec<(RamRegion, usize)>,
+}
+
+impl RamSearch {
+    /// Starts a new search with every location as a candidate.
+    pub fn new(cpu: &Cpu, size: ValueSize, signed: bool) -> Self {
+        let mut search = Self {
+            size,
+            signed,
+            ..Self::default()
+        };
+        search.reset(cpu);
+        search
+    }
+
+    /// Restarts the search with every location as a candidate.
+    pub fn reset(&mut self, cpu: &Cpu) {
+        self.snapshot(cpu);
+        let last_offset = |len: usize| (len + 1).saturating_sub(self.size.bytes());
+        self.candidates = (0..last_offset(self.wram.len()))
+            .map(|offset| (RamRegion::Wram, offset))
+            .chain((0..last_offset(self.prg_ram.len())).map(|offset| (RamRegion::PrgRam, offset)))
+            .collect();
+    }
+
+    /// Removes candidates not matching `filter` and snapshots the remaining values, returning the
+    /// number of candidates left.
+    pub fn filter(&mut self, cpu: &Cpu, filter: SearchFilter) -> usize {
+        let (size, signed) = (self.size, self.signed);
+        let (wram, prg_ram) = (&self.wram, &self.prg_ram);
+        self.candidates.retain(|&(region, offset)| {
+            let (previous, current) = match region {
+                RamRegion::Wram => (wram, cpu.wram()),
+                RamRegion::PrgRam => (prg_ram, cpu.sram()),
+            };
+            match (
+                read_value(current, offset, size, signed),
+                read_value(previous, offset, size, signed),
+            ) {
+                (Some(current), Some(previous)) => filter.matches(size, current, previous),
+                _ => false,
+            }
+        });
+        self.snapshot(cpu);
+        self.candidates.len()
+    }
+
+    #[inline]
+    pub const fn size(&self) -> ValueSize {
+        self.size
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn signed(&self) -> bool {
+        self.signed
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Returns the remaining candidates with their current and previous values.
+    #[must_use]
+    pub fn candidates(&self, cpu: &Cpu) -> Vec<Candidate> {
+        // PRG-RAM is usually mapped at $6000-$7FFF
+        let mut prg_ram_addrs = vec![None; self.prg_ram.len()];
+        for addr in 0x6000..=0x7FFF {
+            if let MappedRead::PrgRam(offset) = cpu.mapper().map_peek(addr) {
+                if let Some(prg_addr) = prg_ram_addrs.get_mut(offset) {
+                    prg_addr.get_or_insert(addr);
+                }
+            }
+        }
+        self.candidates
+            .iter()
+            .map(|&(region, offset)| {
+                let (previous, current, addr) = match region {
+                    RamRegion::Wram => (&self.wram, cpu.wram(), Some(offset as u16)),
+                    RamRegion::PrgRam => (
+                        &self.prg_ram,
+                        cpu.sram(),
+                        prg_ram_addrs.get(offset).copied().flatten(),
+                    ),
+                };
+                Candidate {
+                    region,
+                    offset,
+                    addr,
+                    size: self.size,
+                    value: read_value(current, offset, self.size, self.signed).unwrap_or_default(),
+                    previous: read_value(previous, offset, self.size, self.signed)
+                        .unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    fn snapshot(&mut self, cpu: &Cpu) {
+        self.wram.clear();
+        self.wram.extend_from_slice(cpu.wram());
+        self.prg_ram.clear();
+        self.prg_ram.extend_from_slice(cpu.sram());
+    }
+}
+
+impl fmt::Debug for RamSearch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RamSearch")
+            .field("size", &self.size)
+            .field("signed", &self.signed)
+            .field("candidates", &self.candidates.len())
+            .finish()
+    }
+}
+
+fn read_value(mem: &[u8], offset: usize, size: ValueSize, signed: bool) -> Option<i32> {
+    match (size, signed) {
+        (ValueSize::Byte, false) => mem.get(offset).map(|&val| i32::from(val)),
+        (ValueSize::Byte, true) => mem.get(offset).map(|&val| i32::from(val as i8)),
+        (ValueSize::Word, _) => {
+            let val = u16::from_le_bytes([*mem.get(offset)?, *mem.get(offset + 1)?]);
+            Some(if signed {
+                i32::from(val as i16)
+            } else {
+                i32::from(val)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bus::CpuBus,
+        cart::Cart,
+        common::{Kind, Reset},
+        mem::RamState,
+    };
+
+    fn load_cpu() -> Cpu {
+        let mut cart = Cart::empty();
+        cart.prg_ram = vec![0x00; 0x2000];
+        let mut cpu = Cpu::new(CpuBus::new(RamState::AllZeros));
+        cpu.load_cart(cart);
+        cpu.reset(Kind::Hard);
+        cpu
+    }
+
+    #[test]
+    fn filter_bytes() {
+        let mut cpu = load_cpu();
+        let mut search = RamSearch::new(&cpu, ValueSize::Byte, false);
+        assert_eq!(search.len(), 0x0800 + 0x2000, "all candidates");
+
+        cpu.poke(0x0075, 3);
+        cpu.poke(0x6010, 3);
+        assert_eq!(search.filter(&cpu, SearchFilter::Value(Cmp::Eq, 3)), 2);
+
+        cpu.poke(0x0075, 2);
+        assert_eq!(search.filter(&cpu, SearchFilter::Previous(Cmp::Lt)), 1);
+        cpu.poke(0x0075, 0xFF);
+        assert_eq!(search.filter(&cpu, SearchFilter::ChangedBy(-3)), 1);
+
+        let candidates = search.candidates(&cpu);
+        assert_eq!(candidates[0].addr, Some(0x0075));
+        assert_eq!(candidates[0].value, 0xFF);
+        assert_eq!(candidates[0].previous, 0xFF);
+        assert_eq!(candidates[0].codes(), ["0075:FF"]);
+    }
+
+    #[test]
+    fn filter_words() {
+        let mut cpu = load_cpu();
+        let mut search = RamSearch::new(&cpu, ValueSize::Word, true);
+        cpu.poke(0x6100, 0xFE);
+        cpu.poke(0x6101, 0xFF);
+        assert_eq!(search.filter(&cpu, SearchFilter::Value(Cmp::Lt, 0)), 2);
+
+        let candidates = search.candidates(&cpu);
+        assert_eq!(candidates[0].region, RamRegion::PrgRam);
+        assert_eq!(candidates[0].addr, Some(0x60FF));
+        assert_eq!(candidates[1].value, -2);
+        assert_eq!(candidates[1].codes(), ["6100:FE", "6101:FF"]);
+    }
+}
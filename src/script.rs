@@ -0,0 +1,464 @@
+//! Scripting API for automating emulation with [Rhai](https://rhai.rs) scripts.
+//!
+//! A script runs once when started to register callbacks, which are then called between CPU
+//! instructions while emulation runs:
+//!
+//! ```text
+//! on_frame(|| draw_text(8, 16, `lives: ${read_cpu(0x075A)}`));
+//! on_write(0x075A, |addr, val| if val == 0 { load_state(1) });
+//! on_exec(0x8E04, || set_button(1, "start", true));
+//! save_state(1);
+//! ```
+//!
+//! | Function                                         | Description                                  |
+//! |--------------------------------------------------|----------------------------------------------|
+//! | `on_frame(fn)`                                   | Call `fn()` after every frame.               |
+//! | `on_exec(addr, fn)`, `on_exec(start, end, fn)`   | Call `fn()` before executing an address.     |
+//! | `on_read(addr, fn)`, `on_read(start, end, fn)`   | Call `fn(addr, val)` after a CPU read.       |
+//! | `on_write(addr, fn)`, `on_write(start, end, fn)` | Call `fn(addr, val)` after a CPU write.      |
+//! | `read_cpu(addr)`, `write_cpu(addr, val)`         | Access CPU memory without side effects.      |
+//! | `read_ppu(addr)`, `write_ppu(addr, val)`         | Access PPU memory without side effects.      |
+//! | `button(player, name)`                           | Whether a joypad button is pressed.          |
+//! | `set_button(player, name, pressed)`              | Press or release a joypad button.            |
+//! | `draw_text(x, y, text)`, `clear_text()`          | Draw text over the frame until the next one. |
+//! | `save_state(slot)`, `load_state(slot)`           | Save or load an in-memory save state.        |
+//! | `pc()`, `frame()`                                | Current program counter and frame number.    |
+//! | `quit()`                                         | Request emulation to stop.                   |
+
+use crate::{
+    bus::CpuBus,
+    cpu::Cpu,
+    debugger::{AddrSpace, Address, Watchpoint},
+    input::{JoypadBtnState, Slot},
+    mem::{Access, Mem},
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST};
+use std::{
+    cell::{Ref, RefCell},
+    collections::HashMap,
+    fmt, fs,
+    path::Path,
+    rc::Rc,
+};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Text drawn over the frame by a script, positioned in NES pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Overlay {
+    pub x: i32,
+    pub y: i32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+#[must_use]
+struct Hook {
+    addr: Address,
+    access: Access,
+    callback: FnPtr,
+}
+
+/// State shared with the functions registered on the script `Engine`. While script code runs, the
+/// emulated `Cpu` is swapped into `cpu` so scripts can access it.
+#[must_use]
+struct ScriptState {
+    cpu: Cpu,
+    frame_hooks: Vec<FnPtr>,
+    hooks: Vec<Hook>,
+    overlays: Vec<Overlay>,
+    states: HashMap<i64, Cpu>,
+    quit: bool,
+}
+
+impl ScriptState {
+    fn add_hook(&mut self, addr: Address, access: Access, callback: FnPtr) {
+        if access != Access::Execute {
+            self.cpu.mem_hooks_mut().push(Watchpoint::new(
+                AddrSpace::Cpu,
+                addr.clone(),
+                vec![access],
+                None,
+            ));
+        }
+        self.hooks.push(Hook {
+            addr,
+            access,
+            callback,
+        });
+    }
+}
+
+/// A compiled script which automates a `Cpu` through callbacks.
+#[must_use]
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    state: Rc<RefCell<ScriptState>>,
+    frame: u32,
+}
+
+impl Script {
+    /// Compiles a script from source.
+    ///
+    /// # Errors
+    ///
+    /// If the script fails to compile, an error is returned.
+    pub fn new(source: &str) -> NesResult<Self> {
+        let state = Rc::new(RefCell::new(ScriptState {
+            cpu: Cpu::new(CpuBus::default()),
+            frame_hooks: vec![],
+            hooks: vec![],
+            overlays: vec![],
+            states: HashMap::new(),
+            quit: false,
+        }));
+        let mut engine = Engine::new();
+        engine
+            .on_print(|text| log::info!("{}", text))
+            .on_debug(|text, _, pos| log::debug!("{:?}: {}", pos, text));
+        register_api(&mut engine, &state);
+        let ast = engine
+            .compile(source)
+            .map_err(|err| anyhow!("failed to compile script: {err}"))?;
+        Ok(Self {
+            engine,
+            ast,
+            state,
+            frame: 0,
+        })
+    }
+
+    /// Compiles a script from a file.
+    ///
+    /// # Errors
+    ///
+    /// If the file fails to be read or the script fails to compile, an error is returned.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> NesResult<Self> {
+        let path = path.as_ref();
+        let source =
+            fs::read_to_string(path).with_context(|| format!("failed to read script {path:?}"))?;
+        Self::new(&source)
+    }
+
+    /// Runs the top-level statements of the script, registering its callbacks. Replaces memory
+    /// hooks from any previously started script.
+    ///
+    /// # Errors
+    ///
+    /// If the script fails to run, an error is returned.
+    pub fn start(&mut self, cpu: &mut Cpu) -> NesResult<()> {
+        // Only one script can hook into a `Cpu` at a time
+        cpu.mem_hooks_mut().clear();
+        self.frame = cpu.frame_number();
+        self.with_cpu(cpu, Engine::run_ast)
+    }
+
+    /// Runs any callbacks triggered by the last instruction or by the next instruction about to
+    /// execute. Intended to be called as the inspect function of
+    /// [`ControlDeck::clock_seconds_inspect`](crate::control_deck::ControlDeck::clock_seconds_inspect).
+    ///
+    /// # Errors
+    ///
+    /// If a callback fails to run, an error is returned.
+    pub fn inspect(&mut self, cpu: &mut Cpu) -> NesResult<()> {
+        let hits = cpu.take_mem_hook_hits();
+        let pc = cpu.pc();
+        let frame = cpu.frame_number();
+        let new_frame = frame != self.frame;
+        self.frame = frame;
+
+        let callbacks = {
+            let mut state = self.state.borrow_mut();
+            let mut callbacks = vec![];
+            for hit in hits {
+                callbacks.extend(
+                    state
+                        .hooks
+                        .iter()
+                        .filter(|hook| hook.access == hit.access && hook.addr.contains(hit.addr))
+                        .map(|hook| {
+                            let args = vec![i64::from(hit.addr), i64::from(hit.new)];
+                            (hook.callback.clone(), args)
+                        }),
+                );
+            }
+            if new_frame {
+                state.overlays.clear();
+                callbacks.extend(
+                    state
+                        .frame_hooks
+                        .iter()
+                        .map(|callback| (callback.clone(), vec![])),
+                );
+            }
+            callbacks.extend(
+                state
+                    .hooks
+                    .iter()
+                    .filter(|hook| hook.access == Access::Execute && hook.addr.contains(pc))
+                    .map(|hook| (hook.callback.clone(), vec![])),
+            );
+            callbacks
+        };
+        if callbacks.is_empty() {
+            return Ok(());
+        }
+
+        self.with_cpu(cpu, |engine, ast| {
+            for (callback, args) in callbacks {
+                let _ = callback.call::<Dynamic>(engine, ast, args)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Text currently drawn by the script.
+    #[inline]
+    pub fn overlays(&self) -> Ref<'_, [Overlay]> {
+        Ref::map(self.state.borrow(), |state| state.overlays.as_slice())
+    }
+
+    /// Whether the script has requested emulation to stop.
+    #[inline]
+    #[must_use]
+    pub fn quit_requested(&self) -> bool {
+        self.state.borrow().quit
+    }
+
+    /// Swaps `cpu` into the shared script state while running script code.
+    fn with_cpu<T, F>(&mut self, cpu: &mut Cpu, f: F) -> NesResult<T>
+    where
+        F: FnOnce(&Engine, &AST) -> ScriptResult<T>,
+    {
+        std::mem::swap(cpu, &mut self.state.borrow_mut().cpu);
+        let result = f(&self.engine, &self.ast);
+        std::mem::swap(cpu, &mut self.state.borrow_mut().cpu);
+        result.map_err(|err| anyhow!("script error: {err}"))
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("Script")
+            .field("frame_hooks", &state.frame_hooks.len())
+            .field("hooks", &state.hooks.len())
+            .field("overlays", &state.overlays)
+            .field("states", &state.states.len())
+            .field("quit", &state.quit)
+            .finish()
+    }
+}
+
+fn slot(player: i64) -> ScriptResult<Slot> {
+    match player {
+        1 => Ok(Slot::One),
+        2 => Ok(Slot::Two),
+        3 => Ok(Slot::Three),
+        4 => Ok(Slot::Four),
+        _ => Err(format!("invalid player: {player}").into()),
+    }
+}
+
+fn button(name: &str) -> ScriptResult<JoypadBtnState> {
+    match name.to_lowercase().as_str() {
+        "a" => Ok(JoypadBtnState::A),
+        "b" => Ok(JoypadBtnState::B),
+        "select" => Ok(JoypadBtnState::SELECT),
+        "start" => Ok(JoypadBtnState::START),
+        "up" => Ok(JoypadBtnState::UP),
+        "down" => Ok(JoypadBtnState::DOWN),
+        "left" => Ok(JoypadBtnState::LEFT),
+        "right" => Ok(JoypadBtnState::RIGHT),
+        _ => Err(format!("invalid button: {name:?}").into()),
+    }
+}
+
+fn register_api(engine: &mut Engine, state: &Rc<RefCell<ScriptState>>) {
+    let s = Rc::clone(state);
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        s.borrow_mut().frame_hooks.push(callback);
+    });
+    for (name, access) in [
+        ("on_exec", Access::Execute),
+        ("on_read", Access::Read),
+        ("on_write", Access::Write),
+    ] {
+        let s = Rc::clone(state);
+        engine.register_fn(name, move |addr: i64, callback: FnPtr| {
+            s.borrow_mut()
+                .add_hook(Address::from(addr as u16), access, callback);
+        });
+        let s = Rc::clone(state);
+        engine.register_fn(name, move |start: i64, end: i64, callback: FnPtr| {
+            s.borrow_mut()
+                .add_hook(Address::from(start as u16..=end as u16), access, callback);
+        });
+    }
+
+    let s = Rc::clone(state);
+    engine.register_fn("read_cpu", move |addr: i64| {
+        i64::from(s.borrow().cpu.peek(addr as u16, Access::Dummy))
+    });
+    let s = Rc::clone(state);
+    engine.register_fn("write_cpu", move |addr: i64, val: i64| {
+        s.borrow_mut().cpu.poke(addr as u16, val as u8);
+    });
+    let s = Rc::clone(state);
+    engine.register_fn("read_ppu", move |addr: i64| {
+        i64::from(s.borrow().cpu.ppu().peek(addr as u16, Access::Dummy))
+    });
+    let s = Rc::clone(state);
+    engine.register_fn("write_ppu", move |addr: i64, val: i64| {
+        s.borrow_mut()
+            .cpu
+            .ppu_mut()
+            .write(addr as u16, val as u8, Access::Dummy);
+    });
+    let s = Rc::clone(state);
+    engine.register_fn("pc", move || i64::from(s.borrow().cpu.pc()));
+    let s = Rc::clone(state);
+    engine.register_fn("frame", move || i64::from(s.borrow().cpu.frame_number()));
+
+    let s = Rc::clone(state);
+    engine.register_fn(
+        "button",
+        move |player: i64, name: &str| -> ScriptResult<bool> {
+            Ok(s.borrow().cpu.joypad(slot(player)?).button(button(name)?))
+        },
+    );
+    let s = Rc::clone(state);
+    engine.register_fn(
+        "set_button",
+        move |player: i64, name: &str, pressed: bool| -> ScriptResult<()> {
+            let (slot, button) = (slot(player)?, button(name)?);
+            s.borrow_mut()
+                .cpu
+                .joypad_mut(slot)
+                .set_button(button, pressed);
+            Ok(())
+        },
+    );
+
+    let s = Rc::clone(state);
+    engine.register_fn("draw_text", move |x: i64, y: i64, text: &str| {
+        s.borrow_mut().overlays.push(Overlay {
+            x: x as i32,
+            y: y as i32,
+            text: text.to_string(),
+        });
+    });
+    let s = Rc::clone(state);
+    engine.register_fn("clear_text", move || s.borrow_mut().overlays.clear());
+
+    let s = Rc::clone(state);
+    engine.register_fn("save_state", move |slot: i64| {
+        let mut state = s.borrow_mut();
+        let cpu = state.cpu.clone();
+        state.states.insert(slot, cpu);
+    });
+    let s = Rc::clone(state);
+    engine.register_fn("load_state", move |slot: i64| -> ScriptResult<()> {
+        let mut state = s.borrow_mut();
+        let cpu = state
+            .states
+            .get(&slot)
+            .cloned()
+            .ok_or_else(|| format!("no save state in slot {slot}"))?;
+        state.cpu.load_state(cpu);
+        Ok(())
+    });
+    let s = Rc::clone(state);
+    engine.register_fn("quit", move || s.borrow_mut().quit = true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control_deck::ControlDeck,
+        debugger::{history::History, tests::load_program},
+    };
+
+    // $8000 LDX #$00
+    // $8002 INX
+    // $8003 STX $10
+    // $8005 JMP $8002
+    const PROGRAM: [u8; 8] = [0xA2, 0x00, 0xE8, 0x86, 0x10, 0x4C, 0x02, 0x80];
+
+    fn run(deck: &mut ControlDeck, script: &str, instrs: usize) -> Script {
+        let mut script = Script::new(script).expect("valid script");
+        script.start(deck.cpu_mut()).expect("started script");
+        for _ in 0..instrs {
+            deck.cpu_mut()
+                .clock_inspect(|cpu| script.inspect(cpu).expect("valid callback"));
+        }
+        script
+    }
+
+    #[test]
+    fn hooks() {
+        let mut deck = load_program(&PROGRAM);
+        let script = run(
+            &mut deck,
+            r#"
+            on_exec(0x8002, || write_cpu(0x00, read_cpu(0x00) + 1));
+            on_write(0x10, |addr, val| write_cpu(addr + 1, val * 2));
+            on_read(0x8000, 0x8001, |addr, val| draw_text(0, 0, `${addr}`));
+            set_button(1, "Start", true);
+            "#,
+            7,
+        );
+        let cpu = deck.cpu();
+        assert_eq!(cpu.peek(0x0000, Access::Dummy), 0x02, "exec hook");
+        assert_eq!(cpu.peek(0x0011, Access::Dummy), 0x04, "write hook");
+        assert!(script.overlays().is_empty(), "no data reads");
+        assert!(
+            cpu.joypad(Slot::One).button(JoypadBtnState::START),
+            "button"
+        );
+    }
+
+    #[test]
+    fn save_states() {
+        let mut deck = load_program(&PROGRAM);
+        deck.start_history(History::default());
+        // Snapshot the reset state before the script runs
+        let _ = deck.clock_instr().expect("valid clock");
+        let script = run(
+            &mut deck,
+            r#"
+            on_exec(0x8002, || save_state(1));
+            on_write(0x10, |addr, val| {
+                if val == 3 {
+                    load_state(1);
+                    write_cpu(0x20, read_cpu(addr));
+                    quit();
+                }
+            });
+            "#,
+            11,
+        );
+        assert!(script.quit_requested(), "quit");
+        assert_eq!(deck.cpu().peek(0x0020, Access::Dummy), 0x02, "loaded state");
+        let cycle = deck.cpu().cycle();
+        let _ = deck.clock_instr().expect("valid clock");
+        assert_eq!(
+            deck.history().and_then(History::start_cycle),
+            Some(cycle),
+            "history discarded"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Script::new("on_frame(").is_err(), "compile error");
+        let mut deck = load_program(&PROGRAM);
+        let mut script = Script::new(r#"set_button(1, "turbo", true)"#).expect("valid script");
+        assert!(script.start(deck.cpu_mut()).is_err(), "invalid button");
+    }
+}
@@ -1,8 +1,14 @@
-use crate::ppu::Ppu;
+use crate::{
+    common::{NesRegion, Regional},
+    ppu::Ppu,
+};
 use once_cell::sync::Lazy;
+use palette::Palette;
 use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 
+pub mod palette;
+
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 #[must_use]
 pub enum VideoFilter {
@@ -36,11 +42,94 @@ impl From<usize> for VideoFilter {
     }
 }
 
+/// Rows and columns of pixels cropped from each edge of the frame. Most TVs hide some of the
+/// picture behind the bezel, and games often leave garbage there, so 8 scanlines are cropped from
+/// the top and bottom by default.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub struct Overscan {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Default for Overscan {
+    fn default() -> Self {
+        Self {
+            top: 8,
+            bottom: 8,
+            left: 0,
+            right: 0,
+        }
+    }
+}
+
+impl Overscan {
+    /// The most pixels that can be cropped from a single edge.
+    pub const MAX: u32 = 64;
+
+    /// No cropping.
+    pub const fn none() -> Self {
+        Self {
+            top: 0,
+            bottom: 0,
+            left: 0,
+            right: 0,
+        }
+    }
+
+    /// Limits each edge to `Overscan::MAX`.
+    pub fn clamped(self) -> Self {
+        Self {
+            top: self.top.min(Self::MAX),
+            bottom: self.bottom.min(Self::MAX),
+            left: self.left.min(Self::MAX),
+            right: self.right.min(Self::MAX),
+        }
+    }
+
+    /// Width of the cropped frame.
+    #[inline]
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        let Self { left, right, .. } = self.clamped();
+        Ppu::WIDTH - left - right
+    }
+
+    /// Height of the cropped frame.
+    #[inline]
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        let Self { top, bottom, .. } = self.clamped();
+        Ppu::HEIGHT - top - bottom
+    }
+
+    /// Copies the visible area of a frame of RGBA pixels into `output`.
+    pub fn crop(&self, frame: &[u8], output: &mut Vec<u8>) {
+        let Self { top, left, .. } = self.clamped();
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        output.clear();
+        for row in frame
+            .chunks_exact(4 * Ppu::WIDTH as usize)
+            .skip(top as usize)
+            .take(height)
+        {
+            let start = 4 * left as usize;
+            output.extend_from_slice(&row[start..start + 4 * width]);
+        }
+    }
+}
+
 #[derive(Clone)]
 #[must_use]
 pub struct Video {
     filter: VideoFilter,
+    region: NesRegion,
+    palettes: [Palette; 3],
+    overscan: Overscan,
     output: Vec<u8>,
+    cropped: Vec<u8>,
 }
 
 impl Default for Video {
@@ -58,7 +147,11 @@ impl Video {
         }
         Self {
             filter: VideoFilter::default(),
+            region: NesRegion::default(),
+            palettes: [Palette::default(), Palette::default(), Palette::default()],
+            overscan: Overscan::none(),
             output,
+            cropped: vec![],
         }
     }
 
@@ -72,6 +165,28 @@ impl Video {
         self.filter = filter;
     }
 
+    /// The palette used for Pixellate output in a given region.
+    #[inline]
+    pub const fn palette(&self, region: NesRegion) -> &Palette {
+        &self.palettes[region as usize]
+    }
+
+    /// Set the palette used for Pixellate output in a given region.
+    #[inline]
+    pub fn set_palette(&mut self, region: NesRegion, palette: Palette) {
+        self.palettes[region as usize] = palette;
+    }
+
+    #[inline]
+    pub const fn overscan(&self) -> Overscan {
+        self.overscan
+    }
+
+    #[inline]
+    pub fn set_overscan(&mut self, overscan: Overscan) {
+        self.overscan = overscan.clamped();
+    }
+
     // Returns a fully rendered frame of RENDER_SIZE RGB colors
     pub fn apply_filter(&mut self, buffer: &[u16], frame_number: u32) {
         match self.filter {
@@ -86,11 +201,19 @@ impl Video {
         &self.output
     }
 
+    /// Returns the last filtered frame with overscan cropped.
+    #[must_use]
+    pub fn cropped_output(&mut self) -> &[u8] {
+        self.overscan.crop(&self.output, &mut self.cropped);
+        &self.cropped
+    }
+
     pub fn decode_buffer(&mut self, buffer: &[u16]) {
         assert!(buffer.len() * 4 == self.output.len());
+        let palette = &self.palettes[self.region as usize];
         for (pixel, colors) in buffer.iter().zip(self.output.chunks_exact_mut(4)) {
             assert!(colors.len() > 2);
-            let (red, green, blue) = Ppu::system_palette(*pixel);
+            let (red, green, blue) = palette.color(*pixel);
             colors[0] = red;
             colors[1] = green;
             colors[2] = blue;
@@ -135,11 +258,25 @@ impl std::fmt::Debug for Video {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Video")
             .field("filter", &self.filter)
+            .field("region", &self.region)
+            .field("overscan", &self.overscan)
             .field("output_len", &self.output.len())
             .finish()
     }
 }
 
+impl Regional for Video {
+    #[inline]
+    fn region(&self) -> NesRegion {
+        self.region
+    }
+
+    #[inline]
+    fn set_region(&mut self, region: NesRegion) {
+        self.region = region;
+    }
+}
+
 pub static NTSC_PALETTE: Lazy<Vec<u32>> = Lazy::new(|| {
     // NOTE: There's lot's to clean up here -- too many magic numbers and duplication but
     // I'm afraid to touch it now that it works
@@ -236,3 +373,44 @@ pub static NTSC_PALETTE: Lazy<Vec<u32>> = Lazy::new(|| {
 
     ntsc_palette
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overscan_crop() {
+        let frame: Vec<u8> = (0..Ppu::SIZE as u32).flat_map(u32::to_le_bytes).collect();
+        let pixel = |output: &[u8], index: usize| {
+            u32::from_le_bytes([
+                output[4 * index],
+                output[4 * index + 1],
+                output[4 * index + 2],
+                output[4 * index + 3],
+            ])
+        };
+
+        let mut output = vec![];
+        Overscan::none().crop(&frame, &mut output);
+        assert_eq!(output, frame);
+
+        let overscan = Overscan {
+            top: 8,
+            bottom: 16,
+            left: 4,
+            right: 12,
+        };
+        assert_eq!((overscan.width(), overscan.height()), (240, 216));
+        overscan.crop(&frame, &mut output);
+        assert_eq!(output.len(), 4 * 240 * 216);
+        assert_eq!(pixel(&output, 0), 8 * Ppu::WIDTH + 4);
+        assert_eq!(pixel(&output, 240), 9 * Ppu::WIDTH + 4);
+        assert_eq!(pixel(&output, 240 * 216 - 1), 223 * Ppu::WIDTH + 243);
+
+        let overscan = Overscan {
+            left: 200,
+            ..Overscan::none()
+        };
+        assert_eq!(overscan.width(), Ppu::WIDTH - Overscan::MAX);
+    }
+}
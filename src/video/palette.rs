@@ -0,0 +1,142 @@
+//! Color palettes mapping PPU pixels to RGB.
+//!
+//! Pixels are 9 bits: a 6-bit color from palette RAM plus the 3 color emphasis bits of `PPUMASK`
+//! (red, green and blue on NTSC, with red and green swapped on PAL and Dendy). Palette files are
+//! raw RGB triplets in the same order, either the 64 base colors or all 512 colors, 64 for each
+//! combination of emphasis bits.
+
+use crate::{ppu::Ppu, NesResult};
+use anyhow::{anyhow, Context};
+use std::io::Read;
+
+/// A full 512 color palette, including every combination of color emphasis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Palette {
+    /// Number of base colors.
+    pub const BASE_COLORS: usize = 64;
+    /// Number of colors including emphasis.
+    pub const COLORS: usize = 8 * Self::BASE_COLORS;
+
+    // Emphasis darkens the other two color channels
+    // https://www.nesdev.org/wiki/NTSC_video#Color_Tint_Bits
+    const ATTENUATION: f32 = 0.816_328;
+
+    /// The built-in palette, with emphasis colors derived from the 64 base colors.
+    pub fn builtin() -> Self {
+        let colors: Vec<(u8, u8, u8)> = (0..Self::BASE_COLORS as u16)
+            .map(Ppu::system_palette)
+            .collect();
+        Self::from_base(&colors)
+    }
+
+    /// Loads a `.pal` file with either 64 colors (192 bytes) or 512 colors (1536 bytes). Emphasis
+    /// colors are derived for 64 color palettes.
+    ///
+    /// # Errors
+    ///
+    /// If the palette fails to be read or has an invalid size, an error is returned.
+    pub fn load<R: Read>(reader: &mut R) -> NesResult<Self> {
+        let mut bytes = Vec::with_capacity(3 * Self::COLORS);
+        reader
+            .read_to_end(&mut bytes)
+            .context("failed to read palette")?;
+        let colors: Vec<(u8, u8, u8)> = bytes
+            .chunks_exact(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect();
+        match bytes.len() {
+            len if len == 3 * Self::BASE_COLORS => Ok(Self::from_base(&colors)),
+            len if len == 3 * Self::COLORS => Ok(Self { colors }),
+            len => Err(anyhow!(
+                "invalid palette size: {len} bytes, expected {} or {}",
+                3 * Self::BASE_COLORS,
+                3 * Self::COLORS
+            )),
+        }
+    }
+
+    /// Returns the RGB color for a pixel, including emphasis bits.
+    #[inline]
+    #[must_use]
+    pub fn color(&self, pixel: u16) -> (u8, u8, u8) {
+        self.colors[(pixel as usize) & (Self::COLORS - 1)]
+    }
+
+    fn from_base(base: &[(u8, u8, u8)]) -> Self {
+        let colors = (0..Self::COLORS)
+            .map(|index| {
+                let (red, green, blue) = base[index & (Self::BASE_COLORS - 1)];
+                let emphasis = index / Self::BASE_COLORS;
+                // Columns $E-$F are black and unaffected by emphasis
+                if emphasis == 0 || index & 0x0F >= 0x0E {
+                    return (red, green, blue);
+                }
+                let attenuate = |channel: u8, emphasized: usize| {
+                    // Emphasizing all three darkens every channel
+                    if emphasis & emphasized == 0 || emphasis == 0x07 {
+                        (f32::from(channel) * Self::ATTENUATION) as u8
+                    } else {
+                        channel
+                    }
+                };
+                (
+                    attenuate(red, 0x01),
+                    attenuate(green, 0x02),
+                    attenuate(blue, 0x04),
+                )
+            })
+            .collect();
+        Self { colors }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_emphasis() {
+        let palette = Palette::builtin();
+        assert_eq!(palette.color(0x16), Ppu::system_palette(0x16));
+        let (red, green, blue) = Ppu::system_palette(0x30);
+        let attenuate = |channel: u8| (f32::from(channel) * Palette::ATTENUATION) as u8;
+        // Red emphasis
+        assert_eq!(
+            palette.color(0x40 | 0x30),
+            (red, attenuate(green), attenuate(blue))
+        );
+        // Green and blue emphasis
+        assert_eq!(palette.color(0x180 | 0x30), (attenuate(red), green, blue));
+        // All emphasis
+        assert_eq!(
+            palette.color(0x1C0 | 0x30),
+            (attenuate(red), attenuate(green), attenuate(blue))
+        );
+        assert_eq!(palette.color(0x1C0 | 0x0F), Ppu::system_palette(0x0F));
+    }
+
+    #[test]
+    fn load_pal() {
+        let base: Vec<u8> = (0..3 * Palette::BASE_COLORS).map(|i| i as u8).collect();
+        let palette = Palette::load(&mut base.as_slice()).expect("valid 64 color palette");
+        assert_eq!(palette.color(0x01), (3, 4, 5));
+        assert_eq!(palette.color(0x41), (3, 3, 4), "derived red emphasis");
+
+        let full: Vec<u8> = (0..3 * Palette::COLORS).map(|i| (i / 3) as u8).collect();
+        let palette = Palette::load(&mut full.as_slice()).expect("valid 512 color palette");
+        assert_eq!(palette.color(0x141), (0x41, 0x41, 0x41));
+
+        assert!(Palette::load(&mut [0x00; 100].as_slice()).is_err());
+    }
+}
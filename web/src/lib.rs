@@ -30,8 +30,8 @@ impl Nes {
     pub fn new(output_sample_rate: f32, max_delta: f32) -> Self {
         let mut control_deck = ControlDeck::new(RamState::default());
         control_deck.set_filter(VideoFilter::Pixellate);
-        let input_sample_rate = control_deck.sample_rate();
-        let mut audio = AudioMixer::new(input_sample_rate, output_sample_rate, 4096);
+        let mut audio = AudioMixer::new(output_sample_rate, 4096);
+        control_deck.set_sample_rate(audio.sample_rate());
         let callback = audio.open_callback().expect("valid callback");
         Self {
             paused: true,
@@ -80,6 +80,7 @@ impl Nes {
                 .consume(samples, self.dynamic_rate_control, self.dynamic_rate_delta);
         }
         self.control_deck.clear_audio_samples();
+        self.control_deck.set_sample_rate(self.audio.sample_rate());
     }
 
     pub fn load_rom(&mut self, mut bytes: &[u8]) {